@@ -0,0 +1,82 @@
+use quote::ToTokens;
+use syn::ext::IdentExt;
+use syn::{Attribute, DeriveInput, Error, Expr, ExprLit, Field, Lit, LitStr, Meta, Result};
+
+//把 /// 文档注释合并成一个字符串 作为 description
+pub fn doc_string(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs.iter().filter(|attr| attr.path().is_ident("doc")).filter_map(|attr| match &attr.meta {
+        Meta::NameValue(nv)=> match &nv.value {
+            Expr::Lit(ExprLit { lit: Lit::Str(s), .. })=> Some(s.value().trim().to_string()),
+            _=> None
+        },
+        _=> None
+    }).collect();
+    let doc = lines.join("\n").trim().to_string();
+    if doc.is_empty() { None } else { Some(doc) }
+}
+
+//#[libai(...)] 属性 字段可以用 rename skip default 变体只能用 rename 类型上可以用 tag content untagged
+#[derive(Default)]
+pub struct Attrs {
+    pub rename: Option<String>,
+    pub skip: bool,
+    pub default: bool,
+    pub tag: Option<String>,
+    pub content: Option<String>,
+    pub untagged: bool,
+}
+
+pub fn parse_attrs(attrs: &[Attribute]) -> Result<Attrs> {
+    let mut result = Attrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("libai")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                result.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("tag") {
+                result.tag = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("content") {
+                result.content = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("skip") {
+                result.skip = true;
+            } else if meta.path.is_ident("default") {
+                result.default = true;
+            } else if meta.path.is_ident("untagged") {
+                result.untagged = true;
+            } else {
+                return Err(meta.error("unknown libai attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(result)
+}
+
+//字段在 json object 或者 msgpack map 里的名字
+pub fn field_name(field: &Field, attrs: &Attrs) -> String {
+    attrs.rename.clone().unwrap_or_else(|| field.ident.as_ref().map(|ident| ident.unraw().to_string()).unwrap_or_default())
+}
+
+//enum 的表示方式
+pub enum Repr {
+    External,                       //"Unit" 或者 {"Variant": 内容}
+    Internal(String),               //{"tag": "Variant", 其他字段...}
+    Adjacent(String, String),       //{"tag": "Variant", "content": 内容}
+    Untagged,                       //直接是内容 按顺序尝试每个变体
+}
+
+pub fn enum_repr(input: &DeriveInput) -> Result<Repr> {
+    let attrs = parse_attrs(&input.attrs)?;
+    assert_attrs(&input.ident, attrs.rename.is_some() || attrs.skip || attrs.default, "only tag content untagged can be used on a type")?;
+    match (attrs.tag, attrs.content, attrs.untagged) {
+        (None, None, false)=> Ok(Repr::External),
+        (Some(tag), None, false)=> Ok(Repr::Internal(tag)),
+        (Some(tag), Some(content), false)=> Ok(Repr::Adjacent(tag, content)),
+        (None, None, true)=> Ok(Repr::Untagged),
+        (None, Some(_), _)=> Err(Error::new_spanned(&input.ident, "content must be used with tag")),
+        _=> Err(Error::new_spanned(&input.ident, "untagged can not be used with tag")),
+    }
+}
+
+pub fn assert_attrs<T: ToTokens>(tokens: T, invalid: bool, message: &str) -> Result<()> {
+    if invalid { Err(Error::new_spanned(tokens, message)) } else { Ok(()) }
+}
@@ -0,0 +1,404 @@
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{Data, DeriveInput, Error, Fields, Ident, Result, Type, parse_quote};
+use super::attr::{Attrs, Repr, assert_attrs, enum_repr, field_name, parse_attrs};
+
+//json 和 msgpack 生成的代码结构一样 只是辅助函数所在的模块 trait 和方法名不同
+pub struct Codec {
+    module: TokenStream,
+    encoder: TokenStream,
+    encode: Ident,
+    buffer: TokenStream,
+    decoder: TokenStream,
+    decode: Ident,
+}
+
+pub fn json() -> Codec {
+    Codec {
+        module: quote!(::libai::json),
+        encoder: quote!(::libai::json::ToJson),
+        encode: Ident::new("to_json", Span::call_site()),
+        buffer: quote!(::std::string::String),
+        decoder: quote!(::libai::json::FromJson),
+        decode: Ident::new("from_json", Span::call_site()),
+    }
+}
+
+pub fn msgpack() -> Codec {
+    Codec {
+        module: quote!(::libai::msgpack),
+        encoder: quote!(::libai::msgpack::MsgPack),
+        encode: Ident::new("encode", Span::call_site()),
+        buffer: quote!(::std::vec::Vec<u8>),
+        decoder: quote!(::libai::msgpack::MsgUnpack),
+        decode: Ident::new("decode", Span::call_site()),
+    }
+}
+
+struct FieldInfo<'a> {
+    ident: Option<&'a Ident>,
+    ty: &'a Type,
+    attrs: Attrs,
+    name: String,
+    binding: Ident,
+}
+
+fn field_infos(fields: &Fields) -> Result<Vec<FieldInfo<'_>>> {
+    fields.iter().enumerate().map(|(index, field)| {
+        let attrs = parse_attrs(&field.attrs)?;
+        assert_attrs(field, attrs.tag.is_some() || attrs.content.is_some() || attrs.untagged, "only rename skip default can be used on a field")?;
+        assert_attrs(field, field.ident.is_none() && (attrs.rename.is_some() || attrs.skip || attrs.default), "tuple field does not support libai attributes")?;
+        let binding = match &field.ident {
+            Some(ident)=> format_ident!("__field_{}", ident.unraw()),
+            None=> format_ident!("__field{}", index),
+        };
+        let name = field_name(field, &attrs);
+        Ok(FieldInfo { ident: field.ident.as_ref(), ty: &field.ty, attrs, name, binding })
+    }).collect()
+}
+
+//变体的名字 只能用 rename
+fn variant_name(variant: &syn::Variant) -> Result<String> {
+    let attrs = parse_attrs(&variant.attrs)?;
+    assert_attrs(variant, attrs.skip || attrs.default || attrs.tag.is_some() || attrs.content.is_some() || attrs.untagged, "only rename can be used on a variant")?;
+    Ok(attrs.rename.unwrap_or_else(|| variant.ident.unraw().to_string()))
+}
+
+//解构 self 或者变体 字段绑定到 __field_xxx 跳过的字段不绑定
+fn pattern(path: &TokenStream, fields: &Fields, infos: &[FieldInfo]) -> TokenStream {
+    match fields {
+        Fields::Named(_)=> {
+            let bindings = infos.iter().filter(|f| !f.attrs.skip).map(|f| {
+                let (ident, binding) = (f.ident, &f.binding);
+                quote!(#ident: #binding)
+            });
+            quote!(#path { #(#bindings,)* .. })
+        }
+        Fields::Unnamed(_)=> {
+            let bindings = infos.iter().map(|f| &f.binding);
+            quote!(#path(#(#bindings),*))
+        }
+        Fields::Unit=> quote!(#path),
+    }
+}
+
+impl Codec {
+    //tag 是内部标签 enum 写在最前面的 (标签名, 变体名)
+    fn encode_object(&self, infos: &[FieldInfo], tag: Option<(&str, &str)>) -> TokenStream {
+        let Codec { module, encoder, encode, .. } = self;
+        let offset = tag.is_some() as usize;
+        let active: Vec<&FieldInfo> = infos.iter().filter(|f| !f.attrs.skip).collect();
+        let len = active.len() + offset;
+        let tag = tag.map(|(tag, name)| quote! {
+            #module::object_key(buf, 0, #tag);
+            #module::write_str(buf, #name);
+        });
+        let fields = active.iter().enumerate().map(|(index, f)| {
+            let index = index + offset;
+            let (name, binding) = (&f.name, &f.binding);
+            quote! {
+                #module::object_key(buf, #index, #name);
+                #encoder::#encode(#binding, buf);
+            }
+        });
+        quote! {
+            #module::object_begin(buf, #len);
+            #tag
+            #(#fields)*
+            #module::object_end(buf);
+        }
+    }
+
+    //命名字段是 object 一个元组字段是内部的值 多个是 array 单元是 null
+    fn encode_content(&self, fields: &Fields, infos: &[FieldInfo]) -> TokenStream {
+        let Codec { module, encoder, encode, .. } = self;
+        match fields {
+            Fields::Named(_)=> self.encode_object(infos, None),
+            Fields::Unnamed(_) if infos.len() == 1=> {
+                let binding = &infos[0].binding;
+                quote!(#encoder::#encode(#binding, buf);)
+            }
+            Fields::Unnamed(_)=> {
+                let len = infos.len();
+                let items = infos.iter().enumerate().map(|(index, f)| {
+                    let binding = &f.binding;
+                    quote! {
+                        #module::array_item(buf, #index);
+                        #encoder::#encode(#binding, buf);
+                    }
+                });
+                quote! {
+                    #module::array_begin(buf, #len);
+                    #(#items)*
+                    #module::array_end(buf);
+                }
+            }
+            Fields::Unit=> quote!(#module::write_null(buf);),
+        }
+    }
+
+    fn encode_variant(&self, repr: &Repr, name: &str, fields: &Fields, infos: &[FieldInfo]) -> Result<TokenStream> {
+        let module = &self.module;
+        let content = self.encode_content(fields, infos);
+        Ok(match (repr, fields) {
+            (Repr::External, Fields::Unit)=> quote!(#module::write_str(buf, #name);),
+            (Repr::External, _)=> quote! {
+                #module::object_begin(buf, 1);
+                #module::object_key(buf, 0, #name);
+                #content
+                #module::object_end(buf);
+            },
+            (Repr::Internal(tag), Fields::Unnamed(_))=> {
+                return Err(Error::new_spanned(fields, format!("tuple variant can not use tag = \"{}\" without content", tag)));
+            }
+            (Repr::Internal(tag), _) | (Repr::Adjacent(tag, _), Fields::Unit)=> self.encode_object(infos, Some((tag, name))),
+            (Repr::Adjacent(tag, key), _)=> quote! {
+                #module::object_begin(buf, 2);
+                #module::object_key(buf, 0, #tag);
+                #module::write_str(buf, #name);
+                #module::object_key(buf, 1, #key);
+                #content
+                #module::object_end(buf);
+            },
+            (Repr::Untagged, _)=> content,
+        })
+    }
+
+    //生成的表达式得到 (值, 长度) 从 __buf 里解析 ctor 是 Self 或者 Self::Variant
+    fn decode_content(&self, ctor: &TokenStream, fields: &Fields, infos: &[FieldInfo]) -> TokenStream {
+        let Codec { module, decoder, decode, .. } = self;
+        match fields {
+            Fields::Named(_)=> {
+                let active: Vec<&FieldInfo> = infos.iter().filter(|f| !f.attrs.skip).collect();
+                let declares = active.iter().map(|f| {
+                    let (binding, ty) = (&f.binding, f.ty);
+                    quote!(let mut #binding: ::core::option::Option<#ty> = ::core::option::Option::None;)
+                });
+                let arms = active.iter().map(|f| {
+                    let (name, binding, ty) = (&f.name, &f.binding, f.ty);
+                    quote! {
+                        #name=> {
+                            let (__value, __size) = <#ty as #decoder>::#decode(__value)?;
+                            #binding = ::core::option::Option::Some(__value);
+                            ::core::result::Result::Ok(::core::option::Option::Some(__size))
+                        }
+                    }
+                });
+                let values = infos.iter().map(|f| {
+                    let (ident, name, binding, ty) = (f.ident, &f.name, &f.binding, f.ty);
+                    if f.attrs.skip {
+                        quote!(#ident: ::core::default::Default::default())
+                    } else if f.attrs.default {
+                        quote!(#ident: #binding.unwrap_or_default())
+                    } else {
+                        quote! {
+                            #ident: match #binding {
+                                ::core::option::Option::Some(__value)=> __value,
+                                ::core::option::Option::None=> <#ty as #decoder>::missing().ok_or_else(|| #module::missing_field(#name))?,
+                            }
+                        }
+                    }
+                });
+                let read = if active.is_empty() {
+                    quote!(#module::read_object(__buf, |_, _| ::core::result::Result::Ok(::core::option::Option::None))?)
+                } else {
+                    quote! {
+                        #module::read_object(__buf, |__key, __value| match __key {
+                            #(#arms)*
+                            _=> ::core::result::Result::Ok(::core::option::Option::None),
+                        })?
+                    }
+                };
+                quote! {
+                    {
+                        #(#declares)*
+                        let __size = #read;
+                        (#ctor { #(#values),* }, __size)
+                    }
+                }
+            }
+            Fields::Unnamed(_) if infos.len() == 1=> {
+                let ty = infos[0].ty;
+                quote! {
+                    {
+                        let (__value, __size) = <#ty as #decoder>::#decode(__buf)?;
+                        (#ctor(__value), __size)
+                    }
+                }
+            }
+            Fields::Unnamed(_)=> {
+                let declares = infos.iter().map(|f| {
+                    let (binding, ty) = (&f.binding, f.ty);
+                    quote!(let mut #binding: ::core::option::Option<#ty> = ::core::option::Option::None;)
+                });
+                let arms = infos.iter().enumerate().map(|(index, f)| {
+                    let (binding, ty) = (&f.binding, f.ty);
+                    quote! {
+                        #index=> {
+                            let (__value, __size) = <#ty as #decoder>::#decode(__value)?;
+                            #binding = ::core::option::Option::Some(__value);
+                            ::core::result::Result::Ok(__size)
+                        }
+                    }
+                });
+                let values = infos.iter().enumerate().map(|(index, f)| {
+                    let (binding, index) = (&f.binding, index.to_string());
+                    quote!(#binding.ok_or_else(|| #module::missing_field(#index))?)
+                });
+                quote! {
+                    {
+                        #(#declares)*
+                        let (_, __size) = #module::read_array(__buf, |__index, __value| match __index {
+                            #(#arms)*
+                            _=> #module::skip_value(__value),
+                        })?;
+                        (#ctor(#(#values),*), __size)
+                    }
+                }
+            }
+            Fields::Unit=> quote! {
+                {
+                    let __size = #module::read_null(__buf)?;
+                    (#ctor, __size)
+                }
+            },
+        }
+    }
+
+    fn decode_enum(&self, ident: &Ident, data: &syn::DataEnum, repr: &Repr) -> Result<TokenStream> {
+        let module = &self.module;
+        let mut arms = Vec::new();
+        for variant in data.variants.iter() {
+            let name = variant_name(variant)?;
+            let infos = field_infos(&variant.fields)?;
+            let variant_ident = &variant.ident;
+            let ctor = quote!(Self::#variant_ident);
+            let content = self.decode_content(&ctor, &variant.fields, &infos);
+            arms.push(match (repr, &variant.fields) {
+                (Repr::External | Repr::Adjacent(..), Fields::Unit)=> quote!((#name, _)=> #ctor,),
+                (Repr::External | Repr::Adjacent(..), _)=> quote! {
+                    (#name, ::core::option::Option::Some(__offset))=> {
+                        let __buf = &__buf[__offset..];
+                        (#content).0
+                    }
+                },
+                (Repr::Internal(_), Fields::Unit)=> quote!(#name=> (#ctor, __size),),
+                (Repr::Internal(_), Fields::Named(_))=> quote!(#name=> #content,),
+                (Repr::Internal(_), _)=> return Err(Error::new_spanned(&variant.fields, "tuple variant can not use tag without content")),
+                (Repr::Untagged, _)=> quote! {
+                    if let ::core::result::Result::Ok(__result) = (|| -> ::libai::anyhow::Result<(Self, usize)> { ::core::result::Result::Ok(#content) })() {
+                        return ::core::result::Result::Ok(__result);
+                    }
+                },
+            });
+        }
+        let unknown = quote!(return ::core::result::Result::Err(#module::unknown_variant(&__name)));
+        Ok(match repr {
+            Repr::External=> quote! {
+                let (__name, __offset, __size) = #module::read_variant(__buf)?;
+                let __value = match (__name.as_str(), __offset) {
+                    #(#arms)*
+                    _=> #unknown,
+                };
+                ::core::result::Result::Ok((__value, __size))
+            },
+            Repr::Adjacent(tag, content)=> quote! {
+                let (__name, __offset, __size) = #module::read_adjacent(__buf, #tag, #content)?;
+                let __value = match (__name.as_str(), __offset) {
+                    #(#arms)*
+                    _=> #unknown,
+                };
+                ::core::result::Result::Ok((__value, __size))
+            },
+            Repr::Internal(tag)=> quote! {
+                let (__name, __size) = #module::read_tag(__buf, #tag)?;
+                ::core::result::Result::Ok(match __name.as_str() {
+                    #(#arms)*
+                    _=> #unknown,
+                })
+            },
+            Repr::Untagged=> {
+                let message = format!("data did not match any variant of {}", ident);
+                quote! {
+                    #(#arms)*
+                    ::core::result::Result::Err(::libai::anyhow::anyhow!(#message))
+                }
+            }
+        })
+    }
+
+    pub fn expand_encode(&self, input: &DeriveInput) -> Result<TokenStream> {
+        let Codec { encoder, encode, buffer, .. } = self;
+        let ident = &input.ident;
+        let body = match &input.data {
+            Data::Struct(data)=> {
+                assert_attrs(ident, !matches!(enum_repr(input)?, Repr::External), "tag content untagged can only be used on enum")?;
+                let infos = field_infos(&data.fields)?;
+                let pattern = pattern(&quote!(Self), &data.fields, &infos);
+                let content = self.encode_content(&data.fields, &infos);
+                quote! {
+                    let #pattern = self;
+                    #content
+                }
+            }
+            Data::Enum(data)=> {
+                let repr = enum_repr(input)?;
+                let mut arms = Vec::new();
+                for variant in data.variants.iter() {
+                    let name = variant_name(variant)?;
+                    let infos = field_infos(&variant.fields)?;
+                    let variant_ident = &variant.ident;
+                    let pattern = pattern(&quote!(Self::#variant_ident), &variant.fields, &infos);
+                    let body = self.encode_variant(&repr, &name, &variant.fields, &infos)?;
+                    arms.push(quote!(#pattern=> { #body }));
+                }
+                if arms.is_empty() {
+                    quote!(match *self {})
+                } else {
+                    quote!(match self { #(#arms)* })
+                }
+            }
+            Data::Union(_)=> return Err(Error::new_spanned(ident, "can not derive for union")),
+        };
+        let mut generics = input.generics.clone();
+        for param in generics.type_params_mut() {
+            param.bounds.push(parse_quote!(#encoder));
+        }
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        Ok(quote! {
+            impl #impl_generics #encoder for #ident #ty_generics #where_clause {
+                fn #encode(&self, buf: &mut #buffer) {
+                    #body
+                }
+            }
+        })
+    }
+
+    pub fn expand_decode(&self, input: &DeriveInput) -> Result<TokenStream> {
+        let Codec { decoder, decode, .. } = self;
+        let ident = &input.ident;
+        let body = match &input.data {
+            Data::Struct(data)=> {
+                assert_attrs(ident, !matches!(enum_repr(input)?, Repr::External), "tag content untagged can only be used on enum")?;
+                let infos = field_infos(&data.fields)?;
+                let content = self.decode_content(&quote!(Self), &data.fields, &infos);
+                quote!(::core::result::Result::Ok(#content))
+            }
+            Data::Enum(data)=> self.decode_enum(ident, data, &enum_repr(input)?)?,
+            Data::Union(_)=> return Err(Error::new_spanned(ident, "can not derive for union")),
+        };
+        let mut generics = input.generics.clone();
+        for param in generics.type_params_mut() {
+            param.bounds.push(parse_quote!(#decoder));
+        }
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        Ok(quote! {
+            impl #impl_generics #decoder for #ident #ty_generics #where_clause {
+                fn #decode(__buf: &[u8]) -> ::libai::anyhow::Result<(Self, usize)> {
+                    #body
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,36 @@
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+mod attr;
+mod codec;
+mod tool_schema;
+
+#[proc_macro_derive(ToolSchema, attributes(libai))]
+pub fn derive_tool_schema(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    tool_schema::expand(&input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+#[proc_macro_derive(ToJson, attributes(libai))]
+pub fn derive_to_json(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    codec::json().expand_encode(&input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+#[proc_macro_derive(FromJson, attributes(libai))]
+pub fn derive_from_json(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    codec::json().expand_decode(&input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+#[proc_macro_derive(MsgPack, attributes(libai))]
+pub fn derive_msgpack(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    codec::msgpack().expand_encode(&input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+#[proc_macro_derive(MsgUnpack, attributes(libai))]
+pub fn derive_msgunpack(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    codec::msgpack().expand_decode(&input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
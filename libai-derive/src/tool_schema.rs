@@ -0,0 +1,229 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::{Data, DeriveInput, Fields, GenericParam, Result, Error, parse_quote};
+use super::attr::{Repr, assert_attrs, doc_string, enum_repr, field_name, parse_attrs};
+
+//给 schema 加上 description 的代码
+fn describe(schema: &TokenStream, doc: Option<String>) -> TokenStream {
+    match doc {
+        Some(doc)=> quote! { let _ = #schema.set_key("description", #doc); },
+        None=> quote! {}
+    }
+}
+
+//命名字段生成 object schema skip 的字段不出现 default 的字段不是 required
+fn object_schema(fields: &syn::FieldsNamed) -> Result<TokenStream> {
+    let mut items = Vec::new();
+    for field in fields.named.iter() {
+        let attrs = parse_attrs(&field.attrs)?;
+        if attrs.skip {
+            continue;
+        }
+        let name = field_name(field, &attrs);
+        let ty = &field.ty;
+        let describe = describe(&quote!(field), doc_string(&field.attrs));
+        let required = if attrs.default {
+            quote! {}
+        } else {
+            quote! {
+                if !<#ty as ::libai::schema::ToolSchema>::is_optional() {
+                    let _ = required.push(#name);
+                }
+            }
+        };
+        items.push(quote! {
+            {
+                let field = <#ty as ::libai::schema::ToolSchema>::schema_ref(defs);
+                #describe
+                let _ = properties.set_key(#name, field);
+                #required
+            }
+        });
+    }
+    Ok(quote! {
+        {
+            let schema = ::libai::dynamic::Dynamic::map();
+            let properties = ::libai::dynamic::Dynamic::map();
+            let required = ::libai::dynamic::Dynamic::vec();
+            #(#items)*
+            let _ = schema.set_key("type", "object");
+            let _ = schema.set_key("properties", properties);
+            let _ = schema.set_key("required", required);
+            let _ = schema.set_key("additionalProperties", false);
+            schema
+        }
+    })
+}
+
+//元组字段 一个字段就是内部类型 多个字段是定长数组
+fn tuple_schema(fields: &syn::FieldsUnnamed) -> TokenStream {
+    if fields.unnamed.len() == 1 {
+        let ty = &fields.unnamed[0].ty;
+        return quote! { <#ty as ::libai::schema::ToolSchema>::schema_ref(defs) };
+    }
+    let len = fields.unnamed.len() as i64;
+    let items = fields.unnamed.iter().map(|field| {
+        let ty = &field.ty;
+        quote! { let _ = items.push(<#ty as ::libai::schema::ToolSchema>::schema_ref(defs)); }
+    });
+    quote! {
+        {
+            let schema = ::libai::dynamic::Dynamic::map();
+            let items = ::libai::dynamic::Dynamic::vec();
+            #(#items)*
+            let _ = schema.set_key("type", "array");
+            let _ = schema.set_key("prefixItems", items);
+            let _ = schema.set_key("minItems", #len);
+            let _ = schema.set_key("maxItems", #len);
+            schema
+        }
+    }
+}
+
+fn fields_schema(fields: &Fields) -> Result<TokenStream> {
+    Ok(match fields {
+        Fields::Named(named)=> object_schema(named)?,
+        Fields::Unnamed(unnamed)=> tuple_schema(unnamed),
+        Fields::Unit=> quote! {
+            {
+                let schema = ::libai::dynamic::Dynamic::map();
+                let _ = schema.set_key("type", "null");
+                schema
+            }
+        }
+    })
+}
+
+//变体名字固定的 object 属性 {"const": name}
+fn const_schema(name: &str) -> TokenStream {
+    quote! {
+        {
+            let schema = ::libai::dynamic::Dynamic::map();
+            let _ = schema.set_key("const", #name);
+            schema
+        }
+    }
+}
+
+//只有 keys 这些属性并且都是 required 的 object
+fn closed_object(keys: &[&str], values: &[TokenStream]) -> TokenStream {
+    quote! {
+        {
+            let schema = ::libai::dynamic::Dynamic::map();
+            let properties = ::libai::dynamic::Dynamic::map();
+            let required = ::libai::dynamic::Dynamic::vec();
+            #(
+                let _ = properties.set_key(#keys, #values);
+                let _ = required.push(#keys);
+            )*
+            let _ = schema.set_key("type", "object");
+            let _ = schema.set_key("properties", properties);
+            let _ = schema.set_key("required", required);
+            let _ = schema.set_key("additionalProperties", false);
+            schema
+        }
+    }
+}
+
+//和 ToJson 等 derive 的表示方式一致 外部标签只有单元变体时是字符串枚举 其他情况生成 oneOf
+fn enum_schema(input: &DeriveInput, data: &syn::DataEnum) -> Result<TokenStream> {
+    let repr = enum_repr(input)?;
+    let mut names = Vec::new();
+    for variant in data.variants.iter() {
+        let attrs = parse_attrs(&variant.attrs)?;
+        assert_attrs(variant, attrs.skip || attrs.default || attrs.tag.is_some() || attrs.content.is_some() || attrs.untagged, "only rename can be used on a variant")?;
+        names.push(attrs.rename.unwrap_or_else(|| variant.ident.unraw().to_string()));
+    }
+    if matches!(repr, Repr::External) && data.variants.iter().all(|v| matches!(v.fields, Fields::Unit)) {
+        return Ok(quote! {
+            {
+                let schema = ::libai::dynamic::Dynamic::map();
+                let values = ::libai::dynamic::Dynamic::vec();
+                #( let _ = values.push(#names); )*
+                let _ = schema.set_key("type", "string");
+                let _ = schema.set_key("enum", values);
+                schema
+            }
+        });
+    }
+    let mut variants = Vec::new();
+    for (v, name) in data.variants.iter().zip(names.iter()) {
+        let name = name.as_str();
+        let describe = describe(&quote!(variant), doc_string(&v.attrs));
+        let body = match (&repr, &v.fields) {
+            (Repr::External, Fields::Unit)=> const_schema(name),
+            (Repr::External, fields)=> closed_object(&[name], &[fields_schema(fields)?]),
+            (Repr::Internal(tag) | Repr::Adjacent(tag, _), Fields::Unit)=> closed_object(&[tag], &[const_schema(name)]),
+            (Repr::Internal(tag), Fields::Named(named))=> {
+                let inner = object_schema(named)?;
+                let tag_schema = const_schema(name);
+                quote! {
+                    {
+                        let schema = #inner;
+                        let _ = schema.at("properties").set_key(#tag, #tag_schema);
+                        let _ = schema.at("required").insert(0, #tag);
+                        schema
+                    }
+                }
+            }
+            (Repr::Internal(_), fields)=> return Err(Error::new_spanned(fields, "tuple variant can not use tag without content")),
+            (Repr::Adjacent(tag, content), fields)=> closed_object(&[tag, content], &[const_schema(name), fields_schema(fields)?]),
+            (Repr::Untagged, fields)=> fields_schema(fields)?,
+        };
+        variants.push(quote! {
+            {
+                let variant = #body;
+                #describe
+                let _ = variants.push(variant);
+            }
+        });
+    }
+    Ok(quote! {
+        {
+            let schema = ::libai::dynamic::Dynamic::map();
+            let variants = ::libai::dynamic::Dynamic::vec();
+            #(#variants)*
+            let _ = schema.set_key("oneOf", variants);
+            schema
+        }
+    })
+}
+
+pub fn expand(input: &DeriveInput) -> Result<TokenStream> {
+    let ident = &input.ident;
+    let name = ident.to_string();
+    let body = match &input.data {
+        Data::Struct(data)=> {
+            assert_attrs(ident, !matches!(enum_repr(input)?, Repr::External), "tag content untagged can only be used on enum")?;
+            fields_schema(&data.fields)?
+        }
+        Data::Enum(data)=> enum_schema(input, data)?,
+        Data::Union(_)=> return Err(Error::new_spanned(ident, "ToolSchema can not derive for union")),
+    };
+    let describe = describe(&quote!(schema), doc_string(&input.attrs));
+    let mut generics = input.generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::libai::schema::ToolSchema));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    //泛型的每个实例是不同的 schema 用具体的类型名作为 $defs 的 key 例如 W<i32> 是 W_i32
+    let name = if input.generics.params.iter().any(|param| !matches!(param, GenericParam::Lifetime(_))) {
+        quote!(&::libai::schema::def_name::<Self>())
+    } else {
+        quote!(#name)
+    };
+    Ok(quote! {
+        impl #impl_generics ::libai::schema::ToolSchema for #ident #ty_generics #where_clause {
+            fn schema_body(defs: &::libai::dynamic::Dynamic) -> ::libai::dynamic::Dynamic {
+                let schema = #body;
+                #describe
+                schema
+            }
+
+            fn schema_ref(defs: &::libai::dynamic::Dynamic) -> ::libai::dynamic::Dynamic {
+                ::libai::schema::schema_define::<Self>(defs, #name)
+            }
+        }
+    })
+}
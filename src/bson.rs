@@ -0,0 +1,462 @@
+use anyhow::{anyhow, Result};
+use byteorder::{LittleEndian, WriteBytesExt};
+use smol_str::SmolStr;
+use super::dynamic::Dynamic;
+use super::map::{DuplicatePolicy, MapBuilder};
+use super::assert_err;
+
+//Dynamic 表示不了的 BSON 类型用 MongoDB Extended JSON 的写法 数字和二进制直接用 Dynamic 的类型
+//ObjectId {"$oid": "24 位 hex"} 时间 {"$date": 毫秒} 非 0 子类型的二进制 {"$binary": Bytes, "$type": 子类型}
+//{"$timestamp": {"t", "i"}} {"$regularExpression": {"pattern", "options"}} {"$numberDecimal": "1.5"}
+//{"$code"} {"$code", "$scope"} {"$symbol"} {"$undefined": true} {"$minKey": 1} {"$maxKey": 1} {"$dbPointer": {"$ref", "$id"}}
+pub const BINARY_GENERIC: u8 = 0x00;
+pub const BINARY_FUNCTION: u8 = 0x01;
+pub const BINARY_UUID: u8 = 0x04;
+pub const BINARY_MD5: u8 = 0x05;
+pub const BINARY_ENCRYPTED: u8 = 0x06;
+pub const BINARY_USER: u8 = 0x80;
+
+//解码 BSON 的选项
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    pub duplicates: DuplicatePolicy,
+}
+
+//顶层必须是 Map 或者 AnyMap 循环引用 超出 int64 的 UInt 和带 \0 的 key 返回错误
+pub fn encode(value: &Dynamic, buf: &mut Vec<u8>) -> Result<()> {
+    assert_err!(!is_document(value), anyhow!("bson top-level value must be a document"));
+    write_document(value, buf, &mut Vec::new())
+}
+
+pub fn decode(buf: &[u8]) -> Result<(Dynamic, usize)> {
+    decode_with(buf, &DecodeOptions::default())
+}
+
+pub fn decode_with(buf: &[u8], options: &DecodeOptions) -> Result<(Dynamic, usize)> {
+    Decoder { buf, options }.document(0, false)
+}
+
+//mongodump 的 .bson 文件是连续的多个文档
+pub fn decode_all(buf: &[u8]) -> Result<Vec<Dynamic>> {
+    let decoder = Decoder { buf, options: &DecodeOptions::default() };
+    let mut documents = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let (document, size) = decoder.document(pos, false)?;
+        documents.push(document);
+        pos += size;
+    }
+    Ok(documents)
+}
+
+fn is_document(value: &Dynamic) -> bool {
+    matches!(value, Dynamic::Map(_) | Dynamic::AnyMap(_))
+}
+
+fn entries(value: &Dynamic) -> Result<Vec<(SmolStr, Dynamic)>> {
+    match value {
+        Dynamic::Map(m)=> Ok(m.read().iter().map(|(k, v)| (k.clone(), v.clone())).collect()),
+        Dynamic::AnyMap(m)=> m.read().iter().map(|(k, v)| Ok((k.to_key_string()?, v.clone()))).collect(),
+        Dynamic::Vec(v)=> Ok(v.read().iter().enumerate().map(|(i, v)| (SmolStr::new(i.to_string()), v.clone())).collect()),
+        _=> Err(anyhow!("is not a document"))
+    }
+}
+
+fn write_cstring(buf: &mut Vec<u8>, text: &str) -> Result<()> {
+    assert_err!(text.contains('\0'), anyhow!("bson cstring {:?} can not contain \\0", text));
+    buf.extend_from_slice(text.as_bytes());
+    buf.push(0);
+    Ok(())
+}
+
+fn write_string(buf: &mut Vec<u8>, text: &str) {
+    buf.write_i32::<LittleEndian>(text.len() as i32 + 1).unwrap();
+    buf.extend_from_slice(text.as_bytes());
+    buf.push(0);
+}
+
+//Vec 写成 key 是 "0" "1" 的文档 长度写在开头 最后补上
+fn write_document(value: &Dynamic, buf: &mut Vec<u8>, stack: &mut Vec<usize>) -> Result<()> {
+    let id = value.container_id().unwrap_or_default();
+    assert_err!(stack.contains(&id), anyhow!("cycle can not be bson"));
+    stack.push(id);
+    let start = buf.len();
+    buf.extend_from_slice(&[0; 4]);
+    for (key, item) in entries(value)? {
+        let kind = buf.len();
+        buf.push(0);
+        write_cstring(buf, &key)?;
+        buf[kind] = write_value(&item, buf, stack)?;
+    }
+    buf.push(0);
+    let size = (buf.len() - start) as i32;
+    buf[start..start + 4].copy_from_slice(&size.to_le_bytes());
+    stack.pop();
+    Ok(())
+}
+
+//写出内容 返回类型字节
+fn write_value(value: &Dynamic, buf: &mut Vec<u8>, stack: &mut Vec<usize>) -> Result<u8> {
+    let integer = |v: i64, buf: &mut Vec<u8>| match i32::try_from(v) {
+        Ok(v)=> {
+            buf.write_i32::<LittleEndian>(v).unwrap();
+            0x10
+        }
+        Err(_)=> {
+            buf.write_i64::<LittleEndian>(v).unwrap();
+            0x12
+        }
+    };
+    Ok(match value {
+        Dynamic::Null=> 0x0a,
+        Dynamic::Bool(b)=> {
+            buf.push(*b as u8);
+            0x08
+        }
+        Dynamic::Byte(v)=> integer(*v as i64, buf),
+        Dynamic::Int(v)=> integer(*v, buf),
+        Dynamic::UInt(v)=> integer(i64::try_from(*v).map_err(|_| anyhow!("integer {} is out of bson range", v))?, buf),
+        Dynamic::Float(v)=> {
+            buf.write_f64::<LittleEndian>(*v as f64).unwrap();
+            0x01
+        }
+        Dynamic::Double(v)=> {
+            buf.write_f64::<LittleEndian>(*v).unwrap();
+            0x01
+        }
+        Dynamic::String(s)=> {
+            write_string(buf, s);
+            0x02
+        }
+        Dynamic::Bytes(b)=> {
+            write_binary(buf, BINARY_GENERIC, b);
+            0x05
+        }
+        Dynamic::Vec(_)=> {
+            write_document(value, buf, stack)?;
+            0x04
+        }
+        Dynamic::Map(_) | Dynamic::AnyMap(_)=> match write_extended(value, buf, stack)? {
+            Some(kind)=> kind,
+            None=> {
+                write_document(value, buf, stack)?;
+                0x03
+            }
+        }
+    })
+}
+
+fn write_binary(buf: &mut Vec<u8>, subtype: u8, data: &[u8]) {
+    buf.write_i32::<LittleEndian>(data.len() as i32).unwrap();
+    buf.push(subtype);
+    buf.extend_from_slice(data);
+}
+
+fn object_id(value: &Dynamic) -> Option<[u8; 12]> {
+    let hex = value.as_str().ok()?;
+    let mut id = [0u8; 12];
+    if hex.len() != 24 || !hex.is_ascii() {
+        return None;
+    }
+    for (i, byte) in id.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(id)
+}
+
+//Extended JSON 形式的 map 写成对应的 BSON 类型 形式不对的时候当作普通的文档
+fn write_extended(value: &Dynamic, buf: &mut Vec<u8>, stack: &mut Vec<usize>) -> Result<Option<u8>> {
+    let Dynamic::Map(m) = value else { return Ok(None) };
+    let (first, second, length) = {
+        let map = m.read();
+        let mut keys = map.keys();
+        (keys.next().cloned().unwrap_or_default(), keys.next().cloned().unwrap_or_default(), map.len())
+    };
+    let field = |key: &str| value.get_key(key).unwrap_or_default();
+    let (a, b) = if first.as_str() <= second.as_str() || length < 2 { (first, second) } else { (second, first) };
+    Ok(Some(match (a.as_str(), b.as_str(), length) {
+        ("$oid", _, 1) if object_id(&field("$oid")).is_some()=> {
+            buf.extend_from_slice(&object_id(&field("$oid")).unwrap_or_default());
+            0x07
+        }
+        ("$date", _, 1)=> {
+            let Dynamic::Int(date) = field("$date") else { return Ok(None) };
+            buf.write_i64::<LittleEndian>(date).unwrap();
+            0x09
+        }
+        ("$binary", "$type", 2)=> {
+            let (Dynamic::Bytes(data), Dynamic::Int(subtype @ 0..=255)) = (field("$binary"), field("$type")) else { return Ok(None) };
+            write_binary(buf, subtype as u8, &data);
+            0x05
+        }
+        ("$timestamp", _, 1) if field("$timestamp").is_map()=> {
+            let timestamp = field("$timestamp");
+            let part = |key: &str| match timestamp.get_key(key) {
+                Ok(Dynamic::Int(v))=> u32::try_from(v).ok(),
+                _=> None
+            };
+            let (Some(t), Some(i)) = (part("t"), part("i")) else { return Ok(None) };
+            buf.write_u32::<LittleEndian>(i).unwrap();
+            buf.write_u32::<LittleEndian>(t).unwrap();
+            0x11
+        }
+        ("$regularExpression", _, 1) if field("$regularExpression").is_map()=> {
+            let regex = field("$regularExpression");
+            let (Ok(pattern), Ok(options)) = (regex.get_key("pattern"), regex.get_key("options")) else { return Ok(None) };
+            write_cstring(buf, pattern.as_str()?)?;
+            let mut options: Vec<char> = options.as_str()?.chars().collect();
+            options.sort();                                 //选项按照字母顺序
+            write_cstring(buf, &options.into_iter().collect::<String>())?;
+            0x0b
+        }
+        ("$numberDecimal", _, 1) if field("$numberDecimal").is_string()=> {
+            let Some(decimal) = decimal_from_str(field("$numberDecimal").as_str()?) else { return Ok(None) };
+            buf.extend_from_slice(&decimal);
+            0x13
+        }
+        ("$code", _, 1) if field("$code").is_string()=> {
+            write_string(buf, field("$code").as_str()?);
+            0x0d
+        }
+        ("$code", "$scope", 2) if field("$code").is_string() && is_document(&field("$scope"))=> {
+            let start = buf.len();
+            buf.extend_from_slice(&[0; 4]);
+            write_string(buf, field("$code").as_str()?);
+            write_document(&field("$scope"), buf, stack)?;
+            let size = (buf.len() - start) as i32;
+            buf[start..start + 4].copy_from_slice(&size.to_le_bytes());
+            0x0f
+        }
+        ("$symbol", _, 1) if field("$symbol").is_string()=> {
+            write_string(buf, field("$symbol").as_str()?);
+            0x0e
+        }
+        ("$dbPointer", _, 1) if field("$dbPointer").is_map()=> {
+            let pointer = field("$dbPointer");
+            let (Ok(namespace), Ok(id)) = (pointer.get_key("$ref"), pointer.get_key("$id")) else { return Ok(None) };
+            let (Ok(namespace), Some(id)) = (namespace.as_str(), id.get_key("$oid").ok().as_ref().and_then(object_id)) else { return Ok(None) };
+            write_string(buf, namespace);
+            buf.extend_from_slice(&id);
+            0x0c
+        }
+        ("$undefined", _, 1)=> 0x06,
+        ("$minKey", _, 1)=> 0xff,
+        ("$maxKey", _, 1)=> 0x7f,
+        _=> return Ok(None)
+    }))
+}
+
+fn extended(key: &str, value: Dynamic) -> Dynamic {
+    let map = Dynamic::map();
+    let _ = map.set_key(key, value);
+    map
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+    options: &'a DecodeOptions,
+}
+
+impl Decoder<'_> {
+    fn take(&self, pos: usize, length: usize) -> Result<&[u8]> {
+        self.buf.get(pos..pos + length).ok_or_else(|| anyhow!("unexpected end of bson at offset {}", pos))
+    }
+
+    fn i32_at(&self, pos: usize) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.take(pos, 4)?.try_into()?))
+    }
+
+    fn i64_at(&self, pos: usize) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.take(pos, 8)?.try_into()?))
+    }
+
+    //返回内容和包括 \0 的长度
+    fn cstring(&self, pos: usize) -> Result<(&str, usize)> {
+        let end = self.buf.get(pos..).and_then(|rest| rest.iter().position(|c| *c == 0)).ok_or_else(|| anyhow!("unterminated cstring at offset {}", pos))?;
+        let text = std::str::from_utf8(&self.buf[pos..pos + end]).map_err(|_| anyhow!("invalid utf8 at offset {}", pos))?;
+        Ok((text, end + 1))
+    }
+
+    fn string(&self, pos: usize) -> Result<(&str, usize)> {
+        let length = self.i32_at(pos)?;
+        assert_err!(length < 1, anyhow!("invalid string length {} at offset {}", length, pos));
+        let data = self.take(pos + 4, length as usize)?;
+        assert_err!(data[data.len() - 1] != 0, anyhow!("string is not terminated at offset {}", pos));
+        let text = std::str::from_utf8(&data[..data.len() - 1]).map_err(|_| anyhow!("invalid utf8 at offset {}", pos))?;
+        Ok((text, 4 + length as usize))
+    }
+
+    //文档和数组 数组忽略 key
+    fn document(&self, pos: usize, array: bool) -> Result<(Dynamic, usize)> {
+        let size = self.i32_at(pos)?;
+        assert_err!(size < 5, anyhow!("invalid document size {} at offset {}", size, pos));
+        let end = pos + size as usize;
+        assert_err!(self.take(pos, size as usize)?[size as usize - 1] != 0, anyhow!("document is not terminated at offset {}", end - 1));
+        let mut map = MapBuilder::new(self.options.duplicates);
+        let mut items = Vec::new();
+        let mut cursor = pos + 4;
+        while cursor < end - 1 {
+            let kind = self.buf[cursor];
+            let (key, length) = self.cstring(cursor + 1)?;
+            let offset = cursor;
+            cursor += 1 + length;
+            let (value, length) = self.element(kind, cursor)?;
+            cursor += length;
+            match array {
+                true=> items.push(value),
+                false=> map.insert(SmolStr::from(key), value, offset)?
+            }
+        }
+        assert_err!(cursor != end - 1, anyhow!("document size mismatch at offset {}", pos));
+        Ok((if array { Dynamic::from_vec(items) } else { map.build() }, size as usize))
+    }
+
+    fn element(&self, kind: u8, pos: usize) -> Result<(Dynamic, usize)> {
+        Ok(match kind {
+            0x01=> (Dynamic::Double(f64::from_le_bytes(self.take(pos, 8)?.try_into()?)), 8),
+            0x02=> {
+                let (text, length) = self.string(pos)?;
+                (Dynamic::from(text), length)
+            }
+            0x03=> self.document(pos, false)?,
+            0x04=> self.document(pos, true)?,
+            0x05=> {
+                let length = self.i32_at(pos)?;
+                assert_err!(length < 0, anyhow!("invalid binary length {} at offset {}", length, pos));
+                let subtype = self.take(pos + 4, 1)?[0];
+                let mut data = self.take(pos + 5, length as usize)?;
+                if subtype == 0x02 && data.len() >= 4 {                   //旧的二进制子类型里面还有一个长度
+                    data = &data[4..];
+                }
+                let bytes = Dynamic::from_bytes(data.to_vec());
+                let value = match subtype {
+                    BINARY_GENERIC=> bytes,
+                    _=> {
+                        let map = extended("$binary", bytes);
+                        map.set_key("$type", Dynamic::Int(subtype as i64))?;
+                        map
+                    }
+                };
+                (value, 5 + length as usize)
+            }
+            0x06=> (extended("$undefined", Dynamic::Bool(true)), 0),
+            0x07=> (extended("$oid", Dynamic::from(hex(self.take(pos, 12)?))), 12),
+            0x08=> match self.take(pos, 1)?[0] {
+                0=> (Dynamic::Bool(false), 1),
+                1=> (Dynamic::Bool(true), 1),
+                v=> return Err(anyhow!("invalid boolean {} at offset {}", v, pos))
+            }
+            0x09=> (extended("$date", Dynamic::Int(self.i64_at(pos)?)), 8),
+            0x0a=> (Dynamic::Null, 0),
+            0x0b=> {
+                let (pattern, first) = self.cstring(pos)?;
+                let (options, second) = self.cstring(pos + first)?;
+                let regex = Dynamic::map();
+                regex.set_key("pattern", pattern)?;
+                regex.set_key("options", options)?;
+                (extended("$regularExpression", regex), first + second)
+            }
+            0x0c=> {
+                let (namespace, length) = self.string(pos)?;
+                let pointer = Dynamic::map();
+                pointer.set_key("$ref", namespace)?;
+                pointer.set_key("$id", extended("$oid", Dynamic::from(hex(self.take(pos + length, 12)?))))?;
+                (extended("$dbPointer", pointer), length + 12)
+            }
+            0x0d | 0x0e=> {
+                let (text, length) = self.string(pos)?;
+                (extended(if kind == 0x0d { "$code" } else { "$symbol" }, Dynamic::from(text)), length)
+            }
+            0x0f=> {
+                let size = self.i32_at(pos)?;
+                let (code, length) = self.string(pos + 4)?;
+                let (scope, scope_size) = self.document(pos + 4 + length, false)?;
+                assert_err!(size as usize != 4 + length + scope_size, anyhow!("code with scope size mismatch at offset {}", pos));
+                let value = extended("$code", Dynamic::from(code));
+                value.set_key("$scope", scope)?;
+                (value, size as usize)
+            }
+            0x10=> (Dynamic::Int(self.i32_at(pos)? as i64), 4),
+            0x11=> {
+                let timestamp = Dynamic::map();
+                timestamp.set_key("t", Dynamic::Int(self.i32_at(pos + 4)? as u32 as i64))?;
+                timestamp.set_key("i", Dynamic::Int(self.i32_at(pos)? as u32 as i64))?;
+                (extended("$timestamp", timestamp), 8)
+            }
+            0x12=> (Dynamic::Int(self.i64_at(pos)?), 8),
+            0x13=> (extended("$numberDecimal", Dynamic::from(decimal_to_string(self.take(pos, 16)?.try_into()?))), 16),
+            0xff=> (extended("$minKey", Dynamic::Int(1)), 0),
+            0x7f=> (extended("$maxKey", Dynamic::Int(1)), 0),
+            _=> return Err(anyhow!("unknown bson type 0x{:02x} at offset {}", kind, pos))
+        })
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+const DECIMAL_BIAS: i32 = 6176;
+const DECIMAL_MAX: u128 = 9_999_999_999_999_999_999_999_999_999_999_999;       //34 位十进制
+
+//decimal128 按照 BSON 规范的字符串写法 指数大于 0 或者太小的时候用科学计数法
+fn decimal_to_string(bytes: [u8; 16]) -> String {
+    let value = u128::from_le_bytes(bytes);
+    let high = (value >> 64) as u64;
+    let sign = if high >> 63 == 1 { "-" } else { "" };
+    let combination = (high >> 58) & 0x1f;
+    let (exponent, coefficient) = match combination {
+        0x1e=> return format!("{}Infinity", sign),
+        0x1f=> return "NaN".to_string(),
+        _ if combination >> 3 == 3=> (((high >> 47) & 0x3fff) as i32, 0),   //这种形式的系数总是超出范围 当作 0
+        _=> (((high >> 49) & 0x3fff) as i32, value & ((1u128 << 113) - 1))
+    };
+    let coefficient = if coefficient > DECIMAL_MAX { 0 } else { coefficient };
+    let exponent = exponent - DECIMAL_BIAS;
+    let digits = coefficient.to_string();
+    let adjusted = exponent + digits.len() as i32 - 1;
+    if exponent <= 0 && adjusted >= -6 {
+        let point = digits.len() as i32 + exponent;
+        return match exponent {
+            0=> format!("{}{}", sign, digits),
+            _ if point > 0=> format!("{}{}.{}", sign, &digits[..point as usize], &digits[point as usize..]),
+            _=> format!("{}0.{}{}", sign, "0".repeat(-point as usize), digits)
+        };
+    }
+    let fraction = if digits.len() > 1 { format!(".{}", &digits[1..]) } else { String::new() };
+    format!("{}{}{}E{}{}", sign, &digits[..1], fraction, if adjusted >= 0 { "+" } else { "-" }, adjusted.abs())
+}
+
+//不做舍入 超过 34 位有效数字或者指数超出范围返回 None
+fn decimal_from_str(text: &str) -> Option<[u8; 16]> {
+    let (negative, rest) = match text.strip_prefix('-') {
+        Some(rest)=> (true, rest),
+        None=> (false, text.strip_prefix('+').unwrap_or(text))
+    };
+    let sign = (negative as u128) << 127;
+    if rest.eq_ignore_ascii_case("infinity") || rest.eq_ignore_ascii_case("inf") {
+        return Some((sign | 0x1e << 122).to_le_bytes());
+    }
+    if rest.eq_ignore_ascii_case("nan") {
+        return Some((0x1fu128 << 122).to_le_bytes());
+    }
+    let (mantissa, exponent) = match rest.find(['e', 'E']) {
+        Some(p)=> (&rest[..p], rest[p + 1..].parse::<i32>().ok()?),
+        None=> (rest, 0)
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if integer.is_empty() && fraction.is_empty() || !integer.bytes().chain(fraction.bytes()).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let digits = format!("{}{}", integer, fraction);
+    let digits = digits.trim_start_matches('0');
+    if digits.len() > 34 {
+        return None;
+    }
+    let coefficient = if digits.is_empty() { 0 } else { digits.parse::<u128>().ok()? };
+    let biased = exponent as i64 - fraction.len() as i64 + DECIMAL_BIAS as i64;          //i32 的指数减去小数位数可能溢出
+    if !(0..=0x2fff).contains(&biased) {
+        return None;
+    }
+    Some((sign | (biased as u128) << 113 | coefficient).to_le_bytes())
+}
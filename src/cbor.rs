@@ -0,0 +1,413 @@
+use anyhow::{anyhow, Result};
+use byteorder::{BigEndian, WriteBytesExt};
+use super::dynamic::Dynamic;
+use super::map::build_map;
+use super::msgpack::{read_16, read_32, read_64};
+use super::toml::DATETIME;
+use super::{assert_err, assert_ok};
+
+//和 msgpack 使用同样的 key 和重复 key 的处理方式
+pub use super::msgpack::{DecodeOptions, KeyPolicy};
+
+pub const TAG_DATETIME: u64 = 0;                    //RFC 3339 格式的时间字符串
+pub const TAG_EPOCH: u64 = 1;                       //从 1970 年开始的秒数
+pub const TAG_POSITIVE_BIGNUM: u64 = 2;
+pub const TAG_NEGATIVE_BIGNUM: u64 = 3;
+pub const TAG_ENCODED_CBOR: u64 = 24;               //内容是另一个 cbor 编码的 byte string
+pub const TAG_SELF_DESCRIBE: u64 = 55799;           //只是标记这是 cbor 解码时去掉
+
+//Dynamic 没有 tag 解码时 tag 0 的字符串和 toml 一样变成 {"$datetime": 字符串} 大整数转成数字
+//其他的 tag 变成 {"$tag": tag, "$value": 内容} 编码时这两种形式的 Map 写回 tag
+pub const TAG: &str = "$tag";
+pub const TAG_VALUE: &str = "$value";
+
+pub trait ToCbor {                                  //编码为 cbor (RFC 8949) 格式的 trait
+    fn to_cbor(&self, buf: &mut Vec<u8>);
+}
+
+pub trait FromCbor: Sized {                         //解码 cbor 格式的 trait
+    fn from_cbor(buf: &[u8]) -> Result<(Self, usize)>;
+}
+
+//major 是高 3 位 value 按照大小选择最短的长度
+pub fn write_head(buf: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    if value < 24 {
+        buf.push(major | value as u8);
+    } else if value < 0x100 {
+        buf.push(major | 24);
+        buf.push(value as u8);
+    } else if value < 0x10000 {
+        buf.push(major | 25);
+        buf.write_u16::<BigEndian>(value as u16).unwrap();
+    } else if value < 0x1_0000_0000 {
+        buf.push(major | 26);
+        buf.write_u32::<BigEndian>(value as u32).unwrap();
+    } else {
+        buf.push(major | 27);
+        buf.write_u64::<BigEndian>(value).unwrap();
+    }
+}
+
+//Dynamic 没有 tag 需要的时候先写 tag 再写内容 例如 write_tag(buf, TAG_DATETIME) 之后写一个字符串
+pub fn write_tag(buf: &mut Vec<u8>, tag: u64) {
+    write_head(buf, 6, tag);
+}
+
+fn write_int(buf: &mut Vec<u8>, value: i64) {
+    if value >= 0 {
+        write_head(buf, 0, value as u64);
+    } else {
+        write_head(buf, 1, (-1 - value) as u64);
+    }
+}
+
+pub(crate) fn f16_to_f64(half: u16) -> f64 {
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f64;
+    let value = match exponent {
+        0=> mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0=> f64::INFINITY,
+        31=> f64::NAN,
+        _=> (mantissa + 1024.0) * 2f64.powi(exponent - 25)
+    };
+    if half & 0x8000 != 0 { -value } else { value }
+}
+
+//能无损表示成半精度的时候返回它的位
+fn f64_to_f16(value: f64) -> Option<u16> {
+    if value.is_nan() {
+        return Some(0x7e00);
+    }
+    let sign = if value.is_sign_negative() { 0x8000u16 } else { 0 };
+    let abs = value.abs();
+    if abs == 0.0 || abs.is_infinite() {
+        return Some(sign | if abs == 0.0 { 0 } else { 0x7c00 });
+    }
+    if !(2f64.powi(-24)..=65504.0).contains(&abs) {
+        return None;
+    }
+    let exponent = ((abs.to_bits() >> 52) & 0x7ff) as i32 - 1023;
+    let half = if exponent < -14 {
+        sign | (abs * 2f64.powi(24)) as u16
+    } else {
+        sign | (((exponent + 15) as u16) << 10) | ((abs * 2f64.powi(10 - exponent)) as u16 - 1024)
+    };
+    if f16_to_f64(half) == value { Some(half) } else { None }
+}
+
+//确定性编码的浮点数用能无损表示的最短格式
+fn write_float(buf: &mut Vec<u8>, value: f64) {
+    if let Some(half) = f64_to_f16(value) {
+        buf.push(0xf9);
+        buf.write_u16::<BigEndian>(half).unwrap();
+    } else if (value as f32) as f64 == value {
+        buf.push(0xfa);
+        buf.write_u32::<BigEndian>((value as f32).to_bits()).unwrap();
+    } else {
+        buf.push(0xfb);
+        buf.write_u64::<BigEndian>(value.to_bits()).unwrap();
+    }
+}
+
+//{"$datetime": 字符串} 或者 {"$tag": 非负整数, "$value": 内容} 形式不对的当作普通的 Map
+fn tagged(value: &Dynamic) -> Option<(u64, Dynamic)> {
+    let Dynamic::Map(m) = value else { return None };
+    let map = m.read();
+    match map.len() {
+        1=> map.get(DATETIME).filter(|text| text.is_string()).map(|text| (TAG_DATETIME, text.clone())),
+        2=> {
+            let tag = match map.get(TAG)? {
+                Dynamic::Byte(v)=> *v as u64,
+                Dynamic::Int(v)=> u64::try_from(*v).ok()?,
+                Dynamic::UInt(v)=> *v,
+                _=> return None
+            };
+            Some((tag, map.get(TAG_VALUE)?.clone()))
+        }
+        _=> None
+    }
+}
+
+impl ToCbor for Dynamic {
+    fn to_cbor(&self, buf: &mut Vec<u8>) {
+        encode_dynamic(self, buf, &mut Vec::new());
+    }
+}
+
+//stack 记录当前路径上的容器 遇到循环引用编码为 null
+fn encode_dynamic(value: &Dynamic, buf: &mut Vec<u8>, stack: &mut Vec<usize>) {
+    if let Some(id) = value.container_id() {
+        if stack.contains(&id) {
+            buf.push(0xf6);
+            return;
+        }
+        stack.push(id);
+    }
+    match value {
+        Dynamic::Null=> buf.push(0xf6),
+        Dynamic::Bool(b)=> buf.push(if *b { 0xf5 } else { 0xf4 }),
+        Dynamic::Byte(v)=> write_head(buf, 0, *v as u64),
+        Dynamic::Int(v)=> write_int(buf, *v),
+        Dynamic::UInt(v)=> write_head(buf, 0, *v),
+        Dynamic::Float(v)=> {
+            buf.push(0xfa);
+            buf.write_u32::<BigEndian>(v.to_bits()).unwrap();
+        }
+        Dynamic::Double(v)=> {
+            buf.push(0xfb);
+            buf.write_u64::<BigEndian>(v.to_bits()).unwrap();
+        }
+        Dynamic::String(s)=> {
+            write_head(buf, 3, s.len() as u64);
+            buf.extend_from_slice(s.as_bytes());
+        }
+        Dynamic::Bytes(raw)=> {
+            write_head(buf, 2, raw.len() as u64);
+            buf.extend_from_slice(raw);
+        }
+        Dynamic::Vec(raw)=> {
+            let items = raw.read();
+            write_head(buf, 4, items.len() as u64);
+            items.iter().for_each(|item| encode_dynamic(item, buf, stack));
+        }
+        Dynamic::Map(raw)=> {
+            if let Some((tag, content)) = tagged(value) {
+                write_tag(buf, tag);
+                encode_dynamic(&content, buf, stack);
+            } else {
+                let items = raw.read();
+                write_head(buf, 5, items.len() as u64);
+                items.iter().for_each(|(k, v)| {
+                    write_head(buf, 3, k.len() as u64);
+                    buf.extend_from_slice(k.as_bytes());
+                    encode_dynamic(v, buf, stack);
+                });
+            }
+        }
+        Dynamic::AnyMap(raw)=> {
+            let items = raw.read();
+            write_head(buf, 5, items.len() as u64);
+            items.iter().for_each(|(k, v)| {
+                encode_dynamic(k, buf, stack);
+                encode_dynamic(v, buf, stack);
+            });
+        }
+    }
+    if value.container_id().is_some() {
+        stack.pop();
+    }
+}
+
+//RFC 8949 4.2 的确定性编码 整数和长度用最短的格式 浮点数用能无损表示的最短格式 都是定长的
+//map 的 key 按照编码后的字节排序 循环引用和重复的 key 返回错误
+pub fn encode_deterministic(value: &Dynamic, buf: &mut Vec<u8>) -> Result<()> {
+    encode_sorted(value, buf, &mut Vec::new())
+}
+
+fn encode_sorted(value: &Dynamic, buf: &mut Vec<u8>, stack: &mut Vec<usize>) -> Result<()> {
+    if let Some(id) = value.container_id() {
+        assert_err!(stack.contains(&id), anyhow!("cycle can not be deterministic cbor"));
+        stack.push(id);
+    }
+    match value {
+        Dynamic::Float(v)=> write_float(buf, *v as f64),
+        Dynamic::Double(v)=> write_float(buf, *v),
+        Dynamic::Vec(v)=> {
+            let items = v.read();
+            write_head(buf, 4, items.len() as u64);
+            for item in items.iter() {
+                encode_sorted(item, buf, stack)?;
+            }
+        }
+        Dynamic::Map(m)=> {
+            if let Some((tag, content)) = tagged(value) {
+                write_tag(buf, tag);
+                encode_sorted(&content, buf, stack)?;
+            } else {
+                let entries = m.read().iter().map(|(k, v)| (Dynamic::from(k.as_str()), v.clone())).collect();
+                encode_sorted_map(entries, buf, stack)?;
+            }
+        }
+        Dynamic::AnyMap(m)=> {
+            let entries = m.read().clone();
+            encode_sorted_map(entries, buf, stack)?;
+        }
+        _=> encode_dynamic(value, buf, stack),
+    }
+    if value.container_id().is_some() {
+        stack.pop();
+    }
+    Ok(())
+}
+
+fn encode_sorted_map(entries: Vec<(Dynamic, Dynamic)>, buf: &mut Vec<u8>, stack: &mut Vec<usize>) -> Result<()> {
+    let mut encoded = Vec::with_capacity(entries.len());
+    for (k, v) in entries.iter() {
+        let mut key = Vec::new();
+        encode_sorted(k, &mut key, stack)?;
+        let mut value = Vec::new();
+        encode_sorted(v, &mut value, stack)?;
+        encoded.push((key, value));
+    }
+    encoded.sort();
+    write_head(buf, 5, encoded.len() as u64);
+    for (index, (key, value)) in encoded.iter().enumerate() {
+        assert_err!(index > 0 && encoded[index - 1].0 == *key, anyhow!("duplicate key {:?}", Dynamic::from_cbor(key)?.0));
+        buf.extend_from_slice(key);
+        buf.extend_from_slice(value);
+    }
+    Ok(())
+}
+
+impl FromCbor for Dynamic {
+    fn from_cbor(buf: &[u8]) -> Result<(Self, usize)> {
+        decode_with(buf, &DecodeOptions::default())
+    }
+}
+
+pub fn decode_with(buf: &[u8], options: &DecodeOptions) -> Result<(Dynamic, usize)> {
+    decode_at(buf, 0, options)
+}
+
+//返回 major type 参数和头部的长度 参数是 None 表示不定长 (或者 major 7 的 break)
+fn read_head(buf: &[u8], base: usize) -> Result<(u8, Option<u64>, usize)> {
+    assert_err!(buf.is_empty(), anyhow!("no data"));
+    let major = buf[0] >> 5;
+    let info = buf[0] & 0x1f;
+    let size = match info {
+        0..=23=> return Ok((major, Some(info as u64), 1)),
+        24=> 2,
+        25=> 3,
+        26=> 5,
+        27=> 9,
+        31 if major >= 2 && major != 6=> return Ok((major, None, 1)),
+        _=> return Err(anyhow!("invalid additional info {} for major type {} at offset {}", info, major, base))
+    };
+    assert_err!(buf.len() < size, anyhow!("no data"));
+    let value = match size {
+        2=> buf[1] as u64,
+        3=> read_16(&buf[1..]) as u64,
+        5=> read_32(&buf[1..]) as u64,
+        _=> read_64(&buf[1..]),
+    };
+    Ok((major, Some(value), size))
+}
+
+//定长的 byte string 或者 text string 返回内容和总长度
+fn read_chunk(buf: &[u8], head: usize, length: u64) -> Result<&[u8]> {
+    assert_err!(((buf.len() - head) as u64) < length, anyhow!("no data"));
+    Ok(&buf[head..head + length as usize])
+}
+
+//不定长的字符串由同样 major type 的定长块组成 以 0xff 结束
+fn read_string(buf: &[u8], base: usize, major: u8, length: Option<u64>, head: usize) -> Result<(Vec<u8>, usize)> {
+    if let Some(length) = length {
+        return Ok((read_chunk(buf, head, length)?.to_vec(), head + length as usize));
+    }
+    let mut cursor = head;
+    let mut result = Vec::new();
+    loop {
+        assert_err!(cursor >= buf.len(), anyhow!("no data"));
+        if buf[cursor] == 0xff {
+            return Ok((result, cursor + 1));
+        }
+        let (chunk_major, chunk_length, chunk_head) = read_head(&buf[cursor..], base + cursor)?;
+        let Some(chunk_length) = chunk_length.filter(|_| chunk_major == major) else {
+            return Err(anyhow!("invalid chunk of indefinite string at offset {}", base + cursor));
+        };
+        result.extend_from_slice(read_chunk(&buf[cursor..], chunk_head, chunk_length)?);
+        cursor += chunk_head + chunk_length as usize;
+    }
+}
+
+//大整数的内容是大端的 byte string 超出 64 位的转成 Double 会丢失精度
+fn bignum(raw: &[u8], negative: bool) -> Dynamic {
+    let start = raw.iter().position(|b| *b != 0).unwrap_or(raw.len());
+    let raw = &raw[start..];
+    if raw.len() <= 8 {
+        let value = raw.iter().fold(0u64, |v, b| (v << 8) | *b as u64);
+        return match (negative, i64::try_from(value)) {
+            (false, Ok(v))=> Dynamic::Int(v),
+            (false, Err(_))=> Dynamic::UInt(value),
+            (true, Ok(v))=> Dynamic::Int(-1 - v),
+            (true, Err(_))=> Dynamic::Double(-1.0 - value as f64),
+        };
+    }
+    let value = raw.iter().fold(0f64, |v, b| v * 256.0 + *b as f64);
+    Dynamic::Double(if negative { -1.0 - value } else { value })
+}
+
+//base 是 buf 在整个输入里的偏移 用于错误信息
+fn decode_at(buf: &[u8], base: usize, options: &DecodeOptions) -> Result<(Dynamic, usize)> {
+    let (major, argument, head) = read_head(buf, base)?;
+    match (major, argument) {
+        (0, Some(value))=> Ok((i64::try_from(value).map(Dynamic::Int).unwrap_or(Dynamic::UInt(value)), head)),
+        (1, Some(value))=> Ok((i64::try_from(value).map(|v| Dynamic::Int(-1 - v)).unwrap_or(Dynamic::Double(-1.0 - value as f64)), head)),
+        (2, _)=> {
+            let (raw, size) = read_string(buf, base, 2, argument, head)?;
+            Ok((Dynamic::from_bytes(raw), size))
+        }
+        (3, _)=> {
+            let (raw, size) = read_string(buf, base, 3, argument, head)?;
+            let text = String::from_utf8(raw).map_err(|_| anyhow!("invalid utf8 string at offset {}", base))?;
+            Ok((Dynamic::from(text), size))
+        }
+        (4, _)=> {
+            let mut cursor = head;
+            let mut items = Vec::with_capacity(argument.unwrap_or(0).min(buf.len() as u64) as usize);      //长度来自输入 每个元素至少一个字节
+            while argument.is_some_and(|length| (items.len() as u64) < length) || (argument.is_none() && buf.get(cursor) != Some(&0xff)) {
+                let (item, size) = decode_at(&buf[cursor..], base + cursor, options)?;
+                items.push(item);
+                cursor += size;
+            }
+            if argument.is_none() {
+                cursor += 1;
+            }
+            Ok((Dynamic::from_vec(items), cursor))
+        }
+        (5, _)=> {
+            let mut cursor = head;
+            let mut kvs = Vec::with_capacity(argument.unwrap_or(0).min(buf.len() as u64) as usize);
+            while argument.is_some_and(|length| (kvs.len() as u64) < length) || (argument.is_none() && buf.get(cursor) != Some(&0xff)) {
+                let offset = base + cursor;
+                let (key, size) = decode_at(&buf[cursor..], offset, options)?;
+                cursor += size;
+                let (value, size) = decode_at(&buf[cursor..], base + cursor, options)?;
+                cursor += size;
+                kvs.push((key, value, offset));
+            }
+            if argument.is_none() {
+                cursor += 1;
+            }
+            Ok((build_map(kvs, options.keys, options.duplicates)?, cursor))
+        }
+        (6, Some(tag))=> {
+            let (value, size) = decode_at(&buf[head..], base + head, options)?;
+            let value = match (tag, &value) {
+                (TAG_POSITIVE_BIGNUM, Dynamic::Bytes(raw))=> bignum(raw, false),
+                (TAG_NEGATIVE_BIGNUM, Dynamic::Bytes(raw))=> bignum(raw, true),
+                (TAG_DATETIME, Dynamic::String(_))=> crate::dmap!(DATETIME => value),
+                (TAG_SELF_DESCRIBE, _)=> value,
+                _=> {
+                    let tag = i64::try_from(tag).map(Dynamic::Int).unwrap_or(Dynamic::UInt(tag));
+                    crate::dmap!(TAG => tag, TAG_VALUE => value)
+                }
+            };
+            Ok((value, head + size))
+        }
+        (7, Some(value))=> {
+            let info = buf[0] & 0x1f;
+            assert_ok!(info == 25, (Dynamic::Double(f16_to_f64(value as u16)), head));
+            assert_ok!(info == 26, (Dynamic::Double(f32::from_bits(value as u32) as f64), head));
+            assert_ok!(info == 27, (Dynamic::Double(f64::from_bits(value)), head));
+            match value {
+                20=> Ok((Dynamic::Bool(false), head)),
+                21=> Ok((Dynamic::Bool(true), head)),
+                _=> Ok((Dynamic::Null, head))           //null undefined 和其他简单值
+            }
+        }
+        (7, None)=> Err(anyhow!("unexpected break at offset {}", base)),
+        _=> Err(anyhow!("invalid major type {} at offset {}", major, base))
+    }
+}
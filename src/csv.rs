@@ -0,0 +1,301 @@
+use anyhow::{anyhow, Result};
+use smol_str::SmolStr;
+use super::dynamic::Dynamic;
+use super::json::write_compact;
+use super::assert_err;
+
+//CSV 和 TSV 的选项 header 为 false 时每行是一个 Vec
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    pub header: bool,
+    pub infer: bool,                                //没有引号的单元格推断 数字 bool 空的是 null
+    pub unflatten: bool,                            //读的时候 a.b 的列还原成嵌套的 map 数字的 key 还原成 Vec
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self { delimiter: b',', header: true, infer: true, unflatten: false }
+    }
+}
+
+impl CsvOptions {
+    pub fn tsv() -> Self {
+        Self { delimiter: b'\t', ..Self::default() }
+    }
+
+    //分隔符按字节处理 不能是多字节的 utf8 也不能是引号和换行
+    fn check(&self) -> Result<()> {
+        assert_err!(!self.delimiter.is_ascii() || matches!(self.delimiter, b'"' | b'\r' | b'\n'), anyhow!("invalid csv delimiter {:?}", self.delimiter as char));
+        Ok(())
+    }
+}
+
+pub fn parse(text: &str) -> Result<Dynamic> {
+    parse_with(text, &CsvOptions::default())
+}
+
+//第一行是列名 每行变成一个 Map 列比 header 少的补 null 多的返回错误
+pub fn parse_with(text: &str, options: &CsvOptions) -> Result<Dynamic> {
+    options.check()?;
+    let mut records = records(text, options.delimiter)?.into_iter();
+    let cell = |(text, quoted): (String, bool)| if options.infer && !quoted { infer(text) } else { Dynamic::from(text) };
+    if !options.header {
+        return Ok(Dynamic::from_vec(records.map(|(_, record)| Dynamic::from_vec(record.into_iter().map(cell).collect())).collect()));
+    }
+    let Some((_, header)) = records.next() else { return Ok(Dynamic::vec()) };
+    let names: Vec<SmolStr> = header.into_iter().map(|(name, _)| SmolStr::from(name)).collect();
+    for (index, name) in names.iter().enumerate() {
+        assert_err!(names[..index].contains(name), anyhow!("duplicate column {}", name));
+    }
+    let mut rows = Vec::new();
+    for (line, record) in records {
+        assert_err!(record.len() > names.len(), anyhow!("line {} has {} fields but the header has {}", line, record.len(), names.len()));
+        let mut cells = record.into_iter();
+        let row = Dynamic::map();
+        for name in &names {
+            let value = cells.next().map(cell).unwrap_or_default();
+            match options.unflatten {
+                true=> set_path(&row, name, value)?,
+                false=> {
+                    row.set_key(name, value)?;
+                }
+            }
+        }
+        if options.unflatten {
+            to_vecs(&row)?;
+        }
+        rows.push(row);
+    }
+    Ok(Dynamic::from_vec(rows))
+}
+
+type Record = (usize, Vec<(String, bool)>);
+
+//RFC 4180 的引号 "" 是一个引号 引号里可以有分隔符和换行 返回每条记录开始的行号和单元格 以及是否有引号
+fn records(text: &str, delimiter: u8) -> Result<Vec<Record>> {
+    let buf = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let bytes = buf.as_bytes();
+    let (mut pos, mut line) = (0, 1);
+    let mut records = Vec::new();
+    while pos < bytes.len() {
+        let start = line;
+        let mut record = Vec::new();
+        loop {
+            let mut field = String::new();
+            let quoted = bytes.get(pos) == Some(&b'"');
+            if quoted {
+                pos += 1;
+                loop {
+                    let Some(end) = bytes[pos..].iter().position(|c| *c == b'"').map(|p| pos + p) else {
+                        return Err(anyhow!("unterminated quoted field at line {}", line));
+                    };
+                    field.push_str(&buf[pos..end]);
+                    line += bytes[pos..end].iter().filter(|c| **c == b'\n').count();
+                    pos = end + 1;
+                    if bytes.get(pos) != Some(&b'"') {
+                        break;
+                    }
+                    field.push('"');
+                    pos += 1;
+                }
+                assert_err!(!matches!(bytes.get(pos), None | Some(b'\r' | b'\n')) && bytes[pos] != delimiter, anyhow!("unexpected character after quoted field at line {}", line));
+            } else {
+                let end = bytes[pos..].iter().position(|c| *c == delimiter || *c == b'\r' || *c == b'\n').map_or(bytes.len(), |p| pos + p);
+                field.push_str(&buf[pos..end]);
+                pos = end;
+            }
+            record.push((field, quoted));
+            if bytes.get(pos) != Some(&delimiter) {
+                break;
+            }
+            pos += 1;
+        }
+        if bytes.get(pos) == Some(&b'\r') {
+            pos += 1;
+        }
+        if bytes.get(pos) == Some(&b'\n') {
+            pos += 1;
+            line += 1;
+        }
+        //空行跳过 只有一列的时候空行是一个空的单元格
+        let empty = record.len() == 1 && !record[0].1 && record[0].0.is_empty();
+        if !empty || records.first().is_some_and(|(_, first): &Record| first.len() == 1) {
+            records.push((start, record));
+        }
+    }
+    Ok(records)
+}
+
+//推断单元格的类型 有多余前导 0 的数字例如编号保留成字符串
+fn infer(text: String) -> Dynamic {
+    match text.as_str() {
+        ""=> return Dynamic::Null,
+        "NaN"=> return Dynamic::Double(f64::NAN),
+        "inf"=> return Dynamic::Double(f64::INFINITY),
+        "-inf"=> return Dynamic::Double(f64::NEG_INFINITY),
+        _=> {}
+    }
+    if text.eq_ignore_ascii_case("true") || text.eq_ignore_ascii_case("false") {
+        return Dynamic::Bool(text.eq_ignore_ascii_case("true"));
+    }
+    let digits = text.strip_prefix('-').unwrap_or(&text);
+    let leading_zero = digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.");
+    if !digits.is_empty() && !leading_zero && digits.bytes().next().is_some_and(|c| c.is_ascii_digit() || c == b'.') && digits.bytes().all(|c| c.is_ascii_digit() || matches!(c, b'.' | b'e' | b'E' | b'+' | b'-')) {
+        if let Ok(v) = text.parse::<i64>() {
+            return Dynamic::Int(v);
+        }
+        if let Ok(v) = text.parse::<f64>() {
+            return Dynamic::Double(v);
+        }
+    }
+    Dynamic::from(text)
+}
+
+fn set_path(row: &Dynamic, name: &str, value: Dynamic) -> Result<()> {
+    let mut current = row.clone();
+    let mut parts = name.split('.').peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            current.set_key(part, value)?;
+            break;
+        }
+        current = match current.contains(part)? {
+            true=> current.get_key(part)?,
+            false=> {
+                let next = Dynamic::map();
+                current.set_key(part, next.clone())?;
+                next
+            }
+        };
+        assert_err!(!current.is_map(), anyhow!("column {} conflicts with a value column", name));
+    }
+    Ok(())
+}
+
+//子节点里 key 正好是 0..n 的 map 变成 Vec
+fn to_vecs(map: &Dynamic) -> Result<()> {
+    let keys: Vec<SmolStr> = match map {
+        Dynamic::Map(m)=> m.read().keys().cloned().collect(),
+        _=> return Ok(())
+    };
+    for key in keys {
+        let item = map.get_key(&key)?;
+        to_vecs(&item)?;
+        let length = if item.is_map() { item.len()? } else { 0 };
+        if length > 0 && (0..length).all(|i| item.contains(&i.to_string()).unwrap_or_default()) {
+            map.set_key(&key, Dynamic::from_vec((0..length).map(|i| item.get_key(&i.to_string())).collect::<Result<_>>()?))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn write_csv(value: &Dynamic, buf: &mut String) -> Result<()> {
+    write_with(value, buf, &CsvOptions::default())
+}
+
+//Map 的行展开成 a.b a.0 这样的列 列的顺序是第一次出现的顺序 Vec 的行直接写 null 写成空的单元格
+pub fn write_with(value: &Dynamic, buf: &mut String, options: &CsvOptions) -> Result<()> {
+    options.check()?;
+    let rows = match value {
+        Dynamic::Vec(v)=> v.read().clone(),
+        _=> return Err(anyhow!("csv needs a Vec of rows"))
+    };
+    if rows.iter().all(|row| matches!(row, Dynamic::Vec(_))) {
+        for row in rows {
+            let cells = row.into_vec()?;
+            write_record(cells.iter(), buf, options);
+        }
+        return Ok(());
+    }
+    let mut columns: Vec<SmolStr> = Vec::new();
+    let mut flattened = Vec::new();
+    for (index, row) in rows.iter().enumerate() {
+        assert_err!(!matches!(row, Dynamic::Map(_) | Dynamic::AnyMap(_)), anyhow!("row {} is not a map", index));
+        let mut cells = Vec::new();
+        flatten(row, &mut String::new(), &mut cells, &mut Vec::new())?;
+        for (name, _) in &cells {
+            if !columns.contains(name) {
+                columns.push(name.clone());
+            }
+        }
+        flattened.push(cells);
+    }
+    if options.header {
+        write_record(columns.iter().map(|name| Dynamic::from(name.as_str())).collect::<Vec<_>>().iter(), buf, options);
+    }
+    for cells in flattened {
+        let row: Vec<Dynamic> = columns.iter().map(|name| cells.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone()).unwrap_or_default()).collect();
+        write_record(row.iter(), buf, options);
+    }
+    Ok(())
+}
+
+//非空的 map 和 Vec 展开 空的容器写成 json
+fn flatten(value: &Dynamic, prefix: &mut String, cells: &mut Vec<(SmolStr, Dynamic)>, stack: &mut Vec<usize>) -> Result<()> {
+    let children: Vec<(SmolStr, Dynamic)> = match value {
+        Dynamic::Map(m)=> m.read().iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+        Dynamic::AnyMap(m)=> m.read().iter().map(|(k, v)| Ok((k.to_key_string()?, v.clone()))).collect::<Result<_>>()?,
+        Dynamic::Vec(v)=> v.read().iter().enumerate().map(|(i, v)| (SmolStr::new(i.to_string()), v.clone())).collect(),
+        _=> Vec::new()
+    };
+    if children.is_empty() && !prefix.is_empty() {
+        cells.push((SmolStr::from(prefix.as_str()), value.clone()));
+        return Ok(());
+    }
+    let id = value.container_id().unwrap_or_default();
+    assert_err!(stack.contains(&id), anyhow!("cycle can not be written as csv"));
+    stack.push(id);
+    for (key, child) in children {
+        let length = prefix.len();
+        if length > 0 {
+            prefix.push('.');
+        }
+        prefix.push_str(&key);
+        flatten(&child, prefix, cells, stack)?;
+        prefix.truncate(length);
+    }
+    stack.pop();
+    Ok(())
+}
+
+fn write_record<'a>(cells: impl Iterator<Item = &'a Dynamic>, buf: &mut String, options: &CsvOptions) {
+    for (index, cell) in cells.enumerate() {
+        if index > 0 {
+            buf.push(options.delimiter as char);
+        }
+        let text = match cell {
+            Dynamic::Null=> continue,
+            Dynamic::Bool(b)=> b.to_string(),
+            Dynamic::Byte(v)=> v.to_string(),
+            Dynamic::Int(v)=> v.to_string(),
+            Dynamic::UInt(v)=> v.to_string(),
+            Dynamic::Float(v)=> format!("{:?}", v),
+            Dynamic::Double(v)=> format!("{:?}", v),
+            Dynamic::String(s)=> {
+                write_field(s, true, buf, options);
+                continue;
+            }
+            Dynamic::Bytes(_)=> cell.to_key_string().unwrap_or_default().to_string(),
+            _=> {
+                let mut json = String::new();
+                write_compact(cell, &mut json);
+                json
+            }
+        };
+        write_field(&text, false, buf, options);
+    }
+    buf.push('\n');
+}
+
+//字符串读回来会被推断成其他类型的时候也加上引号
+fn write_field(text: &str, string: bool, buf: &mut String, options: &CsvOptions) {
+    let special = text.bytes().any(|c| c == options.delimiter || matches!(c, b'"' | b'\r' | b'\n')) || text.starts_with('\u{feff}');
+    if special || (string && options.infer && !infer(text.to_string()).is_string()) {
+        buf.push('"');
+        buf.push_str(&text.replace('"', "\"\""));
+        buf.push('"');
+    } else {
+        buf.push_str(text);
+    }
+}
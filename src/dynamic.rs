@@ -1,11 +1,23 @@
-use std::{ops::Deref, str::Utf8Error, sync::{Arc, RwLock}};
+use std::{cell::RefCell, cmp::Ordering, fmt, ops::{Bound, Deref, Range, RangeBounds}, str::Utf8Error, sync::Arc};
+use parking_lot::RwLock;
 use smol_str::SmolStr;
+#[cfg(not(feature = "preserve_order"))]
 use std::collections::BTreeMap;
 use anyhow::{Result, anyhow};
+use super::assert_err;
+
+//默认按照 key 排序 打开 preserve_order 特性后保持插入顺序 (解析 json msgpack 时和原文顺序一致)
+//两种 map 的 new insert get iter 等方法一样 外部代码用 DynamicMap 这个名字而不是 BTreeMap 才能在两种特性下都编译
+#[cfg(not(feature = "preserve_order"))]
+pub type DynamicMap = BTreeMap<SmolStr, Dynamic>;
+#[cfg(feature = "preserve_order")]
+pub type DynamicMap = indexmap::IndexMap<SmolStr, Dynamic>;
 
 //自己定义一个 Dynamic 类型 支持 不同脚本语言的类型的自由转化
-#[derive(Debug, Clone)]
+//容器使用 parking_lot 的 RwLock 不会因为某个线程 panic 而中毒 Send/Sync 由编译器推导
+#[derive(Clone, Default)]
 pub enum Dynamic {
+    #[default]
     Null,
     Bool(bool),
     Byte(u8),
@@ -15,25 +27,18 @@ pub enum Dynamic {
     Double(f64),
     String(Arc<SmolStr>),                           //----上面这些值可以直接修改
     Vec(Arc<RwLock<Vec<Dynamic>>>),
-    Map(Arc<RwLock<BTreeMap<SmolStr, Dynamic>>>),
+    Map(Arc<RwLock<DynamicMap>>),
     Bytes(Arc<Vec<u8>>),
-}
-
-unsafe impl Send for Dynamic {}
-unsafe impl Sync for Dynamic {}
-
-impl Default for Dynamic {
-    fn default() -> Self {
-        Self::Null
-    } 
+    AnyMap(Arc<RwLock<Vec<(Dynamic, Dynamic)>>>),   //key 不是字符串的 map (比如其他语言生成的 msgpack) 保持原来的顺序和 key 类型
 }
 
 impl Dynamic {
     pub fn map()-> Self {
-        Self::Map(Arc::new(RwLock::new(BTreeMap::new())))
+        Self::Map(Arc::new(RwLock::new(DynamicMap::new())))
     }
 
-    pub fn from_map(map: BTreeMap<SmolStr, Dynamic>)-> Self {
+    //参数类型随 preserve_order 特性变化 传 DynamicMap 或者 collect 出来的值 不要写死 BTreeMap
+    pub fn from_map(map: DynamicMap)-> Self {
         Self::Map(Arc::new(RwLock::new(map)))
     }
 
@@ -49,18 +54,16 @@ impl Dynamic {
         Self::Bytes(Arc::new(vec))
     }
 
+    pub fn from_pairs(pairs: Vec<(Dynamic, Dynamic)>)-> Self {
+        Self::AnyMap(Arc::new(RwLock::new(pairs)))
+    }
+
     pub fn is_null(&self)-> bool {
-        match self {
-            Self::Null=> true,
-            _=> false
-        }
+        matches!(self, Self::Null)
     }
     
     pub fn is_string(&self)-> bool {
-        match self {
-            Self::String(_)=> true,
-            _=> false
-        }
+        matches!(self, Self::String(_))
     }
     
     pub fn as_str(&self)-> Result<&str> {
@@ -87,30 +90,60 @@ impl Dynamic {
     
     pub fn into_vec(self)-> Result<Vec<Dynamic>> {
         match self {
-            Self::Vec(v)=> Ok(v.read().unwrap().clone()),
+            Self::Vec(v)=> Ok(v.read().clone()),
             _=> Err(anyhow!("not a Vec"))
         }
     }
 
     pub fn is_bool(&self)-> bool {
-        match self {
-            Self::Bool(_)=> true,
-            _=> false
-        }
+        matches!(self, Self::Bool(_))
     }
     pub fn is_vec(&self)-> bool {
+        matches!(self, Self::Vec(_))
+    }
+    pub fn is_map(&self)-> bool {
+        matches!(self, Self::Map(_))
+    }
+    pub fn is_any_map(&self)-> bool {
+        matches!(self, Self::AnyMap(_))
+    }
+
+    //AnyMap 的 key value 复制出来
+    pub fn pairs(&self)-> Result<Vec<(Dynamic, Dynamic)>> {
         match self {
-            Self::Vec(_)=> true,
-            _=> false
+            Self::AnyMap(m)=> Ok(m.read().clone()),
+            _=> Err(anyhow!("is not a AnyMap"))
         }
     }
-    pub fn is_map(&self)-> bool {
+
+    //标量作为 map 的 key 时转成字符串 Bytes 是 utf8 就直接用 否则转成 16 进制
+    pub fn to_key_string(&self)-> Result<SmolStr> {
         match self {
-            Self::Map(_)=> true,
-            _=> false
+            Self::String(s)=> Ok(s.deref().clone()),
+            Self::Null=> Ok(SmolStr::new_static("null")),
+            Self::Bool(b)=> Ok(SmolStr::new(b.to_string())),
+            Self::Byte(b)=> Ok(SmolStr::new(b.to_string())),
+            Self::Int(i)=> Ok(SmolStr::new(i.to_string())),
+            Self::UInt(u)=> Ok(SmolStr::new(u.to_string())),
+            Self::Float(f)=> Ok(SmolStr::new(f.to_string())),
+            Self::Double(f)=> Ok(SmolStr::new(f.to_string())),
+            Self::Bytes(b)=> Ok(match std::str::from_utf8(b) {
+                Ok(s)=> SmolStr::new(s),
+                Err(_)=> SmolStr::new(b.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+            }),
+            _=> Err(anyhow!("container can not be a key"))
         }
     }
 
+    //AnyMap 的 key 当作字符串使用的时候 标量和 to_key_string 一样 容器 key 用 json 文本
+    pub fn key_text(&self)-> SmolStr {
+        self.to_key_string().unwrap_or_else(|_| {
+            let mut buf = String::new();
+            super::json::ToJson::to_json(self, &mut buf);
+            SmolStr::from(buf)
+        })
+    }
+
     pub fn as_bool(&self)-> Result<bool> {
         match self {
             Self::Bool(b)=> Ok(*b),
@@ -121,19 +154,26 @@ impl Dynamic {
     pub fn len(&self)-> Result<usize> {
         match self {
             Self::Vec(v)=> {
-                Ok(v.read().unwrap().len())
+                Ok(v.read().len())
             },
             Self::Map(m)=> {
-                Ok(m.read().unwrap().len())
+                Ok(m.read().len())
+            },
+            Self::AnyMap(m)=> {
+                Ok(m.read().len())
             },
             _=> Err(anyhow!("is not a Vec"))
         }
     }
+
+    pub fn is_empty(&self)-> Result<bool> {
+        self.len().map(|len| len == 0)
+    }
     
     pub fn get(&self, index: usize)-> Result<Dynamic> {
         match self {
             Self::Vec(v)=> {
-                v.read().unwrap().get(index).map(|item| item.clone() ).ok_or(anyhow!("index {} is outbound", index))
+                v.read().get(index).cloned().ok_or(anyhow!("index {} is outbound", index))
             },
             _=> Err(anyhow!("is not a Vec"))
         }
@@ -142,7 +182,7 @@ impl Dynamic {
     pub fn push<T: Into<Dynamic>>(&self, val: T)-> Result<()> {
         match self {
             Self::Vec(v)=> {
-                v.write().unwrap().push(val.into());
+                v.write().push(val.into());
                 Ok(())
             },
             _=> Err(anyhow!("is not a Vec"))
@@ -152,25 +192,247 @@ impl Dynamic {
     pub fn pop(&self)-> Result<Dynamic> {
         match self {
             Self::Vec(v)=> {
-                v.write().unwrap().pop().ok_or(anyhow!("no more items"))
+                v.write().pop().ok_or(anyhow!("no more items"))
             },
             _=> Err(anyhow!("is not a Vec"))
         }
     }
 
+    fn with_vec<R>(&self, f: impl FnOnce(&Vec<Dynamic>)-> Result<R>)-> Result<R> {
+        match self {
+            Self::Vec(v)=> f(&v.read()),
+            _=> Err(anyhow!("is not a Vec"))
+        }
+    }
+
+    fn with_vec_mut<R>(&self, f: impl FnOnce(&mut Vec<Dynamic>)-> Result<R>)-> Result<R> {
+        match self {
+            Self::Vec(v)=> f(&mut v.write()),
+            _=> Err(anyhow!("is not a Vec"))
+        }
+    }
+
+    pub fn insert<T: Into<Dynamic>>(&self, index: usize, val: T)-> Result<()> {
+        self.with_vec_mut(|v| {
+            assert_err!(index > v.len(), anyhow!("index {} is outbound {}", index, v.len()));
+            v.insert(index, val.into());
+            Ok(())
+        })
+    }
+
+    pub fn remove(&self, index: usize)-> Result<Dynamic> {
+        self.with_vec_mut(|v| {
+            assert_err!(index >= v.len(), anyhow!("index {} is outbound {}", index, v.len()));
+            Ok(v.remove(index))
+        })
+    }
+
+    //替换下标处的值 返回原来的值
+    pub fn set<T: Into<Dynamic>>(&self, index: usize, val: T)-> Result<Dynamic> {
+        self.with_vec_mut(|v| {
+            let len = v.len();
+            let item = v.get_mut(index).ok_or(anyhow!("index {} is outbound {}", index, len))?;
+            Ok(std::mem::replace(item, val.into()))
+        })
+    }
+
+    pub fn truncate(&self, len: usize)-> Result<()> {
+        self.with_vec_mut(|v| {
+            v.truncate(len);
+            Ok(())
+        })
+    }
+
+    //先收集再加写锁 iter 来自同一个 Vec 也不会死锁
+    pub fn extend<T: Into<Dynamic>, I: IntoIterator<Item = T>>(&self, iter: I)-> Result<()> {
+        let items: Vec<Dynamic> = iter.into_iter().map(Into::into).collect();
+        self.with_vec_mut(|v| {
+            v.extend(items);
+            Ok(())
+        })
+    }
+
+    //复制出一个新的 Vec
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R)-> Result<Dynamic> {
+        self.with_vec(|v| {
+            let range = to_range(&range, v.len())?;
+            Ok(Dynamic::from_vec(v[range].to_vec()))
+        })
+    }
+
+    pub fn sort(&self)-> Result<()> {
+        self.sort_by(Dynamic::total_cmp)
+    }
+
+    pub fn sort_by<F: FnMut(&Dynamic, &Dynamic)-> Ordering>(&self, f: F)-> Result<()> {
+        self.with_vec_mut(|v| {
+            v.sort_by(f);
+            Ok(())
+        })
+    }
+
+    pub fn reverse(&self)-> Result<()> {
+        self.with_vec_mut(|v| {
+            v.reverse();
+            Ok(())
+        })
+    }
+
+    //去掉相邻的重复元素 相等按照 total_cmp 判断
+    pub fn dedup(&self)-> Result<()> {
+        self.with_vec_mut(|v| {
+            v.dedup_by(|a, b| a.total_cmp(b) == Ordering::Equal);
+            Ok(())
+        })
+    }
+
+    //Vec 查找元素 Map 查找 value
+    pub fn contains_value(&self, val: &Dynamic)-> Result<bool> {
+        match self {
+            Self::Map(m)=> Ok(m.read().values().any(|item| item.total_cmp(val) == Ordering::Equal)),
+            _=> self.index_of(val).map(|index| index.is_some())
+        }
+    }
+
+    pub fn index_of(&self, val: &Dynamic)-> Result<Option<usize>> {
+        self.with_vec(|v| Ok(v.iter().position(|item| item.total_cmp(val) == Ordering::Equal)))
+    }
+
+    //用 replace_with 替换 range 范围的元素 返回被替换掉的元素
+    pub fn splice<R: RangeBounds<usize>, T: Into<Dynamic>, I: IntoIterator<Item = T>>(&self, range: R, replace_with: I)-> Result<Dynamic> {
+        let items: Vec<Dynamic> = replace_with.into_iter().map(Into::into).collect();
+        self.with_vec_mut(|v| {
+            let range = to_range(&range, v.len())?;
+            Ok(Dynamic::from_vec(v.splice(range, items).collect()))
+        })
+    }
+
+    //类型的顺序 Null < Bool < 数字 < String < Bytes < Vec < Map < AnyMap
+    fn type_rank(&self)-> u8 {
+        match self {
+            Self::Null=> 0,
+            Self::Bool(_)=> 1,
+            Self::Byte(_) | Self::Int(_) | Self::UInt(_) | Self::Float(_) | Self::Double(_)=> 2,
+            Self::String(_)=> 3,
+            Self::Bytes(_)=> 4,
+            Self::Vec(_)=> 5,
+            Self::Map(_)=> 6,
+            Self::AnyMap(_)=> 7,
+        }
+    }
+
+    //数字之间按照数值比较 数值相等时 Byte < Int < UInt < Float < Double 保证是全序
+    fn number_rank(&self)-> u8 {
+        match self {
+            Self::Byte(_)=> 0,
+            Self::Int(_)=> 1,
+            Self::UInt(_)=> 2,
+            Self::Float(_)=> 3,
+            _=> 4,
+        }
+    }
+
+    fn as_i128(&self)-> Option<i128> {
+        match self {
+            Self::Byte(b)=> Some(*b as i128),
+            Self::Int(i)=> Some(*i as i128),
+            Self::UInt(u)=> Some(*u as i128),
+            _=> None
+        }
+    }
+
+    fn as_f64_lossy(&self)-> f64 {
+        match self {
+            Self::Byte(b)=> *b as f64,
+            Self::Int(i)=> *i as f64,
+            Self::UInt(u)=> *u as f64,
+            Self::Float(f)=> *f as f64,
+            Self::Double(f)=> *f,
+            _=> 0.0
+        }
+    }
+
+    //所有 Dynamic 之间的全序 浮点数使用 f64::total_cmp Vec 和 Map 逐个元素比较
+    pub fn total_cmp(&self, other: &Dynamic)-> Ordering {
+        self.cmp_with(other, &mut Vec::new())
+    }
+
+    //stack 记录正在比较的容器对 同一对再次出现说明两边都有循环 当作相等
+    fn cmp_with(&self, other: &Dynamic, stack: &mut Vec<(usize, usize)>)-> Ordering {
+        let rank = self.type_rank().cmp(&other.type_rank());
+        if rank != Ordering::Equal {
+            return rank;
+        }
+        let pair = (self.container_id().unwrap_or_default(), other.container_id().unwrap_or_default());
+        if pair.0 != 0 {
+            if pair.0 == pair.1 || stack.contains(&pair) {
+                return Ordering::Equal;
+            }
+            stack.push(pair);
+        }
+        let order = match (self, other) {
+            (Self::Bool(a), Self::Bool(b))=> a.cmp(b),
+            (Self::String(a), Self::String(b))=> a.cmp(b),
+            (Self::Bytes(a), Self::Bytes(b))=> a.cmp(b),
+            (Self::Vec(a), Self::Vec(b))=> {
+                let (a, b) = (a.read_recursive(), b.read_recursive());
+                a.iter().zip(b.iter()).map(|(x, y)| x.cmp_with(y, stack)).find(|o| *o != Ordering::Equal).unwrap_or(a.len().cmp(&b.len()))
+            }
+            (Self::Map(a), Self::Map(b))=> {
+                let (a, b) = (a.read_recursive(), b.read_recursive());
+                let mut a: Vec<_> = a.iter().collect();                //保持插入顺序时 key 的顺序不参与比较
+                let mut b: Vec<_> = b.iter().collect();
+                a.sort_by(|x, y| x.0.cmp(y.0));
+                b.sort_by(|x, y| x.0.cmp(y.0));
+                a.iter().zip(b.iter()).map(|((ka, va), (kb, vb))| ka.cmp(kb).then_with(|| va.cmp_with(vb, stack))).find(|o| *o != Ordering::Equal).unwrap_or(a.len().cmp(&b.len()))
+            }
+            (Self::AnyMap(a), Self::AnyMap(b))=> {
+                let (a, b) = (a.read_recursive(), b.read_recursive());
+                a.iter().zip(b.iter()).map(|((ka, va), (kb, vb))| ka.cmp_with(kb, stack).then_with(|| va.cmp_with(vb, stack))).find(|o| *o != Ordering::Equal).unwrap_or(a.len().cmp(&b.len()))
+            }
+            _ if self.type_rank() == 2=> {
+                let value = match (self.as_i128(), other.as_i128()) {
+                    (Some(a), Some(b))=> a.cmp(&b),
+                    _=> self.as_f64_lossy().total_cmp(&other.as_f64_lossy())
+                };
+                value.then_with(|| self.number_rank().cmp(&other.number_rank()))
+            }
+            _=> Ordering::Equal
+        };
+        if pair.0 != 0 {
+            stack.pop();
+        }
+        order
+    }
+
+    //AnyMap 按照 key_text 查找 {1: "a"} 可以用 get_key("1") 取到 有多个 key 对应同一个字符串时用第一个
     pub fn get_key(&self, key: &str)-> Result<Dynamic> {
         match self {
             Self::Map(m)=> {
-                m.read().unwrap().get(key).map(|item| item.clone() ).ok_or(anyhow!("key {} is not existed", key))
+                m.read().get(key).cloned().ok_or(anyhow!("key {} is not existed", key))
+            },
+            Self::AnyMap(m)=> {
+                m.read().iter().find(|(k, _)| key_matches(k, key)).map(|(_, v)| v.clone()).ok_or(anyhow!("key {} is not existed", key))
             },
             _=> Err(anyhow!("is not a Map"))
         }      
     }
     
+    //AnyMap 里已经有的 key 保持原来的类型 只替换 value 没有的时候在最后加一个字符串 key
     pub fn set_key<T: Into<Dynamic>>(&self, key: &str, val: T)-> Result<Option<Dynamic>> {
         match self {
             Self::Map(m)=> {
-                Ok(m.write().unwrap().insert(SmolStr::new(key), val.into()))
+                Ok(m.write().insert(SmolStr::new(key), val.into()))
+            },
+            Self::AnyMap(m)=> {
+                let mut pairs = m.write();
+                match pairs.iter_mut().find(|(k, _)| key_matches(k, key)) {
+                    Some((_, v))=> Ok(Some(std::mem::replace(v, val.into()))),
+                    None=> {
+                        pairs.push((Dynamic::from(key), val.into()));
+                        Ok(None)
+                    }
+                }
             },
             _=> Err(anyhow!("is not a Map"))
         }      
@@ -179,7 +441,11 @@ impl Dynamic {
     pub fn remove_key(&self, key: &str)-> Result<Option<Dynamic>> {
         match self {
             Self::Map(m)=> {
-                Ok(m.write().unwrap().remove(key))
+                Ok(map_remove(&mut m.write(), key))
+            },
+            Self::AnyMap(m)=> {
+                let mut pairs = m.write();
+                Ok(pairs.iter().position(|(k, _)| key_matches(k, key)).map(|index| pairs.remove(index).1))
             },
             _=> Err(anyhow!("is not a Map"))
         }      
@@ -188,7 +454,10 @@ impl Dynamic {
     pub fn contains(&self, key: &str)-> Result<bool> {
         match self {
             Self::Map(m)=> {
-                Ok(m.read().unwrap().contains_key(key))
+                Ok(m.read().contains_key(key))
+            },
+            Self::AnyMap(m)=> {
+                Ok(m.read().iter().any(|(k, _)| key_matches(k, key)))
             },
             _=> Err(anyhow!("is not a Map"))
         }      
@@ -199,9 +468,8 @@ impl Dynamic {
             Self::Map(m)=> {
                 match other {
                     Self::Map(other)=> {
-                        for kv in other.read().unwrap().iter() {
-                            m.write().unwrap().insert(kv.0.clone(), kv.1.clone());
-                        }
+                        let items = other.read().clone();               //先复制出来 避免 self 和 other 是同一个容器时死锁
+                        m.write().extend(items);
                         Ok(())
                     },
                     _=> Err(anyhow!("other is not a Map"))
@@ -210,9 +478,8 @@ impl Dynamic {
             Self::Vec(v)=> {
                 match other {
                     Self::Vec(other)=> {
-                        for item in other.read().unwrap().iter() {
-                            v.write().unwrap().push(item.clone());
-                        }
+                        let items = other.read().clone();
+                        v.write().extend(items);
                         Ok(())
                     }
                     _=> Err(anyhow!("other is not a Vec"))
@@ -221,6 +488,164 @@ impl Dynamic {
             _=> Err(anyhow!("is not a Map"))
         }      
     }  
+
+    //容器的 Arc 地址 用来识别同一个容器 检测循环引用
+    pub(crate) fn container_id(&self)-> Option<usize> {
+        match self {
+            Self::Vec(v)=> Some(Arc::as_ptr(v) as *const () as usize),
+            Self::Map(m)=> Some(Arc::as_ptr(m) as *const () as usize),
+            Self::AnyMap(m)=> Some(Arc::as_ptr(m) as *const () as usize),
+            _=> None
+        }
+    }
+
+    pub fn has_cycle(&self)-> bool {
+        fn visit(value: &Dynamic, stack: &mut Vec<usize>)-> bool {
+            let Some(id) = value.container_id() else { return false };
+            if stack.contains(&id) {
+                return true;
+            }
+            stack.push(id);
+            let found = match value {
+                Dynamic::Vec(v)=> v.read().iter().any(|item| visit(item, stack)),
+                Dynamic::Map(m)=> m.read().values().any(|item| visit(item, stack)),
+                Dynamic::AnyMap(m)=> m.read().iter().any(|(k, v)| visit(k, stack) || visit(v, stack)),
+                _=> false
+            };
+            stack.pop();
+            found
+        }
+        visit(self, &mut Vec::new())
+    }
+
+    //把指向祖先容器的引用替换为 Null 打断循环 返回打断的数量
+    pub fn break_cycles(&self)-> usize {
+        fn visit(value: &Dynamic, stack: &mut Vec<usize>)-> usize {
+            let Some(id) = value.container_id() else { return 0 };
+            stack.push(id);
+            let mut count = 0;
+            let mut check = |item: &mut Dynamic, stack: &mut Vec<usize>| {
+                if item.container_id().is_some_and(|child| stack.contains(&child)) {
+                    *item = Dynamic::Null;
+                    count += 1;
+                } else {
+                    count += visit(item, stack);
+                }
+            };
+            match value {
+                Dynamic::Vec(v)=> v.write().iter_mut().for_each(|item| check(item, stack)),
+                Dynamic::Map(m)=> m.write().values_mut().for_each(|item| check(item, stack)),
+                Dynamic::AnyMap(m)=> m.write().iter_mut().for_each(|(k, v)| {
+                    check(k, stack);
+                    check(v, stack);
+                }),
+                _=> {}
+            }
+            stack.pop();
+            count
+        }
+        visit(self, &mut Vec::new())
+    }
+}
+
+//IndexMap 的 remove 会打乱顺序 要用 shift_remove
+#[cfg(not(feature = "preserve_order"))]
+pub(crate) fn map_remove(map: &mut DynamicMap, key: &str)-> Option<Dynamic> {
+    map.remove(key)
+}
+
+//字符串 key 直接比较 不用每次生成 key_text
+pub(crate) fn key_matches(k: &Dynamic, key: &str)-> bool {
+    match k {
+        Dynamic::String(s)=> s.as_str() == key,
+        _=> k.key_text() == key
+    }
+}
+
+#[cfg(feature = "preserve_order")]
+pub(crate) fn map_remove(map: &mut DynamicMap, key: &str)-> Option<Dynamic> {
+    map.shift_remove(key)
+}
+
+fn to_range<R: RangeBounds<usize>>(range: &R, len: usize)-> Result<Range<usize>> {
+    let start = match range.start_bound() {
+        Bound::Included(s)=> *s,
+        Bound::Excluded(s)=> s.checked_add(1).ok_or_else(|| anyhow!("range start is outbound {}", len))?,
+        Bound::Unbounded=> 0
+    };
+    let end = match range.end_bound() {
+        Bound::Included(e)=> e.checked_add(1).ok_or_else(|| anyhow!("range end is outbound {}", len))?,
+        Bound::Excluded(e)=> *e,
+        Bound::Unbounded=> len
+    };
+    assert_err!(start > end || end > len, anyhow!("range {}..{} is outbound {}", start, end, len));
+    Ok(start..end)
+}
+
+struct DebugNode<'a> {                              //带祖先栈的 Debug 输出 遇到循环引用输出 <cycle>
+    value: &'a Dynamic,
+    stack: &'a RefCell<Vec<usize>>,
+}
+
+impl fmt::Debug for DebugNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(id) = self.value.container_id() {
+            if self.stack.borrow().contains(&id) {
+                return f.write_str("<cycle>");
+            }
+            self.stack.borrow_mut().push(id);
+            let result = match self.value {
+                Dynamic::Vec(v)=> f.debug_tuple("Vec").field(&DebugList(&v.read(), self.stack)).finish(),
+                Dynamic::Map(m)=> f.debug_tuple("Map").field(&DebugMap(&m.read(), self.stack)).finish(),
+                Dynamic::AnyMap(m)=> f.debug_tuple("AnyMap").field(&DebugPairs(&m.read(), self.stack)).finish(),
+                _=> unreachable!(),
+            };
+            self.stack.borrow_mut().pop();
+            return result;
+        }
+        match self.value {
+            Dynamic::Null=> f.write_str("Null"),
+            Dynamic::Bool(b)=> f.debug_tuple("Bool").field(b).finish(),
+            Dynamic::Byte(b)=> f.debug_tuple("Byte").field(b).finish(),
+            Dynamic::Int(i)=> f.debug_tuple("Int").field(i).finish(),
+            Dynamic::UInt(u)=> f.debug_tuple("UInt").field(u).finish(),
+            Dynamic::Float(v)=> f.debug_tuple("Float").field(v).finish(),
+            Dynamic::Double(v)=> f.debug_tuple("Double").field(v).finish(),
+            Dynamic::String(s)=> f.debug_tuple("String").field(s).finish(),
+            Dynamic::Bytes(b)=> f.debug_tuple("Bytes").field(b).finish(),
+            Dynamic::Vec(_) | Dynamic::Map(_) | Dynamic::AnyMap(_)=> unreachable!(),
+        }
+    }
+}
+
+struct DebugList<'a>(&'a [Dynamic], &'a RefCell<Vec<usize>>);
+
+impl fmt::Debug for DebugList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.0.iter().map(|value| DebugNode { value, stack: self.1 })).finish()
+    }
+}
+
+struct DebugMap<'a>(&'a DynamicMap, &'a RefCell<Vec<usize>>);
+
+impl fmt::Debug for DebugMap<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.0.iter().map(|(k, value)| (k, DebugNode { value, stack: self.1 }))).finish()
+    }
+}
+
+struct DebugPairs<'a>(&'a [(Dynamic, Dynamic)], &'a RefCell<Vec<usize>>);
+
+impl fmt::Debug for DebugPairs<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.0.iter().map(|(key, value)| (DebugNode { value: key, stack: self.1 }, DebugNode { value, stack: self.1 }))).finish()
+    }
+}
+
+impl fmt::Debug for Dynamic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        DebugNode { value: self, stack: &RefCell::new(Vec::new()) }.fmt(f)
+    }
 }
 
 impl From<String> for Dynamic {
@@ -238,14 +663,14 @@ impl From<&str> for Dynamic {
 impl TryFrom<&[u8]> for Dynamic {
     type Error = Utf8Error;
     fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
-        let s = std::str::from_utf8(value).unwrap();
+        let s = std::str::from_utf8(value)?;
         Ok(Dynamic::from(s))
     }
 }
 
 impl From<bool> for Dynamic {
     fn from(b: bool)-> Self {
-        if b { Self::Bool(true) } else { Self::Bool(false) }
+        Self::Bool(b)
     }
 }
 
@@ -270,10 +695,7 @@ impl From<Vec<Dynamic>> for Dynamic {
 impl PartialEq for Dynamic {
     fn eq(&self, other: &Self) -> bool {
         match self {
-            Self::Null=> {
-                if let Self::Null = other { true }
-                else { false }
-            }
+            Self::Null=> matches!(other, Self::Null),
             Self::Bool(b)=> {
                 if let Self::Bool(o) = other { *b == * o }
                 else { false }
@@ -304,6 +726,7 @@ impl PartialEq for Dynamic {
             }
             Self::Vec(_)=> false,
             Self::Map(_)=> false,
+            Self::AnyMap(_)=> false,
             Self::Bytes(b1)=> {
                 if let Self::Bytes(b2) = other { b1.as_slice() == b2.as_slice() }
                 else { false }
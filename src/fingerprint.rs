@@ -0,0 +1,257 @@
+use std::fmt;
+use super::dynamic::Dynamic;
+
+//数字参与摘要的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberPolicy {
+    #[default]
+    Typed,                  //Byte Int UInt 按整数 Float Double 按 f64 整数和浮点数不同
+    Value,                  //只看数值 Int(1) UInt(1) Double(1.0) 相同 -0.0 和 0 相同
+    Exact,                  //每种变体都不同 Float 按 f32 的位
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FingerprintOptions {
+    pub numbers: NumberPolicy,
+}
+
+//SHA-256 摘要 short 取前 128 位
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Fingerprint(pub [u8; 32]);
+
+impl Fingerprint {
+    pub fn as_bytes(&self)-> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn short(&self)-> u128 {
+        u128::from_be_bytes(self.0[..16].try_into().unwrap_or_default())
+    }
+
+    pub fn to_hex(&self)-> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>)-> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>)-> fmt::Result {
+        write!(f, "Fingerprint({})", self.to_hex())
+    }
+}
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+//流式的 SHA-256 不依赖外部库 摘要和进程 平台无关
+pub struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    filled: usize,
+    length: u64,
+}
+
+impl Default for Sha256 {
+    fn default()-> Self {
+        Self::new()
+    }
+}
+
+impl Sha256 {
+    pub fn new()-> Self {
+        Self {
+            state: [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19],
+            block: [0u8; 64],
+            filled: 0,
+            length: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length = self.length.wrapping_add(data.len() as u64);
+        while !data.is_empty() {
+            let take = (64 - self.filled).min(data.len());
+            self.block[self.filled..self.filled + take].copy_from_slice(&data[..take]);
+            self.filled += take;
+            data = &data[take..];
+            if self.filled == 64 {
+                self.compress();
+                self.filled = 0;
+            }
+        }
+    }
+
+    pub fn finish(mut self)-> [u8; 32] {
+        let bits = self.length.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.filled != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bits.to_be_bytes());
+        let mut result = [0u8; 32];
+        for (chunk, word) in result.chunks_mut(4).zip(self.state.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        result
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u32; 64];
+        for (i, chunk) in self.block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+//map 的 key 先编码到 Vec 里排序 其他内容直接写进摘要
+trait Sink {
+    fn put(&mut self, data: &[u8]);
+}
+
+impl Sink for Sha256 {
+    fn put(&mut self, data: &[u8]) {
+        self.update(data);
+    }
+}
+
+impl Sink for Vec<u8> {
+    fn put(&mut self, data: &[u8]) {
+        self.extend_from_slice(data);
+    }
+}
+
+//每个值先写一个类型字节 变长的内容带上长度 这样不同的树不会拼出同样的字节流
+fn feed<S: Sink>(value: &Dynamic, sink: &mut S, options: &FingerprintOptions, stack: &mut Vec<usize>) {
+    if let Some(id) = value.container_id() {
+        if let Some(position) = stack.iter().position(|v| *v == id) {
+            sink.put(b"^");                                     //循环引用记录往上第几层 和地址无关
+            sink.put(&((stack.len() - position) as u64).to_be_bytes());
+            return;
+        }
+        stack.push(id);
+    }
+    match value {
+        Dynamic::Null=> sink.put(b"n"),
+        Dynamic::Bool(b)=> sink.put(if *b { b"t" } else { b"f" }),
+        Dynamic::Byte(v)=> feed_integer(*v as i128, b'B', sink, options),
+        Dynamic::Int(v)=> feed_integer(*v as i128, b'I', sink, options),
+        Dynamic::UInt(v)=> feed_integer(*v as i128, b'U', sink, options),
+        Dynamic::Float(v) if options.numbers == NumberPolicy::Exact=> {
+            sink.put(b"F");
+            sink.put(&v.to_bits().to_be_bytes());
+        }
+        Dynamic::Float(v)=> feed_float(*v as f64, b'D', sink, options),
+        Dynamic::Double(v)=> feed_float(*v, b'D', sink, options),
+        Dynamic::String(s)=> feed_bytes(b's', s.as_bytes(), sink),
+        Dynamic::Bytes(b)=> feed_bytes(b'b', b, sink),
+        Dynamic::Vec(v)=> {
+            let items = v.read();
+            sink.put(b"[");
+            sink.put(&(items.len() as u64).to_be_bytes());
+            items.iter().for_each(|item| feed(item, sink, options, stack));
+        }
+        Dynamic::Map(m)=> {
+            let items = m.read();
+            let entries = items.iter().map(|(k, v)| {
+                let mut key = Vec::new();
+                feed_bytes(b's', k.as_bytes(), &mut key);
+                (key, v)
+            }).collect();
+            feed_entries(entries, sink, options, stack);
+        }
+        Dynamic::AnyMap(m)=> {                                  //字符串 key 的 AnyMap 和 Map 一样
+            let items = m.read();
+            let entries = items.iter().map(|(k, v)| {
+                let mut key = Vec::new();
+                feed(k, &mut key, options, stack);
+                (key, v)
+            }).collect();
+            feed_entries(entries, sink, options, stack);
+        }
+    }
+    if value.container_id().is_some() {
+        stack.pop();
+    }
+}
+
+fn feed_entries<S: Sink>(mut entries: Vec<(Vec<u8>, &Dynamic)>, sink: &mut S, options: &FingerprintOptions, stack: &mut Vec<usize>) {
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    sink.put(b"{");
+    sink.put(&(entries.len() as u64).to_be_bytes());
+    for (key, value) in entries {
+        sink.put(&key);
+        feed(value, sink, options, stack);
+    }
+}
+
+fn feed_bytes<S: Sink>(tag: u8, data: &[u8], sink: &mut S) {
+    sink.put(&[tag]);
+    sink.put(&(data.len() as u64).to_be_bytes());
+    sink.put(data);
+}
+
+fn feed_integer<S: Sink>(value: i128, tag: u8, sink: &mut S, options: &FingerprintOptions) {
+    sink.put(&[if options.numbers == NumberPolicy::Exact { tag } else { b'i' }]);
+    sink.put(&value.to_be_bytes());
+}
+
+fn feed_float<S: Sink>(value: f64, tag: u8, sink: &mut S, options: &FingerprintOptions) {
+    if options.numbers == NumberPolicy::Value && value.fract() == 0.0 && value.abs() < 1e38 {     //整数值的浮点数按整数处理 -0.0 也变成 0
+        return feed_integer(value as i128, tag, sink, options);
+    }
+    let value = if value.is_nan() { f64::NAN } else { value };  //NaN 只有一种
+    sink.put(&[tag]);
+    sink.put(&value.to_bits().to_be_bytes());
+}
+
+impl Dynamic {
+    //稳定的内容摘要 和 map 的 key 顺序 容器是否共享 进程都无关 不会先生成 json 字符串
+    pub fn fingerprint(&self)-> Fingerprint {
+        self.fingerprint_with(&FingerprintOptions::default())
+    }
+
+    pub fn fingerprint_with(&self, options: &FingerprintOptions)-> Fingerprint {
+        let mut hasher = Sha256::new();
+        feed(self, &mut hasher, options, &mut Vec::new());
+        Fingerprint(hasher.finish())
+    }
+}
+
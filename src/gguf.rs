@@ -0,0 +1,199 @@
+use anyhow::{anyhow, Result};
+use byteorder::{LittleEndian, ReadBytesExt};
+use smol_str::SmolStr;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use super::dynamic::Dynamic;
+use super::assert_err;
+
+const MAGIC: &[u8; 4] = b"GGUF";
+const DEFAULT_ALIGNMENT: u64 = 32;
+const MAX_DIMS: u32 = 4;
+const MAX_STRING: u64 = 1 << 30;
+const MAX_DEPTH: usize = 64;                        //嵌套数组的最大层数 防止恶意的文件栈溢出
+
+//ggml 的张量类型 下标是类型的编号 空的是已经删除的类型
+const TYPE_NAMES: [&str; 40] = [
+    "F32", "F16", "Q4_0", "Q4_1", "", "", "Q5_0", "Q5_1", "Q8_0", "Q8_1", "Q2_K", "Q3_K", "Q4_K", "Q5_K", "Q6_K", "Q8_K",
+    "IQ2_XXS", "IQ2_XS", "IQ3_XXS", "IQ1_S", "IQ4_NL", "IQ3_S", "IQ2_S", "IQ4_XS", "I8", "I16", "I32", "I64", "F64", "IQ1_M", "BF16",
+    "", "", "", "TQ1_0", "TQ2_0", "", "", "", "MXFP4",
+];
+
+pub fn type_name(ggml_type: u32) -> Option<&'static str> {
+    TYPE_NAMES.get(ggml_type as usize).filter(|name| !name.is_empty()).copied()
+}
+
+//offset 是相对于数据区开始的 dims 的第一个是变化最快的维度
+#[derive(Debug, Clone)]
+pub struct GgufTensor {
+    pub name: SmolStr,
+    pub dims: Vec<u64>,
+    pub ggml_type: u32,
+    pub offset: u64,
+}
+
+impl GgufTensor {
+    pub fn elements(&self) -> Option<u64> {        //维度来自文件 乘积溢出时返回 None
+        self.dims.iter().try_fold(1u64, |n, d| n.checked_mul(*d))
+    }
+}
+
+//metadata 是 key 到值的 Map 张量的数据从 data_offset 开始 不会读进来
+#[derive(Debug, Clone)]
+pub struct Gguf {
+    pub version: u32,
+    pub metadata: Dynamic,
+    pub tensors: Vec<GgufTensor>,
+    pub alignment: u64,
+    pub data_offset: u64,
+}
+
+impl Gguf {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    //只读文件开头的 header metadata 和张量信息 支持版本 1 到 3 的小端文件
+    pub fn read<R: Read>(reader: R) -> Result<Self> {
+        let mut reader = Reader { reader, pos: 0, wide: true };
+        let mut magic = [0u8; 4];
+        reader.exact(&mut magic)?;
+        assert_err!(&magic != MAGIC, anyhow!("not a gguf file"));
+        let version = reader.u32()?;
+        assert_err!(version > 0xffff && version.swap_bytes() <= 3, anyhow!("big endian gguf is not supported"));
+        assert_err!(!(1..=3).contains(&version), anyhow!("unsupported gguf version {}", version));
+        reader.wide = version > 1;                  //版本 1 的数量 字符串长度和维度是 u32
+        let tensor_count = reader.count()?;
+        let metadata_count = reader.count()?;
+        let metadata = Dynamic::map();
+        for _ in 0..metadata_count {
+            let key = reader.string()?;
+            assert_err!(metadata.contains(&key)?, anyhow!("duplicate metadata key {}", key));
+            let kind = reader.u32()?;
+            let value = reader.value(kind, 0).map_err(|e| anyhow!("metadata {}: {}", key, e))?;
+            metadata.set_key(&key, value)?;
+        }
+        let alignment = match metadata.get_key("general.alignment") {
+            Ok(Dynamic::Int(v)) if v > 0 && (v as u64).is_power_of_two()=> v as u64,
+            Ok(v)=> return Err(anyhow!("invalid general.alignment {:?}", v)),
+            Err(_)=> DEFAULT_ALIGNMENT
+        };
+        let mut tensors = Vec::new();
+        for _ in 0..tensor_count {
+            let name = SmolStr::from(reader.string()?);
+            let count = reader.u32()?;
+            assert_err!(count > MAX_DIMS, anyhow!("tensor {} has {} dimensions", name, count));
+            let dims = (0..count).map(|_| reader.count()).collect::<Result<Vec<_>>>()?;
+            let ggml_type = reader.u32()?;
+            let offset = reader.u64()?;
+            assert_err!(offset % alignment != 0, anyhow!("tensor {} offset {} is not aligned to {}", name, offset, alignment));
+            assert_err!(tensors.iter().any(|t: &GgufTensor| t.name == name), anyhow!("duplicate tensor {}", name));
+            tensors.push(GgufTensor { name, dims, ggml_type, offset });
+        }
+        let data_offset = reader.pos.div_ceil(alignment) * alignment;
+        Ok(Self { version, metadata, tensors, alignment, data_offset })
+    }
+
+    //{"version": 3, "metadata": {...}, "tensors": [{"name", "shape", "type", "offset"}]} 不认识的类型用编号
+    pub fn to_dynamic(&self) -> Dynamic {
+        let tensors = self.tensors.iter().map(|tensor| {
+            let kind = type_name(tensor.ggml_type).map_or(Dynamic::Int(tensor.ggml_type as i64), Dynamic::from);
+            let shape = Dynamic::from_vec(tensor.dims.iter().map(|d| integer(*d)).collect());
+            crate::dmap!("name" => tensor.name.as_str(), "shape" => shape, "type" => kind, "offset" => integer(tensor.offset))
+        }).collect();
+        crate::dmap!("version" => self.version as i64, "metadata" => self.metadata.clone(), "tensors" => Dynamic::from_vec(tensors))
+    }
+}
+
+fn integer(v: u64) -> Dynamic {
+    match i64::try_from(v) {
+        Ok(v)=> Dynamic::Int(v),
+        Err(_)=> Dynamic::UInt(v)
+    }
+}
+
+//记住读了多少字节 用来计算数据区的位置
+struct Reader<R> {
+    reader: R,
+    pos: u64,
+    wide: bool,
+}
+
+impl<R: Read> Reader<R> {
+    fn exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.reader.read_exact(buf).map_err(|_| anyhow!("unexpected end of gguf at offset {}", self.pos))?;
+        self.pos += buf.len() as u64;
+        Ok(())
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let v = self.reader.read_u32::<LittleEndian>().map_err(|_| anyhow!("unexpected end of gguf at offset {}", self.pos))?;
+        self.pos += 4;
+        Ok(v)
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let v = self.reader.read_u64::<LittleEndian>().map_err(|_| anyhow!("unexpected end of gguf at offset {}", self.pos))?;
+        self.pos += 8;
+        Ok(v)
+    }
+
+    fn count(&mut self) -> Result<u64> {
+        if self.wide { self.u64() } else { Ok(self.u32()? as u64) }
+    }
+
+    //不是 utf8 的字节替换成 U+FFFD 有些模型的词表里有这样的 token
+    fn string(&mut self) -> Result<String> {
+        let length = self.count()?;
+        assert_err!(length > MAX_STRING, anyhow!("string length {} is too large at offset {}", length, self.pos));
+        let mut buf = Vec::new();                   //长度来自文件 按实际读到的数据分配
+        self.reader.by_ref().take(length).read_to_end(&mut buf).map_err(|_| anyhow!("unexpected end of gguf at offset {}", self.pos))?;
+        assert_err!((buf.len() as u64) < length, anyhow!("unexpected end of gguf at offset {}", self.pos + buf.len() as u64));
+        self.pos += length;
+        Ok(match String::from_utf8(buf) {
+            Ok(s)=> s,
+            Err(e)=> String::from_utf8_lossy(e.as_bytes()).into_owned()
+        })
+    }
+
+    fn value(&mut self, kind: u32, depth: usize) -> Result<Dynamic> {
+        let mut byte = [0u8; 1];
+        Ok(match kind {
+            0 | 1 | 7=> {
+                self.exact(&mut byte)?;
+                match kind {
+                    0=> Dynamic::Int(byte[0] as i64),
+                    1=> Dynamic::Int(byte[0] as i8 as i64),
+                    _=> match byte[0] {
+                        0 | 1=> Dynamic::Bool(byte[0] == 1),
+                        v=> return Err(anyhow!("invalid bool {} at offset {}", v, self.pos - 1))
+                    }
+                }
+            }
+            2 | 3=> {
+                let v = self.reader.read_u16::<LittleEndian>().map_err(|_| anyhow!("unexpected end of gguf at offset {}", self.pos))?;
+                self.pos += 2;
+                Dynamic::Int(if kind == 2 { v as i64 } else { v as i16 as i64 })
+            }
+            4=> Dynamic::Int(self.u32()? as i64),
+            5=> Dynamic::Int(self.u32()? as i32 as i64),
+            6=> Dynamic::Float(f32::from_bits(self.u32()?)),
+            8=> Dynamic::from(self.string()?),
+            9=> {                                   //数组里的元素类型相同 可以是嵌套的数组
+                assert_err!(depth >= MAX_DEPTH, anyhow!("arrays nested deeper than {} at offset {}", MAX_DEPTH, self.pos));
+                let kind = self.u32()?;
+                let length = self.count()?;
+                let mut items = Vec::with_capacity(length.min(1 << 16) as usize);
+                for _ in 0..length {
+                    items.push(self.value(kind, depth + 1)?);
+                }
+                Dynamic::from_vec(items)
+            }
+            10=> integer(self.u64()?),
+            11=> Dynamic::Int(self.u64()? as i64),
+            12=> Dynamic::Double(f64::from_bits(self.u64()?)),
+            _=> return Err(anyhow!("unknown value type {}", kind))
+        })
+    }
+}
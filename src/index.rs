@@ -0,0 +1,159 @@
+use smol_str::SmolStr;
+use anyhow::{Result, anyhow};
+use super::dynamic::{Dynamic, key_matches};
+
+//容器在 RwLock 里面 没法实现 std::ops::Index 返回引用 所以取值都是 clone 出来的 (容器只是 Arc 计数加一)
+//usize 用于 Vec 字符串用于 Map
+pub trait DynamicIndex {
+    fn index_into(&self, value: &Dynamic)-> Option<Dynamic>;
+    fn index_set(&self, target: &Dynamic, value: Dynamic)-> Result<Option<Dynamic>>;
+}
+
+impl DynamicIndex for usize {
+    fn index_into(&self, value: &Dynamic)-> Option<Dynamic> {
+        match value {
+            Dynamic::Vec(v)=> v.read().get(*self).cloned(),
+            _=> None
+        }
+    }
+
+    fn index_set(&self, target: &Dynamic, value: Dynamic)-> Result<Option<Dynamic>> {
+        match target {
+            Dynamic::Vec(v)=> {
+                let mut v = v.write();
+                let len = v.len();
+                let item = v.get_mut(*self).ok_or(anyhow!("index {} is outbound {}", self, len))?;
+                Ok(Some(std::mem::replace(item, value)))
+            }
+            _=> Err(anyhow!("is not a Vec"))
+        }
+    }
+}
+
+//整数字面量默认是 i32 所以其他整数类型也转成 usize 负数当作不存在
+macro_rules! int_index {
+    ($($t:ty),*) => {$(
+        impl DynamicIndex for $t {
+            fn index_into(&self, value: &Dynamic)-> Option<Dynamic> {
+                usize::try_from(*self).ok().and_then(|index| index.index_into(value))
+            }
+
+            fn index_set(&self, target: &Dynamic, value: Dynamic)-> Result<Option<Dynamic>> {
+                usize::try_from(*self).map_err(|_| anyhow!("index {} is outbound", self))?.index_set(target, value)
+            }
+        }
+    )*};
+}
+
+int_index!(i32, i64, u32, u64);
+
+impl DynamicIndex for str {
+    fn index_into(&self, value: &Dynamic)-> Option<Dynamic> {
+        match value {
+            Dynamic::Map(m)=> m.read().get(self).cloned(),
+            Dynamic::AnyMap(m)=> m.read().iter().find(|(k, _)| key_matches(k, self)).map(|(_, v)| v.clone()),
+            _=> None
+        }
+    }
+
+    fn index_set(&self, target: &Dynamic, value: Dynamic)-> Result<Option<Dynamic>> {
+        target.set_key(self, value)
+    }
+}
+
+impl DynamicIndex for String {
+    fn index_into(&self, value: &Dynamic)-> Option<Dynamic> {
+        self.as_str().index_into(value)
+    }
+
+    fn index_set(&self, target: &Dynamic, value: Dynamic)-> Result<Option<Dynamic>> {
+        self.as_str().index_set(target, value)
+    }
+}
+
+impl DynamicIndex for SmolStr {
+    fn index_into(&self, value: &Dynamic)-> Option<Dynamic> {
+        self.as_str().index_into(value)
+    }
+
+    fn index_set(&self, target: &Dynamic, value: Dynamic)-> Result<Option<Dynamic>> {
+        self.as_str().index_set(target, value)
+    }
+}
+
+impl<T: DynamicIndex + ?Sized> DynamicIndex for &T {
+    fn index_into(&self, value: &Dynamic)-> Option<Dynamic> {
+        (**self).index_into(value)
+    }
+
+    fn index_set(&self, target: &Dynamic, value: Dynamic)-> Result<Option<Dynamic>> {
+        (**self).index_set(target, value)
+    }
+}
+
+//json pointer (RFC 6901) 的一段 ~1 表示 / ~0 表示 ~
+fn unescape(token: &str)-> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+fn split_pointer(pointer: &str)-> Result<Vec<String>> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rest = pointer.strip_prefix('/').ok_or(anyhow!("pointer {} must start with /", pointer))?;
+    Ok(rest.split('/').map(unescape).collect())
+}
+
+fn step(value: &Dynamic, token: &str)-> Option<Dynamic> {
+    match value {
+        Dynamic::Vec(_)=> token.parse::<usize>().ok().and_then(|index| index.index_into(value)),
+        _=> token.index_into(value)
+    }
+}
+
+impl Dynamic {
+    //v.at("choices").at(0).at("text") 不存在的时候返回 Null 和 serde_json 的 Value 一样
+    pub fn at<I: DynamicIndex>(&self, index: I)-> Dynamic {
+        index.index_into(self).unwrap_or_default()
+    }
+
+    pub fn set_at<I: DynamicIndex, T: Into<Dynamic>>(&self, index: I, val: T)-> Result<Option<Dynamic>> {
+        index.index_set(self, val.into())
+    }
+
+    //按照 json pointer 取值 例如 "/choices/0/text" 不存在的时候返回 Null
+    pub fn pointer(&self, pointer: &str)-> Dynamic {
+        let Ok(tokens) = split_pointer(pointer) else { return Dynamic::Null };
+        let mut current = self.clone();
+        for token in tokens.iter() {
+            match step(&current, token) {
+                Some(next)=> current = next,
+                None=> return Dynamic::Null
+            }
+        }
+        current
+    }
+
+    //按照 json pointer 设置 中间缺少的 Map 会自动创建 Vec 的下标必须存在 "-" 表示追加到末尾
+    pub fn set_pointer<T: Into<Dynamic>>(&self, pointer: &str, val: T)-> Result<Option<Dynamic>> {
+        let tokens = split_pointer(pointer)?;
+        let (last, parents) = tokens.split_last().ok_or(anyhow!("can not set the root"))?;
+        let mut current = self.clone();
+        for token in parents {
+            current = match step(&current, token) {
+                Some(next)=> next,
+                None if current.is_map() || current.is_any_map()=> {
+                    let next = Dynamic::map();
+                    current.set_key(token, next.clone())?;
+                    next
+                }
+                None=> return Err(anyhow!("path {} is not existed", token))
+            };
+        }
+        match &current {
+            Dynamic::Vec(_) if last == "-"=> current.push(val).map(|_| None),
+            Dynamic::Vec(_)=> last.parse::<usize>().map_err(|_| anyhow!("{} is not a index", last))?.index_set(&current, val.into()),
+            _=> last.index_set(&current, val.into())
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::sync::Arc;
+use parking_lot::RwLockReadGuard;
+use smol_str::SmolStr;
+use anyhow::{Result, anyhow};
+use super::dynamic::{Dynamic, DynamicMap};
+
+//持有读锁的迭代器 迭代期间其他线程不能修改容器 返回的元素是 clone 出来的 (容器只是 Arc 计数加一)
+pub struct Iter<'a> {
+    guard: RwLockReadGuard<'a, Vec<Dynamic>>,
+    pos: usize,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Dynamic;
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.guard.get(self.pos).cloned();
+        self.pos += 1;
+        item
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remain = self.guard.len().saturating_sub(self.pos);
+        (remain, Some(remain))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+//map 的迭代器 创建时复制出 key 和 value 的快照 (容器只是 Arc 计数加一) 然后释放读锁
+//AnyMap 的 key 用 key_text 转成字符串
+pub struct Entries {
+    items: std::vec::IntoIter<(SmolStr, Dynamic)>,
+}
+
+impl Iterator for Entries {
+    type Item = (SmolStr, Dynamic);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.items.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.items.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Entries {}
+
+//遍历时的路径 map 用 key vec 用下标
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathItem {
+    Key(SmolStr),
+    Index(usize),
+}
+
+impl Dynamic {
+    //迭代期间一直持有读锁 parking_lot 的锁不能重入 在循环里对同一个容器 push set_key 等写操作会死锁
+    //需要边遍历边修改的时候先 clone 出元素 (into_vec) 或者用 for_each_mut retain
+    pub fn iter(&self)-> Result<Iter<'_>> {
+        match self {
+            Self::Vec(v)=> Ok(Iter { guard: v.read(), pos: 0 }),
+            _=> Err(anyhow!("is not a Vec"))
+        }
+    }
+
+    //和 iter 不同 返回的是快照 不持有锁 循环里可以修改同一个 Map 修改不会影响这次遍历
+    pub fn entries(&self)-> Result<Entries> {
+        let items: Vec<(SmolStr, Dynamic)> = match self {
+            Self::Map(m)=> m.read().iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            Self::AnyMap(m)=> m.read().iter().map(|(k, v)| (k.key_text(), v.clone())).collect(),
+            _=> return Err(anyhow!("is not a Map"))
+        };
+        Ok(Entries { items: items.into_iter() })
+    }
+
+    pub fn keys(&self)-> Result<impl Iterator<Item = SmolStr>> {
+        self.entries().map(|entries| entries.map(|(k, _)| k))
+    }
+
+    pub fn values(&self)-> Result<impl Iterator<Item = Dynamic>> {
+        self.entries().map(|entries| entries.map(|(_, v)| v))
+    }
+
+    //Map 的 key 和 value 一起取出 独占的容器直接拿走 否则复制一份
+    pub fn into_entries(self)-> Result<<DynamicMap as IntoIterator>::IntoIter> {
+        match self {
+            Self::Map(m)=> Ok(Arc::try_unwrap(m).map(|lock| lock.into_inner()).unwrap_or_else(|m| m.read().clone()).into_iter()),
+            _=> Err(anyhow!("is not a Map"))
+        }
+    }
+
+    //Vec 修改每个元素 Map 和 AnyMap 修改每个 value
+    pub fn for_each_mut<F: FnMut(&mut Dynamic)>(&self, mut f: F)-> Result<()> {
+        match self {
+            Self::Vec(v)=> {
+                v.write().iter_mut().for_each(f);
+                Ok(())
+            },
+            Self::Map(m)=> {
+                m.write().values_mut().for_each(f);
+                Ok(())
+            },
+            Self::AnyMap(m)=> {
+                m.write().iter_mut().for_each(|(_, v)| f(v));
+                Ok(())
+            },
+            _=> Err(anyhow!("is not a Vec or Map"))
+        }
+    }
+
+    pub fn retain<F: FnMut(&Dynamic)-> bool>(&self, mut f: F)-> Result<()> {
+        match self {
+            Self::Vec(v)=> {
+                v.write().retain(f);
+                Ok(())
+            },
+            Self::Map(m)=> {
+                m.write().retain(|_, v| f(v));
+                Ok(())
+            },
+            Self::AnyMap(m)=> {
+                m.write().retain(|(_, v)| f(v));
+                Ok(())
+            },
+            _=> Err(anyhow!("is not a Vec or Map"))
+        }
+    }
+
+    //生成一个新的容器 原来的不变
+    pub fn map_values<F: FnMut(&Dynamic)-> Dynamic>(&self, mut f: F)-> Result<Dynamic> {
+        match self {
+            Self::Vec(v)=> Ok(Dynamic::from_vec(v.read().iter().map(f).collect())),
+            Self::Map(m)=> Ok(Dynamic::from_map(m.read().iter().map(|(k, v)| (k.clone(), f(v))).collect())),
+            Self::AnyMap(m)=> Ok(Dynamic::from_pairs(m.read().iter().map(|(k, v)| (k.clone(), f(v))).collect())),
+            _=> Err(anyhow!("is not a Vec or Map"))
+        }
+    }
+
+    //先序遍历整棵树 每一层先复制出子节点再释放锁 所以 visitor 里可以修改容器 循环引用的节点不会重复进入
+    pub fn walk<F: FnMut(&[PathItem], &Dynamic)>(&self, mut visitor: F) {
+        fn visit<F: FnMut(&[PathItem], &Dynamic)>(value: &Dynamic, path: &mut Vec<PathItem>, stack: &mut Vec<usize>, visitor: &mut F) {
+            visitor(path, value);
+            let Some(id) = value.container_id() else { return };
+            if stack.contains(&id) {
+                return;
+            }
+            stack.push(id);
+            match value {
+                Dynamic::Vec(v)=> {
+                    let items = v.read().clone();
+                    for (index, item) in items.iter().enumerate() {
+                        path.push(PathItem::Index(index));
+                        visit(item, path, stack, visitor);
+                        path.pop();
+                    }
+                },
+                Dynamic::Map(m)=> {
+                    let items = m.read().clone();
+                    for (key, item) in items.iter() {
+                        path.push(PathItem::Key(key.clone()));
+                        visit(item, path, stack, visitor);
+                        path.pop();
+                    }
+                },
+                Dynamic::AnyMap(m)=> {                              //AnyMap 的 key 可能不是字符串 路径用第几个 pair
+                    let items = m.read().clone();
+                    for (index, (_, item)) in items.iter().enumerate() {
+                        path.push(PathItem::Index(index));
+                        visit(item, path, stack, visitor);
+                        path.pop();
+                    }
+                },
+                _=> {}
+            }
+            stack.pop();
+        }
+        visit(self, &mut Vec::new(), &mut Vec::new(), &mut visitor)
+    }
+}
+
+//Vec 得到元素 Map 和 AnyMap 得到 value 其他类型是空的 独占的容器直接拿走 否则复制一份
+impl IntoIterator for Dynamic {
+    type Item = Dynamic;
+    type IntoIter = std::vec::IntoIter<Dynamic>;
+    fn into_iter(self) -> Self::IntoIter {
+        match self {
+            Self::Vec(v)=> Arc::try_unwrap(v).map(|lock| lock.into_inner()).unwrap_or_else(|v| v.read().clone()).into_iter(),
+            Self::Map(_)=> self.into_entries().map(|entries| entries.map(|(_, v)| v).collect::<Vec<_>>()).unwrap_or_default().into_iter(),
+            Self::AnyMap(m)=> m.read().iter().map(|(_, v)| v.clone()).collect::<Vec<_>>().into_iter(),
+            _=> Vec::new().into_iter()
+        }
+    }
+}
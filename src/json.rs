@@ -1,8 +1,33 @@
 use anyhow::{anyhow, Result};
 
-const TOKEN: &[u8] = b"01234567890.-+truefalsenull"; //合法的数字和其他 json token
+const TOKEN: &[u8] = b"01234567890.-+Etruefalsenull"; //合法的数字和其他 json token
+
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;        //f64 能精确表示的最大整数 canonical json 的整数范围
+
+fn read_hex4(buf: &[u8]) -> Result<u32> {
+    assert_err!(buf.len() < 4, anyhow!("uncomplete string"));
+    let hex = std::str::from_utf8(&buf[..4])?;
+    u32::from_str_radix(hex, 16).map_err(|_| anyhow!("invalid unicode escape {}", hex))
+}
+
+//\u 后面的 4 位 hex 代理对合成一个字符 单独的代理变成 U+FFFD 返回字符和消耗的长度
+fn read_unicode(buf: &[u8]) -> Result<(char, usize)> {
+    let high = read_hex4(buf)?;
+    if (0xd800..0xdc00).contains(&high) && buf.len() >= 10 && buf[4] == b'\\' && buf[5] == b'u' {
+        let low = read_hex4(&buf[6..])?;
+        if (0xdc00..0xe000).contains(&low) {
+            let code = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+            return Ok((char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER), 10));
+        }
+    }
+    Ok((char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER), 4))
+}
+
 pub trait FromJson: Sized {
     fn from_json(buf: &[u8]) -> Result<(Self, usize)>;
+    fn missing() -> Option<Self> {                  //derive 解析 object 时字段不存在使用的值 Option 返回 Some(None)
+        None
+    }
     fn get_token(buf: &[u8]) -> Result<(&str, usize)> {
         let mut pos = 0usize;
         while pos < buf.len() && TOKEN.contains(&buf[pos]) {
@@ -26,18 +51,14 @@ pub trait FromJson: Sized {
                     b'r'=> vec.push(b'\r'),
                     b'n'=> vec.push(b'\n'),
                     b't'=> vec.push(b'\t'),
+                    b'/'=> vec.push(b'/'),
+                    b'b'=> vec.push(0x08),
+                    b'f'=> vec.push(0x0c),
                     b'u'=> {
-                        let unicode_str = unsafe { std::str::from_utf8_unchecked(&buf[(pos + 1)..(pos + 5)]) };
-                        if let Ok(unicode) = u32::from_str_radix(unicode_str, 16) {
-                            if unicode < 0x80 {
-                                vec.push(unicode as u8);
-                            } else {
-                                if let Some(unicode_char) = char::from_u32(unicode) {
-                                    unicode_char.encode_utf8(&mut vec);
-                                }
-                            }
-                        }
-                        pos += 4;
+                        let (unicode, size) = read_unicode(&buf[pos + 1..])?;
+                        let mut utf8 = [0u8; 4];
+                        vec.extend_from_slice(unicode.encode_utf8(&mut utf8).as_bytes());
+                        pos += size;
                     },
                     _=> {
                         return Err(anyhow!("unknow escape {}", buf[pos]));
@@ -56,70 +77,95 @@ pub trait FromJson: Sized {
 }
 
 use super::dynamic::Dynamic;
+use super::map::{DuplicatePolicy, MapBuilder};
 use super::skip_white;
-use std::collections::BTreeMap;
 use smol_str::SmolStr;
-use super::assert_err;
+use super::{assert_err, assert_ok};
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::hash::{BuildHasher, Hash};
+use std::str::FromStr;
+use std::sync::Arc;
+
+//解析 json 的选项
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    pub duplicates: DuplicatePolicy,
+}
 
 impl FromJson for Dynamic {
     fn from_json(buf: &[u8])-> Result<(Self, usize)> {
-        let mut pos = skip_white(buf)?;
-        if buf[pos] == b'[' {           //是一个 vec
-            pos += 1;
+        parse_with(buf, &ParseOptions::default())
+    }
+}
+
+pub fn parse_with(buf: &[u8], options: &ParseOptions)-> Result<(Dynamic, usize)> {
+    parse_value(buf, 0, options)
+}
+
+//base 是 buf 在整个输入里的偏移 用于错误信息
+fn parse_value(buf: &[u8], base: usize, options: &ParseOptions)-> Result<(Dynamic, usize)> {
+    let mut pos = skip_white(buf)?;
+    if buf[pos] == b'[' {           //是一个 vec
+        pos += 1;
+        pos += skip_white(&buf[pos..])?;
+        let mut vec = Vec::<Dynamic>::new();
+        while buf[pos] != b']' {
+            let (item, size) = parse_value(&buf[pos..], base + pos, options)?;
+            vec.push(item);
+            pos += size;
             pos += skip_white(&buf[pos..])?;
-            let mut vec = Vec::<Self>::new();
-            while buf[pos] != b']' {
-                let (item, size) = Self::from_json(&buf[pos..])?;
-                vec.push(item);
-                pos += size;
+            if buf[pos] == b',' {
+                pos += 1;
                 pos += skip_white(&buf[pos..])?;
-                if buf[pos] == b',' {
-                    pos += 1;
-                    pos += skip_white(&buf[pos..])?;
-                }
-            };
-            Ok((Dynamic::from_vec(vec), pos + 1))
-        } else if buf[pos] == b'{' {           //是一个 object
+            }
+        };
+        Ok((Dynamic::from_vec(vec), pos + 1))
+    } else if buf[pos] == b'{' {           //是一个 object
+        pos += 1;
+        pos += skip_white(&buf[pos..])?;
+        let mut obj = MapBuilder::new(options.duplicates);
+        while buf[pos] != b'}' {
+            assert_err!(buf[pos] != b'"', anyhow!("need a string key"));
+            let offset = base + pos;
+            let (key, size) = Dynamic::get_string(&buf[pos..])?;
+            pos += size;
+            pos += skip_white(&buf[pos..])?;
+            assert_err!(buf[pos] != b':', anyhow!("need a :"));
             pos += 1;
             pos += skip_white(&buf[pos..])?;
-            let mut obj = BTreeMap::new();
-            while buf[pos] != b'}' {
-                assert_err!(buf[pos] != b'"', anyhow!("need a string key"));
-                let (key, size) = Self::get_string(&buf[pos..])?;
-                pos += size;
-                pos += skip_white(&buf[pos..])?;
-                assert_err!(buf[pos] != b':', anyhow!("need a :"));
+            let (item, size) = parse_value(&buf[pos..], base + pos, options)?;
+            obj.insert(SmolStr::from(key), item, offset)?;
+            pos += size;
+            pos += skip_white(&buf[pos..])?;
+            if buf[pos] == b',' {
                 pos += 1;
                 pos += skip_white(&buf[pos..])?;
-                let (item, size) = Self::from_json(&buf[pos..])?;
-                obj.insert(SmolStr::from(key), item);
-                pos += size;
-                pos += skip_white(&buf[pos..])?;
-                if buf[pos] == b',' {
-                    pos += 1;
-                    pos += skip_white(&buf[pos..])?;
-                }
-            }
-            Ok((Dynamic::from_map(obj), pos + 1))
-        } else if buf[pos] == b'"' {
-            let (s, size) = Self::get_string(&buf[pos..])?;
-            Ok((s.into(), size))
-        } else {
-            let (token, size) = Self::get_token(&buf[pos..])?;
-            if token == "true" {
-                Ok((Dynamic::from(true), size))
-            } else if token == "false" {
-                Ok((Dynamic::from(false), size))
-            } else if token == "null" {
-                Ok((Dynamic::Null, size))
-            } else if token.contains('.') {
-                let v = token.parse::<f64>()?;
-                Ok((Dynamic::from(v), size))
-            } else {
-                let v = token.parse::<i64>()?;
-                Ok((Dynamic::from(v), size))
             }
         }
+        Ok((obj.build(), pos + 1))
+    } else if buf[pos] == b'"' {
+        let (s, size) = Dynamic::get_string(&buf[pos..])?;
+        Ok((s.into(), pos + size))
+    } else {
+        let (token, size) = Dynamic::get_token(&buf[pos..])?;
+        assert_err!(size == 0, anyhow!("unexpected char {} at offset {}", buf[pos] as char, base + pos));
+        let size = pos + size;                      //和容器一样 返回的长度包括前面的空白
+        if token == "true" {
+            Ok((Dynamic::from(true), size))
+        } else if token == "false" {
+            Ok((Dynamic::from(false), size))
+        } else if token == "null" {
+            Ok((Dynamic::Null, size))
+        } else if let Ok(v) = token.parse::<i64>() {
+            Ok((Dynamic::from(v), size))
+        } else if let Ok(v) = token.parse::<u64>() {     //i64 放不下的正整数 和 UInt 输出的一致
+            Ok((Dynamic::UInt(v), size))
+        } else {                                    //小数 指数 以及 u64 也放不下的整数
+            let v = token.parse::<f64>()?;
+            Ok((Dynamic::from(v), size))
+        }
     }
 }
 
@@ -127,7 +173,7 @@ pub trait ToJson {
     fn to_json(&self, buf: &mut String);
 }
 
-impl ToJson for &str {
+impl ToJson for str {
     fn to_json(&self, buf: &mut String) {
         let mut formatted = self.as_bytes().iter().fold(vec![b'\"'], |mut vec, ch| {
             match ch {
@@ -136,6 +182,9 @@ impl ToJson for &str {
                 b'\n'=> { vec.extend_from_slice(&[0x5c, 0x6e]); vec }
                 b'\r'=> { vec.extend_from_slice(&[0x5c, 0x72]); vec }
                 b'\t'=> { vec.extend_from_slice(&[0x5c, 0x74]); vec }
+                0x08=> { vec.extend_from_slice(&[0x5c, b'b']); vec }
+                0x0c=> { vec.extend_from_slice(&[0x5c, b'f']); vec }
+                0..0x20=> { vec.extend_from_slice(format!("\\u{:04x}", ch).as_bytes()); vec }     //其他控制字符 json 里不能直接出现
                 _=> { vec.push(*ch); vec }
             }
         });
@@ -144,41 +193,683 @@ impl ToJson for &str {
     }
 }
 
-impl ToJson for i64 {
+//整数直接输出 浮点数 NaN 和无穷大 json 表示不了 输出 null
+macro_rules! int_json {
+    ($($t:ty),*) => {$(
+        impl ToJson for $t {
+            fn to_json(&self, buf: &mut String) {
+                buf.push_str(&self.to_string());
+            }
+        }
+
+        impl FromJson for $t {
+            fn from_json(buf: &[u8]) -> Result<(Self, usize)> {
+                let pos = skip_white(buf)?;
+                let (token, size) = Self::get_token(&buf[pos..])?;
+                let value = token.parse::<$t>().map_err(|_| anyhow!("{} is not a {}", token, stringify!($t)))?;
+                Ok((value, pos + size))
+            }
+        }
+    )*};
+}
+
+int_json!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! float_json {
+    ($($t:ty),*) => {$(
+        impl ToJson for $t {
+            fn to_json(&self, buf: &mut String) {
+                if self.is_finite() {
+                    buf.push_str(&self.to_string());
+                } else {
+                    buf.push_str("null");
+                }
+            }
+        }
+
+        impl FromJson for $t {
+            fn from_json(buf: &[u8]) -> Result<(Self, usize)> {
+                let pos = skip_white(buf)?;
+                let (token, size) = Self::get_token(&buf[pos..])?;
+                let value = token.parse::<$t>().map_err(|_| anyhow!("{} is not a {}", token, stringify!($t)))?;
+                Ok((value, pos + size))
+            }
+        }
+    )*};
+}
+
+float_json!(f32, f64);
+
+impl ToJson for bool {
     fn to_json(&self, buf: &mut String) {
-        buf.push_str(&self.to_string());
+        buf.push_str(if *self { "true" } else { "false" });
     }
 }
 
-impl ToJson for Dynamic {
+impl FromJson for bool {
+    fn from_json(buf: &[u8]) -> Result<(Self, usize)> {
+        let pos = skip_white(buf)?;
+        let (token, size) = Self::get_token(&buf[pos..])?;
+        match token {
+            "true"=> Ok((true, pos + size)),
+            "false"=> Ok((false, pos + size)),
+            _=> Err(anyhow!("{} is not a bool", token))
+        }
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self, buf: &mut String) {
+        self.as_str().to_json(buf);
+    }
+}
+
+impl FromJson for String {
+    fn from_json(buf: &[u8]) -> Result<(Self, usize)> {
+        let pos = skip_white(buf)?;
+        assert_err!(buf[pos] != b'"', anyhow!("need a string"));
+        let (s, size) = Self::get_string(&buf[pos..])?;
+        Ok((s, pos + size))
+    }
+}
+
+impl ToJson for SmolStr {
+    fn to_json(&self, buf: &mut String) {
+        self.as_str().to_json(buf);
+    }
+}
+
+impl FromJson for SmolStr {
+    fn from_json(buf: &[u8]) -> Result<(Self, usize)> {
+        String::from_json(buf).map(|(s, size)| (SmolStr::from(s), size))
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for &T {
+    fn to_json(&self, buf: &mut String) {
+        (**self).to_json(buf);
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for Box<T> {
+    fn to_json(&self, buf: &mut String) {
+        (**self).to_json(buf);
+    }
+}
+
+impl<T: FromJson> FromJson for Box<T> {
+    fn from_json(buf: &[u8]) -> Result<(Self, usize)> {
+        T::from_json(buf).map(|(value, size)| (Box::new(value), size))
+    }
+
+    fn missing() -> Option<Self> {
+        T::missing().map(Box::new)
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for Arc<T> {
+    fn to_json(&self, buf: &mut String) {
+        (**self).to_json(buf);
+    }
+}
+
+impl<T: FromJson> FromJson for Arc<T> {
+    fn from_json(buf: &[u8]) -> Result<(Self, usize)> {
+        T::from_json(buf).map(|(value, size)| (Arc::new(value), size))
+    }
+
+    fn missing() -> Option<Self> {
+        T::missing().map(Arc::new)
+    }
+}
+
+//None 输出 null derive 的字段不存在的时候是 None
+impl<T: ToJson> ToJson for Option<T> {
     fn to_json(&self, buf: &mut String) {
         match self {
-            Self::Bool(b) => if *b { buf.push_str("true") } else { buf.push_str("false") }
-            Self::Double(f) => buf.push_str(&f.to_string()),
-            Self::Int(i) => i.to_json(buf),
-            Self::Null => buf.push_str("null"),
-            Self::String(s) => s.as_str().to_json(buf),
-            Self::Vec(a) => {
-                buf.push('[');
-                let mut once = super::ZOnce::new("", ",\n");
-                a.read().unwrap().iter().for_each(|item| {
-                    buf.push_str(once.take());
-                    item.to_json(buf);
-                });
-                buf.push(']');
+            Some(value)=> value.to_json(buf),
+            None=> buf.push_str("null")
+        }
+    }
+}
+
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(buf: &[u8]) -> Result<(Self, usize)> {
+        let pos = skip_white(buf)?;
+        assert_ok!(buf[pos..].starts_with(b"null"), (None, pos + 4));
+        T::from_json(buf).map(|(value, size)| (Some(value), size))
+    }
+
+    fn missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn to_json(&self, buf: &mut String) {
+        array_begin(buf, self.len());
+        self.iter().enumerate().for_each(|(index, item)| {
+            array_item(buf, index);
+            item.to_json(buf);
+        });
+        array_end(buf);
+    }
+}
+
+impl<T: ToJson, const N: usize> ToJson for [T; N] {
+    fn to_json(&self, buf: &mut String) {
+        self.as_slice().to_json(buf);
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self, buf: &mut String) {
+        self.as_slice().to_json(buf);
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json(buf: &[u8]) -> Result<(Self, usize)> {
+        let mut result = Vec::new();
+        let (_, size) = read_array(buf, |_, item| {
+            let (value, size) = T::from_json(item)?;
+            result.push(value);
+            Ok(size)
+        })?;
+        Ok((result, size))
+    }
+}
+
+impl<T: FromJson, const N: usize> FromJson for [T; N] {
+    fn from_json(buf: &[u8]) -> Result<(Self, usize)> {
+        let (result, size) = Vec::<T>::from_json(buf)?;
+        let len = result.len();
+        let array = result.try_into().map_err(|_| anyhow!("need {} items but got {}", N, len))?;
+        Ok((array, size))
+    }
+}
+
+//元组是定长的 array
+macro_rules! tuple_json {
+    ($len: expr, $($name:ident $index:tt),+) => {
+        impl<$($name: ToJson),+> ToJson for ($($name,)+) {
+            fn to_json(&self, buf: &mut String) {
+                array_begin(buf, $len);
+                $(
+                    array_item(buf, $index);
+                    self.$index.to_json(buf);
+                )+
+                array_end(buf);
             }
-            Self::Map(m) => {
-                buf.push('{');
-                let mut once = super::ZOnce::new("", ",\n");
-                m.read().unwrap().iter().for_each(|(k, v)| {
-                    buf.push_str(once.take());
-                    k.as_str().to_json(buf);
-                    buf.push_str(": ");
-                    v.to_json(buf);
-                });
-                buf.push('}');
-            },
-            _=> buf.push_str("null")
-        }
-    }
-}
\ No newline at end of file
+        }
+
+        impl<$($name: FromJson),+> FromJson for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn from_json(buf: &[u8]) -> Result<(Self, usize)> {
+                $( let mut $name = None; )+
+                let (count, size) = read_array(buf, |index, item| match index {
+                    $( $index=> {
+                        let (value, size) = $name::from_json(item)?;
+                        $name = Some(value);
+                        Ok(size)
+                    } )+
+                    _=> skip_value(item)
+                })?;
+                assert_err!(count != $len, anyhow!("need {} items but got {}", $len, count));
+                Ok((($($name.ok_or(anyhow!("no data"))?,)+), size))
+            }
+        }
+    };
+}
+
+tuple_json!(1, A 0);
+tuple_json!(2, A 0, B 1);
+tuple_json!(3, A 0, B 1, C 2);
+tuple_json!(4, A 0, B 1, C 2, D 3);
+tuple_json!(5, A 0, B 1, C 2, D 3, E 4);
+tuple_json!(6, A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_json!(7, A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_json!(8, A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+//json 的 key 只能是字符串 所以 map 的 key 用 Display 输出 FromStr 解析 (整数 key 和 serde_json 一样变成字符串)
+fn write_map<'a, K: Display + 'a, V: ToJson + 'a, I: Iterator<Item = (&'a K, &'a V)>>(buf: &mut String, len: usize, items: I) {
+    object_begin(buf, len);
+    items.enumerate().for_each(|(index, (k, v))| {
+        object_key(buf, index, &k.to_string());
+        v.to_json(buf);
+    });
+    object_end(buf);
+}
+
+fn read_map<K: FromStr, V: FromJson, F: FnMut(K, V)>(buf: &[u8], mut insert: F) -> Result<usize> where K::Err: Display {
+    read_object(buf, |key, item| {
+        let key = key.parse::<K>().map_err(|err| anyhow!("invalid key {}: {}", key, err))?;
+        let (value, size) = V::from_json(item)?;
+        insert(key, value);
+        Ok(Some(size))
+    })
+}
+
+impl<K: Display, V: ToJson, S> ToJson for HashMap<K, V, S> {
+    fn to_json(&self, buf: &mut String) {
+        write_map(buf, self.len(), self.iter());
+    }
+}
+
+impl<K: FromStr + Eq + Hash, V: FromJson, S: BuildHasher + Default> FromJson for HashMap<K, V, S> where K::Err: Display {
+    fn from_json(buf: &[u8]) -> Result<(Self, usize)> {
+        let mut result = HashMap::default();
+        let size = read_map(buf, |k, v| { result.insert(k, v); })?;
+        Ok((result, size))
+    }
+}
+
+impl<K: Display, V: ToJson> ToJson for BTreeMap<K, V> {
+    fn to_json(&self, buf: &mut String) {
+        write_map(buf, self.len(), self.iter());
+    }
+}
+
+impl<K: FromStr + Ord, V: FromJson> FromJson for BTreeMap<K, V> where K::Err: Display {
+    fn from_json(buf: &[u8]) -> Result<(Self, usize)> {
+        let mut result = BTreeMap::new();
+        let size = read_map(buf, |k, v| { result.insert(k, v); })?;
+        Ok((result, size))
+    }
+}
+
+impl ToJson for Dynamic {
+    fn to_json(&self, buf: &mut String) {
+        write_json(self, buf, &mut Vec::new());
+    }
+}
+
+//stack 记录当前路径上的容器 遇到循环引用输出 null
+fn write_json(value: &Dynamic, buf: &mut String, stack: &mut Vec<usize>) {
+    if let Some(id) = value.container_id() {
+        if stack.contains(&id) {
+            buf.push_str("null");
+            return;
+        }
+        stack.push(id);
+    }
+    match value {
+        Dynamic::Bool(b) => if *b { buf.push_str("true") } else { buf.push_str("false") }
+        Dynamic::Byte(v) => v.to_json(buf),
+        Dynamic::Int(i) => i.to_json(buf),
+        Dynamic::UInt(v) => v.to_json(buf),
+        Dynamic::Float(f) => f.to_json(buf),                    //NaN 和无穷大输出 null
+        Dynamic::Double(f) => f.to_json(buf),
+        Dynamic::Null => buf.push_str("null"),
+        Dynamic::String(s) => s.as_str().to_json(buf),
+        Dynamic::Vec(a) => {
+            let (comma, _) = separators();
+            buf.push('[');
+            let mut once = super::ZOnce::new("", comma);
+            a.read().iter().for_each(|item| {
+                buf.push_str(once.take());
+                write_json(item, buf, stack);
+            });
+            buf.push(']');
+        }
+        Dynamic::Map(m) => {
+            let (comma, colon) = separators();
+            buf.push('{');
+            let mut once = super::ZOnce::new("", comma);
+            m.read().iter().for_each(|(k, v)| {
+                buf.push_str(once.take());
+                k.as_str().to_json(buf);
+                buf.push_str(colon);
+                write_json(v, buf, stack);
+            });
+            buf.push('}');
+        },
+        Dynamic::AnyMap(m) => {                                 //json 的 key 只能是字符串 容器 key 写成 json 文本
+            let (comma, colon) = separators();
+            buf.push('{');
+            let mut once = super::ZOnce::new("", comma);
+            m.read().iter().for_each(|(k, v)| {
+                buf.push_str(once.take());
+                k.key_text().as_str().to_json(buf);
+                buf.push_str(colon);
+                write_json(v, buf, stack);
+            });
+            buf.push('}');
+        },
+        Dynamic::Bytes(_) => buf.push_str("null")
+    }
+    if value.container_id().is_some() {
+        stack.pop();
+    }
+}
+
+thread_local! {
+    static COMPACT: Cell<bool> = const { Cell::new(false) };      //write_compact 期间为 true
+}
+
+//(元素之间 key 后面) 的分隔符 默认的输出每个元素一行
+fn separators()-> (&'static str, &'static str) {
+    if COMPACT.get() { (",", ":") } else { (",\n", ": ") }
+}
+
+//panic 的时候也要恢复原来的状态
+struct CompactGuard(bool);
+
+impl Drop for CompactGuard {
+    fn drop(&mut self) {
+        COMPACT.set(self.0);
+    }
+}
+
+//紧凑的 json 没有任何空白 一个值只占一行 (字符串里的换行和控制字符都转义了) 用于 JSON Lines
+//直接写到 buf 里 分隔符由 object_key array_item 输出 所以 derive 的类型也是紧凑的
+pub fn write_compact<T: ToJson + ?Sized>(value: &T, buf: &mut String) {
+    let _guard = CompactGuard(COMPACT.replace(true));
+    value.to_json(buf);
+}
+
+//RFC 8785 (JCS) 规范化输出 用于签名和内容寻址 没有空白 key 按照 UTF-16 编码单元排序
+//数字按照 ECMAScript 的规则输出 整数超过 ±(2^53-1) 时 f64 表示不了 返回错误而不是舍入
+//NaN 无穷大 Bytes 循环引用 以及 AnyMap 转成字符串后重复的 key 返回错误
+pub fn write_canonical(value: &Dynamic, buf: &mut String) -> Result<()> {
+    write_jcs(value, buf, &mut Vec::new())
+}
+
+fn write_jcs(value: &Dynamic, buf: &mut String, stack: &mut Vec<usize>) -> Result<()> {
+    if let Some(id) = value.container_id() {
+        assert_err!(stack.contains(&id), anyhow!("cycle can not be canonical json"));
+        stack.push(id);
+    }
+    match value {
+        Dynamic::Null=> buf.push_str("null"),
+        Dynamic::Bool(b)=> b.to_json(buf),
+        Dynamic::Byte(v)=> buf.push_str(&v.to_string()),
+        Dynamic::Int(v)=> {
+            assert_err!(v.unsigned_abs() > MAX_SAFE_INTEGER, anyhow!("integer {} is out of the canonical json range", v));
+            buf.push_str(&v.to_string());
+        }
+        Dynamic::UInt(v)=> {
+            assert_err!(*v > MAX_SAFE_INTEGER, anyhow!("integer {} is out of the canonical json range", v));
+            buf.push_str(&v.to_string());
+        }
+        Dynamic::Float(v)=> write_jcs_number(*v as f64, buf)?,
+        Dynamic::Double(v)=> write_jcs_number(*v, buf)?,
+        Dynamic::String(s)=> write_jcs_string(s, buf),
+        Dynamic::Bytes(_)=> return Err(anyhow!("bytes can not be canonical json")),
+        Dynamic::Vec(v)=> {
+            buf.push('[');
+            for (index, item) in v.read().iter().enumerate() {
+                if index > 0 {
+                    buf.push(',');
+                }
+                write_jcs(item, buf, stack)?;
+            }
+            buf.push(']');
+        }
+        Dynamic::Map(m)=> {
+            let guard = m.read();
+            let entries: Vec<(&str, &Dynamic)> = guard.iter().map(|(k, v)| (k.as_str(), v)).collect();
+            write_jcs_object(entries, buf, stack)?;
+        }
+        Dynamic::AnyMap(m)=> {
+            let guard = m.read();
+            let mut keys = Vec::with_capacity(guard.len());
+            for (k, _) in guard.iter() {
+                keys.push(k.to_key_string()?);
+            }
+            let entries = keys.iter().map(|k| k.as_str()).zip(guard.iter().map(|(_, v)| v)).collect();
+            write_jcs_object(entries, buf, stack)?;
+        }
+    }
+    if value.container_id().is_some() {
+        stack.pop();
+    }
+    Ok(())
+}
+
+fn write_jcs_object(mut entries: Vec<(&str, &Dynamic)>, buf: &mut String, stack: &mut Vec<usize>) -> Result<()> {
+    entries.sort_by(|a, b| a.0.encode_utf16().cmp(b.0.encode_utf16()));
+    buf.push('{');
+    for (index, (key, value)) in entries.iter().enumerate() {
+        if index > 0 {
+            assert_err!(entries[index - 1].0 == *key, anyhow!("duplicate key {:?}", key));
+            buf.push(',');
+        }
+        write_jcs_string(key, buf);
+        buf.push(':');
+        write_jcs(value, buf, stack)?;
+    }
+    buf.push('}');
+    Ok(())
+}
+
+//只转义 " \ 和控制字符 其他字符原样输出
+fn write_jcs_string(s: &str, buf: &mut String) {
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"'=> buf.push_str("\\\""),
+            '\\'=> buf.push_str("\\\\"),
+            '\u{8}'=> buf.push_str("\\b"),
+            '\u{c}'=> buf.push_str("\\f"),
+            '\n'=> buf.push_str("\\n"),
+            '\r'=> buf.push_str("\\r"),
+            '\t'=> buf.push_str("\\t"),
+            c if (c as u32) < 0x20=> buf.push_str(&format!("\\u{:04x}", c as u32)),
+            c=> buf.push(c)
+        }
+    }
+    buf.push('"');
+}
+
+//ECMAScript 的 Number.prototype.toString 先取最短的能还原的十进制数字 再按照指数决定格式
+fn write_jcs_number(value: f64, buf: &mut String) -> Result<()> {
+    assert_err!(!value.is_finite(), anyhow!("{} can not be canonical json", value));
+    if value == 0.0 {                               //包括 -0
+        buf.push('0');
+        return Ok(());
+    }
+    if value < 0.0 {
+        buf.push('-');
+    }
+    let scientific = format!("{:e}", value.abs());
+    let (mantissa, exponent) = scientific.split_once('e').ok_or(anyhow!("invalid number {}", scientific))?;
+    let mut digits: String = mantissa.chars().filter(|c| *c != '.').collect();   //{:e} 是最短的能还原的数字
+    if let Some(even) = tie_to_even(value.abs(), &digits, exponent)? {
+        digits = even;
+    }
+    let k = digits.len() as i32;
+    let n = exponent.parse::<i32>()? + 1;           //value = 0.digits * 10^n
+    if k <= n && n <= 21 {
+        buf.push_str(&digits);
+        (k..n).for_each(|_| buf.push('0'));
+    } else if 0 < n && n <= 21 {
+        buf.push_str(&digits[..n as usize]);
+        buf.push('.');
+        buf.push_str(&digits[n as usize..]);
+    } else if -6 < n && n <= 0 {
+        buf.push_str("0.");
+        (n..0).for_each(|_| buf.push('0'));
+        buf.push_str(&digits);
+    } else {
+        buf.push_str(&digits[..1]);
+        if k > 1 {
+            buf.push('.');
+            buf.push_str(&digits[1..]);
+        }
+        buf.push_str(&format!("e{}{}", if n > 0 { "+" } else { "-" }, (n - 1).abs()));
+    }
+    Ok(())
+}
+
+//最短的数字有两个一样接近的候选时 ECMAScript 取偶数 Rust 取的可能是奇数
+//只有最后一位是奇数 并且相邻的偶数也能还原的时候才用精确展开判断是不是正好在中间
+fn tie_to_even(value: f64, digits: &str, exponent: &str) -> Result<Option<String>> {
+    let last = digits.as_bytes()[digits.len() - 1] - b'0';
+    if last.is_multiple_of(2) {
+        return Ok(None);
+    }
+    let head = &digits[..digits.len() - 1];
+    for even in [last - 1, last + 1].into_iter().filter(|d| *d <= 9) {
+        let candidate = format!("{}{}", head, even);
+        if format!("{}e{}", candidate, exponent.parse::<i32>()? + 1 - digits.len() as i32).parse::<f64>().ok() != Some(value) {
+            continue;
+        }
+        let middle = format!("{}{}5", head, last.min(even));
+        let exact = format!("{:.800e}", value);
+        let (mantissa, _) = exact.split_once('e').ok_or(anyhow!("invalid number {}", exact))?;
+        let exact_digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+        if exact_digits.starts_with(&middle) && exact_digits[middle.len()..].bytes().all(|b| b == b'0') {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+//下面是 derive 宏生成的代码使用的辅助函数 msgpack 模块里有同名的一组 写入 object 和 array 时不需要关心分隔符
+pub fn object_begin(buf: &mut String, _length: usize) {
+    buf.push('{');
+}
+
+pub fn object_key(buf: &mut String, index: usize, key: &str) {
+    let (comma, colon) = separators();
+    if index > 0 {
+        buf.push_str(comma);
+    }
+    key.to_json(buf);
+    buf.push_str(colon);
+}
+
+pub fn object_end(buf: &mut String) {
+    buf.push('}');
+}
+
+pub fn array_begin(buf: &mut String, _length: usize) {
+    buf.push('[');
+}
+
+pub fn array_item(buf: &mut String, index: usize) {
+    if index > 0 {
+        buf.push_str(separators().0);
+    }
+}
+
+pub fn array_end(buf: &mut String) {
+    buf.push(']');
+}
+
+pub fn write_str(buf: &mut String, s: &str) {
+    s.to_json(buf);
+}
+
+pub fn write_null(buf: &mut String) {
+    buf.push_str("null");
+}
+
+//跳过一个值 返回长度
+pub fn skip_value(buf: &[u8]) -> Result<usize> {
+    Dynamic::from_json(buf).map(|(_, size)| size)
+}
+
+//遍历 object 的每个 key field 返回 Some(长度) 表示已经解析了 value 返回 None 的 value 会被跳过
+pub fn read_object<F: FnMut(&str, &[u8]) -> Result<Option<usize>>>(buf: &[u8], mut field: F) -> Result<usize> {
+    let mut pos = skip_white(buf)?;
+    assert_err!(buf[pos] != b'{', anyhow!("need a object"));
+    pos += 1;
+    pos += skip_white(&buf[pos..])?;
+    while buf[pos] != b'}' {
+        assert_err!(buf[pos] != b'"', anyhow!("need a string key"));
+        let (key, size) = Dynamic::get_string(&buf[pos..])?;
+        pos += size;
+        pos += skip_white(&buf[pos..])?;
+        assert_err!(buf[pos] != b':', anyhow!("need a :"));
+        pos += 1;
+        pos += match field(&key, &buf[pos..])? {
+            Some(size)=> size,
+            None=> skip_value(&buf[pos..])?
+        };
+        pos += skip_white(&buf[pos..])?;
+        if buf[pos] == b',' {
+            pos += 1;
+            pos += skip_white(&buf[pos..])?;
+        }
+    }
+    Ok(pos + 1)
+}
+
+//遍历 array 的每个元素 返回元素个数和长度
+pub fn read_array<F: FnMut(usize, &[u8]) -> Result<usize>>(buf: &[u8], mut item: F) -> Result<(usize, usize)> {
+    let mut pos = skip_white(buf)?;
+    assert_err!(buf[pos] != b'[', anyhow!("need a array"));
+    pos += 1;
+    pos += skip_white(&buf[pos..])?;
+    let mut count = 0usize;
+    while buf[pos] != b']' {
+        pos += item(count, &buf[pos..])?;
+        count += 1;
+        pos += skip_white(&buf[pos..])?;
+        if buf[pos] == b',' {
+            pos += 1;
+            pos += skip_white(&buf[pos..])?;
+        }
+    }
+    Ok((count, pos + 1))
+}
+
+//内部标签的 enum 先取出标签的值 返回整个 object 的长度
+pub fn read_tag(buf: &[u8], tag: &str) -> Result<(SmolStr, usize)> {
+    let (value, size) = Dynamic::from_json(buf)?;
+    let name = value.at(tag).into_string().map_err(|_| anyhow!("tag {} is not found", tag))?;
+    Ok((name, size))
+}
+
+//外部标签的 enum 单元变体是字符串 其他是只有一个 key 的 object 返回变体名 内容的偏移 和总长度
+pub fn read_variant(buf: &[u8]) -> Result<(SmolStr, Option<usize>, usize)> {
+    let pos = skip_white(buf)?;
+    if buf[pos] == b'"' {
+        let (name, size) = Dynamic::get_string(&buf[pos..])?;
+        return Ok((SmolStr::from(name), None, pos + size));
+    }
+    let mut variant = None;
+    let size = read_object(buf, |key, value| {
+        assert_err!(variant.is_some(), anyhow!("enum object must have only one key"));
+        variant = Some((SmolStr::new(key), buf.len() - value.len()));
+        Ok(None)
+    })?;
+    let (name, offset) = variant.ok_or(anyhow!("enum object is empty"))?;
+    Ok((name, Some(offset), size))
+}
+
+//相邻标签的 enum {tag: 变体名, content: 内容} 返回变体名 内容的偏移 和总长度
+pub fn read_adjacent(buf: &[u8], tag: &str, content: &str) -> Result<(SmolStr, Option<usize>, usize)> {
+    let mut name = None;
+    let mut offset = None;
+    let size = read_object(buf, |key, value| {
+        if key == tag {
+            let (v, size) = Dynamic::from_json(value)?;
+            name = Some(v.into_string()?);
+            return Ok(Some(size));
+        }
+        if key == content {
+            offset = Some(buf.len() - value.len());
+        }
+        Ok(None)
+    })?;
+    Ok((name.ok_or(anyhow!("tag {} is not found", tag))?, offset, size))
+}
+
+//单元结构体和 untagged 的单元变体是 null 返回长度
+pub fn read_null(buf: &[u8]) -> Result<usize> {
+    let pos = skip_white(buf)?;
+    let (token, size) = Dynamic::get_token(&buf[pos..])?;
+    assert_err!(token != "null", anyhow!("need a null"));
+    Ok(pos + size)
+}
+
+pub fn missing_field(name: &str) -> anyhow::Error {
+    anyhow!("missing field {}", name)
+}
+
+pub fn unknown_variant(name: &str) -> anyhow::Error {
+    anyhow!("unknown variant {}", name)
+}
@@ -0,0 +1,151 @@
+use anyhow::{anyhow, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use super::dynamic::Dynamic;
+use super::json::{write_compact, FromJson, ToJson};
+use super::assert_err;
+
+//读 JSON Lines 的选项
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    pub continue_on_error: bool,                    //坏的行返回错误以后继续读 默认第一个错误以后结束
+}
+
+//每一行解析成一个 T 空行跳过 错误信息里带上行号 行号从 1 开始
+pub struct Reader<R, T = Dynamic> {
+    reader: R,
+    options: ReadOptions,
+    line: usize,
+    buf: Vec<u8>,
+    finished: bool,
+    _marker: PhantomData<T>,
+}
+
+impl<T: FromJson> Reader<BufReader<File>, T> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead, T: FromJson> Reader<R, T> {
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, ReadOptions::default())
+    }
+
+    pub fn with_options(reader: R, options: ReadOptions) -> Self {
+        Self { reader, options, line: 0, buf: Vec::new(), finished: false, _marker: PhantomData }
+    }
+
+    pub fn line(&self) -> usize {                  //最后读到的行号
+        self.line
+    }
+}
+
+impl<R: BufRead, T: FromJson> Iterator for Reader<R, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            self.buf.clear();
+            match self.reader.read_until(b'\n', &mut self.buf) {
+                Ok(0)=> self.finished = true,
+                Ok(_)=> {
+                    self.line += 1;
+                    let mut line = self.buf.as_slice();
+                    if self.line == 1 {
+                        line = line.strip_prefix(b"\xef\xbb\xbf").unwrap_or(line);      //去掉 BOM
+                    }
+                    if line.iter().all(u8::is_ascii_whitespace) {
+                        continue;
+                    }
+                    let result = parse_line(line, self.line);
+                    self.finished = result.is_err() && !self.options.continue_on_error;
+                    return Some(result);
+                }
+                Err(e)=> {
+                    self.finished = true;
+                    return Some(Err(anyhow!("line {}: {}", self.line + 1, e)));
+                }
+            }
+        }
+        None
+    }
+}
+
+fn parse_line<T: FromJson>(line: &[u8], number: usize) -> Result<T> {
+    let (value, size) = T::from_json(line).map_err(|e| anyhow!("line {}: {}", number, e))?;
+    assert_err!(!line[size.min(line.len())..].iter().all(u8::is_ascii_whitespace), anyhow!("line {}: unexpected content after the value", number));
+    Ok(value)
+}
+
+//整个内容在换行处切成 threads 块并行解析 结果和 Reader 一样按照行的顺序 threads 为 0 时使用 CPU 的个数
+pub fn parse_parallel<T: FromJson + Send>(data: &[u8], threads: usize) -> Vec<Result<T>> {
+    let threads = match threads {
+        0=> std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n=> n
+    };
+    let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+    let mut chunks = Vec::new();                    //每一块的内容和第一行的行号
+    let (mut start, mut line) = (0, 1);
+    let size = data.len().div_ceil(threads).max(1);
+    while start < data.len() {
+        let target = (start + size).min(data.len());
+        let end = data[target..].iter().position(|c| *c == b'\n').map_or(data.len(), |p| target + p + 1);
+        chunks.push((&data[start..end], line));
+        line += data[start..end].iter().filter(|c| **c == b'\n').count();
+        start = end;
+    }
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = chunks.into_iter().map(|(chunk, first)| scope.spawn(move || {
+            chunk.split(|c| *c == b'\n').enumerate()
+                .filter(|(_, line)| !line.iter().all(u8::is_ascii_whitespace))
+                .map(|(index, line)| parse_line(line, first + index))
+                .collect::<Vec<_>>()
+        })).collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap_or_else(|_| vec![Err(anyhow!("parse thread panicked"))])).collect()
+    })
+}
+
+//每条记录写成一行紧凑的 json
+pub struct Writer<W: Write> {
+    writer: W,
+    line: String,
+    count: usize,
+}
+
+impl Writer<BufWriter<File>> {
+    //追加到文件的末尾 文件不存在就建立
+    pub fn append<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::new(BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?)))
+    }
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, line: String::new(), count: 0 }
+    }
+
+    pub fn write<T: ToJson + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.line.clear();
+        write_compact(value, &mut self.line);
+        self.line.push('\n');
+        self.writer.write_all(self.line.as_bytes())?;
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn count(&self) -> usize {                 //已经写了多少条记录
+        self.count
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+
+    pub fn into_inner(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
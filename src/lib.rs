@@ -1,8 +1,27 @@
 pub mod dynamic;
+pub mod iter;
+pub mod index;
+pub mod map;
+pub mod fingerprint;
+pub mod schema;
 pub mod json;
+pub mod jsonl;
 pub mod msgpack;
+pub mod cbor;
+pub mod bson;
+pub mod yaml;
+pub mod toml;
+pub mod csv;
+pub mod xml;
+pub mod safetensors;
+pub mod gguf;
+pub mod npy;
 
-use anyhow::{Result, anyhow};
+#[cfg(feature = "derive")]
+pub use libai_derive::{ToolSchema, ToJson, FromJson, MsgPack, MsgUnpack};
+pub use ::anyhow;                       //derive 宏生成的代码用到
+
+use ::anyhow::{Result, anyhow};
 pub fn skip_white(buf: &[u8]) -> Result<usize> {
     let mut pos = 0usize;
     while pos < buf.len() && (buf[pos] == b' ' || buf[pos] == b'\r' || buf[pos] == b'\t' || buf[pos] == b'\n') {
@@ -50,7 +69,7 @@ macro_rules! assert_err {
 #[macro_export]
 macro_rules! dmap {
     ($($k:expr => $v:expr), *) => {{
-        let mut map = std::collections::BTreeMap::new();
+        let mut map = $crate::dynamic::DynamicMap::new();
         $( let _ = map.insert(smol_str::SmolStr::from($k), Dynamic::from($v)); )*
         Dynamic::from_map(map)
     }};
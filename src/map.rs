@@ -0,0 +1,331 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use parking_lot::RwLockWriteGuard;
+use smol_str::SmolStr;
+use anyhow::{Result, anyhow};
+use super::dynamic::{Dynamic, DynamicMap, map_remove};
+
+//持有写锁的 entry 返回的值是 clone 出来的 (容器只是 Arc 计数加一)
+pub struct Entry<'a> {
+    guard: RwLockWriteGuard<'a, DynamicMap>,
+    key: SmolStr,
+}
+
+impl Entry<'_> {
+    pub fn key(&self)-> &str {
+        self.key.as_str()
+    }
+
+    pub fn get(&self)-> Option<Dynamic> {
+        self.guard.get(&self.key).cloned()
+    }
+
+    pub fn or_insert<T: Into<Dynamic>>(self, default: T)-> Dynamic {
+        self.or_insert_with(|| default.into())
+    }
+
+    pub fn or_insert_with<F: FnOnce()-> Dynamic>(mut self, f: F)-> Dynamic {
+        self.guard.entry(self.key).or_insert_with(f).clone()
+    }
+
+    pub fn or_default(self)-> Dynamic {
+        self.or_insert_with(Dynamic::default)
+    }
+
+    pub fn and_modify<F: FnOnce(&mut Dynamic)>(mut self, f: F)-> Self {
+        if let Some(value) = self.guard.get_mut(&self.key) {
+            f(value);
+        }
+        self
+    }
+}
+
+//合并两个 Map 时同一个 key 都有值 (且不都是 Map) 的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    Overwrite,              //用 other 的值覆盖
+    Keep,                   //保留原来的值
+    ConcatVec,              //都是 Vec 的时候连接起来 否则覆盖
+    Error,                  //返回错误
+}
+
+impl Dynamic {
+    pub fn entry(&self, key: &str)-> Result<Entry<'_>> {
+        match self {
+            Self::Map(m)=> Ok(Entry { guard: m.write(), key: SmolStr::new(key) }),
+            _=> Err(anyhow!("is not a Map"))
+        }
+    }
+
+    //不存在的时候返回 default 不会插入
+    pub fn get_or_default<T: Into<Dynamic>>(&self, key: &str, default: T)-> Result<Dynamic> {
+        match self {
+            Self::Map(m)=> Ok(m.read().get(key).cloned().unwrap_or_else(|| default.into())),
+            _=> Err(anyhow!("is not a Map"))
+        }
+    }
+
+    //from 不存在返回 false to 已经存在返回错误
+    pub fn rename_key(&self, from: &str, to: &str)-> Result<bool> {
+        match self {
+            Self::Map(m)=> {
+                let mut m = m.write();
+                if !m.contains_key(from) {
+                    return Ok(false);
+                }
+                if from != to {
+                    if m.contains_key(to) {
+                        return Err(anyhow!("key {} is existed", to));
+                    }
+                    #[cfg(not(feature = "preserve_order"))]
+                    {
+                        let value = map_remove(&mut m, from).unwrap_or_default();
+                        m.insert(SmolStr::new(to), value);
+                    }
+                    #[cfg(feature = "preserve_order")]
+                    {
+                        let index = m.get_index_of(from).unwrap_or_default();         //保持原来的位置
+                        let value = map_remove(&mut m, from).unwrap_or_default();
+                        m.shift_insert(index, SmolStr::new(to), value);
+                    }
+                }
+                Ok(true)
+            },
+            _=> Err(anyhow!("is not a Map"))
+        }
+    }
+
+    //retain 只能看到 value 这里同时传入 key
+    pub fn retain_entries<F: FnMut(&str, &Dynamic)-> bool>(&self, mut f: F)-> Result<()> {
+        match self {
+            Self::Map(m)=> {
+                m.write().retain(|k, v| f(k.as_str(), v));
+                Ok(())
+            },
+            _=> Err(anyhow!("is not a Map"))
+        }
+    }
+
+    pub fn keys_sorted(&self)-> Result<Vec<SmolStr>> {
+        match self {
+            Self::Map(m)=> {
+                let mut keys: Vec<SmolStr> = m.read().keys().cloned().collect();
+                keys.sort();
+                Ok(keys)
+            },
+            _=> Err(anyhow!("is not a Map"))
+        }
+    }
+
+    //复制整棵树 不和原来的共享容器 循环引用的地方变成 Null
+    pub fn deep_clone(&self)-> Dynamic {
+        fn copy(value: &Dynamic, stack: &mut Vec<usize>)-> Dynamic {
+            let Some(id) = value.container_id() else { return value.clone() };
+            if stack.contains(&id) {
+                return Dynamic::Null;
+            }
+            stack.push(id);
+            let result = match value {
+                Dynamic::Vec(v)=> Dynamic::from_vec(v.read().iter().map(|item| copy(item, stack)).collect()),
+                Dynamic::Map(m)=> Dynamic::from_map(m.read().iter().map(|(k, v)| (k.clone(), copy(v, stack))).collect()),
+                Dynamic::AnyMap(m)=> Dynamic::from_pairs(m.read().iter().map(|(k, v)| (copy(k, stack), copy(v, stack))).collect()),
+                _=> value.clone()
+            };
+            stack.pop();
+            result
+        }
+        copy(self, &mut Vec::new())
+    }
+
+    //把 other 递归合并到 self 两边都是 Map 的 key 继续往下合并 其他冲突按照 strategy 处理
+    //合并进来的值都是 deep_clone 的 之后修改 self 不会影响 other
+    //MergeStrategy::Error 遇到冲突就返回 之前已经合并的 key 不会回滚
+    //stack 记录正在合并的 (target, other) 有循环引用时同一对再次出现就不再往下合并
+    pub fn deep_merge(&self, other: &Dynamic, strategy: MergeStrategy)-> Result<()> {
+        fn merge(target: &Dynamic, other: &Dynamic, strategy: MergeStrategy, path: &mut Vec<SmolStr>, stack: &mut Vec<(usize, usize)>)-> Result<()> {
+            let (Dynamic::Map(m), Dynamic::Map(o)) = (target, other) else {
+                return Err(anyhow!("is not a Map"));
+            };
+            let pair = (target.container_id().unwrap_or_default(), other.container_id().unwrap_or_default());
+            if stack.contains(&pair) {
+                return Ok(());
+            }
+            stack.push(pair);
+            let items = o.read().clone();               //先复制出来 self 和 other 是同一个容器时不会死锁
+            for (key, value) in items.iter() {
+                let existed = m.read().get(key).cloned();
+                let merged = match existed {
+                    None=> value.deep_clone(),
+                    Some(current) if current.is_map() && value.is_map()=> {
+                        path.push(key.clone());
+                        merge(&current, value, strategy, path, stack)?;
+                        path.pop();
+                        continue;
+                    }
+                    Some(current)=> match strategy {
+                        MergeStrategy::Overwrite=> value.deep_clone(),
+                        MergeStrategy::Keep=> continue,
+                        MergeStrategy::ConcatVec if current.is_vec() && value.is_vec()=> {
+                            current.extend(value.deep_clone())?;
+                            continue;
+                        }
+                        MergeStrategy::ConcatVec=> value.deep_clone(),
+                        MergeStrategy::Error=> {
+                            path.push(key.clone());
+                            return Err(anyhow!("key /{} is conflicted", path.join("/")));
+                        }
+                    }
+                };
+                m.write().insert(key.clone(), merged);
+            }
+            stack.pop();
+            Ok(())
+        }
+        merge(self, other, strategy, &mut Vec::new(), &mut Vec::new())
+    }
+}
+
+//解析 json msgpack 时 map 出现重复 key 的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    #[default]
+    LastWins,               //后面的覆盖前面的
+    FirstWins,              //保留第一个
+    Error,                  //返回错误 带上 key 和偏移
+    Collect,                //所有的值收集到一个 Vec 里
+}
+
+//map 的 key 不是字符串时的处理方式 (Python Go 等语言生成的 msgpack 经常用整数 二进制 nil 作为 key cbor 的 COSE 也是整数 key)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyPolicy {
+    Strict,                 //返回错误
+    Stringify,              //标量 key 转成字符串 解码为 Map
+    #[default]
+    Preserve,               //解码为 AnyMap 编码时还原原来的 key 类型
+}
+
+//解码器用来构造 map 的辅助结构 offset 是 key 在整个输入里的偏移 用于错误信息
+pub(crate) struct MapBuilder {
+    map: DynamicMap,
+    policy: DuplicatePolicy,
+    collected: HashSet<SmolStr>,
+}
+
+impl MapBuilder {
+    pub(crate) fn new(policy: DuplicatePolicy)-> Self {
+        Self { map: DynamicMap::new(), policy, collected: HashSet::new() }
+    }
+
+    pub(crate) fn insert(&mut self, key: SmolStr, value: Dynamic, offset: usize)-> Result<()> {
+        let Some(existed) = self.map.get_mut(&key) else {
+            self.map.insert(key, value);
+            return Ok(());
+        };
+        match self.policy {
+            DuplicatePolicy::LastWins=> *existed = value,
+            DuplicatePolicy::FirstWins=> {},
+            DuplicatePolicy::Error=> return Err(anyhow!("duplicate key {:?} at offset {}", key.as_str(), offset)),
+            DuplicatePolicy::Collect=> {
+                if self.collected.contains(&key) {
+                    existed.push(value)?;
+                } else {
+                    *existed = Dynamic::from_vec(vec![std::mem::take(existed), value]);
+                    self.collected.insert(key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn build(self)-> Dynamic {
+        Dynamic::from_map(self.map)
+    }
+}
+
+//AnyMap 的 key 按照 total_cmp 排序 用来在 BTreeMap 里查找重复的 key
+struct PairKey(Dynamic);
+
+impl PartialEq for PairKey {
+    fn eq(&self, other: &Self)-> bool {
+        self.0.total_cmp(&other.0) == Ordering::Equal
+    }
+}
+
+impl Eq for PairKey {}
+
+impl PartialOrd for PairKey {
+    fn partial_cmp(&self, other: &Self)-> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PairKey {
+    fn cmp(&self, other: &Self)-> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+//和 MapBuilder 一样 用于 key 不全是字符串的 AnyMap 保持 key 第一次出现的顺序
+struct PairsBuilder {
+    keys: BTreeMap<PairKey, (usize, bool)>,           //value 的下标 是否已经 Collect 成 Vec
+    values: Vec<Dynamic>,
+    policy: DuplicatePolicy,
+}
+
+impl PairsBuilder {
+    fn new(policy: DuplicatePolicy)-> Self {
+        Self { keys: BTreeMap::new(), values: Vec::new(), policy }
+    }
+
+    fn insert(&mut self, key: Dynamic, value: Dynamic, offset: usize)-> Result<()> {
+        let key = PairKey(key);
+        let Some((index, collected)) = self.keys.get_mut(&key) else {
+            self.keys.insert(key, (self.values.len(), false));
+            self.values.push(value);
+            return Ok(());
+        };
+        let existed = &mut self.values[*index];
+        match self.policy {
+            DuplicatePolicy::LastWins=> *existed = value,
+            DuplicatePolicy::FirstWins=> {},
+            DuplicatePolicy::Error=> return Err(anyhow!("duplicate key {:?} at offset {}", key.0, offset)),
+            DuplicatePolicy::Collect=> {
+                if *collected {
+                    existed.push(value)?;
+                } else {
+                    *existed = Dynamic::from_vec(vec![std::mem::take(existed), value]);
+                    *collected = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn build(self)-> Dynamic {
+        let mut keys: Vec<_> = self.keys.into_iter().map(|(key, (index, _))| (index, key.0)).collect();
+        keys.sort_unstable_by_key(|(index, _)| *index);
+        Dynamic::from_pairs(keys.into_iter().map(|(_, key)| key).zip(self.values).collect())
+    }
+}
+
+//kvs 是 (key, value, key 的偏移) msgpack 和 cbor 的解码器共用
+pub(crate) fn build_map(kvs: Vec<(Dynamic, Dynamic, usize)>, keys: KeyPolicy, duplicates: DuplicatePolicy)-> Result<Dynamic> {
+    if let Some((key, _, offset)) = kvs.iter().find(|(k, _, _)| !k.is_string()) {
+        match keys {
+            KeyPolicy::Strict=> return Err(anyhow!("map key {:?} at offset {} is not a string", key, offset)),
+            KeyPolicy::Preserve=> {
+                let mut pairs = PairsBuilder::new(duplicates);
+                for (k, v, offset) in kvs {
+                    pairs.insert(k, v, offset)?;
+                }
+                return Ok(pairs.build());
+            }
+            KeyPolicy::Stringify=> {}
+        }
+    }
+    let mut map = MapBuilder::new(duplicates);
+    for (k, v, offset) in kvs {
+        map.insert(k.to_key_string()?, v, offset)?;
+    }
+    Ok(map.build())
+}
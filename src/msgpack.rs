@@ -2,11 +2,11 @@ pub trait MsgPack {                                 //编码为 msgpakc 格式
     fn encode(&self, buf: &mut Vec<u8>);
 }
 
-use std::collections::BTreeMap;
-
 use byteorder::{BigEndian, WriteBytesExt};
-use smol_str::SmolStr;
-impl MsgPack for &str {
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+impl MsgPack for str {
     fn encode(&self, buf: &mut Vec<u8>) {
         let length = self.len();
         if length < 0x20 {
@@ -28,19 +28,19 @@ impl MsgPack for &str {
 impl MsgPack for i64 {
     fn encode(&self, buf: &mut Vec<u8>) {
         let value = *self;
-        if value >= 0 && value < 128 {
+        if (0..128).contains(&value) {
             buf.push(value as u8);
-        } else if value < 0 && value > -32 {
-            let raw = unsafe { std::mem::transmute::<i8, u8>(value as i8) };
+        } else if (-32..0).contains(&value) {
+            let raw = value as i8 as u8;
             buf.push(raw);
         } else {
-            if value >= -0x80 && value < 0x80 {
+            if (-0x80..0x80).contains(&value) {
                 buf.push(0xd0);
                 buf.write_i8(value as i8).unwrap();
-            } else if value >= -0x8000 && value < 0x8000 {
+            } else if (-0x8000..0x8000).contains(&value) {
                 buf.push(0xd1);
                 buf.write_i16::<BigEndian>(value as i16).unwrap();
-            } else if value >= -0x8000_0000 && value < 0x8000_0000 {
+            } else if (-0x8000_0000..0x8000_0000).contains(&value) {
                 buf.push(0xd2);
                 buf.write_i32::<BigEndian>(value as i32).unwrap();
             } else {
@@ -51,83 +51,317 @@ impl MsgPack for i64 {
     }
 }
 
+//无符号数用 uint 格式 其他整数类型转成 i64 或者 u64 编码
+impl MsgPack for u64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let value = *self;
+        if value < 0x80 {
+            buf.push(value as u8);
+        } else if value < 0x100 {
+            buf.push(0xcc);
+            buf.push(value as u8);
+        } else if value < 0x10000 {
+            buf.push(0xcd);
+            buf.write_u16::<BigEndian>(value as u16).unwrap();
+        } else if value < 0x1_0000_0000 {
+            buf.push(0xce);
+            buf.write_u32::<BigEndian>(value as u32).unwrap();
+        } else {
+            buf.push(0xcf);
+            buf.write_u64::<BigEndian>(value).unwrap();
+        }
+    }
+}
+
+macro_rules! int_pack {
+    ($base: ty, $($t:ty),*) => {$(
+        impl MsgPack for $t {
+            fn encode(&self, buf: &mut Vec<u8>) {
+                (*self as $base).encode(buf);
+            }
+        }
+    )*};
+}
+
+int_pack!(i64, i8, i16, i32, isize);
+int_pack!(u64, u8, u16, u32, usize);
+
+impl MsgPack for f32 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(0xca);
+        buf.write_u32::<BigEndian>(self.to_bits()).unwrap();
+    }
+}
+
+impl MsgPack for f64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(0xcb);
+        buf.write_u64::<BigEndian>(self.to_bits()).unwrap();
+    }
+}
+
+impl MsgPack for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(if *self { 0xc3 } else { 0xc2 });
+    }
+}
+
+impl MsgPack for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_str().encode(buf);
+    }
+}
+
+impl MsgPack for SmolStr {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_str().encode(buf);
+    }
+}
+
+impl<T: MsgPack + ?Sized> MsgPack for &T {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (**self).encode(buf);
+    }
+}
+
+impl<T: MsgPack + ?Sized> MsgPack for Box<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (**self).encode(buf);
+    }
+}
+
+impl<T: MsgPack + ?Sized> MsgPack for Arc<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (**self).encode(buf);
+    }
+}
+
+impl<T: MsgPack> MsgPack for Option<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Some(value)=> value.encode(buf),
+            None=> buf.push(0xc0)
+        }
+    }
+}
+
+impl<T: MsgPack> MsgPack for [T] {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        write_array_header(buf, self.len());
+        self.iter().for_each(|item| item.encode(buf));
+    }
+}
+
+impl<T: MsgPack, const N: usize> MsgPack for [T; N] {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_slice().encode(buf);
+    }
+}
+
+impl<T: MsgPack> MsgPack for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_slice().encode(buf);
+    }
+}
+
+impl<K: MsgPack, V: MsgPack, S> MsgPack for HashMap<K, V, S> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        write_map_header(buf, self.len());
+        self.iter().for_each(|(k, v)| {
+            k.encode(buf);
+            v.encode(buf);
+        });
+    }
+}
+
+impl<K: MsgPack, V: MsgPack> MsgPack for BTreeMap<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        write_map_header(buf, self.len());
+        self.iter().for_each(|(k, v)| {
+            k.encode(buf);
+            v.encode(buf);
+        });
+    }
+}
+
 use super::dynamic::Dynamic;
+use super::map::{DuplicatePolicy, build_map};
+pub use super::map::KeyPolicy;
+use smol_str::SmolStr;
 
 impl MsgPack for Dynamic {
     fn encode(&self, buf: &mut Vec<u8>) {
-        match self {
-            Dynamic::Null=> buf.push(0xc0),
-            Dynamic::Bool(b)=> buf.push(if *b { 0xc3 } else { 0xc2 }),
-            Dynamic::Byte(b) => {
-                buf.push(0xcc);
-                buf.push(*b as u8);
-            }
-            Dynamic::Int(v) => v.encode(buf),
-            Dynamic::UInt(v)=> (*v as i64).encode(buf),                 //rune 脚本语言的 Value 不支持 u64 所以我们按照 i64 处理
-            Dynamic::Double(v) => {
-                buf.push(0xcb);
-                let int_value = unsafe { std::mem::transmute::<f64, u64>(*v) };
-                buf.write_u64::<BigEndian>(int_value).unwrap();
-            }
-            Dynamic::Float(v) => {                                      //Value 不支持 f32 按照 f64 处理
-                buf.push(0xcb);
-                let int_value = unsafe { std::mem::transmute::<f64, u64>(*v as f64) };
-                buf.write_u64::<BigEndian>(int_value).unwrap();
-            }
-            Dynamic::String(s) => s.as_str().encode(buf),
-            Dynamic::Bytes(raw) => {
-                let length = raw.len();
-                if length < 0x100 {
-                    buf.push(0xc4);
-                    buf.push(length as u8);
-                } else if length < 0x10000 {
-                    buf.push(0xc5);
-                    buf.write_u16::<BigEndian>(length as u16).unwrap();
-                } else {
-                    buf.push(0xc6);
-                    buf.write_u32::<BigEndian>(length as u32).unwrap();
-                }
-                buf.extend_from_slice(raw.as_slice());
-            }
-            Dynamic::Vec(raw) => {
-                let length = raw.read().unwrap().len();
-                if length < 0x10 {
-                      buf.push(0x90 | length as u8);
-                } else if length < 0x10000 {
-                    buf.push(0xdc);
-                    buf.write_u16::<BigEndian>(length as u16).unwrap();
-                } else {
-                    buf.push(0xdd);
-                    buf.write_u32::<BigEndian>(length as u32).unwrap();
-                }
-                raw.read().unwrap().iter().for_each(|item| item.encode(buf));
+        encode_dynamic(self, buf, &mut Vec::new());
+    }
+}
+
+//stack 记录当前路径上的容器 遇到循环引用编码为 nil
+fn encode_dynamic(value: &Dynamic, buf: &mut Vec<u8>, stack: &mut Vec<usize>) {
+    if let Some(id) = value.container_id() {
+        if stack.contains(&id) {
+            buf.push(0xc0);
+            return;
+        }
+        stack.push(id);
+    }
+    match value {
+        Dynamic::Null=> buf.push(0xc0),
+        Dynamic::Bool(b)=> buf.push(if *b { 0xc3 } else { 0xc2 }),
+        Dynamic::Byte(b) => {
+            buf.push(0xcc);
+            buf.push(*b);
+        }
+        Dynamic::Int(v) => v.encode(buf),
+        Dynamic::UInt(v)=> v.encode(buf),                           //超过 i64 的值用 uint64 解码回来还是 UInt
+        Dynamic::Double(v) => {
+            buf.push(0xcb);
+            let int_value = v.to_bits();
+            buf.write_u64::<BigEndian>(int_value).unwrap();
+        }
+        Dynamic::Float(v) => {                                      //Value 不支持 f32 按照 f64 处理
+            buf.push(0xcb);
+            let int_value = (*v as f64).to_bits();
+            buf.write_u64::<BigEndian>(int_value).unwrap();
+        }
+        Dynamic::String(s) => s.as_str().encode(buf),
+        Dynamic::Bytes(raw) => {
+            let length = raw.len();
+            if length < 0x100 {
+                buf.push(0xc4);
+                buf.push(length as u8);
+            } else if length < 0x10000 {
+                buf.push(0xc5);
+                buf.write_u16::<BigEndian>(length as u16).unwrap();
+            } else {
+                buf.push(0xc6);
+                buf.write_u32::<BigEndian>(length as u32).unwrap();
             }
-            Dynamic::Map(raw) => {
-                let length = raw.read().unwrap().len();
-                if length < 16 {
-                    buf.push(0x80 | length as u8);
-                } else if length <= 0x10000 {
-                    buf.push(0xde);
-                    buf.write_u16::<BigEndian>(length as u16).unwrap();
-                } else {
-                    buf.push(0xdf);
-                    buf.write_u32::<BigEndian>(length as u32).unwrap();
-                }
-                raw.read().unwrap().iter().for_each(|(k, v)| {
-                    k.as_str().encode(buf);
-                    v.encode(buf);
-                });
+            buf.extend_from_slice(raw.as_slice());
+        }
+        Dynamic::Vec(raw) => {
+            let items = raw.read();                 //只加一次读锁 保证长度和内容一致
+            write_array_header(buf, items.len());
+            items.iter().for_each(|item| encode_dynamic(item, buf, stack));
+        }
+        Dynamic::Map(raw) => {
+            let items = raw.read();
+            write_map_header(buf, items.len());
+            items.iter().for_each(|(k, v)| {
+                k.as_str().encode(buf);
+                encode_dynamic(v, buf, stack);
+            });
+        }
+        Dynamic::AnyMap(raw) => {                       //按照原来的 key 类型编码
+            let items = raw.read();
+            write_map_header(buf, items.len());
+            items.iter().for_each(|(k, v)| {
+                encode_dynamic(k, buf, stack);
+                encode_dynamic(v, buf, stack);
+            });
+        }
+    }
+    if value.container_id().is_some() {
+        stack.pop();
+    }
+}
+
+//规范化的 msgpack 用于签名和内容寻址 整数 字符串 二进制 容器都用最短的格式 非负整数用 uint 格式
+//能无损转成 f32 的浮点数用 float32 map 的 key 按照编码后的字节排序 循环引用和重复的 key 返回错误
+pub fn encode_canonical(value: &Dynamic, buf: &mut Vec<u8>) -> Result<()> {
+    encode_sorted(value, buf, &mut Vec::new())
+}
+
+fn encode_sorted(value: &Dynamic, buf: &mut Vec<u8>, stack: &mut Vec<usize>) -> Result<()> {
+    if let Some(id) = value.container_id() {
+        assert_err!(stack.contains(&id), anyhow!("cycle can not be canonical msgpack"));
+        stack.push(id);
+    }
+    match value {
+        Dynamic::Byte(v)=> v.encode(buf),
+        Dynamic::Int(v) if *v >= 0=> (*v as u64).encode(buf),
+        Dynamic::Int(v)=> v.encode(buf),
+        Dynamic::UInt(v)=> v.encode(buf),
+        Dynamic::Float(v) if v.is_nan()=> f32::NAN.encode(buf),
+        Dynamic::Float(v)=> v.encode(buf),
+        Dynamic::Double(v) if v.is_nan()=> f32::NAN.encode(buf),
+        Dynamic::Double(v) if (*v as f32) as f64 == *v=> (*v as f32).encode(buf),
+        Dynamic::Double(v)=> v.encode(buf),
+        Dynamic::Vec(v)=> {
+            let items = v.read();
+            write_array_header(buf, items.len());
+            for item in items.iter() {
+                encode_sorted(item, buf, stack)?;
             }
         }
+        Dynamic::Map(m)=> {
+            let entries = m.read().iter().map(|(k, v)| (Dynamic::from(k.as_str()), v.clone())).collect();
+            encode_sorted_map(entries, buf, stack)?;
+        }
+        Dynamic::AnyMap(m)=> {
+            let entries = m.read().clone();
+            encode_sorted_map(entries, buf, stack)?;
+        }
+        _=> encode_dynamic(value, buf, stack),
+    }
+    if value.container_id().is_some() {
+        stack.pop();
+    }
+    Ok(())
+}
+
+fn encode_sorted_map(entries: Vec<(Dynamic, Dynamic)>, buf: &mut Vec<u8>, stack: &mut Vec<usize>) -> Result<()> {
+    let mut encoded = Vec::with_capacity(entries.len());
+    for (k, v) in entries.iter() {
+        let mut key = Vec::new();
+        encode_sorted(k, &mut key, stack)?;
+        let mut value = Vec::new();
+        encode_sorted(v, &mut value, stack)?;
+        encoded.push((key, value));
+    }
+    encoded.sort();
+    write_map_header(buf, encoded.len());
+    for (index, (key, value)) in encoded.iter().enumerate() {
+        assert_err!(index > 0 && encoded[index - 1].0 == *key, anyhow!("duplicate key {:?}", Dynamic::decode(key)?.0));
+        buf.extend_from_slice(key);
+        buf.extend_from_slice(value);
+    }
+    Ok(())
+}
+
+pub fn write_array_header(buf: &mut Vec<u8>, length: usize) {
+    if length < 0x10 {
+        buf.push(0x90 | length as u8);
+    } else if length < 0x10000 {
+        buf.push(0xdc);
+        buf.write_u16::<BigEndian>(length as u16).unwrap();
+    } else {
+        buf.push(0xdd);
+        buf.write_u32::<BigEndian>(length as u32).unwrap();
     }
 }
+
+pub fn write_map_header(buf: &mut Vec<u8>, length: usize) {
+    if length < 16 {
+        buf.push(0x80 | length as u8);
+    } else if length < 0x10000 {
+        buf.push(0xde);
+        buf.write_u16::<BigEndian>(length as u16).unwrap();
+    } else {
+        buf.push(0xdf);
+        buf.write_u32::<BigEndian>(length as u32).unwrap();
+    }
+}
+
 use anyhow::{anyhow, Result};
 
 pub trait MsgUnpack: Sized {                        //解码 msgpack 格式的 trait
     fn decode(buf: &[u8]) -> Result<(Self, usize)>;
+    fn missing() -> Option<Self> {                  //derive 解码 map 时字段不存在使用的值 Option 返回 Some(None)
+        None
+    }
     fn decode_array(buf: &[u8], length: usize) -> Result<(Vec<Self>, usize)> {
         let mut cursor = 0usize;
-        let mut result = Vec::with_capacity(length);
+        let mut result = Vec::with_capacity(length.min(buf.len()));
         for _ in 0..length {
             let (value, size) = Self::decode(&buf[cursor..])?;
             result.push(value);
@@ -157,246 +391,624 @@ pub(crate) fn read_64(raw: &[u8]) -> u64 {
     raw[7] as u64 | (raw[6] as u64) << 8 | (raw[5] as u64) << 16 | (raw[4] as u64) << 24 | (raw[3] as u64) << 32 | (raw[2] as u64) << 40 | (raw[1] as u64) << 48 | (raw[0] as u64) << 56
 }
 
-fn vec_to_dynamic(kvs: Vec<Dynamic>) -> Result<Dynamic> {
-    let mut map: BTreeMap<SmolStr, Dynamic> = BTreeMap::new();
-    let mut key: Option<Dynamic> = None;
-    for kv in kvs {
-        if let Some(k) = key.take() {
-            map.insert(k.into_string()?, kv);
-        } else {
-            key = Some(kv);
-        }
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    pub keys: KeyPolicy,
+    pub duplicates: DuplicatePolicy,
+}
+
+//key 都是字符串的时候总是解码为 Map
+fn decode_map(buf: &[u8], base: usize, length: usize, options: &DecodeOptions) -> Result<(Dynamic, usize)> {
+    let mut cursor = 0usize;
+    let mut kvs = Vec::with_capacity(length.min(buf.len()));              //长度来自输入 每个元素至少一个字节
+    for _ in 0..length {
+        let offset = base + cursor;
+        let (key, size) = decode_at(&buf[cursor..], offset, options)?;
+        cursor += size;
+        let (value, size) = decode_at(&buf[cursor..], base + cursor, options)?;
+        cursor += size;
+        kvs.push((key, value, offset));
     }
-    Ok(Dynamic::from_map(map))   
+    Ok((build_map(kvs, options.keys, options.duplicates)?, cursor))
+}
+
+fn decode_items(buf: &[u8], base: usize, length: usize, options: &DecodeOptions) -> Result<(Vec<Dynamic>, usize)> {
+    let mut cursor = 0usize;
+    let mut result = Vec::with_capacity(length.min(buf.len()));
+    for _ in 0..length {
+        let (value, size) = decode_at(&buf[cursor..], base + cursor, options)?;
+        result.push(value);
+        cursor += size;
+    }
+    Ok((result, cursor))
 }
 
 use super::{assert_err, assert_ok};
 
-impl MsgUnpack for Dynamic {
+//整数的各种编码统一读成 i128 再转换成需要的类型
+fn read_integer(buf: &[u8]) -> Result<(i128, usize)> {
+    assert_err!(buf.is_empty(), anyhow!("no data"));
+    let first_byte = buf[0];
+    assert_ok!(first_byte <= 0x7f, (first_byte as i128, 1));
+    assert_ok!(first_byte >= 0xe0, (first_byte as i8 as i128, 1));
+    let size = match first_byte {
+        0xcc | 0xd0=> 2,
+        0xcd | 0xd1=> 3,
+        0xce | 0xd2=> 5,
+        0xcf | 0xd3=> 9,
+        _=> return Err(anyhow!("need a integer but got {}", first_byte))
+    };
+    assert_err!(buf.len() < size, anyhow!("no data"));
+    let raw = &buf[1..];
+    let value = match first_byte {
+        0xcc=> read_8(raw) as i128,
+        0xcd=> read_16(raw) as i128,
+        0xce=> read_32(raw) as i128,
+        0xcf=> read_64(raw) as i128,
+        0xd0=> read_8(raw) as i8 as i128,
+        0xd1=> read_16(raw) as i16 as i128,
+        0xd2=> read_32(raw) as i32 as i128,
+        _=> read_64(raw) as i64 as i128,
+    };
+    Ok((value, size))
+}
+
+macro_rules! int_unpack {
+    ($($t:ty),*) => {$(
+        impl MsgUnpack for $t {
+            fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+                let (value, size) = read_integer(buf)?;
+                let value = <$t>::try_from(value).map_err(|_| anyhow!("{} is out of {}", value, stringify!($t)))?;
+                Ok((value, size))
+            }
+        }
+    )*};
+}
+
+int_unpack!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+//浮点数也接受整数编码
+impl MsgUnpack for f64 {
     fn decode(buf: &[u8]) -> Result<(Self, usize)> {
-        assert_err!(buf.len() < 1, anyhow!("no data"));
-        let first_byte = buf[0];
-        assert_ok!(first_byte <= 0x7f, (Dynamic::from(first_byte as i64), 1));
-        assert_ok!(first_byte >= 0xe0, (Dynamic::from(first_byte as i64 - 256), 1));
-        if first_byte >= 0x80 && first_byte <= 0x8f {
-            let len = (first_byte & 0x0f) as usize;
-            let (value, size) = Self::decode_array(&buf[1..], len * 2)?;
-            return vec_to_dynamic(value).map(|r| (r, 1 + size));
+        assert_err!(buf.is_empty(), anyhow!("no data"));
+        if buf[0] == 0xca {
+            assert_err!(buf.len() < 5, anyhow!("no data"));
+            return Ok((f32::from_bits(read_32(&buf[1..])) as f64, 5));
         }
-        if first_byte >= 0x90 && first_byte <= 0x9f {
-            let len = (first_byte & 0x0f) as usize;
-            let (value, size) = Self::decode_array(&buf[1..], len)?;
-            return Ok((Dynamic::from_vec(value), 1 + size));
+        if buf[0] == 0xcb {
+            assert_err!(buf.len() < 9, anyhow!("no data"));
+            return Ok((f64::from_bits(read_64(&buf[1..])), 9));
         }
+        read_integer(buf).map(|(value, size)| (value as f64, size))
+    }
+}
+
+impl MsgUnpack for f32 {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        f64::decode(buf).map(|(value, size)| (value as f32, size))
+    }
+}
 
-        if first_byte >= 0xa0 && first_byte <= 0xbf {
-            let len = (first_byte & 0x1f) as usize;
-            assert_err!(buf.len() < 1 + len, anyhow!("no data"));
-            return Ok(Dynamic::try_from(&buf[1..1 + len]).map(|r| (r, 1 + len))?);
+impl MsgUnpack for bool {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        match buf.first() {
+            Some(0xc2)=> Ok((false, 1)),
+            Some(0xc3)=> Ok((true, 1)),
+            Some(_)=> Err(anyhow!("need a bool")),
+            None=> Err(anyhow!("no data"))
         }
+    }
+}
 
-        assert_ok!(first_byte == 0xc0, (Dynamic::Null, 1));
-        assert_err!(first_byte == 0xc1, anyhow!("0xc1 never used"));
-        assert_ok!(first_byte == 0xc2, (false.into(), 1));
-        assert_ok!(first_byte == 0xc3, (true.into(), 1));
+//返回字符串和总长度
+fn read_str(buf: &[u8]) -> Result<(&str, usize)> {
+    assert_err!(buf.is_empty(), anyhow!("no data"));
+    let first_byte = buf[0];
+    let (len, head) = if (0xa0..=0xbf).contains(&first_byte) {
+        ((first_byte & 0x1f) as usize, 1)
+    } else if first_byte == 0xd9 && buf.len() >= 2 {
+        (read_8(&buf[1..]) as usize, 2)
+    } else if first_byte == 0xda && buf.len() >= 3 {
+        (read_16(&buf[1..]) as usize, 3)
+    } else if first_byte == 0xdb && buf.len() >= 5 {
+        (read_32(&buf[1..]) as usize, 5)
+    } else {
+        return Err(anyhow!("need a string"));
+    };
+    assert_err!(buf.len() < head + len, anyhow!("no data"));
+    Ok((std::str::from_utf8(&buf[head..head + len])?, head + len))
+}
 
-        if first_byte == 0xc4 {
-            assert_err!(buf.len() < 2, anyhow!("no data"));
-            let len = read_8(&buf[1..]) as usize;
-            assert_err!(buf.len() < 2 + len, anyhow!("no data"));
-            return Ok((Dynamic::from_bytes(buf[2..2 + len].to_vec()), 2 + len));
-        }
+impl MsgUnpack for String {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        read_str(buf).map(|(s, size)| (s.to_string(), size))
+    }
+}
 
-        if first_byte == 0xc5 {
-            assert_err!(buf.len() < 3, anyhow!("no data"));
-            let len = read_16(&buf[1..]) as usize;
-            assert_err!(buf.len() < 2 + len, anyhow!("no data"));
-            return Ok((Dynamic::from_bytes(buf[3..3 + len].to_vec()), 3 + len));
-        }
+impl MsgUnpack for SmolStr {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        read_str(buf).map(|(s, size)| (SmolStr::new(s), size))
+    }
+}
 
-        if first_byte == 0xc6 {
-            assert_err!(buf.len() < 5, anyhow!("no data"));
-            let len = read_32(&buf[1..]) as usize;
-            assert_err!(buf.len() < 5 + len, anyhow!("no data"));
-            return Ok((Dynamic::from_bytes(buf[5..5 + len].to_vec()), 5 + len));
-        }
+impl<T: MsgUnpack> MsgUnpack for Box<T> {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        T::decode(buf).map(|(value, size)| (Box::new(value), size))
+    }
 
-        if first_byte == 0xc7 {
-            assert_err!(buf.len() < 3, anyhow!("no data"));
-            let len = read_8(&buf[1..]) as usize;
-            let _type_id = unsafe { std::mem::transmute::<u8, i8>(buf[2]) };
-            assert_err!(buf.len() < 3 + len, anyhow!("no data"));
-            //let _value = raw[3..3 + len].to_vec();
-            return Ok((Dynamic::Null, 3 + len)); //暂时没实现
-        }
+    fn missing() -> Option<Self> {
+        T::missing().map(Box::new)
+    }
+}
 
-        if first_byte == 0xc8 {
-            assert_err!(buf.len() < 4, anyhow!("no data"));
-            let len = read_16(&buf[1..]) as usize;
-            let _type_id = unsafe { std::mem::transmute::<u8, i8>(buf[3]) };
-            assert_err!(buf.len() < 4 + len, anyhow!("no data"));
-            //let _value = raw[4..4 + len].to_vec();
-            return Ok((Dynamic::Null, 4 + len)); //暂时没实现
-        }
+impl<T: MsgUnpack> MsgUnpack for Arc<T> {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        T::decode(buf).map(|(value, size)| (Arc::new(value), size))
+    }
 
-        if first_byte == 0xc9 {
-            assert_err!(buf.len() < 6, anyhow!("no data"));
-            let len = read_32(&buf[1..]) as usize;
-            let _type_id = unsafe { std::mem::transmute::<u8, i8>(buf[5]) };
-            assert_err!(buf.len() < 6 + len, anyhow!("no data"));
-            //let _value = raw[6..6 + len].to_vec();
-            return Ok((Dynamic::Null, 6 + len)); //暂时没实现
-        }
+    fn missing() -> Option<Self> {
+        T::missing().map(Arc::new)
+    }
+}
 
-        if first_byte == 0xca {
-            assert_err!(buf.len() < 5, anyhow!("no data"));
-            let raw_value = read_32(&buf[1..]) as u32;
-            let value = unsafe { std::mem::transmute::<u32, f32>(raw_value) };
-            return Ok((Dynamic::from(value as f64), 5));
-        }
+//nil 是 None derive 的字段不存在的时候也是 None
+impl<T: MsgUnpack> MsgUnpack for Option<T> {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        assert_ok!(buf.first() == Some(&0xc0), (None, 1));
+        T::decode(buf).map(|(value, size)| (Some(value), size))
+    }
 
-        if first_byte == 0xcb {
-            assert_err!(buf.len() < 9, anyhow!("no data"));
-            let raw_value = read_64(&buf[1..]);
-            let value = unsafe { std::mem::transmute::<u64, f64>(raw_value) };
-            return Ok((Dynamic::from(value), 9));
-        }
+    fn missing() -> Option<Self> {
+        Some(None)
+    }
+}
 
-        if first_byte == 0xcc {
-            assert_err!(buf.len() < 2, anyhow!("no data"));
-            let value = read_8(&buf[1..]);
-            return Ok((Dynamic::from(value as i64), 2));
-        }
+impl<T: MsgUnpack> MsgUnpack for Vec<T> {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        let (length, head) = read_header(buf, false)?;
+        let (result, size) = T::decode_array(&buf[head..], length)?;
+        Ok((result, head + size))
+    }
+}
 
-        if first_byte == 0xcd {
-            assert_err!(buf.len() < 3, anyhow!("no data"));
-            let value = read_16(&buf[1..]);
-            return Ok((Dynamic::from(value as i64), 3));
-        }
+impl<T: MsgUnpack, const N: usize> MsgUnpack for [T; N] {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        let (result, size) = Vec::<T>::decode(buf)?;
+        let len = result.len();
+        let array = result.try_into().map_err(|_| anyhow!("need {} items but got {}", N, len))?;
+        Ok((array, size))
+    }
+}
 
-        if first_byte == 0xce {
-            assert_err!(buf.len() < 5, anyhow!("no data"));
-            let value = read_32(&buf[1..]);
-            return Ok((Dynamic::from(value as i64), 5));
-        }
+fn read_map<K: MsgUnpack, V: MsgUnpack, F: FnMut(K, V)>(buf: &[u8], mut insert: F) -> Result<usize> {
+    let (length, mut cursor) = read_header(buf, true)?;
+    for _ in 0..length {
+        let (key, size) = K::decode(&buf[cursor..])?;
+        cursor += size;
+        let (value, size) = V::decode(&buf[cursor..])?;
+        cursor += size;
+        insert(key, value);
+    }
+    Ok(cursor)
+}
 
-        if first_byte == 0xcf {
-            assert_err!(buf.len() < 9, anyhow!("no data"));
-            let value = read_64(&buf[1..]);
-            return Ok((Dynamic::from(value as i64), 9));
-        }
+impl<K: MsgUnpack + Eq + Hash, V: MsgUnpack, S: BuildHasher + Default> MsgUnpack for HashMap<K, V, S> {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        let mut result = HashMap::default();
+        let size = read_map(buf, |k, v| { result.insert(k, v); })?;
+        Ok((result, size))
+    }
+}
 
-        if first_byte == 0xd0 {
-            assert_err!(buf.len() < 2, anyhow!("no data"));
-            let raw_value = read_8(&buf[1..]) as i8;
-            let value = raw_value as i64; //unsafe { std::mem::transmute::<u64, i64>(raw_value) };
-            return Ok((Dynamic::from(value), 2));
-        }
+impl<K: MsgUnpack + Ord, V: MsgUnpack> MsgUnpack for BTreeMap<K, V> {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        let mut result = BTreeMap::new();
+        let size = read_map(buf, |k, v| { result.insert(k, v); })?;
+        Ok((result, size))
+    }
+}
 
-        if first_byte == 0xd1 {
-            assert_err!(buf.len() < 3, anyhow!("no data"));
-            let raw_value = read_16(&buf[1..]);
-            let value = unsafe { std::mem::transmute::<u16, i16>(raw_value) } as i64;
-            return Ok((Dynamic::from(value), 3));
+//元组是定长的 array
+macro_rules! tuple_pack {
+    ($len: expr, $($name:ident $index:tt),+) => {
+        impl<$($name: MsgPack),+> MsgPack for ($($name,)+) {
+            fn encode(&self, buf: &mut Vec<u8>) {
+                write_array_header(buf, $len);
+                $( self.$index.encode(buf); )+
+            }
         }
 
-        if first_byte == 0xd2 {
-            assert_err!(buf.len() < 5, anyhow!("no data"));
-            let raw_value = read_32(&buf[1..]);
-            let value = unsafe { std::mem::transmute::<u32, i32>(raw_value) } as i64;
-            return Ok((Dynamic::from(value), 5));
+        impl<$($name: MsgUnpack),+> MsgUnpack for ($($name,)+) {
+            fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+                let (length, mut cursor) = read_header(buf, false)?;
+                assert_err!(length != $len, anyhow!("need {} items but got {}", $len, length));
+                let value = ($({
+                    let (value, size) = $name::decode(&buf[cursor..])?;
+                    cursor += size;
+                    value
+                },)+);
+                Ok((value, cursor))
+            }
         }
+    };
+}
 
-        if first_byte == 0xd3 {
-            assert_err!(buf.len() < 9, anyhow!("no data"));
-            let raw_value = read_64(&buf[1..]);
-            let value = unsafe { std::mem::transmute::<u64, i64>(raw_value) };
-            return Ok((Dynamic::from(value), 9));
-        }
+tuple_pack!(1, A 0);
+tuple_pack!(2, A 0, B 1);
+tuple_pack!(3, A 0, B 1, C 2);
+tuple_pack!(4, A 0, B 1, C 2, D 3);
+tuple_pack!(5, A 0, B 1, C 2, D 3, E 4);
+tuple_pack!(6, A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_pack!(7, A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_pack!(8, A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
 
-        if first_byte == 0xd4 {
-            assert_err!(buf.len() < 3, anyhow!("no data"));
-            let _type_id = unsafe { std::mem::transmute::<u8, i8>(buf[1]) };
-            //let _value = raw[2..3].to_vec();
-            return Ok((Dynamic::Null, 3));
-        }
+impl MsgUnpack for Dynamic {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        decode_with(buf, &DecodeOptions::default())
+    }
+}
 
-        if first_byte == 0xd5 {
-            assert_err!(buf.len() < 4, anyhow!("no data"));
-            let _type_id = unsafe { std::mem::transmute::<u8, i8>(buf[1]) };
-            //let _value = raw[2..4].to_vec();
-            return Ok((Dynamic::Null, 4));
-        }
+pub fn decode_with(buf: &[u8], options: &DecodeOptions) -> Result<(Dynamic, usize)> {
+    decode_at(buf, 0, options)
+}
 
-        if first_byte == 0xd6 {
-            assert_err!(buf.len() < 6, anyhow!("no data"));
-            let _type_id = unsafe { std::mem::transmute::<u8, i8>(buf[1]) };
-            //let _value = raw[2..6].to_vec();
-            return Ok((Dynamic::Null, 6));
-        }
+//base 是 buf 在整个输入里的偏移 用于错误信息
+fn decode_at(buf: &[u8], base: usize, options: &DecodeOptions) -> Result<(Dynamic, usize)> {
+    assert_err!(buf.is_empty(), anyhow!("no data"));
+    let first_byte = buf[0];
+    assert_ok!(first_byte <= 0x7f, (Dynamic::from(first_byte as i64), 1));
+    assert_ok!(first_byte >= 0xe0, (Dynamic::from(first_byte as i64 - 256), 1));
+    if (0x80..=0x8f).contains(&first_byte) {
+        let len = (first_byte & 0x0f) as usize;
+        let (value, size) = decode_map(&buf[1..], base + 1, len, options)?;
+        return Ok((value, 1 + size));
+    }
+    if (0x90..=0x9f).contains(&first_byte) {
+        let len = (first_byte & 0x0f) as usize;
+        let (value, size) = decode_items(&buf[1..], base + 1, len, options)?;
+        return Ok((Dynamic::from_vec(value), 1 + size));
+    }
 
-        if first_byte == 0xd7 {
-            assert_err!(buf.len() < 10, anyhow!("no data"));
-            let _type_id = unsafe { std::mem::transmute::<u8, i8>(buf[1]) };
-            //let _value = raw[2..10].to_vec();
-            return Ok((Dynamic::Null, 10));
-        }
+    if (0xa0..=0xbf).contains(&first_byte) {
+        let len = (first_byte & 0x1f) as usize;
+        assert_err!(buf.len() < 1 + len, anyhow!("no data"));
+        return Ok(Dynamic::try_from(&buf[1..1 + len]).map(|r| (r, 1 + len))?);
+    }
 
-        if first_byte == 0xd8 {
-            assert_err!(buf.len() < 18, anyhow!("no data"));
-            let _type_id = unsafe { std::mem::transmute::<u8, i8>(buf[1]) };
-            //let _value = raw[2..18].to_vec();
-            return Ok((Dynamic::Null, 18));
-        }
+    assert_ok!(first_byte == 0xc0, (Dynamic::Null, 1));
+    assert_err!(first_byte == 0xc1, anyhow!("0xc1 never used"));
+    assert_ok!(first_byte == 0xc2, (false.into(), 1));
+    assert_ok!(first_byte == 0xc3, (true.into(), 1));
 
-        if first_byte == 0xd9 {
-            assert_err!(buf.len() < 2, anyhow!("no data"));
-            let len = read_8(&buf[1..]) as usize;
-            assert_err!(buf.len() < 2 + len, anyhow!("no data"));
-            return Ok(Dynamic::try_from(&buf[2..2 + len]).map(|r| (r, 2 + len))?);
-        }
+    if first_byte == 0xc4 {
+        assert_err!(buf.len() < 2, anyhow!("no data"));
+        let len = read_8(&buf[1..]) as usize;
+        assert_err!(buf.len() < 2 + len, anyhow!("no data"));
+        return Ok((Dynamic::from_bytes(buf[2..2 + len].to_vec()), 2 + len));
+    }
 
-        if first_byte == 0xda {
-            assert_err!(buf.len() < 3, anyhow!("no data"));
-            let len = read_16(&buf[1..]) as usize;
-            assert_err!(buf.len() < 3 + len, anyhow!("no data"));
-            return Ok(Dynamic::try_from(&buf[3..3 + len]).map(|r| (r, 3 + len))?);
-        }
+    if first_byte == 0xc5 {
+        assert_err!(buf.len() < 3, anyhow!("no data"));
+        let len = read_16(&buf[1..]) as usize;
+        assert_err!(buf.len() < 3 + len, anyhow!("no data"));
+        return Ok((Dynamic::from_bytes(buf[3..3 + len].to_vec()), 3 + len));
+    }
 
-        if first_byte == 0xdb {
-            assert_err!(buf.len() < 5, anyhow!("no data"));
-            let len = read_32(&buf[1..]) as usize;
-            assert_err!(buf.len() < 5 + len, anyhow!("no data"));
-            return Ok(Dynamic::try_from(&buf[5..5 + len]).map(|r| (r, 5 + len))?);
-        }
+    if first_byte == 0xc6 {
+        assert_err!(buf.len() < 5, anyhow!("no data"));
+        let len = read_32(&buf[1..]) as usize;
+        assert_err!(buf.len() < 5 + len, anyhow!("no data"));
+        return Ok((Dynamic::from_bytes(buf[5..5 + len].to_vec()), 5 + len));
+    }
 
-        if first_byte == 0xdc {
-            assert_err!(buf.len() < 3, anyhow!("no data"));
-            let len = read_16(&buf[1..]) as usize;
-            let (value, size) = Self::decode_array(&buf[3..], len)?;
-            return Ok((Dynamic::from_vec(value), 3 + size));
-        }
+    if first_byte == 0xc7 {
+        assert_err!(buf.len() < 3, anyhow!("no data"));
+        let len = read_8(&buf[1..]) as usize;
+        let _type_id = buf[2] as i8;
+        assert_err!(buf.len() < 3 + len, anyhow!("no data"));
+        //let _value = raw[3..3 + len].to_vec();
+        return Ok((Dynamic::Null, 3 + len)); //暂时没实现
+    }
 
-        if first_byte == 0xdd {
-            assert_err!(buf.len() < 5, anyhow!("no data"));
-            let len = read_32(&buf[1..]) as usize;
-            let (value, size) = Self::decode_array(&buf[5..], len)?;
-            return Ok((Dynamic::from_vec(value), 5 + size));
-        }
+    if first_byte == 0xc8 {
+        assert_err!(buf.len() < 4, anyhow!("no data"));
+        let len = read_16(&buf[1..]) as usize;
+        let _type_id = buf[3] as i8;
+        assert_err!(buf.len() < 4 + len, anyhow!("no data"));
+        //let _value = raw[4..4 + len].to_vec();
+        return Ok((Dynamic::Null, 4 + len)); //暂时没实现
+    }
 
-        if first_byte == 0xde {
-            assert_err!(buf.len() < 3, anyhow!("no data"));
-            let len = read_16(&buf[1..]) as usize;
-            let (value, size) = Self::decode_array(&buf[3..], len * 2)?;
-            return vec_to_dynamic(value).map(|r| (r, 3 + size));
-        }
+    if first_byte == 0xc9 {
+        assert_err!(buf.len() < 6, anyhow!("no data"));
+        let len = read_32(&buf[1..]) as usize;
+        let _type_id = buf[5] as i8;
+        assert_err!(buf.len() < 6 + len, anyhow!("no data"));
+        //let _value = raw[6..6 + len].to_vec();
+        return Ok((Dynamic::Null, 6 + len)); //暂时没实现
+    }
 
-        if first_byte == 0xdf {
-            assert_err!(buf.len() < 5, anyhow!("no data"));
-            let len = read_32(&buf[1..]) as usize;
-            let (value, size) = Self::decode_array(&buf[5..], len * 2)?;
-            return vec_to_dynamic(value).map(|r| (r, 5 + size));
+    if first_byte == 0xca {
+        assert_err!(buf.len() < 5, anyhow!("no data"));
+        let raw_value = read_32(&buf[1..]);
+        let value = f32::from_bits(raw_value);
+        return Ok((Dynamic::from(value as f64), 5));
+    }
+
+    if first_byte == 0xcb {
+        assert_err!(buf.len() < 9, anyhow!("no data"));
+        let raw_value = read_64(&buf[1..]);
+        let value = f64::from_bits(raw_value);
+        return Ok((Dynamic::from(value), 9));
+    }
+
+    if first_byte == 0xcc {
+        assert_err!(buf.len() < 2, anyhow!("no data"));
+        let value = read_8(&buf[1..]);
+        return Ok((Dynamic::from(value as i64), 2));
+    }
+
+    if first_byte == 0xcd {
+        assert_err!(buf.len() < 3, anyhow!("no data"));
+        let value = read_16(&buf[1..]);
+        return Ok((Dynamic::from(value as i64), 3));
+    }
+
+    if first_byte == 0xce {
+        assert_err!(buf.len() < 5, anyhow!("no data"));
+        let value = read_32(&buf[1..]);
+        return Ok((Dynamic::from(value as i64), 5));
+    }
+
+    if first_byte == 0xcf {
+        assert_err!(buf.len() < 9, anyhow!("no data"));
+        let value = read_64(&buf[1..]);
+        if value > i64::MAX as u64 {                        //i64 放不下的才用 UInt
+            return Ok((Dynamic::UInt(value), 9));
         }
-        Err(anyhow!("error code {}", first_byte))
+        return Ok((Dynamic::from(value as i64), 9));
+    }
+
+    if first_byte == 0xd0 {
+        assert_err!(buf.len() < 2, anyhow!("no data"));
+        let raw_value = read_8(&buf[1..]) as i8;
+        let value = raw_value as i64; //unsafe { std::mem::transmute::<u64, i64>(raw_value) };
+        return Ok((Dynamic::from(value), 2));
+    }
+
+    if first_byte == 0xd1 {
+        assert_err!(buf.len() < 3, anyhow!("no data"));
+        let raw_value = read_16(&buf[1..]);
+        let value = raw_value as i16 as i64;
+        return Ok((Dynamic::from(value), 3));
+    }
+
+    if first_byte == 0xd2 {
+        assert_err!(buf.len() < 5, anyhow!("no data"));
+        let raw_value = read_32(&buf[1..]);
+        let value = raw_value as i32 as i64;
+        return Ok((Dynamic::from(value), 5));
+    }
+
+    if first_byte == 0xd3 {
+        assert_err!(buf.len() < 9, anyhow!("no data"));
+        let raw_value = read_64(&buf[1..]);
+        let value = raw_value as i64;
+        return Ok((Dynamic::from(value), 9));
+    }
+
+    if first_byte == 0xd4 {
+        assert_err!(buf.len() < 3, anyhow!("no data"));
+        let _type_id = buf[1] as i8;
+        //let _value = raw[2..3].to_vec();
+        return Ok((Dynamic::Null, 3));
+    }
+
+    if first_byte == 0xd5 {
+        assert_err!(buf.len() < 4, anyhow!("no data"));
+        let _type_id = buf[1] as i8;
+        //let _value = raw[2..4].to_vec();
+        return Ok((Dynamic::Null, 4));
+    }
+
+    if first_byte == 0xd6 {
+        assert_err!(buf.len() < 6, anyhow!("no data"));
+        let _type_id = buf[1] as i8;
+        //let _value = raw[2..6].to_vec();
+        return Ok((Dynamic::Null, 6));
+    }
+
+    if first_byte == 0xd7 {
+        assert_err!(buf.len() < 10, anyhow!("no data"));
+        let _type_id = buf[1] as i8;
+        //let _value = raw[2..10].to_vec();
+        return Ok((Dynamic::Null, 10));
+    }
+
+    if first_byte == 0xd8 {
+        assert_err!(buf.len() < 18, anyhow!("no data"));
+        let _type_id = buf[1] as i8;
+        //let _value = raw[2..18].to_vec();
+        return Ok((Dynamic::Null, 18));
+    }
+
+    if first_byte == 0xd9 {
+        assert_err!(buf.len() < 2, anyhow!("no data"));
+        let len = read_8(&buf[1..]) as usize;
+        assert_err!(buf.len() < 2 + len, anyhow!("no data"));
+        return Ok(Dynamic::try_from(&buf[2..2 + len]).map(|r| (r, 2 + len))?);
+    }
+
+    if first_byte == 0xda {
+        assert_err!(buf.len() < 3, anyhow!("no data"));
+        let len = read_16(&buf[1..]) as usize;
+        assert_err!(buf.len() < 3 + len, anyhow!("no data"));
+        return Ok(Dynamic::try_from(&buf[3..3 + len]).map(|r| (r, 3 + len))?);
+    }
+
+    if first_byte == 0xdb {
+        assert_err!(buf.len() < 5, anyhow!("no data"));
+        let len = read_32(&buf[1..]) as usize;
+        assert_err!(buf.len() < 5 + len, anyhow!("no data"));
+        return Ok(Dynamic::try_from(&buf[5..5 + len]).map(|r| (r, 5 + len))?);
+    }
+
+    if first_byte == 0xdc {
+        assert_err!(buf.len() < 3, anyhow!("no data"));
+        let len = read_16(&buf[1..]) as usize;
+        let (value, size) = decode_items(&buf[3..], base + 3, len, options)?;
+        return Ok((Dynamic::from_vec(value), 3 + size));
+    }
+
+    if first_byte == 0xdd {
+        assert_err!(buf.len() < 5, anyhow!("no data"));
+        let len = read_32(&buf[1..]) as usize;
+        let (value, size) = decode_items(&buf[5..], base + 5, len, options)?;
+        return Ok((Dynamic::from_vec(value), 5 + size));
+    }
+
+    if first_byte == 0xde {
+        assert_err!(buf.len() < 3, anyhow!("no data"));
+        let len = read_16(&buf[1..]) as usize;
+        let (value, size) = decode_map(&buf[3..], base + 3, len, options)?;
+        return Ok((value, 3 + size));
+    }
+
+    if first_byte == 0xdf {
+        assert_err!(buf.len() < 5, anyhow!("no data"));
+        let len = read_32(&buf[1..]) as usize;
+        let (value, size) = decode_map(&buf[5..], base + 5, len, options)?;
+        return Ok((value, 5 + size));
     }
+    Err(anyhow!("error code {}", first_byte))
+}
+
+//下面是 derive 宏生成的代码使用的辅助函数 和 json 模块里的同名函数对应
+pub fn object_begin(buf: &mut Vec<u8>, length: usize) {
+    write_map_header(buf, length);
+}
+
+pub fn object_key(buf: &mut Vec<u8>, _index: usize, key: &str) {
+    key.encode(buf);
+}
+
+pub fn object_end(_buf: &mut Vec<u8>) {}
+
+pub fn array_begin(buf: &mut Vec<u8>, length: usize) {
+    write_array_header(buf, length);
+}
+
+pub fn array_item(_buf: &mut Vec<u8>, _index: usize) {}
+
+pub fn array_end(_buf: &mut Vec<u8>) {}
+
+pub fn write_str(buf: &mut Vec<u8>, s: &str) {
+    s.encode(buf);
+}
+
+pub fn write_null(buf: &mut Vec<u8>) {
+    buf.push(0xc0);
+}
+
+//返回 map 或者 array 的元素个数和头部长度
+fn read_header(buf: &[u8], map: bool) -> Result<(usize, usize)> {
+    assert_err!(buf.is_empty(), anyhow!("no data"));
+    let (fix, short, long) = if map { (0x80, 0xde, 0xdf) } else { (0x90, 0xdc, 0xdd) };
+    let first_byte = buf[0];
+    if first_byte & 0xf0 == fix {
+        Ok(((first_byte & 0x0f) as usize, 1))
+    } else if first_byte == short {
+        assert_err!(buf.len() < 3, anyhow!("no data"));
+        Ok((read_16(&buf[1..]) as usize, 3))
+    } else if first_byte == long {
+        assert_err!(buf.len() < 5, anyhow!("no data"));
+        Ok((read_32(&buf[1..]) as usize, 5))
+    } else {
+        Err(anyhow!("need a {}", if map { "map" } else { "array" }))
+    }
+}
+
+//跳过一个值 返回长度
+pub fn skip_value(buf: &[u8]) -> Result<usize> {
+    Dynamic::decode(buf).map(|(_, size)| size)
+}
+
+//遍历 map 的每个 key field 返回 Some(长度) 表示已经解码了 value 返回 None 的 value 会被跳过
+pub fn read_object<F: FnMut(&str, &[u8]) -> Result<Option<usize>>>(buf: &[u8], mut field: F) -> Result<usize> {
+    let (length, mut cursor) = read_header(buf, true)?;
+    for _ in 0..length {
+        let (key, size) = Dynamic::decode(&buf[cursor..])?;
+        cursor += size;
+        let key = key.into_string().map_err(|_| anyhow!("need a string key"))?;
+        cursor += match field(&key, &buf[cursor..])? {
+            Some(size)=> size,
+            None=> skip_value(&buf[cursor..])?
+        };
+    }
+    Ok(cursor)
+}
+
+//遍历 array 的每个元素 返回元素个数和长度
+pub fn read_array<F: FnMut(usize, &[u8]) -> Result<usize>>(buf: &[u8], mut item: F) -> Result<(usize, usize)> {
+    let (length, mut cursor) = read_header(buf, false)?;
+    for index in 0..length {
+        cursor += item(index, &buf[cursor..])?;
+    }
+    Ok((length, cursor))
+}
+
+//内部标签的 enum 先取出标签的值 返回整个 map 的长度
+pub fn read_tag(buf: &[u8], tag: &str) -> Result<(SmolStr, usize)> {
+    let (value, size) = Dynamic::decode(buf)?;
+    let name = value.at(tag).into_string().map_err(|_| anyhow!("tag {} is not found", tag))?;
+    Ok((name, size))
+}
+
+//外部标签的 enum 单元变体是字符串 其他是只有一个 key 的 map 返回变体名 内容的偏移 和总长度
+pub fn read_variant(buf: &[u8]) -> Result<(SmolStr, Option<usize>, usize)> {
+    assert_err!(buf.is_empty(), anyhow!("no data"));
+    if (0xa0..=0xbf).contains(&buf[0]) || (0xd9..=0xdb).contains(&buf[0]) {
+        let (name, size) = Dynamic::decode(buf)?;
+        return Ok((name.into_string()?, None, size));
+    }
+    let mut variant = None;
+    let size = read_object(buf, |key, value| {
+        assert_err!(variant.is_some(), anyhow!("enum map must have only one key"));
+        variant = Some((SmolStr::new(key), buf.len() - value.len()));
+        Ok(None)
+    })?;
+    let (name, offset) = variant.ok_or(anyhow!("enum map is empty"))?;
+    Ok((name, Some(offset), size))
+}
+
+//相邻标签的 enum {tag: 变体名, content: 内容} 返回变体名 内容的偏移 和总长度
+pub fn read_adjacent(buf: &[u8], tag: &str, content: &str) -> Result<(SmolStr, Option<usize>, usize)> {
+    let mut name = None;
+    let mut offset = None;
+    let size = read_object(buf, |key, value| {
+        if key == tag {
+            let (v, size) = Dynamic::decode(value)?;
+            name = Some(v.into_string()?);
+            return Ok(Some(size));
+        }
+        if key == content {
+            offset = Some(buf.len() - value.len());
+        }
+        Ok(None)
+    })?;
+    Ok((name.ok_or(anyhow!("tag {} is not found", tag))?, offset, size))
+}
+
+//单元结构体和 untagged 的单元变体是 nil 返回长度
+pub fn read_null(buf: &[u8]) -> Result<usize> {
+    assert_err!(buf.first() != Some(&0xc0), anyhow!("need a nil"));
+    Ok(1)
+}
+
+pub fn missing_field(name: &str) -> anyhow::Error {
+    anyhow!("missing field {}", name)
+}
+
+pub fn unknown_variant(name: &str) -> anyhow::Error {
+    anyhow!("unknown variant {}", name)
 }
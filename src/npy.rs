@@ -0,0 +1,535 @@
+use anyhow::{anyhow, Result};
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use smol_str::SmolStr;
+use super::cbor::f16_to_f64;
+use super::dynamic::Dynamic;
+use super::assert_err;
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+//支持的元素类型 不支持复数 字符串和结构体
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dtype {
+    Bool, I8, I16, I32, I64, U8, U16, U32, U64, F16, F32, F64,
+}
+
+impl Dtype {
+    //numpy 的 descr 例如 <f4 |u1 >i8 返回类型和是否是大端
+    pub fn parse(descr: &str) -> Result<(Self, bool)> {
+        let (order, kind) = match descr.as_bytes().first() {
+            Some(b'<' | b'|' | b'=')=> (false, &descr[1..]),
+            Some(b'>')=> (true, &descr[1..]),
+            _=> (false, descr)
+        };
+        let dtype = match kind {
+            "b1" | "?"=> Self::Bool,
+            "i1"=> Self::I8,
+            "i2"=> Self::I16,
+            "i4"=> Self::I32,
+            "i8"=> Self::I64,
+            "u1"=> Self::U8,
+            "u2"=> Self::U16,
+            "u4"=> Self::U32,
+            "u8"=> Self::U64,
+            "f2"=> Self::F16,
+            "f4"=> Self::F32,
+            "f8"=> Self::F64,
+            _=> return Err(anyhow!("unsupported dtype {}", descr))
+        };
+        Ok((dtype, order))
+    }
+
+    pub fn descr(&self) -> &'static str {          //写出的都是小端
+        match self {
+            Self::Bool=> "|b1",
+            Self::I8=> "|i1",
+            Self::I16=> "<i2",
+            Self::I32=> "<i4",
+            Self::I64=> "<i8",
+            Self::U8=> "|u1",
+            Self::U16=> "<u2",
+            Self::U32=> "<u4",
+            Self::U64=> "<u8",
+            Self::F16=> "<f2",
+            Self::F32=> "<f4",
+            Self::F64=> "<f8",
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Bool | Self::I8 | Self::U8=> 1,
+            Self::I16 | Self::U16 | Self::F16=> 2,
+            Self::I32 | Self::U32 | Self::F32=> 4,
+            Self::I64 | Self::U64 | Self::F64=> 8,
+        }
+    }
+}
+
+//data 总是小端 C 顺序 (最后一维变化最快) 读的时候大端和 Fortran 顺序会转换过来
+//字段不公开 只能通过 new 构造 保证 data 的长度和 dtype shape 一致
+#[derive(Debug, Clone)]
+pub struct NdArray {
+    dtype: Dtype,
+    shape: Vec<usize>,
+    data: Vec<u8>,
+}
+
+impl NdArray {
+    pub fn new(dtype: Dtype, shape: Vec<usize>, data: Vec<u8>) -> Result<Self> {
+        let expected = shape.iter().try_fold(dtype.size(), |n, d| n.checked_mul(*d)).ok_or_else(|| anyhow!("array is too large"))?;
+        assert_err!(data.len() != expected, anyhow!("{:?} {:?} needs {} bytes but got {}", dtype, shape, expected, data.len()));
+        Ok(Self { dtype, shape, data })
+    }
+
+    pub fn dtype(&self) -> Dtype {
+        self.dtype
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn elements(&self) -> usize {              //shape 是空的时候是标量 一个元素
+        self.shape.iter().product()
+    }
+
+    fn value(&self, index: usize) -> Dynamic {
+        let raw = &self.data[index * self.dtype.size()..(index + 1) * self.dtype.size()];
+        match self.dtype {
+            Dtype::Bool=> Dynamic::Bool(raw[0] != 0),
+            Dtype::I8=> Dynamic::Int(raw[0] as i8 as i64),
+            Dtype::I16=> Dynamic::Int(LittleEndian::read_i16(raw) as i64),
+            Dtype::I32=> Dynamic::Int(LittleEndian::read_i32(raw) as i64),
+            Dtype::I64=> Dynamic::Int(LittleEndian::read_i64(raw)),
+            Dtype::U8=> Dynamic::Int(raw[0] as i64),
+            Dtype::U16=> Dynamic::Int(LittleEndian::read_u16(raw) as i64),
+            Dtype::U32=> Dynamic::Int(LittleEndian::read_u32(raw) as i64),
+            Dtype::U64=> match LittleEndian::read_u64(raw) {
+                v if v > i64::MAX as u64=> Dynamic::UInt(v),
+                v=> Dynamic::Int(v as i64)
+            }
+            Dtype::F16=> Dynamic::Float(f16_to_f64(LittleEndian::read_u16(raw)) as f32),
+            Dtype::F32=> Dynamic::Float(LittleEndian::read_f32(raw)),
+            Dtype::F64=> Dynamic::Double(LittleEndian::read_f64(raw)),
+        }
+    }
+
+    //嵌套的 Vec 标量的 shape 是 () 直接是一个值
+    pub fn to_dynamic(&self) -> Dynamic {
+        let mut index = 0;
+        self.nested(0, &mut index)
+    }
+
+    fn nested(&self, axis: usize, index: &mut usize) -> Dynamic {
+        if axis == self.shape.len() {
+            *index += 1;
+            return self.value(*index - 1);
+        }
+        Dynamic::from_vec((0..self.shape[axis]).map(|_| self.nested(axis + 1, index)).collect())
+    }
+
+    //嵌套的 Vec 转成数组 全是 bool 是 Bool 全是整数是 I64 (超出 i64 的是 U64) 全是 Float 是 F32 其他的数字是 F64
+    pub fn from_dynamic(value: &Dynamic) -> Result<Self> {
+        let mut shape = Vec::new();
+        let mut current = value.clone();
+        while let Dynamic::Vec(v) = &current {
+            shape.push(v.read().len());
+            let first = v.read().first().cloned();
+            match first {
+                Some(first)=> current = first,
+                None=> break
+            }
+        }
+        let mut items = Vec::new();
+        flatten(value, &shape, &mut items)?;
+        let all = |f: fn(&Dynamic) -> bool| items.iter().all(f);
+        let dtype = if items.is_empty() {
+            Dtype::F64                              //和 numpy 一样 空的数组是 float64
+        } else if all(|v| v.is_bool()) {
+            Dtype::Bool
+        } else if all(|v| matches!(v, Dynamic::Byte(_) | Dynamic::Int(_) | Dynamic::UInt(_))) {
+            match items.iter().any(|v| matches!(v, Dynamic::UInt(u) if *u > i64::MAX as u64)) {
+                true=> Dtype::U64,
+                false=> Dtype::I64
+            }
+        } else if all(|v| matches!(v, Dynamic::Float(_))) {
+            Dtype::F32
+        } else {
+            Dtype::F64
+        };
+        let mut data = Vec::with_capacity(items.len() * dtype.size());
+        for item in &items {
+            match (dtype, item) {
+                (Dtype::Bool, Dynamic::Bool(b))=> data.push(*b as u8),
+                (Dtype::U64, Dynamic::Byte(v))=> data.write_u64::<LittleEndian>(*v as u64)?,
+                (Dtype::U64, Dynamic::Int(v)) if *v >= 0=> data.write_u64::<LittleEndian>(*v as u64)?,
+                (Dtype::U64, Dynamic::UInt(v))=> data.write_u64::<LittleEndian>(*v)?,
+                (Dtype::I64, Dynamic::Byte(v))=> data.write_i64::<LittleEndian>(*v as i64)?,
+                (Dtype::I64, Dynamic::Int(v))=> data.write_i64::<LittleEndian>(*v)?,
+                (Dtype::I64, Dynamic::UInt(v))=> data.write_i64::<LittleEndian>(*v as i64)?,
+                (Dtype::F32, Dynamic::Float(v))=> data.write_f32::<LittleEndian>(*v)?,
+                (Dtype::F64, Dynamic::Byte(v))=> data.write_f64::<LittleEndian>(*v as f64)?,
+                (Dtype::F64, Dynamic::Int(v))=> data.write_f64::<LittleEndian>(*v as f64)?,
+                (Dtype::F64, Dynamic::UInt(v))=> data.write_f64::<LittleEndian>(*v as f64)?,
+                (Dtype::F64, Dynamic::Float(v))=> data.write_f64::<LittleEndian>(*v as f64)?,
+                (Dtype::F64, Dynamic::Double(v))=> data.write_f64::<LittleEndian>(*v)?,
+                _=> return Err(anyhow!("{:?} can not be in a {:?} array", item, dtype))
+            }
+        }
+        Self::new(dtype, shape, data)
+    }
+
+    //{"dtype": "<f4", "shape": [2, 3], "data": Bytes} 数据不转换 适合大的数组
+    pub fn to_map(&self) -> Dynamic {
+        let shape = Dynamic::from_vec(self.shape.iter().map(|d| Dynamic::Int(*d as i64)).collect());
+        crate::dmap!("dtype" => self.dtype.descr(), "shape" => shape, "data" => Dynamic::from_bytes(self.data.clone()))
+    }
+
+    pub fn from_map(value: &Dynamic) -> Result<Self> {
+        let (dtype, big_endian) = Dtype::parse(value.get_key("dtype")?.as_str()?)?;
+        let shape = value.get_key("shape")?.into_vec()?.iter().map(|d| Ok(usize::try_from(d.as_u64()?)?)).collect::<Result<Vec<_>>>()?;
+        let Dynamic::Bytes(data) = value.get_key("data")? else { return Err(anyhow!("data is not Bytes")) };
+        let mut array = Self::new(dtype, shape, data.to_vec())?;
+        if big_endian {
+            array.swap_bytes();
+        }
+        Ok(array)
+    }
+
+    fn swap_bytes(&mut self) {
+        let size = self.dtype.size();
+        if size > 1 {
+            self.data.chunks_exact_mut(size).for_each(|chunk| chunk.reverse());
+        }
+    }
+
+    //Fortran 顺序 (第一维变化最快) 转成 C 顺序
+    fn fortran_to_c(&mut self) {
+        let (size, count) = (self.dtype.size(), self.elements());
+        if self.shape.len() < 2 || count == 0 {
+            return;
+        }
+        let mut data = vec![0u8; self.data.len()];
+        let mut index = vec![0usize; self.shape.len()];
+        for target in 0..count {
+            let mut source = 0;
+            for axis in (0..self.shape.len()).rev() {
+                source = source * self.shape[axis] + index[axis];
+            }
+            data[target * size..(target + 1) * size].copy_from_slice(&self.data[source * size..(source + 1) * size]);
+            for axis in (0..self.shape.len()).rev() {   //C 顺序的下一个下标
+                index[axis] += 1;
+                if index[axis] < self.shape[axis] {
+                    break;
+                }
+                index[axis] = 0;
+            }
+        }
+        self.data = data;
+    }
+}
+
+fn flatten(value: &Dynamic, shape: &[usize], items: &mut Vec<Dynamic>) -> Result<()> {
+    match (value, shape.split_first()) {
+        (Dynamic::Vec(v), Some((length, rest)))=> {
+            let v = v.read();
+            assert_err!(v.len() != *length, anyhow!("ragged array {} != {}", v.len(), length));
+            for item in v.iter() {
+                flatten(item, rest, items)?;
+            }
+        }
+        (Dynamic::Vec(_) | Dynamic::Map(_) | Dynamic::AnyMap(_), _) | (_, Some(_))=> return Err(anyhow!("ragged or non-numeric array")),
+        (value, None)=> items.push(value.clone())
+    }
+    Ok(())
+}
+
+//版本 1.0 的 header 长度是 u16 2.0 和 3.0 是 u32 header 是 Python 的 dict 字面量
+pub fn read_npy(buf: &[u8]) -> Result<NdArray> {
+    assert_err!(buf.len() < 10 || &buf[..6] != MAGIC, anyhow!("not a npy file"));
+    let (length, start) = match buf[6] {
+        1=> (LittleEndian::read_u16(&buf[8..10]) as usize, 10),
+        2 | 3 if buf.len() >= 12=> (LittleEndian::read_u32(&buf[8..12]) as usize, 12),
+        v=> return Err(anyhow!("unsupported npy version {}", v))
+    };
+    let header = buf.get(start..start + length).ok_or_else(|| anyhow!("npy header is truncated"))?;
+    let header = Literal { buf: header, pos: 0 }.value()?;
+    assert_err!(!header.is_map(), anyhow!("npy header is not a dict"));
+    let descr = header.get_key("descr")?;
+    assert_err!(!descr.is_string(), anyhow!("structured dtype is not supported"));
+    let (dtype, big_endian) = Dtype::parse(descr.as_str()?)?;
+    let fortran = header.get_key("fortran_order")?.as_bool()?;
+    let shape = header.get_key("shape")?.into_vec()?.iter().map(|d| match d {
+        Dynamic::Int(v)=> usize::try_from(*v).map_err(|_| anyhow!("invalid shape")),
+        _=> Err(anyhow!("invalid shape"))
+    }).collect::<Result<Vec<_>>>()?;
+    let mut array = NdArray::new(dtype, shape, buf[start + length..].to_vec())?;
+    if big_endian {
+        array.swap_bytes();
+    }
+    if fortran {
+        array.fortran_to_c();
+    }
+    Ok(array)
+}
+
+//小端 C 顺序 header 用空格补齐到 64 字节对齐 太长的时候用版本 2.0
+pub fn write_npy(array: &NdArray, buf: &mut Vec<u8>) {
+    let shape = match array.shape.len() {
+        1=> format!("({},)", array.shape[0]),
+        _=> format!("({})", array.shape.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", "))
+    };
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", array.dtype.descr(), shape);
+    let preamble = if header.len() + 11 > u16::MAX as usize { 12 } else { 10 };
+    while (preamble + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+    buf.extend_from_slice(MAGIC);
+    match preamble {
+        10=> {
+            buf.extend_from_slice(&[1, 0]);
+            buf.write_u16::<LittleEndian>(header.len() as u16).unwrap();
+        }
+        _=> {
+            buf.extend_from_slice(&[2, 0]);
+            buf.write_u32::<LittleEndian>(header.len() as u32).unwrap();
+        }
+    }
+    buf.extend_from_slice(header.as_bytes());
+    buf.extend_from_slice(&array.data);
+}
+
+//npy header 里用到的 Python 字面量 dict tuple list 字符串 整数 True False None
+struct Literal<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl Literal<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.buf.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Dynamic> {
+        self.skip_whitespace();
+        let c = *self.buf.get(self.pos).ok_or_else(|| anyhow!("unexpected end of npy header"))?;
+        match c {
+            b'{' | b'(' | b'['=> {
+                self.pos += 1;
+                let end = match c { b'{'=> b'}', b'('=> b')', _=> b']' };
+                let (map, mut items) = (Dynamic::map(), Vec::new());
+                loop {
+                    self.skip_whitespace();
+                    if self.buf.get(self.pos) == Some(&end) {
+                        self.pos += 1;
+                        break;
+                    }
+                    let item = self.value()?;
+                    self.skip_whitespace();
+                    if c == b'{' {
+                        assert_err!(self.buf.get(self.pos) != Some(&b':'), anyhow!("expect : at {} of npy header", self.pos));
+                        self.pos += 1;
+                        map.set_key(item.as_str()?, self.value()?)?;
+                        self.skip_whitespace();
+                    } else {
+                        items.push(item);
+                    }
+                    match self.buf.get(self.pos) {
+                        Some(b',')=> self.pos += 1,
+                        Some(v) if *v == end=> {}
+                        _=> return Err(anyhow!("expect , at {} of npy header", self.pos))
+                    }
+                }
+                Ok(if c == b'{' { map } else { Dynamic::from_vec(items) })
+            }
+            b'\'' | b'"'=> {
+                self.pos += 1;
+                let mut text = Vec::new();
+                loop {
+                    match self.buf.get(self.pos) {
+                        None=> return Err(anyhow!("unterminated string in npy header")),
+                        Some(q) if *q == c=> break,
+                        Some(b'\\')=> {
+                            text.extend(self.buf.get(self.pos + 1));
+                            self.pos += 1;
+                        }
+                        Some(v)=> text.push(*v)
+                    }
+                    self.pos += 1;
+                }
+                self.pos += 1;
+                Ok(Dynamic::from(String::from_utf8(text)?))
+            }
+            b'-' | b'0'..=b'9'=> {
+                let start = self.pos;
+                self.pos += 1;
+                while self.buf.get(self.pos).is_some_and(u8::is_ascii_digit) {
+                    self.pos += 1;
+                }
+                let value = std::str::from_utf8(&self.buf[start..self.pos])?.parse::<i64>()?;
+                if self.buf.get(self.pos) == Some(&b'L') {     //Python 2 写的长整数
+                    self.pos += 1;
+                }
+                Ok(Dynamic::Int(value))
+            }
+            _=> {
+                let start = self.pos;
+                while self.buf.get(self.pos).is_some_and(u8::is_ascii_alphabetic) {
+                    self.pos += 1;
+                }
+                match &self.buf[start..self.pos] {
+                    b"True"=> Ok(Dynamic::Bool(true)),
+                    b"False"=> Ok(Dynamic::Bool(false)),
+                    b"None"=> Ok(Dynamic::Null),
+                    _=> Err(anyhow!("unexpected character at {} of npy header", start))
+                }
+            }
+        }
+    }
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, b| CRC_TABLE[((crc ^ *b as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+fn slice(buf: &[u8], pos: usize, length: usize) -> Result<&[u8]> {
+    buf.get(pos..pos.saturating_add(length)).ok_or_else(|| anyhow!("npz is truncated at offset {}", pos))
+}
+
+fn u16_at(buf: &[u8], pos: usize) -> Result<usize> {
+    Ok(LittleEndian::read_u16(slice(buf, pos, 2)?) as usize)
+}
+
+fn u32_at(buf: &[u8], pos: usize) -> Result<u64> {
+    Ok(LittleEndian::read_u32(slice(buf, pos, 4)?) as u64)
+}
+
+fn u64_at(buf: &[u8], pos: usize) -> Result<u64> {
+    Ok(LittleEndian::read_u64(slice(buf, pos, 8)?))
+}
+
+//npz 是 zip 每个数组是一个 名字.npy 的文件 np.savez 不压缩 np.savez_compressed 是 deflate 其他的文件忽略
+pub fn read_npz(buf: &[u8]) -> Result<Vec<(SmolStr, NdArray)>> {
+    let search = buf.len().saturating_sub(22 + 0xffff);
+    let end = (search..buf.len().saturating_sub(21)).rev().find(|p| buf[*p..].starts_with(b"PK\x05\x06")).ok_or_else(|| anyhow!("not a npz file"))?;
+    let (mut count, mut offset) = (u16_at(buf, end + 10)? as u64, u32_at(buf, end + 16)?);
+    if (count == 0xffff || offset == 0xffff_ffff) && end >= 20 && buf[end - 20..].starts_with(b"PK\x06\x07") {
+        let zip64 = u64_at(buf, end - 12)? as usize;          //zip64 的目录结束记录
+        assert_err!(!slice(buf, zip64, 4)?.starts_with(b"PK\x06\x06"), anyhow!("invalid zip64 end of central directory"));
+        (count, offset) = (u64_at(buf, zip64 + 32)?, u64_at(buf, zip64 + 48)?);
+    }
+    let mut arrays = Vec::new();
+    let mut pos = offset as usize;
+    for _ in 0..count {
+        assert_err!(!slice(buf, pos, 4)?.starts_with(b"PK\x01\x02"), anyhow!("invalid central directory at offset {}", pos));
+        let (method, crc) = (u16_at(buf, pos + 10)?, u32_at(buf, pos + 16)? as u32);
+        let (mut compressed, mut size) = (u32_at(buf, pos + 20)?, u32_at(buf, pos + 24)?);
+        let (name_length, extra_length, comment_length) = (u16_at(buf, pos + 28)?, u16_at(buf, pos + 30)?, u16_at(buf, pos + 32)?);
+        let mut local = u32_at(buf, pos + 42)?;
+        let name = String::from_utf8_lossy(slice(buf, pos + 46, name_length)?).into_owned();
+        let extra = slice(buf, pos + 46 + name_length, extra_length)?;
+        let mut field = 0;
+        while field + 4 <= extra.len() {            //zip64 的扩展字段 按顺序放着是 0xffffffff 的值
+            let (id, length) = (u16_at(extra, field)?, u16_at(extra, field + 2)?);
+            if id == 1 {
+                let mut values = slice(extra, field + 4, length)?.chunks_exact(8).map(LittleEndian::read_u64);
+                for value in [&mut size, &mut compressed, &mut local] {
+                    if *value == 0xffff_ffff {
+                        *value = values.next().ok_or_else(|| anyhow!("invalid zip64 extra field"))?;
+                    }
+                }
+            }
+            field += 4 + length;
+        }
+        pos += 46 + name_length + extra_length + comment_length;
+        let Some(name) = name.strip_suffix(".npy") else { continue };
+        let local = local as usize;
+        assert_err!(!slice(buf, local, 4)?.starts_with(b"PK\x03\x04"), anyhow!("invalid local header for {}", name));
+        let start = local + 30 + u16_at(buf, local + 26)? + u16_at(buf, local + 28)?;
+        let raw = slice(buf, start, compressed as usize)?;
+        let data = match method {
+            0=> raw.to_vec(),
+            8=> {                                   //最多解压到声明的大小 不会被压缩炸弹耗尽内存
+                let limit = usize::try_from(size).map_err(|_| anyhow!("{} is too large", name))?;
+                miniz_oxide::inflate::decompress_to_vec_with_limit(raw, limit).map_err(|e| anyhow!("can not inflate {}: {:?}", name, e.status))?
+            }
+            _=> return Err(anyhow!("unsupported compression method {} for {}", method, name))
+        };
+        assert_err!(data.len() as u64 != size || crc32(&data) != crc, anyhow!("crc mismatch for {}", name));
+        arrays.push((SmolStr::from(name), read_npy(&data).map_err(|e| anyhow!("{}: {}", name, e))?));
+    }
+    Ok(arrays)
+}
+
+//本地文件头 有 offset 的时候是中央目录的项目
+fn zip_header(buf: &mut Vec<u8>, offset: Option<u32>, crc: u32, size: u32, name: &str) {
+    buf.extend_from_slice(if offset.is_some() { b"PK\x01\x02\x14\x00" } else { b"PK\x03\x04" });
+    buf.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0x21, 0]);                  //版本 标志 不压缩 时间 1980-01-01
+    buf.write_u32::<LittleEndian>(crc).unwrap();
+    buf.write_u32::<LittleEndian>(size).unwrap();
+    buf.write_u32::<LittleEndian>(size).unwrap();
+    buf.write_u16::<LittleEndian>(name.len() as u16).unwrap();
+    buf.extend_from_slice(&[0, 0]);
+    if let Some(offset) = offset {
+        buf.extend_from_slice(&[0; 6]);             //注释 磁盘 内部属性
+        buf.write_u32::<LittleEndian>(0o644 << 16).unwrap();
+        buf.write_u32::<LittleEndian>(offset).unwrap();
+    }
+    buf.extend_from_slice(name.as_bytes());
+}
+
+//和 np.savez 一样不压缩 不支持超过 4GB 的 zip64
+pub fn write_npz(arrays: &[(&str, &NdArray)], buf: &mut Vec<u8>) -> Result<()> {
+    assert_err!(arrays.len() >= 0xffff, anyhow!("too many arrays for npz"));
+    let base = buf.len();
+    let mut directory = Vec::new();
+    for (name, array) in arrays {
+        let mut data = Vec::new();
+        write_npy(array, &mut data);
+        let name = format!("{}.npy", name);
+        let (offset, crc) = (buf.len() - base, crc32(&data));
+        assert_err!(offset + data.len() > u32::MAX as usize, anyhow!("npz larger than 4GB is not supported"));
+        zip_header(buf, None, crc, data.len() as u32, &name);
+        zip_header(&mut directory, Some(offset as u32), crc, data.len() as u32, &name);
+        buf.extend_from_slice(&data);
+    }
+    let offset = buf.len() - base;
+    buf.extend_from_slice(&directory);
+    buf.extend_from_slice(b"PK\x05\x06\x00\x00\x00\x00");
+    buf.write_u16::<LittleEndian>(arrays.len() as u16)?;
+    buf.write_u16::<LittleEndian>(arrays.len() as u16)?;
+    buf.write_u32::<LittleEndian>(directory.len() as u32)?;
+    buf.write_u32::<LittleEndian>(offset as u32)?;
+    buf.extend_from_slice(&[0, 0]);
+    Ok(())
+}
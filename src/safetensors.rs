@@ -0,0 +1,188 @@
+use anyhow::{anyhow, Result};
+use smol_str::SmolStr;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use super::dynamic::Dynamic;
+use super::json::FromJson;
+use super::assert_err;
+
+const MAX_HEADER: u64 = 100 * 1024 * 1024;          //规范里 header 最大 100MB
+
+//张量的元素类型 名字和 header 里的一样
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dtype {
+    Bool, U8, I8, F8E5M2, F8E4M3, I16, U16, F16, BF16, I32, U32, F32, I64, U64, F64,
+}
+
+impl Dtype {
+    pub fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "BOOL"=> Self::Bool,
+            "U8"=> Self::U8,
+            "I8"=> Self::I8,
+            "F8_E5M2"=> Self::F8E5M2,
+            "F8_E4M3"=> Self::F8E4M3,
+            "I16"=> Self::I16,
+            "U16"=> Self::U16,
+            "F16"=> Self::F16,
+            "BF16"=> Self::BF16,
+            "I32"=> Self::I32,
+            "U32"=> Self::U32,
+            "F32"=> Self::F32,
+            "I64"=> Self::I64,
+            "U64"=> Self::U64,
+            "F64"=> Self::F64,
+            _=> return Err(anyhow!("unknown dtype {}", name))
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Bool=> "BOOL",
+            Self::U8=> "U8",
+            Self::I8=> "I8",
+            Self::F8E5M2=> "F8_E5M2",
+            Self::F8E4M3=> "F8_E4M3",
+            Self::I16=> "I16",
+            Self::U16=> "U16",
+            Self::F16=> "F16",
+            Self::BF16=> "BF16",
+            Self::I32=> "I32",
+            Self::U32=> "U32",
+            Self::F32=> "F32",
+            Self::I64=> "I64",
+            Self::U64=> "U64",
+            Self::F64=> "F64",
+        }
+    }
+
+    pub fn size(&self) -> usize {                  //每个元素的字节数
+        match self {
+            Self::Bool | Self::U8 | Self::I8 | Self::F8E5M2 | Self::F8E4M3=> 1,
+            Self::I16 | Self::U16 | Self::F16 | Self::BF16=> 2,
+            Self::I32 | Self::U32 | Self::F32=> 4,
+            Self::I64 | Self::U64 | Self::F64=> 8,
+        }
+    }
+}
+
+//offsets 是相对于数据区开始的 [begin, end)
+#[derive(Debug, Clone)]
+pub struct TensorInfo {
+    pub name: SmolStr,
+    pub dtype: Dtype,
+    pub shape: Vec<usize>,
+    pub offsets: (usize, usize),
+}
+
+impl TensorInfo {
+    pub fn elements(&self) -> usize {              //shape 是空的时候是标量 一个元素
+        self.shape.iter().product()
+    }
+
+    pub fn size(&self) -> usize {
+        self.offsets.1 - self.offsets.0
+    }
+}
+
+//开头 8 字节的小端长度 然后是 json 的 header 后面是所有张量的数据 打开的时候只读 header 张量的数据用 read 按需读
+pub struct SafeTensors<R> {
+    reader: R,
+    header: Dynamic,
+    tensors: Vec<TensorInfo>,
+    data_start: u64,
+}
+
+impl SafeTensors<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> SafeTensors<R> {
+    //检查每个张量的大小和 dtype shape 一致 没有超出文件 也没有互相重叠
+    pub fn new(mut reader: R) -> Result<Self> {
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let mut length = [0u8; 8];
+        reader.read_exact(&mut length).map_err(|_| anyhow!("safetensors file is too short"))?;
+        let length = u64::from_le_bytes(length);
+        assert_err!(length > MAX_HEADER || 8 + length > file_size, anyhow!("invalid safetensors header size {}", length));
+        let mut text = vec![0u8; length as usize];
+        reader.read_exact(&mut text)?;
+        assert_err!(text.first() != Some(&b'{'), anyhow!("safetensors header is not a json object"));
+        let (header, size) = Dynamic::from_json(&text).map_err(|e| anyhow!("invalid safetensors header: {}", e))?;
+        assert_err!(!text[size.min(text.len())..].iter().all(u8::is_ascii_whitespace), anyhow!("unexpected content after safetensors header"));
+        assert_err!(!header.is_map(), anyhow!("safetensors header is not a json object"));
+        let data_size = (file_size - 8 - length) as usize;
+        let mut tensors = Vec::new();
+        let entries: Vec<(SmolStr, Dynamic)> = match &header {
+            Dynamic::Map(m)=> m.read().iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            _=> Vec::new()
+        };
+        for (name, entry) in entries {
+            if name == "__metadata__" {
+                continue;
+            }
+            tensors.push(tensor_info(name, &entry, data_size)?);
+        }
+        tensors.sort_by_key(|tensor| tensor.offsets);
+        for pair in tensors.windows(2) {
+            assert_err!(pair[0].offsets.1 > pair[1].offsets.0, anyhow!("tensor {} overlaps {}", pair[1].name, pair[0].name));
+        }
+        Ok(Self { reader, header, tensors, data_start: 8 + length })
+    }
+
+    pub fn header(&self) -> &Dynamic {              //解析出来的整个 header
+        &self.header
+    }
+
+    //__metadata__ 里的字符串 没有的时候是空的 map
+    pub fn metadata(&self) -> Dynamic {
+        self.header.get_key("__metadata__").unwrap_or_else(|_| Dynamic::map())
+    }
+
+    pub fn tensors(&self) -> &[TensorInfo] {       //按照数据的位置排序
+        &self.tensors
+    }
+
+    pub fn tensor(&self, name: &str) -> Option<&TensorInfo> {
+        self.tensors.iter().find(|tensor| tensor.name == name)
+    }
+
+    //读一个张量的原始字节 小端 行优先
+    pub fn read(&mut self, name: &str) -> Result<Dynamic> {
+        let tensor = self.tensor(name).ok_or_else(|| anyhow!("tensor {} not found", name))?;
+        let (start, size) = (self.data_start + tensor.offsets.0 as u64, tensor.size());
+        self.reader.seek(SeekFrom::Start(start))?;
+        let mut data = vec![0u8; size];
+        self.reader.read_exact(&mut data)?;
+        Ok(Dynamic::from_bytes(data))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+fn tensor_info(name: SmolStr, entry: &Dynamic, data_size: usize) -> Result<TensorInfo> {
+    let field = |key: &str| entry.get_key(key).map_err(|_| anyhow!("tensor {} has no {}", name, key));
+    let dtype = Dtype::parse(field("dtype")?.as_str()?)?;
+    let shape = sizes(&field("shape")?).ok_or_else(|| anyhow!("tensor {} has invalid shape", name))?;
+    let offsets = sizes(&field("data_offsets")?).ok_or_else(|| anyhow!("tensor {} has invalid data_offsets", name))?;
+    assert_err!(offsets.len() != 2 || offsets[0] > offsets[1], anyhow!("tensor {} has invalid data_offsets", name));
+    assert_err!(offsets[1] > data_size, anyhow!("tensor {} ends at {} after the end of data {}", name, offsets[1], data_size));
+    let elements = shape.iter().try_fold(1usize, |n, d| n.checked_mul(*d)).ok_or_else(|| anyhow!("tensor {} is too large", name))?;
+    let expected = elements.checked_mul(dtype.size()).ok_or_else(|| anyhow!("tensor {} is too large", name))?;
+    assert_err!(offsets[1] - offsets[0] != expected, anyhow!("tensor {} has {} bytes but {} {:?} needs {}", name, offsets[1] - offsets[0], dtype.name(), shape, expected));
+    Ok(TensorInfo { name, dtype, shape, offsets: (offsets[0], offsets[1]) })
+}
+
+fn sizes(value: &Dynamic) -> Option<Vec<usize>> {
+    value.clone().into_vec().ok()?.iter().map(|v| match v {
+        Dynamic::Int(i)=> usize::try_from(*i).ok(),
+        Dynamic::UInt(u)=> usize::try_from(*u).ok(),
+        _=> None
+    }).collect()
+}
@@ -0,0 +1,561 @@
+use std::collections::HashMap;
+use std::fmt;
+use regex::Regex;
+use smol_str::SmolStr;
+use anyhow::{Result, anyhow};
+use super::dynamic::Dynamic;
+
+//JSON Schema (draft 2020-12) 的核心关键字校验 schema 本身是一个 Dynamic (一般由 from_json 解析得到)
+//支持 type properties required items prefixItems enum const anyOf oneOf allOf not $ref $defs pattern
+//minimum maximum exclusiveMinimum exclusiveMaximum minLength maxLength minItems maxItems additionalProperties
+//$ref 只支持本文档内的 json pointer 例如 "#/$defs/name" 其他不认识的关键字忽略
+
+//校验失败的位置 instance_path 是被校验值的 json pointer schema_path 是出错关键字在 schema 里的 json pointer
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub instance_path: String,
+    pub schema_path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {} (schema {})", self.message, if self.instance_path.is_empty() { "/" } else { &self.instance_path }, self.schema_path)
+    }
+}
+
+#[derive(Default)]
+struct Keywords {
+    types: Vec<SmolStr>,
+    properties: Vec<(SmolStr, usize)>,
+    required: Vec<SmolStr>,
+    prefix_items: Vec<usize>,
+    items: Option<usize>,
+    additional: Option<usize>,
+    enumeration: Option<Vec<Dynamic>>,
+    constant: Option<Dynamic>,
+    any_of: Vec<usize>,
+    one_of: Vec<usize>,
+    all_of: Vec<usize>,
+    not: Option<usize>,
+    reference: Option<(String, usize)>,
+    pattern: Option<Regex>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    exclusive_minimum: Option<f64>,
+    exclusive_maximum: Option<f64>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
+}
+
+enum Node {
+    Bool(bool),
+    Object(Box<Keywords>),
+}
+
+//编译后的 schema 所有子 schema 放在 nodes 里 按照 json pointer 索引 用来解析 $ref
+pub struct Schema {
+    nodes: Vec<(String, Node)>,
+    pointers: HashMap<String, usize>,
+}
+
+const CYCLE: &str = "schema references itself without consuming the value";
+
+fn escape(token: &str)-> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+fn as_number(value: &Dynamic)-> Option<f64> {
+    match value {
+        Dynamic::Byte(b)=> Some(*b as f64),
+        Dynamic::Int(i)=> Some(*i as f64),
+        Dynamic::UInt(u)=> Some(*u as f64),
+        Dynamic::Float(f)=> Some(*f as f64),
+        Dynamic::Double(f)=> Some(*f),
+        _=> None
+    }
+}
+
+fn as_count(value: &Dynamic, keyword: &str)-> Result<usize> {
+    match value {
+        Dynamic::Int(v) if *v >= 0=> Ok(*v as usize),
+        Dynamic::UInt(v)=> Ok(*v as usize),
+        _=> Err(anyhow!("{} must be a non-negative integer", keyword))
+    }
+}
+
+fn as_integer(value: &Dynamic)-> Option<i128> {
+    match value {
+        Dynamic::Byte(b)=> Some(*b as i128),
+        Dynamic::Int(i)=> Some(*i as i128),
+        Dynamic::UInt(u)=> Some(*u as i128),
+        _=> None
+    }
+}
+
+//整数之间精确比较 和浮点数比较时浮点数必须正好是这个整数 不经过 f64 舍入
+fn number_eq(a: &Dynamic, b: &Dynamic)-> Option<bool> {
+    let (x, y) = (as_number(a)?, as_number(b)?);
+    Some(match (as_integer(a), as_integer(b)) {
+        (Some(i), Some(j))=> i == j,
+        (Some(i), None)=> y.fract() == 0.0 && y.abs() < 2f64.powi(127) && y as i128 == i,
+        (None, Some(j))=> x.fract() == 0.0 && x.abs() < 2f64.powi(127) && x as i128 == j,
+        (None, None)=> x == y
+    })
+}
+
+//Map 和 AnyMap 的 key 都按照字符串比较
+fn object_entries(value: &Dynamic)-> Option<Vec<(SmolStr, Dynamic)>> {
+    match value {
+        Dynamic::Map(m)=> Some(m.read_recursive().iter().map(|(k, v)| (k.clone(), v.clone())).collect()),
+        Dynamic::AnyMap(m)=> Some(m.read_recursive().iter().map(|(k, v)| (k.key_text(), v.clone())).collect()),
+        _=> None
+    }
+}
+
+//json 语义的相等 1 和 1.0 相等 map 不考虑 key 的顺序
+fn json_eq(a: &Dynamic, b: &Dynamic)-> bool {
+    if let Some(equal) = number_eq(a, b) {
+        return equal;
+    }
+    if let (Some(x), Some(y)) = (object_entries(a), object_entries(b)) {
+        return x.len() == y.len() && x.iter().all(|(k, v)| y.iter().any(|(l, w)| k == l && json_eq(v, w)));
+    }
+    match (a, b) {
+        (Dynamic::Vec(x), Dynamic::Vec(y))=> {
+            let (x, y) = (x.read_recursive(), y.read_recursive());
+            x.len() == y.len() && x.iter().zip(y.iter()).all(|(i, j)| json_eq(i, j))
+        }
+        _=> a == b
+    }
+}
+
+fn type_name(value: &Dynamic)-> &'static str {
+    match value {
+        Dynamic::Null=> "null",
+        Dynamic::Bool(_)=> "boolean",
+        Dynamic::Byte(_) | Dynamic::Int(_) | Dynamic::UInt(_)=> "integer",
+        Dynamic::Float(_) | Dynamic::Double(_)=> "number",
+        Dynamic::String(_)=> "string",
+        Dynamic::Vec(_)=> "array",
+        Dynamic::Map(_) | Dynamic::AnyMap(_)=> "object",
+        Dynamic::Bytes(_)=> "bytes",
+    }
+}
+
+fn is_type(value: &Dynamic, name: &str)-> bool {
+    match name {
+        "integer"=> match value {
+            Dynamic::Float(f)=> f.fract() == 0.0,
+            Dynamic::Double(f)=> f.fract() == 0.0,
+            _=> type_name(value) == "integer"
+        },
+        "number"=> as_number(value).is_some(),
+        _=> type_name(value) == name
+    }
+}
+
+impl Schema {
+    pub fn compile(schema: &Dynamic)-> Result<Self> {
+        let mut compiled = Self { nodes: Vec::new(), pointers: HashMap::new() };
+        compiled.compile_node(schema, String::new())?;
+        for index in 0..compiled.nodes.len() {               //所有子 schema 都编译完了再解析 $ref
+            let target = match &compiled.nodes[index].1 {
+                Node::Object(k)=> k.reference.as_ref().map(|(r, _)| r.clone()),
+                _=> None
+            };
+            if let Some(reference) = target {
+                let pointer = reference.strip_prefix('#').ok_or(anyhow!("only local $ref is supported: {}", reference))?;
+                let id = *compiled.pointers.get(pointer).ok_or(anyhow!("$ref {} is not found", reference))?;
+                if let Node::Object(k) = &mut compiled.nodes[index].1 {
+                    k.reference = Some((reference, id));
+                }
+            }
+        }
+        Ok(compiled)
+    }
+
+    fn compile_list(&mut self, value: &Dynamic, path: &str, keyword: &str)-> Result<Vec<usize>> {
+        let items = value.clone().into_vec().map_err(|_| anyhow!("{} must be an array", keyword))?;
+        items.iter().enumerate().map(|(i, item)| self.compile_node(item, format!("{}/{}/{}", path, keyword, i))).collect()
+    }
+
+    fn compile_node(&mut self, schema: &Dynamic, path: String)-> Result<usize> {
+        let id = self.nodes.len();
+        self.pointers.insert(path.clone(), id);
+        if let Dynamic::Bool(b) = schema {
+            self.nodes.push((path, Node::Bool(*b)));
+            return Ok(id);
+        }
+        if !schema.is_map() {
+            return Err(anyhow!("schema at {} must be an object or a bool", if path.is_empty() { "/" } else { &path }));
+        }
+        self.nodes.push((path.clone(), Node::Bool(true)));          //先占位 子 schema 的下标在它后面
+        let mut k = Keywords::default();
+        for (key, value) in schema.entries()? {
+            match key.as_str() {
+                "type"=> {
+                    k.types = match &value {
+                        Dynamic::String(s)=> vec![s.as_ref().clone()],
+                        Dynamic::Vec(_)=> value.clone().into_iter().map(|t| t.into_string()).collect::<Result<_>>().map_err(|_| anyhow!("type must be a string or an array of strings"))?,
+                        _=> return Err(anyhow!("type must be a string or an array of strings"))
+                    };
+                }
+                "properties"=> {
+                    for (name, sub) in value.entries().map_err(|_| anyhow!("properties must be an object"))? {
+                        let sub = self.compile_node(&sub, format!("{}/properties/{}", path, escape(&name)))?;
+                        k.properties.push((name, sub));
+                    }
+                }
+                "$defs" | "definitions"=> {
+                    for (name, sub) in value.entries().map_err(|_| anyhow!("{} must be an object", key))? {
+                        self.compile_node(&sub, format!("{}/{}/{}", path, key, escape(&name)))?;
+                    }
+                }
+                "required"=> k.required = value.clone().into_iter().map(|t| t.into_string()).collect::<Result<_>>().map_err(|_| anyhow!("required must be an array of strings"))?,
+                "prefixItems"=> k.prefix_items = self.compile_list(&value, &path, "prefixItems")?,
+                "items"=> k.items = Some(self.compile_node(&value, format!("{}/items", path))?),
+                "additionalProperties"=> k.additional = Some(self.compile_node(&value, format!("{}/additionalProperties", path))?),
+                "not"=> k.not = Some(self.compile_node(&value, format!("{}/not", path))?),
+                "enum"=> k.enumeration = Some(value.clone().into_vec().map_err(|_| anyhow!("enum must be an array"))?),
+                "const"=> k.constant = Some(value),
+                "anyOf"=> k.any_of = self.compile_list(&value, &path, "anyOf")?,
+                "oneOf"=> k.one_of = self.compile_list(&value, &path, "oneOf")?,
+                "allOf"=> k.all_of = self.compile_list(&value, &path, "allOf")?,
+                "$ref"=> k.reference = Some((value.as_str().map_err(|_| anyhow!("$ref must be a string"))?.to_string(), 0)),
+                "pattern"=> k.pattern = Some(Regex::new(value.as_str().map_err(|_| anyhow!("pattern must be a string"))?)?),
+                "minimum"=> k.minimum = as_number(&value),
+                "maximum"=> k.maximum = as_number(&value),
+                "exclusiveMinimum"=> k.exclusive_minimum = as_number(&value),
+                "exclusiveMaximum"=> k.exclusive_maximum = as_number(&value),
+                "minLength"=> k.min_length = Some(as_count(&value, "minLength")?),
+                "maxLength"=> k.max_length = Some(as_count(&value, "maxLength")?),
+                "minItems"=> k.min_items = Some(as_count(&value, "minItems")?),
+                "maxItems"=> k.max_items = Some(as_count(&value, "maxItems")?),
+                _=> {}
+            }
+        }
+        self.nodes[id].1 = Node::Object(Box::new(k));
+        Ok(id)
+    }
+
+    //返回所有的错误 没有错误就是校验通过
+    pub fn validate(&self, instance: &Dynamic)-> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        self.validate_node(0, instance, &mut String::new(), &mut errors, &mut Vec::new());
+        errors
+    }
+
+    pub fn is_valid(&self, instance: &Dynamic)-> bool {
+        self.validate(instance).is_empty()
+    }
+
+    //stack 记录当前路径上的 (schema 下标, 被校验的值) 同一个值再次进入同一个 schema 说明 $ref 循环了 不会结束
+    //容器用 container_id 所以循环引用的值也能发现 读容器用 read_recursive 同一个容器嵌套读的时候不会死锁
+    fn validate_node(&self, id: usize, instance: &Dynamic, path: &mut String, errors: &mut Vec<ValidationError>, stack: &mut Vec<(usize, usize)>) {
+        let (schema_path, node) = &self.nodes[id];
+        let visit = (id, instance.container_id().unwrap_or(instance as *const Dynamic as usize));
+        if stack.contains(&visit) {
+            errors.push(ValidationError { instance_path: path.clone(), schema_path: schema_path.clone(), message: CYCLE.into() });
+            return;
+        }
+        stack.push(visit);
+        self.validate_keywords(node, schema_path, instance, path, errors, stack);
+        stack.pop();
+    }
+
+    fn validate_keywords(&self, node: &Node, schema_path: &str, instance: &Dynamic, path: &mut String, errors: &mut Vec<ValidationError>, stack: &mut Vec<(usize, usize)>) {
+        let mut error = |keyword: &str, message: String| errors.push(ValidationError {
+            instance_path: path.clone(),
+            schema_path: format!("{}/{}", schema_path, keyword),
+            message,
+        });
+        let k = match node {
+            Node::Bool(true)=> return,
+            Node::Bool(false)=> {
+                errors.push(ValidationError { instance_path: path.clone(), schema_path: schema_path.to_string(), message: "schema is false".into() });
+                return;
+            }
+            Node::Object(k)=> k,
+        };
+        if !k.types.is_empty() && !k.types.iter().any(|t| is_type(instance, t)) {
+            error("type", format!("expected {} but found {}", k.types.join(" or "), type_name(instance)));
+        }
+        if let Some(values) = &k.enumeration {
+            if !values.iter().any(|v| json_eq(v, instance)) {
+                error("enum", "value is not one of the enum values".into());
+            }
+        }
+        if let Some(value) = &k.constant {
+            if !json_eq(value, instance) {
+                error("const", "value is not equal to const".into());
+            }
+        }
+        if let Some(number) = as_number(instance) {
+            if k.minimum.is_some_and(|m| number < m) {
+                error("minimum", format!("{} is less than {}", number, k.minimum.unwrap_or_default()));
+            }
+            if k.maximum.is_some_and(|m| number > m) {
+                error("maximum", format!("{} is greater than {}", number, k.maximum.unwrap_or_default()));
+            }
+            if k.exclusive_minimum.is_some_and(|m| number <= m) {
+                error("exclusiveMinimum", format!("{} is not greater than {}", number, k.exclusive_minimum.unwrap_or_default()));
+            }
+            if k.exclusive_maximum.is_some_and(|m| number >= m) {
+                error("exclusiveMaximum", format!("{} is not less than {}", number, k.exclusive_maximum.unwrap_or_default()));
+            }
+        }
+        if let Ok(s) = instance.as_str() {
+            let length = s.chars().count();
+            if k.min_length.is_some_and(|m| length < m) {
+                error("minLength", format!("length {} is less than {}", length, k.min_length.unwrap_or_default()));
+            }
+            if k.max_length.is_some_and(|m| length > m) {
+                error("maxLength", format!("length {} is greater than {}", length, k.max_length.unwrap_or_default()));
+            }
+            if let Some(pattern) = &k.pattern {
+                if !pattern.is_match(s) {
+                    error("pattern", format!("does not match pattern {}", pattern.as_str()));
+                }
+            }
+        }
+        if let Dynamic::Vec(v) = instance {
+            let items = v.read_recursive();
+            if k.min_items.is_some_and(|m| items.len() < m) {
+                error("minItems", format!("{} items is less than {}", items.len(), k.min_items.unwrap_or_default()));
+            }
+            if k.max_items.is_some_and(|m| items.len() > m) {
+                error("maxItems", format!("{} items is greater than {}", items.len(), k.max_items.unwrap_or_default()));
+            }
+            for (index, item) in items.iter().enumerate() {
+                let sub = k.prefix_items.get(index).copied().or(if index >= k.prefix_items.len() { k.items } else { None });
+                if let Some(sub) = sub {
+                    let len = path.len();
+                    path.push_str(&format!("/{}", index));
+                    self.validate_node(sub, item, path, errors, stack);
+                    path.truncate(len);
+                }
+            }
+        }
+        match instance {                            //AnyMap 的 key 用 key_text 和 get_key 一样
+            Dynamic::Map(m)=> {
+                let map = m.read_recursive();
+                self.validate_object(k, schema_path, map.iter().map(|(name, value)| (name.clone(), value)).collect(), path, errors, stack);
+            }
+            Dynamic::AnyMap(m)=> {
+                let pairs = m.read_recursive();
+                self.validate_object(k, schema_path, pairs.iter().map(|(name, value)| (name.key_text(), value)).collect(), path, errors, stack);
+            }
+            _=> {}
+        }
+        for sub in k.all_of.iter() {
+            self.validate_node(*sub, instance, path, errors, stack);
+        }
+        if !k.any_of.is_empty() && !k.any_of.iter().any(|sub| self.check(*sub, instance, path, errors, stack)) {
+            errors.push(ValidationError { instance_path: path.clone(), schema_path: format!("{}/anyOf", schema_path), message: "value does not match any schema".into() });
+        }
+        if !k.one_of.is_empty() {
+            let matched = k.one_of.iter().filter(|sub| self.check(**sub, instance, path, errors, stack)).count();
+            if matched != 1 {
+                errors.push(ValidationError { instance_path: path.clone(), schema_path: format!("{}/oneOf", schema_path), message: format!("value matches {} schemas instead of exactly one", matched) });
+            }
+        }
+        if let Some(sub) = k.not {
+            if self.check(sub, instance, path, errors, stack) {
+                errors.push(ValidationError { instance_path: path.clone(), schema_path: format!("{}/not", schema_path), message: "value should not match the schema".into() });
+            }
+        }
+        if let Some((_, target)) = &k.reference {
+            self.validate_node(*target, instance, path, errors, stack);
+        }
+    }
+
+    fn validate_object(&self, k: &Keywords, schema_path: &str, entries: Vec<(SmolStr, &Dynamic)>, path: &mut String, errors: &mut Vec<ValidationError>, stack: &mut Vec<(usize, usize)>) {
+        for name in k.required.iter() {
+            if !entries.iter().any(|(key, _)| key == name) {
+                errors.push(ValidationError { instance_path: path.clone(), schema_path: format!("{}/required", schema_path), message: format!("property {} is required", name) });
+            }
+        }
+        for (name, value) in entries {
+            let sub = k.properties.iter().find(|(p, _)| *p == name).map(|(_, sub)| *sub).or(k.additional);
+            if let Some(sub) = sub {
+                let len = path.len();
+                path.push('/');
+                path.push_str(&escape(&name));
+                self.validate_node(sub, value, path, errors, stack);
+                path.truncate(len);
+            }
+        }
+    }
+
+    //anyOf oneOf not 只需要知道是否通过 和外面共用 stack 这样经过它们的 $ref 循环也能发现 循环的错误要报告出去
+    fn check(&self, id: usize, instance: &Dynamic, path: &mut String, errors: &mut Vec<ValidationError>, stack: &mut Vec<(usize, usize)>)-> bool {
+        let mut result = Vec::new();
+        self.validate_node(id, instance, path, &mut result, stack);
+        let valid = result.is_empty();
+        errors.extend(result.into_iter().filter(|e| e.message == CYCLE));
+        valid
+    }
+}
+
+//从 rust 类型生成 JSON Schema 用来声明 function calling 的工具参数 一般用 #[derive(ToolSchema)] 实现
+//schema_body 是类型本身的 schema schema_ref 是字段引用这个类型时使用的 schema
+//derive 生成的类型放到 $defs 里 字段里使用 $ref 基本类型直接内联
+pub trait ToolSchema {
+    fn schema_body(defs: &Dynamic)-> Dynamic;
+
+    fn schema_ref(defs: &Dynamic)-> Dynamic {
+        Self::schema_body(defs)
+    }
+
+    //Option 的字段不放到 required 里
+    fn is_optional()-> bool {
+        false
+    }
+
+    fn tool_schema()-> Dynamic {
+        let defs = Dynamic::map();
+        let schema = Self::schema_body(&defs);
+        let _ = defs.remove_key(DEF_TYPES);
+        if defs.len().unwrap_or_default() > 0 {
+            let _ = schema.set_key("$defs", defs);
+        }
+        schema
+    }
+}
+
+//$defs 里记录每个名字是哪个类型 tool_schema 输出前删掉
+const DEF_TYPES: &str = "$types";
+
+//derive 生成的 schema_ref 调用 先放一个占位再生成 body 这样递归的类型不会死循环
+//名字已经被别的类型用了 (例如 a::Config 和 b::Config) 换成带模块路径的名字
+pub fn schema_define<T: ToolSchema + ?Sized>(defs: &Dynamic, name: &str)-> Dynamic {
+    let types = defs.get_key(DEF_TYPES).unwrap_or_else(|_| {
+        let types = Dynamic::map();
+        let _ = defs.set_key(DEF_TYPES, types.clone());
+        types
+    });
+    let type_name = std::any::type_name::<T>();
+    let name = match types.get_key(name) {
+        Ok(owner) if owner.as_str().unwrap_or_default() != type_name=> sanitize(type_name),
+        _=> name.to_string()
+    };
+    if !defs.contains(&name).unwrap_or_default() {
+        let _ = types.set_key(&name, type_name);
+        let _ = defs.set_key(&name, Dynamic::Null);
+        let body = T::schema_body(defs);
+        let _ = defs.set_key(&name, body);
+    }
+    let reference = Dynamic::map();
+    let _ = reference.set_key("$ref", format!("#/$defs/{}", escape(&name)));
+    reference
+}
+
+//泛型类型在 $defs 里的名字 去掉模块路径 其他符号换成 _ 例如 a::W<alloc::string::String> 是 W_String
+pub fn def_name<T: ?Sized>()-> String {
+    let mut path = String::new();
+    let mut start = 0;
+    for c in std::any::type_name::<T>().chars() {
+        path.push(c);
+        if path.ends_with("::") {
+            path.truncate(start);
+        } else if !c.is_alphanumeric() && c != '_' && c != ':' {
+            start = path.len();
+        }
+    }
+    sanitize(&path)
+}
+
+fn sanitize(path: &str)-> String {
+    let mut name = String::new();
+    for c in path.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            name.push(c);
+        } else if !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    name.trim_end_matches('_').to_string()
+}
+
+fn type_schema(name: &str)-> Dynamic {
+    let schema = Dynamic::map();
+    let _ = schema.set_key("type", name);
+    schema
+}
+
+macro_rules! primitive_schema {
+    ($name: expr, $($t:ty),*) => {$(
+        impl ToolSchema for $t {
+            fn schema_body(_defs: &Dynamic)-> Dynamic {
+                type_schema($name)
+            }
+        }
+    )*};
+}
+
+primitive_schema!("string", String, str, SmolStr, char);
+primitive_schema!("boolean", bool);
+primitive_schema!("integer", i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+primitive_schema!("number", f32, f64);
+
+impl ToolSchema for Dynamic {                   //任意值
+    fn schema_body(_defs: &Dynamic)-> Dynamic {
+        Dynamic::map()
+    }
+}
+
+//ToJson 把 None 写成 null 所以 schema 也允许 null
+impl<T: ToolSchema> ToolSchema for Option<T> {
+    fn schema_body(defs: &Dynamic)-> Dynamic {
+        let schema = Dynamic::map();
+        let _ = schema.set_key("anyOf", Dynamic::from_vec(vec![T::schema_ref(defs), type_schema("null")]));
+        schema
+    }
+
+    fn is_optional()-> bool {
+        true
+    }
+}
+
+impl<T: ToolSchema + ?Sized> ToolSchema for Box<T> {
+    fn schema_body(defs: &Dynamic)-> Dynamic {
+        T::schema_ref(defs)
+    }
+}
+
+impl<T: ToolSchema + ?Sized> ToolSchema for &T {
+    fn schema_body(defs: &Dynamic)-> Dynamic {
+        T::schema_ref(defs)
+    }
+}
+
+impl<T: ToolSchema> ToolSchema for Vec<T> {
+    fn schema_body(defs: &Dynamic)-> Dynamic {
+        let schema = type_schema("array");
+        let _ = schema.set_key("items", T::schema_ref(defs));
+        schema
+    }
+}
+
+impl<T: ToolSchema> ToolSchema for [T] {
+    fn schema_body(defs: &Dynamic)-> Dynamic {
+        Vec::<T>::schema_body(defs)
+    }
+}
+
+impl<K, V: ToolSchema> ToolSchema for std::collections::BTreeMap<K, V> {
+    fn schema_body(defs: &Dynamic)-> Dynamic {
+        let schema = type_schema("object");
+        let _ = schema.set_key("additionalProperties", V::schema_ref(defs));
+        schema
+    }
+}
+
+impl<K, V: ToolSchema, S> ToolSchema for HashMap<K, V, S> {
+    fn schema_body(defs: &Dynamic)-> Dynamic {
+        std::collections::BTreeMap::<K, V>::schema_body(defs)
+    }
+}
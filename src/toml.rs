@@ -0,0 +1,721 @@
+use anyhow::{anyhow, Result};
+use smol_str::SmolStr;
+use std::collections::HashSet;
+use super::dynamic::Dynamic;
+use super::assert_err;
+
+pub const DATETIME: &str = "$datetime";
+
+//TOML 1.0 解析成 Map 日期时间变成 {"$datetime": "1979-05-27T07:32:00Z"} 和字符串区分开 write_toml 写回不带引号的日期时间
+//里面是 RFC 3339 的写法 (空格分隔的写成 T) 带时区的 本地日期时间 本地日期 本地时间从字符串的形式就能看出来
+pub fn parse(text: &str) -> Result<Dynamic> {
+    let mut parser = Parser {
+        text,
+        buf: text.as_bytes(),
+        pos: 0,
+        headers: HashSet::new(),
+        dotted: HashSet::new(),
+        inline: HashSet::new(),
+        arrays: HashSet::new(),
+    };
+    parser.document()
+}
+
+fn is_bare(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b'-'
+}
+
+fn id(value: &Dynamic) -> usize {
+    value.container_id().unwrap_or_default()
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    buf: &'a [u8],
+    pos: usize,
+    headers: HashSet<usize>,                                //[table] 定义过的表
+    dotted: HashSet<usize>,                                 //a.b = 1 隐式生成的表
+    inline: HashSet<usize>,                                 //{ } 定义的表 不能再扩展
+    arrays: HashSet<usize>,                                 //[[table]] 生成的数组
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.buf.get(self.pos).copied()
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        let line_start = self.buf[..self.pos].iter().rposition(|c| *c == b'\n').map_or(0, |p| p + 1);
+        let line = self.buf[..self.pos].iter().filter(|c| **c == b'\n').count() + 1;
+        anyhow!("{} at line {} column {}", message, line, self.pos - line_start + 1)
+    }
+
+    fn expect(&mut self, token: &str) -> Result<()> {
+        assert_err!(!self.buf[self.pos..].starts_with(token.as_bytes()), self.error(&format!("expected {}", token)));
+        self.pos += token.len();
+        Ok(())
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    //跳过空白 注释和换行
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                Some(b'#')=> while !matches!(self.peek(), None | Some(b'\n')) {
+                    self.pos += 1;
+                }
+                Some(b'\r' | b'\n')=> self.pos += 1,
+                _=> break
+            }
+        }
+    }
+
+    //键值对和表头后面只能有注释
+    fn line_end(&mut self) -> Result<()> {
+        self.skip_spaces();
+        if self.peek() == Some(b'#') {
+            while !matches!(self.peek(), None | Some(b'\n')) {
+                self.pos += 1;
+            }
+        }
+        if self.buf[self.pos..].starts_with(b"\r\n") {
+            self.pos += 1;
+        }
+        match self.peek() {
+            None=> Ok(()),
+            Some(b'\n')=> {
+                self.pos += 1;
+                Ok(())
+            }
+            _=> Err(self.error("expected a new line"))
+        }
+    }
+
+    fn document(&mut self) -> Result<Dynamic> {
+        let root = Dynamic::map();
+        let mut current = root.clone();
+        loop {
+            self.skip_blank_lines();
+            match self.peek() {
+                None=> break,
+                Some(b'[')=> {
+                    let array = self.buf[self.pos..].starts_with(b"[[");
+                    self.pos += 1 + array as usize;
+                    let start = self.pos;
+                    let keys = self.keys()?;
+                    self.skip_spaces();
+                    self.expect(if array { "]]" } else { "]" })?;
+                    current = self.table(&root, &keys, array).map_err(|e| {
+                        self.pos = start;
+                        self.error(&e.to_string())
+                    })?;
+                }
+                Some(_)=> {
+                    let start = self.pos;
+                    let keys = self.keys()?;
+                    self.skip_spaces();
+                    self.expect("=")?;
+                    self.skip_spaces();
+                    let value = self.value()?;
+                    self.insert(&current, &keys, value).map_err(|e| {
+                        self.pos = start;
+                        self.error(&e.to_string())
+                    })?;
+                }
+            }
+            self.line_end()?;
+        }
+        Ok(root)
+    }
+
+    //[a.b] 和 [[a.b]] 找到或者建立对应的表 中间经过的表数组取最后一个元素
+    fn table(&mut self, root: &Dynamic, keys: &[SmolStr], array: bool) -> Result<Dynamic> {
+        let mut current = root.clone();
+        let Some((last, path)) = keys.split_last() else { return Err(anyhow!("empty table name")) };
+        for key in path {
+            current = match current.contains(key)? {
+                true=> match current.get_key(key)? {
+                    next @ Dynamic::Map(_) if !self.inline.contains(&id(&next))=> next,
+                    next @ Dynamic::Vec(_) if self.arrays.contains(&id(&next))=> next.get(next.len()? - 1)?,
+                    _=> return Err(anyhow!("key {} is already defined as a value", key))
+                }
+                false=> {
+                    let table = Dynamic::map();
+                    current.set_key(key, table.clone())?;
+                    table
+                }
+            };
+        }
+        if array {
+            let table = Dynamic::map();
+            match current.contains(last)? {
+                true=> match current.get_key(last)? {
+                    tables @ Dynamic::Vec(_) if self.arrays.contains(&id(&tables))=> tables.push(table.clone())?,
+                    _=> return Err(anyhow!("key {} is not an array of tables", last))
+                }
+                false=> {
+                    let tables = Dynamic::from_vec(vec![table.clone()]);
+                    self.arrays.insert(id(&tables));
+                    current.set_key(last, tables)?;
+                }
+            }
+            return Ok(table);
+        }
+        let table = match current.contains(last)? {
+            true=> match current.get_key(last)? {
+                table @ Dynamic::Map(_) if ![&self.headers, &self.dotted, &self.inline].iter().any(|set| set.contains(&id(&table)))=> table,
+                _=> return Err(anyhow!("table {} is already defined", last))
+            }
+            false=> {
+                let table = Dynamic::map();
+                current.set_key(last, table.clone())?;
+                table
+            }
+        };
+        self.headers.insert(id(&table));
+        Ok(table)
+    }
+
+    //a.b.c = 1 中间的表不存在就建立
+    fn insert(&mut self, table: &Dynamic, keys: &[SmolStr], value: Dynamic) -> Result<()> {
+        let mut current = table.clone();
+        let Some((last, path)) = keys.split_last() else { return Err(anyhow!("empty key")) };
+        for key in path {
+            current = match current.contains(key)? {
+                true=> match current.get_key(key)? {
+                    next @ Dynamic::Map(_) if !self.inline.contains(&id(&next))=> next,
+                    _=> return Err(anyhow!("key {} is already defined as a value", key))
+                }
+                false=> {
+                    let next = Dynamic::map();
+                    current.set_key(key, next.clone())?;
+                    self.dotted.insert(id(&next));
+                    next
+                }
+            };
+        }
+        assert_err!(current.contains(last)?, anyhow!("duplicate key {}", last));
+        current.set_key(last, value)?;
+        Ok(())
+    }
+
+    fn keys(&mut self) -> Result<Vec<SmolStr>> {
+        let mut keys = Vec::new();
+        loop {
+            self.skip_spaces();
+            let key = match self.peek() {
+                Some(b'"')=> self.basic(false)?,
+                Some(b'\'')=> self.literal(false)?,
+                _=> {
+                    let start = self.pos;
+                    while self.peek().is_some_and(is_bare) {
+                        self.pos += 1;
+                    }
+                    assert_err!(start == self.pos, self.error("expected a key"));
+                    self.text[start..self.pos].to_string()
+                }
+            };
+            keys.push(SmolStr::from(key));
+            self.skip_spaces();
+            if self.peek() != Some(b'.') {
+                break;
+            }
+            self.pos += 1;
+        }
+        Ok(keys)
+    }
+
+    fn value(&mut self) -> Result<Dynamic> {
+        match self.peek() {
+            Some(b'"')=> Ok(Dynamic::from(self.basic(self.buf[self.pos..].starts_with(b"\"\"\""))?)),
+            Some(b'\'')=> Ok(Dynamic::from(self.literal(self.buf[self.pos..].starts_with(b"'''"))?)),
+            Some(b'[')=> self.array(),
+            Some(b'{')=> self.inline_table(),
+            _=> {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, b'_' | b'+' | b'-' | b'.' | b':')) {
+                    self.pos += 1;
+                    //日期和时间之间可以是空格
+                    if self.pos - start == 10 && self.peek() == Some(b' ') && self.buf.get(self.pos + 1).is_some_and(u8::is_ascii_digit) && self.buf[start + 4] == b'-' {
+                        self.pos += 1;
+                    }
+                }
+                let token = &self.text[start..self.pos];
+                let value = match token {
+                    "true"=> Some(Dynamic::Bool(true)),
+                    "false"=> Some(Dynamic::Bool(false)),
+                    _=> datetime(token).map(|text| crate::dmap!(DATETIME => text)).or_else(|| number(token))
+                };
+                value.ok_or_else(|| {
+                    self.pos = start;
+                    self.error(&format!("invalid value {:?}", token))
+                })
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Dynamic> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            if self.peek() == Some(b']') {
+                break;
+            }
+            items.push(self.value()?);
+            self.skip_blank_lines();
+            match self.peek() {
+                Some(b',')=> self.pos += 1,
+                Some(b']')=> break,
+                _=> return Err(self.error("expected , or ]"))
+            }
+        }
+        self.pos += 1;
+        Ok(Dynamic::from_vec(items))
+    }
+
+    //和 TOML 1.1 一样允许换行和最后的逗号
+    fn inline_table(&mut self) -> Result<Dynamic> {
+        self.pos += 1;
+        let table = Dynamic::map();
+        loop {
+            self.skip_blank_lines();
+            if self.peek() == Some(b'}') {
+                break;
+            }
+            let start = self.pos;
+            let keys = self.keys()?;
+            self.expect("=")?;
+            self.skip_spaces();
+            let value = self.value()?;
+            self.insert(&table, &keys, value).map_err(|e| {
+                self.pos = start;
+                self.error(&e.to_string())
+            })?;
+            self.skip_blank_lines();
+            match self.peek() {
+                Some(b',')=> self.pos += 1,
+                Some(b'}')=> break,
+                _=> return Err(self.error("expected , or }"))
+            }
+        }
+        self.pos += 1;
+        self.inline.insert(id(&table));
+        Ok(table)
+    }
+
+    //" 和 """ 的字符串 多行的开头紧跟的换行去掉 行尾的 \ 连接下一行
+    fn basic(&mut self, multiline: bool) -> Result<String> {
+        let start = self.pos;
+        self.pos += if multiline { 3 } else { 1 };
+        if multiline {
+            self.skip_newline();
+        }
+        let mut text = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                self.pos = start;
+                return Err(self.error("unterminated string"));
+            };
+            match c {
+                b'"' if !multiline=> {
+                    self.pos += 1;
+                    break;
+                }
+                b'"' if self.buf[self.pos..].starts_with(b"\"\"\"")=> {
+                    let quotes = self.buf[self.pos..].iter().take_while(|c| **c == b'"').count().min(5);
+                    (3..quotes).for_each(|_| text.push('"'));
+                    self.pos += quotes;
+                    break;
+                }
+                b'\\'=> self.escape(&mut text, multiline)?,
+                b'\n' | b'\r' if !multiline=> return Err(self.error("new line in a string")),
+                _=> self.char(&mut text)?
+            }
+        }
+        Ok(text)
+    }
+
+    //' 和 ''' 的字符串没有转义
+    fn literal(&mut self, multiline: bool) -> Result<String> {
+        let start = self.pos;
+        self.pos += if multiline { 3 } else { 1 };
+        if multiline {
+            self.skip_newline();
+        }
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None=> {
+                    self.pos = start;
+                    return Err(self.error("unterminated string"));
+                }
+                Some(b'\'') if !multiline=> {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'\'') if self.buf[self.pos..].starts_with(b"'''")=> {
+                    let quotes = self.buf[self.pos..].iter().take_while(|c| **c == b'\'').count().min(5);
+                    (3..quotes).for_each(|_| text.push('\''));
+                    self.pos += quotes;
+                    break;
+                }
+                Some(b'\n' | b'\r') if !multiline=> return Err(self.error("new line in a string")),
+                _=> self.char(&mut text)?
+            }
+        }
+        Ok(text)
+    }
+
+    fn skip_newline(&mut self) {
+        if self.buf[self.pos..].starts_with(b"\r\n") {
+            self.pos += 2;
+        } else if self.peek() == Some(b'\n') {
+            self.pos += 1;
+        }
+    }
+
+    //字符串里除了 tab 和多行里的换行 不能有控制字符
+    fn char(&mut self, text: &mut String) -> Result<()> {
+        let c = self.text[self.pos..].chars().next().unwrap_or_default();
+        if c.is_control() && c != '\t' && c != '\n' && !self.buf[self.pos..].starts_with(b"\r\n") {
+            return Err(self.error("control character in a string"));
+        }
+        if c != '\r' {
+            text.push(c);
+        }
+        self.pos += c.len_utf8();
+        Ok(())
+    }
+
+    fn escape(&mut self, text: &mut String, multiline: bool) -> Result<()> {
+        self.pos += 1;
+        let c = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+        if multiline && matches!(c, b' ' | b'\t' | b'\r' | b'\n') {       //行尾的 \ 去掉后面所有的空白
+            let end = self.buf[self.pos..].iter().position(|c| !matches!(c, b' ' | b'\t' | b'\r' | b'\n')).map_or(self.buf.len(), |p| self.pos + p);
+            assert_err!(!self.buf[self.pos..end].contains(&b'\n'), self.error("invalid escape"));
+            self.pos = end;
+            return Ok(());
+        }
+        self.pos += 1;
+        let length = match c {
+            b'u'=> 4,
+            b'U'=> 8,
+            _=> {
+                text.push(match c {
+                    b'b'=> '\x08',
+                    b't'=> '\t',
+                    b'n'=> '\n',
+                    b'f'=> '\x0c',
+                    b'r'=> '\r',
+                    b'e'=> '\x1b',
+                    b'"'=> '"',
+                    b'\\'=> '\\',
+                    _=> {
+                        self.pos -= 2;
+                        return Err(self.error(&format!("invalid escape \\{}", c as char)));
+                    }
+                });
+                return Ok(());
+            }
+        };
+        let code = self.text.get(self.pos..self.pos + length).and_then(|hex| u32::from_str_radix(hex, 16).ok()).and_then(char::from_u32);
+        let code = code.ok_or_else(|| self.error("invalid unicode escape"))?;
+        text.push(code);
+        self.pos += length;
+        Ok(())
+    }
+}
+
+//整数不能有多余的 0 下划线必须在两个数字之间
+fn digits(text: &str, radix: u32) -> Option<String> {
+    let bytes = text.as_bytes();
+    let valid = !text.is_empty() && bytes.iter().enumerate().all(|(i, c)| match c {
+        b'_'=> i > 0 && i + 1 < bytes.len() && bytes[i - 1] != b'_' && (bytes[i + 1] as char).is_digit(radix),
+        _=> (*c as char).is_digit(radix)
+    });
+    valid.then(|| text.replace('_', ""))
+}
+
+fn number(token: &str) -> Option<Dynamic> {
+    match token {
+        "inf" | "+inf"=> return Some(Dynamic::Double(f64::INFINITY)),
+        "-inf"=> return Some(Dynamic::Double(f64::NEG_INFINITY)),
+        "nan" | "+nan" | "-nan"=> return Some(Dynamic::Double(f64::NAN)),
+        _=> {}
+    }
+    for (prefix, radix) in [("0x", 16), ("0o", 8), ("0b", 2)] {
+        if let Some(rest) = token.strip_prefix(prefix) {
+            return i64::from_str_radix(&digits(rest, radix)?, radix).ok().map(Dynamic::Int);
+        }
+    }
+    let unsigned = token.strip_prefix(['+', '-']).unwrap_or(token);
+    let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+        Some(p)=> (&unsigned[..p], Some(&unsigned[p + 1..])),
+        None=> (unsigned, None)
+    };
+    let (integer, fraction) = match mantissa.split_once('.') {
+        Some((integer, fraction))=> (integer, Some(fraction)),
+        None=> (mantissa, None)
+    };
+    let integer = digits(integer, 10)?;
+    if integer.len() > 1 && integer.starts_with('0') {
+        return None;
+    }
+    let sign = if token.starts_with('-') { "-" } else { "" };
+    if fraction.is_none() && exponent.is_none() {
+        return format!("{}{}", sign, integer).parse().ok().map(Dynamic::Int);
+    }
+    let mut text = format!("{}{}", sign, integer);
+    if let Some(fraction) = fraction {
+        text = format!("{}.{}", text, digits(fraction, 10)?);
+    }
+    if let Some(exponent) = exponent {
+        let unsigned = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        text = format!("{}e{}{}", text, if exponent.starts_with('-') { "-" } else { "" }, digits(unsigned, 10)?);
+    }
+    text.parse().ok().map(Dynamic::Double)
+}
+
+//日期 日期时间 时间 检查范围后统一成 RFC 3339 的写法
+fn datetime(token: &str) -> Option<String> {
+    let field = |text: &str, range: std::ops::Range<usize>, max: u32| -> Option<u32> {
+        let part = text.get(range)?;
+        part.bytes().all(|c| c.is_ascii_digit()).then(|| part.parse().ok()).flatten().filter(|v| *v <= max)
+    };
+    let mut result = String::new();
+    let mut rest = token;
+    let date = token.len() >= 10 && token.as_bytes()[4] == b'-' && token.as_bytes()[7] == b'-';
+    if date {
+        let year = field(token, 0..4, 9999)?;
+        let month = field(token, 5..7, 12).filter(|m| *m > 0)?;
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        let days = [31, if leap { 29 } else { 28 }, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31][month as usize - 1];
+        field(token, 8..10, days).filter(|d| *d > 0)?;
+        result.push_str(&token[..10]);
+        rest = &token[10..];
+        if rest.is_empty() {
+            return Some(result);
+        }
+        if !rest.starts_with(['T', 't', ' ']) {
+            return None;
+        }
+        result.push('T');
+        rest = &rest[1..];
+    }
+    if rest.len() < 8 || rest.as_bytes()[2] != b':' || rest.as_bytes()[5] != b':' {
+        return None;
+    }
+    field(rest, 0..2, 23)?;
+    field(rest, 3..5, 59)?;
+    field(rest, 6..8, 60)?;
+    result.push_str(&rest[..8]);
+    rest = &rest[8..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let length = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if length == 0 {
+            return None;
+        }
+        result.push_str(&rest[..length + 1]);
+        rest = &fraction[length..];
+    }
+    match rest {
+        ""=> {}
+        "Z" | "z" if date=> result.push('Z'),
+        _ if date && rest.len() == 6 && rest.starts_with(['+', '-']) && rest.as_bytes()[3] == b':'=> {
+            field(rest, 1..3, 23)?;
+            field(rest, 4..6, 59)?;
+            result.push_str(rest);
+        }
+        _=> return None
+    }
+    Some(result)
+}
+
+//Map 写成 TOML 先写当前表的值 再写子表和表数组 null 和顶层不是 Map 的值不能表示
+pub fn write_toml(value: &Dynamic, buf: &mut String) -> Result<()> {
+    let entries = table_entries(value)?.ok_or_else(|| anyhow!("top-level value must be a table"))?;
+    let mut stack = vec![id(value)];
+    write_table(&entries, &mut Vec::new(), buf, &mut stack)
+}
+
+fn table_entries(value: &Dynamic) -> Result<Option<Vec<(SmolStr, Dynamic)>>> {
+    Ok(match value {
+        Dynamic::Map(m)=> Some(m.read().iter().map(|(k, v)| (k.clone(), v.clone())).collect()),
+        Dynamic::AnyMap(m)=> Some(m.read().iter().map(|(k, v)| Ok((k.to_key_string()?, v.clone()))).collect::<Result<_>>()?),
+        _=> None
+    })
+}
+
+fn is_table(value: &Dynamic) -> bool {
+    matches!(value, Dynamic::Map(_) | Dynamic::AnyMap(_)) && datetime_text(value).is_none()
+}
+
+//只有一个 $datetime 并且是合法的日期时间的 Map 形式不对的当作普通的表
+fn datetime_text(value: &Dynamic) -> Option<String> {
+    match value {
+        Dynamic::Map(m)=> {
+            let map = m.read();
+            if map.len() != 1 {
+                return None;
+            }
+            datetime(map.get(DATETIME)?.as_str().ok()?)
+        }
+        _=> None
+    }
+}
+
+//元素都是表的非空数组写成 [[name]]
+fn is_table_array(value: &Dynamic) -> bool {
+    match value {
+        Dynamic::Vec(v)=> {
+            let items = v.read();
+            !items.is_empty() && items.iter().all(is_table)
+        }
+        _=> false
+    }
+}
+
+fn enter(value: &Dynamic, stack: &mut Vec<usize>) -> Result<()> {
+    if let Some(id) = value.container_id() {
+        assert_err!(stack.contains(&id), anyhow!("cycle can not be written as toml"));
+        stack.push(id);
+    }
+    Ok(())
+}
+
+fn write_path(path: &[SmolStr], buf: &mut String) {
+    for (index, key) in path.iter().enumerate() {
+        if index > 0 {
+            buf.push('.');
+        }
+        write_key(key, buf);
+    }
+}
+
+fn write_table(entries: &[(SmolStr, Dynamic)], path: &mut Vec<SmolStr>, buf: &mut String, stack: &mut Vec<usize>) -> Result<()> {
+    for (key, value) in entries.iter().filter(|(_, v)| !is_table(v) && !is_table_array(v)) {
+        write_key(key, buf);
+        buf.push_str(" = ");
+        path.push(key.clone());
+        match value {
+            Dynamic::String(s)=> write_string(s, s.contains('\n'), buf),
+            _=> write_value(value, path, buf, stack)?
+        }
+        path.pop();
+        buf.push('\n');
+    }
+    for (key, value) in entries.iter().filter(|(_, v)| is_table(v)) {
+        path.push(key.clone());
+        enter(value, stack)?;
+        let table = table_entries(value)?.unwrap_or_default();
+        if table.is_empty() || table.iter().any(|(_, v)| !is_table(v) && !is_table_array(v)) {      //只有子表的表不用写表头
+            buf.push_str(if buf.is_empty() { "[" } else { "\n[" });
+            write_path(path, buf);
+            buf.push_str("]\n");
+        }
+        write_table(&table, path, buf, stack)?;
+        stack.pop();
+        path.pop();
+    }
+    for (key, value) in entries.iter().filter(|(_, v)| is_table_array(v)) {
+        path.push(key.clone());
+        enter(value, stack)?;
+        for item in value.clone().into_vec()? {
+            enter(&item, stack)?;
+            buf.push_str(if buf.is_empty() { "[[" } else { "\n[[" });
+            write_path(path, buf);
+            buf.push_str("]]\n");
+            write_table(&table_entries(&item)?.unwrap_or_default(), path, buf, stack)?;
+            stack.pop();
+        }
+        stack.pop();
+        path.pop();
+    }
+    Ok(())
+}
+
+//数组和行内表里的值
+fn write_value(value: &Dynamic, path: &[SmolStr], buf: &mut String, stack: &mut Vec<usize>) -> Result<()> {
+    match value {
+        Dynamic::Null=> return Err(anyhow!("null at {} can not be written as toml", path.join("."))),
+        Dynamic::Bool(b)=> buf.push_str(if *b { "true" } else { "false" }),
+        Dynamic::Byte(v)=> buf.push_str(&v.to_string()),
+        Dynamic::Int(v)=> buf.push_str(&v.to_string()),
+        Dynamic::UInt(v)=> {
+            assert_err!(*v > i64::MAX as u64, anyhow!("integer {} at {} is out of toml range", v, path.join(".")));
+            buf.push_str(&v.to_string());
+        }
+        Dynamic::Float(v) if v.is_finite()=> buf.push_str(&format!("{:?}", v)),
+        Dynamic::Double(v) if v.is_finite()=> buf.push_str(&format!("{:?}", v)),
+        Dynamic::Float(v)=> write_special(*v as f64, buf),
+        Dynamic::Double(v)=> write_special(*v, buf),
+        Dynamic::String(s)=> write_string(s, false, buf),
+        Dynamic::Bytes(_)=> return Err(anyhow!("bytes at {} can not be written as toml", path.join("."))),
+        Dynamic::Vec(v)=> {
+            enter(value, stack)?;
+            buf.push('[');
+            for (index, item) in v.read().clone().iter().enumerate() {
+                if index > 0 {
+                    buf.push_str(", ");
+                }
+                write_value(item, path, buf, stack)?;
+            }
+            buf.push(']');
+            stack.pop();
+        }
+        Dynamic::Map(_) | Dynamic::AnyMap(_)=> {
+            if let Some(text) = datetime_text(value) {
+                buf.push_str(&text);
+                return Ok(());
+            }
+            enter(value, stack)?;
+            buf.push('{');
+            for (index, (key, item)) in table_entries(value)?.unwrap_or_default().iter().enumerate() {
+                buf.push_str(if index > 0 { ", " } else { " " });
+                write_key(key, buf);
+                buf.push_str(" = ");
+                write_value(item, path, buf, stack)?;
+            }
+            buf.push_str(if buf.ends_with('{') { "}" } else { " }" });
+            stack.pop();
+        }
+    }
+    Ok(())
+}
+
+fn write_special(value: f64, buf: &mut String) {
+    buf.push_str(if value.is_nan() { "nan" } else if value > 0.0 { "inf" } else { "-inf" });
+}
+
+fn write_key(key: &str, buf: &mut String) {
+    if !key.is_empty() && key.bytes().all(is_bare) {
+        buf.push_str(key);
+    } else {
+        write_string(key, false, buf);
+    }
+}
+
+//表里多行的字符串例如 prompt 用 """ 写出来更好读
+fn write_string(s: &str, multiline: bool, buf: &mut String) {
+    buf.push_str(if multiline { "\"\"\"\n" } else { "\"" });
+    for c in s.chars() {
+        match c {
+            '"'=> buf.push_str("\\\""),
+            '\\'=> buf.push_str("\\\\"),
+            '\n' if multiline=> buf.push('\n'),
+            '\n'=> buf.push_str("\\n"),
+            '\t'=> buf.push('\t'),
+            '\r'=> buf.push_str("\\r"),
+            c if c.is_control()=> buf.push_str(&format!("\\u{:04x}", c as u32)),
+            c=> buf.push(c)
+        }
+    }
+    buf.push_str(if multiline { "\"\"\"" } else { "\"" });
+}
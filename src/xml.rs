@@ -0,0 +1,461 @@
+use anyhow::{anyhow, Result};
+use smol_str::SmolStr;
+use super::dynamic::Dynamic;
+use super::assert_err;
+
+//XML 和 Dynamic 的对应 元素变成 {名字: 内容} 属性是 "@名字" 文字是 "#text" 同名的子元素变成 Vec
+//只有文字的元素是字符串 空的元素是 null 所有的值都是字符串 不推断类型
+//<a x="1"><b>hi</b><b/>text</a> 是 {"a": {"@x": "1", "b": ["hi", null], "#text": "text"}}
+//文字和子元素混在一起时不保留顺序 所有的文字合并成一个 #text 写回时放在子元素前面
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    pub lenient: bool,                              //用于模型输出 标签外面的文字忽略 不配对的 < & 当作文字 没有关闭的标签到结尾自动关闭
+}
+
+pub fn parse(text: &str) -> Result<Dynamic> {
+    parse_with(text, &ParseOptions::default())
+}
+
+//严格模式只有一个根元素 结果是 {根元素名字: 内容} 宽松模式是所有顶层的元素 文字去掉两边的空白
+pub fn parse_with(text: &str, options: &ParseOptions) -> Result<Dynamic> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text).replace("\r\n", "\n");
+    let mut parser = Parser { text: &text, buf: text.as_bytes(), pos: 0, lenient: options.lenient };
+    let mut root = Element::default();
+    match options.lenient {
+        true=> while let Some(p) = parser.text[parser.pos..].find('<') {
+            parser.pos += p;
+            let start = parser.pos;
+            match parser.name_start(start + 1) {
+                true=> match parser.element(&mut Vec::new()) {
+                    Ok((name, value))=> root.add(name, value),
+                    Err(_)=> parser.pos = start + 1
+                }
+                false=> parser.pos += 1
+            }
+        }
+        false=> {
+            parser.misc()?;
+            assert_err!(!parser.text[parser.pos..].starts_with('<') || !parser.name_start(parser.pos + 1), parser.error("expect the root element"));
+            let (name, value) = parser.element(&mut Vec::new())?;
+            root.add(name, value);
+            parser.misc()?;
+            assert_err!(parser.pos < parser.buf.len(), parser.error("unexpected content after the root element"));
+        }
+    }
+    Ok(if root.children.is_null() { Dynamic::map() } else { root.children })
+}
+
+//模型输出里所有 <tag>...</tag> 的原始内容 不做解析和转义 最后一个没有关闭的到结尾为止
+pub fn extract(text: &str, tag: &str) -> Vec<String> {
+    let (open, close) = (format!("<{}", tag), format!("</{}>", tag));
+    let mut sections = Vec::new();
+    let mut rest = text;
+    while let Some(p) = rest.find(&open) {
+        rest = &rest[p + open.len()..];
+        let Some(end) = rest.find('>') else { break };
+        if !matches!(rest.as_bytes().first(), Some(b'>' | b' ' | b'\t' | b'\n' | b'\r')) || rest[..end].ends_with('/') {
+            continue;                               //名字只是前缀相同 或者是空的元素
+        }
+        rest = &rest[end + 1..];
+        let end = rest.find(&close).unwrap_or(rest.len());
+        sections.push(rest[..end].trim().to_string());
+        rest = &rest[(end + close.len()).min(rest.len())..];
+    }
+    sections
+}
+
+#[derive(Default)]
+struct Element {
+    children: Dynamic,
+    text: String,
+}
+
+impl Element {
+    //同名的第二个子元素出现时变成 Vec 子元素的值不会是 Vec
+    fn add(&mut self, name: SmolStr, value: Dynamic) {
+        if self.children.is_null() {
+            self.children = Dynamic::map();
+        }
+        match self.children.get_key(&name) {
+            Ok(Dynamic::Vec(v))=> v.write().push(value),
+            Ok(first)=> {
+                let _ = self.children.set_key(&name, Dynamic::from_vec(vec![first, value]));
+            }
+            Err(_)=> {
+                let _ = self.children.set_key(&name, value);
+            }
+        }
+    }
+
+    fn build(self, lenient: bool) -> Dynamic {
+        let text = if lenient || self.children.is_map() { self.text.trim() } else { self.text.as_str() };
+        let text = if text.trim().is_empty() { "" } else { text };
+        match self.children.is_map() {
+            true=> {
+                if !text.is_empty() {
+                    let _ = self.children.set_key("#text", text);
+                }
+                self.children
+            }
+            false if text.is_empty()=> Dynamic::Null,
+            false=> Dynamic::from(text)
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    buf: &'a [u8],
+    pos: usize,
+    lenient: bool,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> anyhow::Error {
+        let before = &self.text[..self.pos.min(self.text.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |p| p + 1) + 1;
+        anyhow!("{} at line {} column {}", message, line, column)
+    }
+
+    fn name_start(&self, pos: usize) -> bool {
+        matches!(self.buf.get(pos), Some(c) if c.is_ascii_alphabetic() || matches!(c, b'_' | b':') || *c >= 0x80)
+    }
+
+    fn name(&mut self) -> Result<SmolStr> {
+        assert_err!(!self.name_start(self.pos), self.error("invalid name"));
+        let start = self.pos;
+        while self.name_start(self.pos) || matches!(self.buf.get(self.pos), Some(c) if c.is_ascii_digit() || matches!(c, b'-' | b'.')) {
+            self.pos += 1;
+        }
+        Ok(SmolStr::new(&self.text[start..self.pos]))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.buf.get(self.pos), Some(b' ' | b'\t' | b'\r' | b'\n')) {
+            self.pos += 1;
+        }
+    }
+
+    //跳到 end 的后面 宽松模式没有 end 的时候到结尾
+    fn skip_past(&mut self, end: &str) -> Result<()> {
+        match self.text[self.pos..].find(end) {
+            Some(p)=> self.pos += p + end.len(),
+            None if self.lenient=> self.pos = self.buf.len(),
+            None=> return Err(self.error(&format!("missing {}", end)))
+        }
+        Ok(())
+    }
+
+    //根元素前后的空白 注释 处理指令 和 DOCTYPE
+    fn misc(&mut self) -> Result<()> {
+        loop {
+            self.skip_whitespace();
+            let rest = &self.text[self.pos..];
+            if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!DOCTYPE") {
+                let mut depth = 0;                  //内部子集 [...] 里面也有 >
+                loop {
+                    match self.buf.get(self.pos) {
+                        None=> return Err(self.error("unterminated DOCTYPE")),
+                        Some(b'[')=> depth += 1,
+                        Some(b']')=> depth -= 1,
+                        Some(b'>') if depth == 0=> break,
+                        _=> {}
+                    }
+                    self.pos += 1;
+                }
+                self.pos += 1;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    //&lt; &#10; 这样的实体 宽松模式不认识的 & 当作文字
+    fn entity(&mut self, out: &mut String) -> Result<()> {
+        let rest = &self.text[self.pos + 1..];
+        let end = rest.find(';').filter(|p| *p <= 10);
+        let decoded = end.and_then(|end| match &rest[..end] {
+            "lt"=> Some('<'),
+            "gt"=> Some('>'),
+            "amp"=> Some('&'),
+            "quot"=> Some('"'),
+            "apos"=> Some('\''),
+            code=> match code.strip_prefix("#x").or_else(|| code.strip_prefix("#X")) {
+                Some(hex)=> u32::from_str_radix(hex, 16).ok(),
+                None=> code.strip_prefix('#').and_then(|digits| digits.parse().ok())
+            }.and_then(char::from_u32)
+        });
+        match (decoded, end) {
+            (Some(c), Some(end))=> {
+                out.push(c);
+                self.pos += end + 2;
+            }
+            _ if self.lenient=> {
+                out.push('&');
+                self.pos += 1;
+            }
+            _=> return Err(self.error("invalid entity"))
+        }
+        Ok(())
+    }
+
+    fn attribute_value(&mut self) -> Result<String> {
+        let quote = match self.buf.get(self.pos) {
+            Some(c @ (b'"' | b'\''))=> *c,
+            _ if self.lenient=> {                   //宽松模式允许没有引号的值
+                let start = self.pos;
+                while !matches!(self.buf.get(self.pos), None | Some(b' ' | b'\t' | b'\n' | b'>')) && !self.text[self.pos..].starts_with("/>") {
+                    self.pos += 1;
+                }
+                return Ok(self.text[start..self.pos].to_string());
+            }
+            _=> return Err(self.error("expect a quoted attribute value"))
+        };
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            match self.buf.get(self.pos) {
+                None=> return Err(self.error("unterminated attribute value")),
+                Some(c) if *c == quote=> break,
+                Some(b'&')=> self.entity(&mut value)?,
+                Some(b'<') if !self.lenient=> return Err(self.error("< in attribute value")),
+                Some(_)=> {
+                    let c = self.text[self.pos..].chars().next().unwrap_or_default();
+                    value.push(match c {
+                        '\t' | '\n'=> ' ',                   //属性值里的空白规范化成空格
+                        c=> c
+                    });
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+        self.pos += 1;
+        Ok(value)
+    }
+
+    //从 < 开始 返回名字和内容 stack 是打开的祖先元素 宽松模式用来处理错位的结束标签
+    fn element(&mut self, stack: &mut Vec<SmolStr>) -> Result<(SmolStr, Dynamic)> {
+        self.pos += 1;
+        let name = self.name()?;
+        let mut element = Element::default();
+        loop {
+            self.skip_whitespace();
+            match self.buf.get(self.pos) {
+                Some(b'>')=> {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'/') if self.buf.get(self.pos + 1) == Some(&b'>')=> {
+                    self.pos += 2;
+                    return Ok((name, element.build(self.lenient)));
+                }
+                None=> return Err(self.error(&format!("unterminated tag <{}>", name))),
+                _=> {
+                    let attribute = SmolStr::new(format!("@{}", self.name()?));
+                    self.skip_whitespace();
+                    let value = match self.buf.get(self.pos) {
+                        Some(b'=')=> {
+                            self.pos += 1;
+                            self.skip_whitespace();
+                            self.attribute_value()?
+                        }
+                        _ if self.lenient=> String::new(),
+                        _=> return Err(self.error("expect = after attribute name"))
+                    };
+                    if element.children.is_null() {
+                        element.children = Dynamic::map();
+                    }
+                    assert_err!(element.children.contains(&attribute)?, self.error(&format!("duplicate attribute {}", &attribute[1..])));
+                    element.children.set_key(&attribute, value)?;
+                }
+            }
+        }
+        stack.push(name.clone());
+        loop {
+            let rest = &self.text[self.pos..];
+            if rest.is_empty() {
+                assert_err!(!self.lenient, self.error(&format!("unclosed element <{}>", name)));
+                break;
+            } else if rest.starts_with("</") {
+                let start = self.pos;
+                self.pos += 2;
+                let close = self.name();
+                self.skip_whitespace();
+                match close {
+                    Ok(close) if close == name && self.buf.get(self.pos) == Some(&b'>')=> {
+                        self.pos += 1;
+                        break;
+                    }
+                    Ok(close) if self.lenient && stack.contains(&close)=> {
+                        self.pos = start;           //祖先的结束标签 这个元素到这里结束
+                        break;
+                    }
+                    Ok(_) if self.lenient=> {
+                        self.skip_past(">")?;       //别的结束标签 当作这个元素的结束
+                        break;
+                    }
+                    _ if self.lenient=> {
+                        element.text.push('<');
+                        self.pos = start + 1;
+                    }
+                    _=> {
+                        self.pos = start;
+                        return Err(self.error(&format!("mismatched end tag for <{}>", name)));
+                    }
+                }
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += 9;
+                let start = self.pos;
+                self.skip_past("]]>")?;
+                let end = if self.text[..self.pos].ends_with("]]>") { self.pos - 3 } else { self.pos };
+                element.text.push_str(&self.text[start..end]);
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with('<') && self.name_start(self.pos + 1) {
+                let start = self.pos;
+                match self.element(stack) {
+                    Ok((child, value))=> element.add(child, value),
+                    Err(_) if self.lenient=> {
+                        element.text.push('<');
+                        self.pos = start + 1;
+                    }
+                    Err(e)=> return Err(e)
+                }
+            } else if rest.starts_with('<') {
+                assert_err!(!self.lenient, self.error("invalid <"));
+                element.text.push('<');
+                self.pos += 1;
+            } else if rest.starts_with('&') {
+                self.entity(&mut element.text)?;
+            } else {
+                let end = rest.find(['<', '&']).unwrap_or(rest.len());
+                element.text.push_str(&rest[..end]);
+                self.pos += end;
+            }
+        }
+        stack.pop();
+        Ok((name, element.build(self.lenient)))
+    }
+}
+
+//value 是只有一个 key 的 Map key 是根元素的名字 对应关系和 parse 一样 有子元素的时候缩进两个空格
+pub fn write_xml(value: &Dynamic, buf: &mut String) -> Result<()> {
+    let entries = children(value)?;
+    assert_err!(entries.len() != 1 || entries[0].0.starts_with(['@', '#']), anyhow!("xml needs a map with a single root element"));
+    let (name, root) = &entries[0];
+    assert_err!(root.is_vec(), anyhow!("xml can not have more than one root element"));
+    write_element(name, root, buf, 0, &mut Vec::new())?;
+    buf.push('\n');
+    Ok(())
+}
+
+fn children(value: &Dynamic) -> Result<Vec<(SmolStr, Dynamic)>> {
+    match value {
+        Dynamic::Map(m)=> Ok(m.read().iter().map(|(k, v)| (k.clone(), v.clone())).collect()),
+        Dynamic::AnyMap(m)=> m.read().iter().map(|(k, v)| Ok((k.to_key_string()?, v.clone()))).collect(),
+        _=> Err(anyhow!("is not a map"))
+    }
+}
+
+fn scalar(value: &Dynamic) -> Result<String> {
+    Ok(match value {
+        Dynamic::Float(v)=> format!("{:?}", v),
+        Dynamic::Double(v)=> format!("{:?}", v),
+        Dynamic::Vec(_) | Dynamic::Map(_) | Dynamic::AnyMap(_)=> return Err(anyhow!("attribute and text must be scalar")),
+        _=> value.to_key_string()?.to_string()
+    })
+}
+
+fn check_name(name: &str) -> Result<()> {
+    let valid = Parser { text: name, buf: name.as_bytes(), pos: 0, lenient: false }.name().is_ok_and(|n| n.len() == name.len());
+    assert_err!(!valid, anyhow!("{:?} is not a valid xml name", name));
+    Ok(())
+}
+
+//XML 1.0 不能表示 \t \n \r 以外的控制字符
+fn escape(text: &str, attribute: bool, buf: &mut String) -> Result<()> {
+    for c in text.chars() {
+        match c {
+            '<'=> buf.push_str("&lt;"),
+            '>'=> buf.push_str("&gt;"),
+            '&'=> buf.push_str("&amp;"),
+            '"' if attribute=> buf.push_str("&quot;"),
+            '\n' if attribute=> buf.push_str("&#10;"),
+            '\t' if attribute=> buf.push_str("&#9;"),
+            '\r'=> buf.push_str("&#13;"),
+            c if c < ' ' && c != '\n' && c != '\t'=> return Err(anyhow!("control character {:?} can not be xml", c)),
+            c=> buf.push(c)
+        }
+    }
+    Ok(())
+}
+
+fn write_element(name: &str, value: &Dynamic, buf: &mut String, indent: usize, stack: &mut Vec<usize>) -> Result<()> {
+    check_name(name)?;
+    buf.push('<');
+    buf.push_str(name);
+    let entries = match value {
+        Dynamic::Null=> {
+            buf.push_str("/>");
+            return Ok(());
+        }
+        Dynamic::Map(_) | Dynamic::AnyMap(_)=> children(value)?,
+        Dynamic::Vec(_)=> return Err(anyhow!("nested Vec in <{}> can not be xml", name)),
+        _=> {
+            buf.push('>');
+            escape(&scalar(value)?, false, buf)?;
+            buf.push_str(&format!("</{}>", name));
+            return Ok(());
+        }
+    };
+    let id = value.container_id().unwrap_or_default();
+    assert_err!(stack.contains(&id), anyhow!("cycle can not be xml"));
+    stack.push(id);
+    let mut text = None;
+    let mut elements = Vec::new();
+    for (key, item) in &entries {
+        if let Some(attribute) = key.strip_prefix('@') {
+            check_name(attribute)?;
+            buf.push_str(&format!(" {}=\"", attribute));
+            escape(&scalar(item)?, true, buf)?;
+            buf.push('"');
+        } else if key == "#text" {
+            text = Some(scalar(item)?);
+        } else {
+            match item {
+                Dynamic::Vec(v)=> elements.extend(v.read().iter().map(|item| (key.clone(), item.clone()))),
+                _=> elements.push((key.clone(), item.clone()))
+            }
+        }
+    }
+    if text.is_none() && elements.is_empty() {
+        buf.push_str("/>");
+        stack.pop();
+        return Ok(());
+    }
+    buf.push('>');
+    if let Some(text) = &text {                     //有文字的时候不缩进 缩进的空白会变成文字的一部分
+        escape(text, false, buf)?;
+    }
+    for (key, item) in &elements {
+        if text.is_none() {
+            buf.push('\n');
+            buf.push_str(&"  ".repeat(indent + 1));
+        }
+        write_element(key, item, buf, indent + 1, stack)?;
+    }
+    if text.is_none() {
+        buf.push('\n');
+        buf.push_str(&"  ".repeat(indent));
+    }
+    buf.push_str(&format!("</{}>", name));
+    stack.pop();
+    Ok(())
+}
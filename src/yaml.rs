@@ -0,0 +1,961 @@
+use anyhow::{anyhow, Result};
+use smol_str::SmolStr;
+use std::collections::HashMap;
+use super::dynamic::Dynamic;
+use super::map::{DuplicatePolicy, MapBuilder};
+use super::assert_err;
+
+//解析 yaml 的选项 重复 key 的处理和 json 一样
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    pub duplicates: DuplicatePolicy,
+    pub max_alias_nodes: usize,                     //一个文档里 alias 展开复制的节点总数 防止 billion laughs
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self { duplicates: DuplicatePolicy::default(), max_alias_nodes: 1_000_000 }
+    }
+}
+
+//只有一个文档的 yaml 空的输入返回 Null 多个文档用 parse_all
+pub fn parse(text: &str) -> Result<Dynamic> {
+    let mut documents = parse_with(text, &ParseOptions::default())?;
+    assert_err!(documents.len() > 1, anyhow!("{} documents found, use parse_all", documents.len()));
+    Ok(documents.pop().unwrap_or_default())
+}
+
+pub fn parse_all(text: &str) -> Result<Vec<Dynamic>> {
+    parse_with(text, &ParseOptions::default())
+}
+
+//YAML 1.2 的块结构和 flow 结构 anchor 在 alias 处展开成独立的复制 `<<` 合并 map
+pub fn parse_with(text: &str, options: &ParseOptions) -> Result<Vec<Dynamic>> {
+    let mut parser = Parser { text, buf: text.as_bytes(), pos: 0, anchors: HashMap::new(), expanded: 0, options };
+    parser.documents()
+}
+
+fn is_blank(c: Option<u8>) -> bool {
+    matches!(c, None | Some(b' ' | b'\t' | b'\r' | b'\n'))
+}
+
+fn is_flow_indicator(c: u8) -> bool {
+    matches!(c, b',' | b'[' | b']' | b'{' | b'}')
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    buf: &'a [u8],
+    pos: usize,
+    anchors: HashMap<String, (Dynamic, usize)>,       //anchor 的值和节点数
+    expanded: usize,                                //当前文档里 alias 已经展开的节点数
+    options: &'a ParseOptions,
+}
+
+//值里面的节点数 容器和标量都算一个
+fn nodes(value: &Dynamic) -> usize {
+    match value {
+        Dynamic::Vec(v)=> 1 + v.read().iter().map(nodes).sum::<usize>(),
+        Dynamic::Map(m)=> 1 + m.read().values().map(nodes).sum::<usize>(),
+        Dynamic::AnyMap(m)=> 1 + m.read().iter().map(|(k, v)| nodes(k) + nodes(v)).sum::<usize>(),
+        _=> 1
+    }
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.buf.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.buf.get(self.pos + offset).copied()
+    }
+
+    fn column(&self) -> usize {
+        self.pos - self.buf[..self.pos].iter().rposition(|c| *c == b'\n').map_or(0, |p| p + 1)
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        let line = self.buf[..self.pos].iter().filter(|c| **c == b'\n').count() + 1;
+        anyhow!("{} at line {} column {}", message, line, self.column() + 1)
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_line(&mut self) {                               //停在换行符上
+        while !matches!(self.peek(), None | Some(b'\n')) {
+            self.pos += 1;
+        }
+    }
+
+    //跳过空白 注释和空行 停在下一个内容上
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                Some(b'#')=> self.skip_line(),
+                Some(b'\r' | b'\n')=> self.pos += 1,
+                _=> break
+            }
+        }
+    }
+
+    fn at_line_end(&self) -> bool {
+        matches!(self.peek(), None | Some(b'\r' | b'\n' | b'#'))
+    }
+
+    fn at_document_marker(&self) -> bool {
+        let rest = &self.buf[self.pos..];
+        self.column() == 0 && (rest.starts_with(b"---") || rest.starts_with(b"...")) && is_blank(self.peek_at(3))
+    }
+
+    fn documents(&mut self) -> Result<Vec<Dynamic>> {
+        let mut documents = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            while self.column() == 0 && self.peek() == Some(b'%') {         //%YAML %TAG 指令忽略
+                self.skip_line();
+                self.skip_blank_lines();
+            }
+            if self.peek().is_none() {
+                break;
+            }
+            if self.at_document_marker() {
+                self.pos += 3;
+                if self.buf[self.pos - 1] == b'.' {                         //单独的 ... 没有文档
+                    continue;
+                }
+            }
+            self.anchors.clear();
+            self.expanded = 0;
+            let document = self.block_node(-1, false)?;
+            self.skip_blank_lines();
+            assert_err!(self.peek().is_some() && !self.at_document_marker(), self.error("unexpected content"));
+            documents.push(document);
+            if self.at_document_marker() && self.buf[self.pos] == b'.' {
+                self.pos += 3;
+            }
+        }
+        Ok(documents)
+    }
+
+    //在后面的行里找节点 缩进不大于 parent 的时候节点是空的 same 表示 map 的值可以是同样缩进的序列
+    fn block_node(&mut self, parent: isize, same: bool) -> Result<Dynamic> {
+        self.skip_blank_lines();
+        if self.peek().is_none() || self.at_document_marker() {
+            return Ok(Dynamic::Null);
+        }
+        let column = self.column() as isize;
+        let sequence = self.peek() == Some(b'-') && is_blank(self.peek_at(1));
+        if column < parent || (column == parent && !(same && sequence)) {
+            return Ok(Dynamic::Null);
+        }
+        self.node(parent, true, same)
+    }
+
+    //当前位置开始的节点 compact 表示可以是块结构的 map 或者序列
+    fn node(&mut self, parent: isize, compact: bool, same: bool) -> Result<Dynamic> {
+        let column = self.column();                         //key 前面的属性也算在 map 的缩进里
+        let (anchor, tag) = self.properties()?;
+        let value = if (anchor.is_some() || tag.is_some()) && self.at_line_end() {
+            self.block_node(parent, same)?                              //属性后面换行 节点在后面的行
+        } else {
+            match self.peek() {
+                Some(b'*')=> self.alias()?,
+                Some(b'|' | b'>')=> {
+                    let text = self.block_scalar(parent)?;
+                    self.typed(text, false, tag.as_deref())?
+                }
+                Some(b'[' | b'{')=> self.flow_node()?,
+                Some(b'-') if compact && is_blank(self.peek_at(1))=> self.block_sequence(self.column())?,
+                Some(b'?') if is_blank(self.peek_at(1))=> return Err(self.error("explicit keys are not supported")),
+                _=> match compact {
+                    true=> match self.mapping_key()? {
+                        Some(key)=> self.block_mapping(column, key)?,
+                        None=> self.scalar(parent, false, tag.as_deref())?
+                    }
+                    false=> self.scalar(parent, false, tag.as_deref())?
+                }
+            }
+        };
+        if let Some(anchor) = anchor {
+            self.anchors.insert(anchor, (value.clone(), nodes(&value)));
+        }
+        Ok(value)
+    }
+
+    //节点前面的 &anchor 和 !tag
+    fn properties(&mut self) -> Result<(Option<String>, Option<String>)> {
+        let (mut anchor, mut tag) = (None, None);
+        loop {
+            match self.peek() {
+                Some(b'&')=> {
+                    self.pos += 1;
+                    let name = self.name();
+                    assert_err!(name.is_empty(), self.error("empty anchor name"));
+                    anchor = Some(name);
+                }
+                Some(b'!')=> tag = Some(self.name()),
+                _=> break
+            }
+            self.skip_spaces();
+        }
+        Ok((anchor, tag))
+    }
+
+    fn name(&mut self) -> String {
+        let start = self.pos;
+        while !is_blank(self.peek()) && !self.peek().is_some_and(is_flow_indicator) {
+            self.pos += 1;
+        }
+        self.text[start..self.pos].to_string()
+    }
+
+    fn alias(&mut self) -> Result<Dynamic> {
+        let start = self.pos;
+        self.pos += 1;
+        let name = self.name();
+        match self.anchors.get(&name) {
+            Some((value, count))=> {
+                self.expanded = self.expanded.saturating_add(*count);
+                assert_err!(self.expanded > self.options.max_alias_nodes, self.error(&format!("aliases expand to more than {} nodes", self.options.max_alias_nodes)));
+                Ok(value.deep_clone())
+            }
+            None=> {
+                self.pos = start;
+                Err(self.error(&format!("unknown anchor {}", name)))
+            }
+        }
+    }
+
+    //试着在当前行读一个 map 的 key 后面必须是 ': ' 不是 key 的时候位置不变 返回 key 和是否 plain
+    fn mapping_key(&mut self) -> Result<Option<(String, bool)>> {
+        let start = self.pos;
+        let key = match self.peek() {
+            Some(quote @ (b'"' | b'\''))=> (self.quoted(quote)?, false),
+            _=> {
+                let end = self.plain_end(self.pos, false);
+                self.pos = end;
+                (self.text[start..end].to_string(), true)
+            }
+        };
+        self.skip_spaces();
+        if self.peek() == Some(b':') && is_blank(self.peek_at(1)) && !self.buf[start..self.pos].contains(&b'\n') {
+            self.pos += 1;
+            return Ok(Some(key));
+        }
+        self.pos = start;
+        Ok(None)
+    }
+
+    fn block_mapping(&mut self, column: usize, mut key: (String, bool)) -> Result<Dynamic> {
+        let mut map = MapBuilder::new(self.options.duplicates);
+        let mut merges = Vec::new();
+        loop {
+            let offset = self.pos;
+            self.skip_spaces();
+            let value = match self.at_line_end() {
+                true=> self.block_node(column as isize, true)?,
+                false=> self.node(column as isize, false, false)?
+            };
+            match key {
+                (key, true) if key == "<<"=> merges.push(value),
+                (key, _)=> map.insert(SmolStr::from(key), value, offset)?
+            }
+            self.skip_blank_lines();
+            if self.peek().is_none() || self.at_document_marker() || self.column() < column {
+                break;
+            }
+            assert_err!(self.column() > column, self.error("bad indentation of a mapping entry"));
+            key = self.mapping_key()?.ok_or_else(|| self.error("expected a mapping key"))?;
+        }
+        self.merge(map.build(), merges)
+    }
+
+    //<< 的值是一个 map 或者 map 的列表 只加入不存在的 key 前面的优先
+    fn merge(&self, map: Dynamic, merges: Vec<Dynamic>) -> Result<Dynamic> {
+        for value in merges {
+            let sources = if value.is_vec() { value.into_vec()? } else { vec![value] };
+            for source in sources {
+                let pairs = match &source {
+                    Dynamic::Map(m)=> m.read().iter().map(|(k, v)| (k.clone(), v.clone())).collect::<Vec<_>>(),
+                    Dynamic::AnyMap(_)=> source.pairs()?.into_iter().map(|(k, v)| Ok((k.to_key_string()?, v))).collect::<Result<_>>()?,
+                    _=> return Err(self.error("merge value must be a map"))
+                };
+                for (key, value) in pairs {
+                    if !map.contains(&key)? {
+                        map.set_key(&key, value)?;
+                    }
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    fn block_sequence(&mut self, column: usize) -> Result<Dynamic> {
+        let mut items = Vec::new();
+        loop {
+            self.pos += 1;                                  //跳过 -
+            self.skip_spaces();
+            let item = match self.at_line_end() {
+                true=> self.block_node(column as isize, false)?,
+                false=> self.node(column as isize, true, false)?
+            };
+            items.push(item);
+            self.skip_blank_lines();
+            if self.peek().is_none() || self.at_document_marker() || self.column() != column || self.peek() != Some(b'-') || !is_blank(self.peek_at(1)) {
+                break;
+            }
+        }
+        Ok(Dynamic::from_vec(items))
+    }
+
+    fn scalar(&mut self, parent: isize, flow: bool, tag: Option<&str>) -> Result<Dynamic> {
+        match self.peek() {
+            Some(quote @ (b'"' | b'\''))=> {
+                let text = self.quoted(quote)?;
+                self.typed(text, false, tag)
+            }
+            _=> {
+                let text = self.plain(parent, flow);
+                assert_err!(text.is_empty(), self.error("unexpected character"));
+                self.typed(text, true, tag)
+            }
+        }
+    }
+
+    //一行里 plain scalar 的结束位置 遇到 ': ' ' #' 和行尾 flow 里还有 , [ ] { } 不包括结尾的空白
+    fn plain_end(&self, start: usize, flow: bool) -> usize {
+        let mut pos = start;
+        while pos < self.buf.len() {
+            let c = self.buf[pos];
+            let next = self.buf.get(pos + 1).copied();
+            if c == b'\n' || c == b'\r' || (flow && is_flow_indicator(c)) {
+                break;
+            }
+            if c == b':' && (is_blank(next) || (flow && next.is_some_and(is_flow_indicator))) {
+                break;
+            }
+            if c == b'#' && pos > start && matches!(self.buf[pos - 1], b' ' | b'\t') {
+                break;
+            }
+            pos += 1;
+        }
+        while pos > start && matches!(self.buf[pos - 1], b' ' | b'\t') {
+            pos -= 1;
+        }
+        pos
+    }
+
+    //plain scalar 可以跨行 后面的行缩进要大于 parent 换行折叠成空格 空行变成换行
+    fn plain(&mut self, parent: isize, flow: bool) -> String {
+        let mut text = String::new();
+        loop {
+            let end = self.plain_end(self.pos, flow);
+            text.push_str(&self.text[self.pos..end]);
+            self.pos = end;
+            let save = self.pos;
+            self.skip_spaces();
+            if !matches!(self.peek(), Some(b'\r' | b'\n')) {
+                self.pos = save;
+                break;
+            }
+            let mut breaks = 0;
+            loop {
+                match self.peek() {
+                    Some(b'\n')=> breaks += 1,
+                    Some(b' ' | b'\t' | b'\r')=> {},
+                    _=> break
+                }
+                self.pos += 1;
+            }
+            let stop = match self.peek() {
+                None | Some(b'#')=> true,
+                Some(c) if flow=> is_flow_indicator(c) || (c == b':' && (is_blank(self.peek_at(1)) || self.peek_at(1).is_some_and(is_flow_indicator))),
+                Some(_)=> self.column() as isize <= parent || self.at_document_marker()
+            };
+            if stop {
+                self.pos = save;
+                break;
+            }
+            match breaks {
+                1=> text.push(' '),
+                _=> (1..breaks).for_each(|_| text.push('\n'))
+            }
+        }
+        text
+    }
+
+    //单引号里 '' 是一个引号 双引号支持转义 跨行的时候和 plain 一样折叠
+    fn quoted(&mut self, quote: u8) -> Result<String> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                self.pos = start;
+                return Err(self.error("unterminated string"));
+            };
+            match c {
+                b'\'' if quote == b'\''=> {
+                    self.pos += 1;
+                    if self.peek() != Some(b'\'') {
+                        break;
+                    }
+                    text.push('\'');
+                    self.pos += 1;
+                }
+                b'"' if quote == b'"'=> {
+                    self.pos += 1;
+                    break;
+                }
+                b'\\' if quote == b'"'=> self.escape(&mut text)?,
+                b' ' | b'\t' | b'\r' | b'\n'=> {
+                    let end = self.buf[self.pos..].iter().position(|c| !matches!(c, b' ' | b'\t' | b'\r')).map_or(self.buf.len(), |p| self.pos + p);
+                    if self.buf.get(end) == Some(&b'\n') {                      //行尾的空白去掉
+                        self.pos = end;
+                        let breaks = self.skip_breaks();
+                        match breaks {
+                            1=> text.push(' '),
+                            _=> (1..breaks).for_each(|_| text.push('\n'))
+                        }
+                    } else {
+                        text.push_str(&self.text[self.pos..end]);
+                        self.pos = end;
+                    }
+                }
+                _=> {
+                    let special = |c: &u8| *c == quote || (quote == b'"' && *c == b'\\') || matches!(c, b' ' | b'\t' | b'\r' | b'\n');
+                    let end = self.buf[self.pos..].iter().position(special).map_or(self.buf.len(), |p| self.pos + p);   //只在这种引号的特殊字符处停下
+                    text.push_str(&self.text[self.pos..end]);
+                    self.pos = end;
+                }
+            }
+        }
+        Ok(text)
+    }
+
+    //跳过换行和下一行开头的空白 返回换行的个数
+    fn skip_breaks(&mut self) -> usize {
+        let mut breaks = 0;
+        while let Some(c @ (b' ' | b'\t' | b'\r' | b'\n')) = self.peek() {
+            breaks += (c == b'\n') as usize;
+            self.pos += 1;
+        }
+        breaks
+    }
+
+    fn hex(&mut self, length: usize) -> Result<u32> {
+        let digits = self.text.get(self.pos..self.pos + length).ok_or_else(|| self.error("uncomplete escape"))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error(&format!("invalid escape {}", digits)))?;
+        self.pos += length;
+        Ok(code)
+    }
+
+    fn escape(&mut self, text: &mut String) -> Result<()> {
+        self.pos += 1;
+        let c = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+        self.pos += 1;
+        let ch = match c {
+            b'0'=> '\0',
+            b'a'=> '\x07',
+            b'b'=> '\x08',
+            b't' | b'\t'=> '\t',
+            b'n'=> '\n',
+            b'v'=> '\x0b',
+            b'f'=> '\x0c',
+            b'r'=> '\r',
+            b'e'=> '\x1b',
+            b' ' | b'"' | b'/' | b'\\'=> c as char,
+            b'N'=> '\u{85}',
+            b'_'=> '\u{a0}',
+            b'L'=> '\u{2028}',
+            b'P'=> '\u{2029}',
+            b'x'=> char::from_u32(self.hex(2)?).unwrap_or(char::REPLACEMENT_CHARACTER),
+            b'U'=> char::from_u32(self.hex(8)?).unwrap_or(char::REPLACEMENT_CHARACTER),
+            b'u'=> {
+                let high = self.hex(4)?;
+                let mut code = high;
+                if (0xd800..0xdc00).contains(&high) && self.buf[self.pos..].starts_with(b"\\u") {     //代理对
+                    let save = self.pos;
+                    self.pos += 2;
+                    match self.hex(4)? {
+                        low @ 0xdc00..=0xdfff=> code = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00),
+                        _=> self.pos = save
+                    }
+                }
+                char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+            }
+            b'\r' | b'\n'=> {                               //转义的换行 连接下一行不加空格
+                self.pos -= 1;
+                let breaks = self.skip_breaks();
+                (1..breaks).for_each(|_| text.push('\n'));
+                return Ok(());
+            }
+            _=> {
+                self.pos -= 2;
+                return Err(self.error(&format!("invalid escape \\{}", c as char)));
+            }
+        };
+        text.push(ch);
+        Ok(())
+    }
+
+    //| 保留换行 > 折叠换行 后面可以有 - + 和缩进数字 parent 是所属节点的缩进
+    fn block_scalar(&mut self, parent: isize) -> Result<String> {
+        let literal = self.buf[self.pos] == b'|';
+        self.pos += 1;
+        let mut chomp = b' ';
+        let mut indent = None;
+        for _ in 0..2 {
+            match self.peek() {
+                Some(c @ (b'-' | b'+'))=> chomp = c,
+                Some(c @ b'1'..=b'9')=> indent = Some((parent + (c - b'0') as isize).max(0) as usize),
+                _=> break
+            }
+            self.pos += 1;
+        }
+        self.skip_spaces();
+        assert_err!(!self.at_line_end(), self.error("unexpected content after block scalar header"));
+        self.skip_line();
+        self.pos = (self.pos + 1).min(self.buf.len());
+        let mut lines = Vec::new();
+        let mut ended = false;                              //最后一行后面有换行
+        while self.pos < self.buf.len() {
+            let end = self.buf[self.pos..].iter().position(|c| *c == b'\n').map_or(self.buf.len(), |p| self.pos + p);
+            let line = self.text[self.pos..end].trim_end_matches('\r');
+            let spaces = line.bytes().take_while(|c| *c == b' ').count();
+            if spaces < line.len() {
+                let need = *indent.get_or_insert(spaces);
+                if spaces < need || spaces as isize <= parent || self.at_document_marker() {
+                    break;
+                }
+            }
+            lines.push(line);
+            ended = end < self.buf.len();
+            self.pos = (end + 1).min(self.buf.len());
+        }
+        let indent = indent.unwrap_or(0);
+        let lines: Vec<&str> = lines.iter().map(|line| line.get(indent..).unwrap_or("")).collect();
+        let trailing = lines.iter().rev().take_while(|line| line.is_empty()).count();
+        let body = &lines[..lines.len() - trailing];
+        let mut text = if literal { body.join("\n") } else { fold(body) };
+        let breaks = trailing + ended as usize;
+        match chomp {
+            b'-'=> {},
+            b'+'=> (0..breaks).for_each(|_| text.push('\n')),
+            _=> if !body.is_empty() && breaks > 0 {
+                text.push('\n');
+            }
+        }
+        Ok(text)
+    }
+
+    fn flow_node(&mut self) -> Result<Dynamic> {
+        let (anchor, tag) = self.properties()?;
+        self.skip_blank_lines();
+        let value = match self.peek() {
+            Some(b'[')=> self.flow_sequence()?,
+            Some(b'{')=> self.flow_mapping()?,
+            Some(b'*')=> self.alias()?,
+            Some(b',' | b']' | b'}')=> self.typed(String::new(), true, tag.as_deref())?,
+            _=> self.scalar(-1, true, tag.as_deref())?
+        };
+        if let Some(anchor) = anchor {
+            self.anchors.insert(anchor, (value.clone(), nodes(&value)));
+        }
+        Ok(value)
+    }
+
+    fn flow_value(&mut self) -> Result<Dynamic> {
+        self.skip_blank_lines();
+        match self.peek() {
+            Some(b',' | b']' | b'}')=> Ok(Dynamic::Null),
+            _=> self.flow_node()
+        }
+    }
+
+    fn flow_sequence(&mut self) -> Result<Dynamic> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            match self.peek() {
+                Some(b']')=> break,
+                None=> return Err(self.error("unterminated flow sequence")),
+                _=> {}
+            }
+            let mut item = self.flow_node()?;
+            self.skip_blank_lines();
+            if self.peek() == Some(b':') {                  //[a: b] 是只有一个键值对的 map
+                self.pos += 1;
+                let value = self.flow_value()?;
+                let pair = Dynamic::map();
+                pair.set_key(&item.to_key_string()?, value)?;
+                item = pair;
+                self.skip_blank_lines();
+            }
+            items.push(item);
+            match self.peek() {
+                Some(b',')=> self.pos += 1,
+                Some(b']')=> {},
+                _=> return Err(self.error("expected , or ]"))
+            }
+        }
+        self.pos += 1;
+        Ok(Dynamic::from_vec(items))
+    }
+
+    fn flow_mapping(&mut self) -> Result<Dynamic> {
+        self.pos += 1;
+        let mut map = MapBuilder::new(self.options.duplicates);
+        let mut merges = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            match self.peek() {
+                Some(b'}')=> break,
+                None=> return Err(self.error("unterminated flow mapping")),
+                _=> {}
+            }
+            let offset = self.pos;
+            let key = match self.peek() {
+                Some(quote @ (b'"' | b'\''))=> (self.quoted(quote)?, false),
+                _=> (self.plain(-1, true), true)
+            };
+            assert_err!(key.0.is_empty() && key.1, self.error("expected a mapping key"));
+            self.skip_blank_lines();
+            let value = match self.peek() {
+                Some(b':')=> {
+                    self.pos += 1;
+                    self.flow_value()?
+                }
+                _=> Dynamic::Null
+            };
+            match key {
+                (key, true) if key == "<<"=> merges.push(value),
+                (key, _)=> map.insert(SmolStr::from(key), value, offset)?
+            }
+            self.skip_blank_lines();
+            match self.peek() {
+                Some(b',')=> self.pos += 1,
+                Some(b'}')=> {},
+                _=> return Err(self.error("expected , or }"))
+            }
+        }
+        self.pos += 1;
+        self.merge(map.build(), merges)
+    }
+
+    //按照 tag 或者 core schema 决定 scalar 的类型 引号里的内容总是字符串
+    fn typed(&self, text: String, plain: bool, tag: Option<&str>) -> Result<Dynamic> {
+        let tag = tag.map(|tag| match tag {
+            "!"=> "",                                       //单独的 ! 表示不做类型判断
+            _=> tag.strip_prefix("!!").or_else(|| tag.strip_prefix("!<tag:yaml.org,2002:")?.strip_suffix('>')).unwrap_or(tag)
+        });
+        let invalid = |text: &str| self.error(&format!("invalid !!{} value {:?}", tag.unwrap_or_default(), text));
+        Ok(match tag {
+            Some("str" | "")=> Dynamic::from(text),
+            Some("null")=> Dynamic::Null,
+            Some("bool")=> match resolve(&text) {
+                value @ Dynamic::Bool(_)=> value,
+                _=> return Err(invalid(&text))
+            }
+            Some("int")=> match resolve(&text) {
+                value @ (Dynamic::Int(_) | Dynamic::UInt(_))=> value,
+                _=> return Err(invalid(&text))
+            }
+            Some("float")=> match resolve(&text) {
+                Dynamic::Int(v)=> Dynamic::Double(v as f64),
+                Dynamic::UInt(v)=> Dynamic::Double(v as f64),
+                value @ Dynamic::Double(_)=> value,
+                _=> return Err(invalid(&text))
+            }
+            Some("binary")=> Dynamic::from_bytes(base64_decode(&text).ok_or_else(|| invalid(&text))?),
+            _ if plain=> resolve(&text),
+            _=> Dynamic::from(text)
+        })
+    }
+}
+
+//YAML 1.2 core schema 判断 plain scalar 的类型
+fn resolve(text: &str) -> Dynamic {
+    match text {
+        "" | "~" | "null" | "Null" | "NULL"=> return Dynamic::Null,
+        "true" | "True" | "TRUE"=> return Dynamic::Bool(true),
+        "false" | "False" | "FALSE"=> return Dynamic::Bool(false),
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF"=> return Dynamic::Double(f64::INFINITY),
+        "-.inf" | "-.Inf" | "-.INF"=> return Dynamic::Double(f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN"=> return Dynamic::Double(f64::NAN),
+        _=> {}
+    }
+    let digits = text.strip_prefix(['-', '+']).unwrap_or(text);
+    if !digits.is_empty() && digits.bytes().all(|c| c.is_ascii_digit()) {
+        if let Ok(v) = text.parse::<i64>() {
+            return Dynamic::Int(v);
+        }
+        if let Ok(v) = text.parse::<u64>() {
+            return Dynamic::UInt(v);
+        }
+        return Dynamic::Double(text.parse().unwrap_or_default());
+    }
+    for (prefix, radix) in [("0x", 16), ("0o", 8)] {
+        if let Some(digits) = text.strip_prefix(prefix) {
+            if let Ok(v) = i64::from_str_radix(digits, radix) {
+                return Dynamic::Int(v);
+            }
+            if let Ok(v) = u64::from_str_radix(digits, radix) {
+                return Dynamic::UInt(v);
+            }
+        }
+    }
+    if text.bytes().any(|c| c.is_ascii_digit()) && text.bytes().all(|c| c.is_ascii_digit() || matches!(c, b'.' | b'e' | b'E' | b'+' | b'-')) {
+        if let Ok(v) = text.parse::<f64>() {
+            return Dynamic::Double(v);
+        }
+    }
+    Dynamic::from(text)
+}
+
+//> 的折叠 两个普通行之间的换行变成空格 空行保留换行 缩进更多的行不折叠
+fn fold(lines: &[&str]) -> String {
+    let mut text = String::new();
+    let mut empty = 0;
+    let mut started = false;
+    let mut normal = false;
+    for line in lines {
+        if line.is_empty() {
+            empty += 1;
+            continue;
+        }
+        let current = !line.starts_with([' ', '\t']);
+        let breaks = match (started, normal && current) {
+            (false, _)=> empty,
+            (true, true) if empty == 0=> {
+                text.push(' ');
+                0
+            }
+            (true, true)=> empty,
+            (true, false)=> empty + 1
+        };
+        (0..breaks).for_each(|_| text.push('\n'));
+        text.push_str(line);
+        (started, normal, empty) = (true, current, 0);
+    }
+    text
+}
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | ((*b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            text.push(if i <= chunk.len() { BASE64[((n >> (18 - 6 * i)) & 63) as usize] as char } else { '=' });
+        }
+    }
+    text
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    let (mut n, mut bits) = (0u32, 0);
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        n = (n << 6) | BASE64.iter().position(|b| *b == c)? as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((n >> bits) as u8);
+        }
+    }
+    Some(data)
+}
+
+//把 Dynamic 写成块结构的 yaml 包含换行的字符串用 | 块 scalar 循环引用写成 null
+pub fn write_yaml(value: &Dynamic, buf: &mut String) {
+    let mut stack = Vec::new();
+    if let Some(id) = value.container_id() {
+        stack.push(id);
+    }
+    match (value, entries(value)) {
+        (_, Some(entries)) if !entries.is_empty()=> write_entries(&entries, 0, false, buf, &mut stack),
+        (Dynamic::Vec(v), _) if !v.read().is_empty()=> {
+            let items = v.read().clone();
+            write_items(&items, 0, false, buf, &mut stack);
+        }
+        (Dynamic::String(s), _) if is_block(s)=> write_block(s, -1, 2, buf),
+        _=> {
+            write_scalar(value, buf);
+            buf.push('\n');
+        }
+    }
+}
+
+//多个文档 每个前面加上 ---
+pub fn write_documents(values: &[Dynamic], buf: &mut String) {
+    for value in values {
+        buf.push_str("---\n");
+        write_yaml(value, buf);
+    }
+}
+
+fn entries(value: &Dynamic) -> Option<Vec<(SmolStr, Dynamic)>> {
+    match value {
+        Dynamic::Map(m)=> Some(m.read().iter().map(|(k, v)| (k.clone(), v.clone())).collect()),
+        Dynamic::AnyMap(m)=> Some(m.read().iter().map(|(k, v)| (k.key_text(), v.clone())).collect()),
+        _=> None
+    }
+}
+
+fn write_indent(indent: usize, buf: &mut String) {
+    (0..indent).for_each(|_| buf.push(' '));
+}
+
+//inline 表示第一个条目跟在 "- " 后面
+fn write_entries(entries: &[(SmolStr, Dynamic)], indent: usize, inline: bool, buf: &mut String, stack: &mut Vec<usize>) {
+    for (index, (key, value)) in entries.iter().enumerate() {
+        if index > 0 || !inline {
+            write_indent(indent, buf);
+        }
+        write_string(key, buf);
+        buf.push(':');
+        write_node(value, indent as isize, indent + 2, false, buf, stack);
+    }
+}
+
+fn write_items(items: &[Dynamic], indent: usize, inline: bool, buf: &mut String, stack: &mut Vec<usize>) {
+    for (index, item) in items.iter().enumerate() {
+        if index > 0 || !inline {
+            write_indent(indent, buf);
+        }
+        buf.push('-');
+        write_node(item, indent as isize, indent + 2, true, buf, stack);
+    }
+}
+
+//写在 "key:" 或者 "-" 后面的值 parent 是所在节点的缩进 indent 是下一层的缩进
+fn write_node(value: &Dynamic, parent: isize, indent: usize, item: bool, buf: &mut String, stack: &mut Vec<usize>) {
+    if let Some(id) = value.container_id() {
+        if stack.contains(&id) {
+            buf.push_str(" null\n");
+            return;
+        }
+        stack.push(id);
+    }
+    match (value, entries(value)) {
+        (_, Some(entries)) if !entries.is_empty()=> {
+            buf.push(if item { ' ' } else { '\n' });
+            write_entries(&entries, indent, item, buf, stack);
+        }
+        (Dynamic::Vec(v), _) if !v.read().is_empty()=> {
+            let items = v.read().clone();
+            buf.push(if item { ' ' } else { '\n' });
+            write_items(&items, indent, item, buf, stack);
+        }
+        (Dynamic::String(s), _) if is_block(s)=> {
+            buf.push(' ');
+            write_block(s, parent, indent, buf);
+        }
+        _=> {
+            buf.push(' ');
+            write_scalar(value, buf);
+            buf.push('\n');
+        }
+    }
+    if value.container_id().is_some() {
+        stack.pop();
+    }
+}
+
+fn write_scalar(value: &Dynamic, buf: &mut String) {
+    match value {
+        Dynamic::Null=> buf.push_str("null"),
+        Dynamic::Bool(b)=> buf.push_str(if *b { "true" } else { "false" }),
+        Dynamic::Byte(v)=> buf.push_str(&v.to_string()),
+        Dynamic::Int(v)=> buf.push_str(&v.to_string()),
+        Dynamic::UInt(v)=> buf.push_str(&v.to_string()),
+        Dynamic::Float(v) if v.is_finite()=> buf.push_str(&format!("{:?}", v)),
+        Dynamic::Double(v) if v.is_finite()=> buf.push_str(&format!("{:?}", v)),
+        Dynamic::Float(v)=> write_special(*v as f64, buf),
+        Dynamic::Double(v)=> write_special(*v, buf),
+        Dynamic::String(s)=> write_string(s, buf),
+        Dynamic::Bytes(b)=> {
+            buf.push_str("!!binary ");
+            buf.push_str(&base64_encode(b));
+        }
+        Dynamic::Vec(_)=> buf.push_str("[]"),
+        Dynamic::Map(_) | Dynamic::AnyMap(_)=> buf.push_str("{}")
+    }
+}
+
+fn write_special(value: f64, buf: &mut String) {
+    buf.push_str(if value.is_nan() { ".nan" } else if value > 0.0 { ".inf" } else { "-.inf" });
+}
+
+//不会被当成其他类型或者结构的字符串不加引号
+fn is_plain(s: &str) -> bool {
+    !s.starts_with([' ', '\t', '-', '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%', '@', '`'])
+        && !s.ends_with([' ', '\t', ':'])
+        && !s.starts_with("...") && s != "<<"
+        && !s.contains(": ") && !s.contains(" #")
+        && s.chars().all(|c| !c.is_control() && !matches!(c, '\u{85}' | '\u{2028}' | '\u{2029}' | '\u{feff}'))
+        && resolve(s).is_string()
+}
+
+fn write_string(s: &str, buf: &mut String) {
+    if is_plain(s) {
+        return buf.push_str(s);
+    }
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"'=> buf.push_str("\\\""),
+            '\\'=> buf.push_str("\\\\"),
+            '\n'=> buf.push_str("\\n"),
+            '\t'=> buf.push_str("\\t"),
+            '\r'=> buf.push_str("\\r"),
+            '\0'=> buf.push_str("\\0"),
+            '\u{85}'=> buf.push_str("\\N"),
+            '\u{2028}'=> buf.push_str("\\L"),
+            '\u{2029}'=> buf.push_str("\\P"),
+            '\u{feff}'=> buf.push_str("\\ufeff"),
+            c if c.is_control()=> buf.push_str(&format!("\\x{:02x}", c as u32)),
+            c=> buf.push(c)
+        }
+    }
+    buf.push('"');
+}
+
+//多行的字符串 例如 prompt 用 | 块 scalar 写出来更好读
+fn is_block(s: &str) -> bool {
+    s.contains('\n')
+        && !s.trim_end_matches('\n').is_empty()
+        && s.chars().all(|c| c == '\n' || c == '\t' || (!c.is_control() && !matches!(c, '\u{85}' | '\u{2028}' | '\u{2029}' | '\u{feff}')))
+}
+
+fn write_block(s: &str, parent: isize, indent: usize, buf: &mut String) {
+    let body = s.trim_end_matches('\n');
+    let breaks = s.len() - body.len();
+    buf.push('|');
+    if body.trim_start_matches('\n').starts_with([' ', '\t']) {            //第一行以空白开头要写出缩进
+        buf.push_str(&(indent as isize - parent).to_string());
+    }
+    buf.push_str(match breaks {
+        0=> "-",
+        1=> "",
+        _=> "+"
+    });
+    buf.push('\n');
+    for line in body.split('\n') {
+        if !line.is_empty() {
+            write_indent(indent, buf);
+            buf.push_str(line);
+        }
+        buf.push('\n');
+    }
+    (1..breaks).for_each(|_| buf.push('\n'));
+}
@@ -0,0 +1,127 @@
+use libai::bson::{decode, decode_all, encode, BINARY_UUID};
+use libai::dynamic::Dynamic;
+use libai::json::FromJson;
+
+fn hex(raw: &[u8]) -> String {
+    raw.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Vec<u8> {
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+}
+
+fn json(text: &str) -> Dynamic {
+    Dynamic::from_json(text.as_bytes()).unwrap().0
+}
+
+#[test]
+fn corpus() {
+    //bsonspec.org 和 BSON corpus 的字节 解码后和 json 一样 再编码和原来的字节一样
+    let vectors = [
+        ("160000000268656c6c6f0006000000776f726c640000", r#"{"hello":"world"}"#),
+        ("310000000442534f4e002600000002300008000000617765736f6d65000131003333333333331440103200c20700000000", r#"{"BSON":["awesome",5.05,1986]}"#),
+        ("0c000000106900ffffffff00", r#"{"i":-1}"#),
+        ("10000000126100000000800000000000", r#"{"a":2147483648}"#),
+        ("090000000862000100", r#"{"b":true}"#),
+        ("080000000a610000", r#"{"a":null}"#),
+        ("1400000007610056e1fc72e0c917e9c471416100", r#"{"a":{"$oid":"56e1fc72e0c917e9c4714161"}}"#),
+        ("10000000096100c5d8d6cc3b01000000", r#"{"a":{"$date":1356351330501}}"#),
+        ("100000001161002a00000015cd5b0700", r#"{"a":{"$timestamp":{"t":123456789,"i":42}}}"#),
+        ("0f0000000b610061626300696d0000", r#"{"a":{"$regularExpression":{"pattern":"abc","options":"im"}}}"#),
+        ("180000001364000100000000000000000000000000403000", r#"{"d":{"$numberDecimal":"1"}}"#),
+        ("1800000013640001000000000000000000000000003e3000", r#"{"d":{"$numberDecimal":"0.1"}}"#),
+        ("180000001364000000000000000000000000000000007800", r#"{"d":{"$numberDecimal":"Infinity"}}"#),
+        ("18000000136400000000000000000000000000000000f800", r#"{"d":{"$numberDecimal":"-Infinity"}}"#),
+        ("180000001364000000000000000000000000000000007c00", r#"{"d":{"$numberDecimal":"NaN"}}"#),
+        ("08000000ff610000", r#"{"a":{"$minKey":1}}"#),
+        ("080000007f610000", r#"{"a":{"$maxKey":1}}"#),
+        ("100000000d6100040000006162630000", r#"{"a":{"$code":"abc"}}"#),
+        ("0d000000037800050000000000", r#"{"x":{}}"#),
+    ];
+    for (vector, expected) in vectors {
+        let raw = unhex(vector);
+        let (value, size) = decode(&raw).unwrap_or_else(|e| panic!("{}: {}", vector, e));
+        assert_eq!(size, raw.len());
+        assert_eq!(value.fingerprint(), json(expected).fingerprint(), "{}", vector);
+        let mut buf = Vec::new();
+        encode(&value, &mut buf).unwrap();
+        assert_eq!(hex(&buf), vector, "{}", expected);
+    }
+}
+
+#[test]
+fn binary() {
+    //二进制 子类型 0 是 Bytes 其他子类型保留子类型
+    let (value, _) = decode(&unhex("1d000000057800100000000473ffd26444b34c6990e8e7d1dfc035d400")).unwrap();
+    let binary = value.get_key("x").unwrap();
+    assert_eq!(binary.get_key("$type").unwrap(), Dynamic::Int(BINARY_UUID as i64));
+    assert!(matches!(binary.get_key("$binary").unwrap(), Dynamic::Bytes(b) if b.len() == 16));
+    let value = libai::dmap!("raw" => Dynamic::from_bytes(vec![1, 2, 3]));
+    let mut buf = Vec::new();
+    encode(&value, &mut buf).unwrap();
+    assert_eq!(hex(&buf), "120000000572617700030000000001020300");
+}
+
+#[test]
+fn decimal128() {
+    //decimal128 的字符串读写
+    let decimals = [
+        ("0", "0"), ("-0", "-0"), ("+1.5", "1.5"), ("-1.234567890123456789012345678901234E+6144", "-1.234567890123456789012345678901234E+6144"),
+        ("1000E0", "1000"), ("1E3", "1E+3"), ("0.000001", "0.000001"), ("1.0E-7", "1.0E-7"), ("123.456E-10", "1.23456E-8"), ("inf", "Infinity"),
+    ];
+    for (text, expected) in decimals {
+        let value = libai::dmap!("d" => libai::dmap!("$numberDecimal" => text));
+        let mut buf = Vec::new();
+        encode(&value, &mut buf).unwrap();
+        assert_eq!(buf[4], 0x13, "{}", text);
+        let decoded = decode(&buf).unwrap().0.get_key("d").unwrap().get_key("$numberDecimal").unwrap();
+        assert_eq!(decoded.as_str().unwrap(), expected);
+    }
+
+    for bad in ["1.5.5", "", "e3", "12345678901234567890123456789012345", "1E+7000", "1e2147483647", "0.1e-2147483648", "1e2147483648"] {    //不合法的或者要舍入的保留成普通文档
+        let value = libai::dmap!("d" => libai::dmap!("$numberDecimal" => bad));
+        let mut buf = Vec::new();
+        encode(&value, &mut buf).unwrap();
+        assert_eq!(buf[4], 0x03, "{}", bad);
+    }
+}
+
+#[test]
+fn round_trip() {
+    //嵌套的值读回来一样 形式不对的 Extended JSON 当作普通文档
+    let value = json(r#"{"trace":{"id":{"$oid":"000102030405060708090a0b"},"steps":[{"role":"user","tokens":12,"score":0.5,"big":9007199254740993},{"role":"tool","ok":false,"args":[]}],"when":{"$date":-1}},"fake":{"$oid":"xyz"},"both":{"$date":1,"x":2}}"#);
+    value.set_key("blob", Dynamic::from_bytes(vec![0; 100])).unwrap();
+    let mut buf = Vec::new();
+    encode(&value, &mut buf).unwrap();
+    buf.extend_from_slice(&buf.clone());
+    let documents = decode_all(&buf).unwrap();
+    assert_eq!(documents.len(), 2);
+    for document in documents {
+        assert_eq!(document.fingerprint(), value.fingerprint());
+    }
+}
+
+#[test]
+fn encode_errors() {
+    //不能编码的值
+    let cycle = Dynamic::map();
+    cycle.set_key("self", cycle.clone()).unwrap();
+    assert!(encode(&cycle, &mut Vec::new()).is_err());
+    cycle.remove_key("self").unwrap();
+    for bad in [json("[1]"), json("1"), libai::dmap!("a\0b" => 1i64), libai::dmap!("u" => Dynamic::UInt(u64::MAX))] {
+        assert!(encode(&bad, &mut Vec::new()).is_err());
+    }
+}
+
+#[test]
+fn decode_errors() {
+    //不合法的字节
+    let invalid = [
+        "", "0500", "0500000001", "06000000000000", "0900000008620002000000", "0c0000000261000500000061626300",
+        "0d000000026100ffffffff0000", "0800000020610000", "0a0000000a61", "1000000012610000000080000000",
+    ];
+    for vector in invalid {
+        assert!(decode(&unhex(vector)).is_err(), "{}", vector);
+    }
+    assert!(decode(&unhex("0800000020610000")).unwrap_err().to_string().contains("0x20"));
+}
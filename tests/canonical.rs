@@ -0,0 +1,94 @@
+use libai::dynamic::Dynamic;
+use libai::json::{FromJson, write_canonical};
+use libai::msgpack::encode_canonical;
+
+fn canonical(value: &Dynamic) -> String {
+    let mut buf = String::new();
+    write_canonical(value, &mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn numbers() {
+    //RFC 8785 附录 B 的数字
+    let numbers = [
+        (0x0000000000000000u64, "0"),
+        (0x8000000000000000, "0"),
+        (0x0000000000000001, "5e-324"),
+        (0x8000000000000001, "-5e-324"),
+        (0x7fefffffffffffff, "1.7976931348623157e+308"),
+        (0xffefffffffffffff, "-1.7976931348623157e+308"),
+        (0x4340000000000000, "9007199254740992"),
+        (0xc340000000000000, "-9007199254740992"),
+        (0x4430000000000000, "295147905179352830000"),
+        (0x44b52d02c7e14af5, "9.999999999999997e+22"),
+        (0x44b52d02c7e14af6, "1e+23"),
+        (0x44b52d02c7e14af7, "1.0000000000000001e+23"),
+        (0x444b1ae4d6e2ef4e, "999999999999999700000"),
+        (0x444b1ae4d6e2ef4f, "999999999999999900000"),
+        (0x444b1ae4d6e2ef50, "1e+21"),
+        (0x3eb0c6f7a0b5ed8c, "9.999999999999997e-7"),
+        (0x3eb0c6f7a0b5ed8d, "0.000001"),
+        (0x41b3de4355555553, "333333333.3333332"),
+        (0x41b3de4355555554, "333333333.33333325"),
+        (0x41b3de4355555555, "333333333.3333333"),
+        (0x41b3de4355555556, "333333333.3333334"),
+        (0x41b3de4355555557, "333333333.33333343"),
+        (0xbecbf647612f3696, "-0.0000033333333333333333"),
+        (0x43143ff3c1cb0959, "1424953923781206.2"),
+    ];
+    for (bits, expected) in numbers {
+        assert_eq!(canonical(&Dynamic::from(f64::from_bits(bits))), expected);
+    }
+    for bits in [0x7fffffffffffffffu64, 0x7ff0000000000000] {
+        assert!(write_canonical(&Dynamic::from(f64::from_bits(bits)), &mut String::new()).is_err());
+    }
+
+    //整数在 ±(2^53-1) 之内原样输出 超出的不能舍入成同一个数字
+    for (value, expected) in [(Dynamic::Int(9007199254740991), "9007199254740991"), (Dynamic::Int(-9007199254740991), "-9007199254740991"), (Dynamic::UInt(42), "42"), (Dynamic::Byte(255), "255")] {
+        assert_eq!(canonical(&value), expected);
+    }
+    for value in [Dynamic::Int(9007199254740992), Dynamic::Int(9007199254740993), Dynamic::Int(i64::MIN), Dynamic::UInt(u64::MAX)] {
+        assert!(write_canonical(&value, &mut String::new()).is_err(), "{:?}", value);
+    }
+}
+
+#[test]
+fn example() {
+    //RFC 8785 3.2.2 的例子
+    let input = r#"{
+        "numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+        "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+        "literals": [null, true, false]
+    }"#;
+    let (value, _) = Dynamic::from_json(input.as_bytes()).unwrap();
+    assert_eq!(canonical(&value), r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}"#);
+}
+
+#[test]
+fn key_order() {
+    //RFC 8785 3.2.3 key 按照 UTF-16 排序
+    let input = r#"{
+        "€": "Euro Sign",
+        "\r": "Carriage Return",
+        "דּ": "Hebrew Letter Dalet With Dagesh",
+        "1": "One",
+        "😀": "Emoji: Grinning Face",
+        "\u0080": "Control",
+        "ö": "Latin Small Letter O With Diaeresis"
+    }"#;
+    let (value, _) = Dynamic::from_json(input.as_bytes()).unwrap();
+    let expected = "{\"\\r\":\"Carriage Return\",\"1\":\"One\",\"\u{80}\":\"Control\",\"\u{f6}\":\"Latin Small Letter O With Diaeresis\",\
+        \"\u{20ac}\":\"Euro Sign\",\"\u{1f600}\":\"Emoji: Grinning Face\",\"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}";
+    assert_eq!(canonical(&value), expected);
+}
+
+#[test]
+fn msgpack() {
+    //msgpack 整数用最短的格式 map 的 key 按照编码后的字节排序
+    let value = libai::dmap!("b" => 200i64, "a" => -32i64, "c" => 1.5f64, "d" => 0.1f64);
+    let mut buf = Vec::new();
+    encode_canonical(&value, &mut buf).unwrap();
+    assert_eq!(buf, [0x84, 0xa1, b'a', 0xe0, 0xa1, b'b', 0xcc, 200, 0xa1, b'c', 0xca, 0x3f, 0xc0, 0, 0,
+        0xa1, b'd', 0xcb, 0x3f, 0xb9, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a]);
+}
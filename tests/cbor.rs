@@ -0,0 +1,90 @@
+use libai::cbor::{FromCbor, ToCbor, encode_deterministic, TAG_ENCODED_CBOR};
+use libai::dynamic::Dynamic;
+
+fn hex(raw: &[u8]) -> String {
+    raw.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Vec<u8> {
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+}
+
+#[test]
+fn rfc8949() {
+    //RFC 8949 附录 A 解码后再确定性编码 和原来的字节一样
+    let vectors = [
+        "00", "01", "0a", "17", "1818", "1819", "1864", "1903e8", "1a000f4240", "1b000000e8d4a51000",
+        "1bffffffffffffffff", "20", "29", "3863", "3903e7",
+        "f90000", "f98000", "f93c00", "fb3ff199999999999a", "f93e00", "f97bff", "fa47c35000", "fa7f7fffff",
+        "fb7e37e43c8800759c", "f90001", "f90400", "f9c400", "fbc010666666666666", "f97c00", "f97e00", "f9fc00",
+        "f4", "f5", "f6", "40", "4401020304", "60", "6161", "6449455446", "62225c", "62c3bc", "63e6b0b4",
+        "80", "83010203", "8301820203820405", "a0", "a201020304", "a26161016162820203",
+        "c074323031332d30332d32315432303a30343a30305a", "c11a514b67b0", "c1fb41d452d9ec200000", "d74401020304",
+        "d818456449455446", "d82076687474703a2f2f7777772e6578616d706c652e636f6d",
+    ];
+    for vector in vectors {
+        let raw = unhex(vector);
+        let (value, size) = Dynamic::from_cbor(&raw).unwrap();
+        assert_eq!(size, raw.len());
+        let mut buf = Vec::new();
+        encode_deterministic(&value, &mut buf).unwrap();
+        assert_eq!(hex(&buf), vector, "{:?}", value);
+    }
+}
+
+#[test]
+fn tags_and_indefinite() {
+    //大整数转成数字 self-describe 的标记去掉 不定长的项目解码后和定长的一样
+    let decoded = [
+        ("c249010000000000000000", "fb43f0000000000000"),
+        ("c2420100", "190100"),
+        ("d9d9f7a0", "a0"),
+        ("5f42010243030405ff", "450102030405"),
+        ("7f657374726561646d696e67ff", "6973747265616d696e67"),
+        ("9fff", "80"),
+        ("9f018202039f0405ffff", "8301820203820405"),
+        ("bf61610161629f0203ffff", "a26161016162820203"),
+    ];
+    for (input, expected) in decoded {
+        let raw = unhex(input);
+        let (value, size) = Dynamic::from_cbor(&raw).unwrap();
+        assert_eq!(size, raw.len());
+        let mut buf = Vec::new();
+        value.to_cbor(&mut buf);
+        assert_eq!(hex(&buf), expected, "{:?}", value);
+    }
+}
+
+#[test]
+fn deterministic() {
+    //确定性编码的 map key 按照编码后的字节排序
+    let value = libai::dmap!("b" => 1i64, "aa" => 2i64, "a" => 3i64);
+    let mut buf = Vec::new();
+    encode_deterministic(&value, &mut buf).unwrap();
+    assert_eq!(hex(&buf), "a361610361620162616102");
+    assert!(Dynamic::from_cbor(&unhex("9f01")).is_err());
+    assert!(Dynamic::from_cbor(&unhex("ff")).unwrap_err().to_string().contains("offset 0"));
+}
+
+#[test]
+fn tags() {
+    //tag 0 和 toml 一样是 {"$datetime": 字符串} 其他的 tag 是 {"$tag": tag, "$value": 内容}
+    let (value, _) = Dynamic::from_cbor(&unhex("c074323031332d30332d32315432303a30343a30305a")).unwrap();
+    assert_eq!(value.get_key("$datetime").unwrap(), Dynamic::from("2013-03-21T20:04:00Z"));
+    let (value, _) = Dynamic::from_cbor(&unhex("c11a514b67b0")).unwrap();
+    assert_eq!((value.get_key("$tag").unwrap(), value.get_key("$value").unwrap()), (Dynamic::Int(1), Dynamic::Int(1363896240)));
+    let (value, _) = Dynamic::from_cbor(&unhex("d818456449455446")).unwrap();
+    assert_eq!(value.get_key("$tag").unwrap(), Dynamic::Int(TAG_ENCODED_CBOR as i64));
+    let Dynamic::Bytes(inner) = value.get_key("$value").unwrap() else { panic!("{:?}", value) };
+    assert_eq!(Dynamic::from_cbor(&inner).unwrap().0, Dynamic::from("IETF"));
+    let (value, _) = Dynamic::from_cbor(&unhex("dbffffffffffffffff00")).unwrap();
+    assert_eq!(value.get_key("$tag").unwrap(), Dynamic::UInt(u64::MAX));
+
+    //形式不对的 Map 还是普通的 Map
+    for text in [r#"{"$datetime": 1}"#, r#"{"$tag": -1, "$value": 1}"#, r#"{"$tag": 1, "$other": 1}"#, r#"{"$tag": 1}"#] {
+        let (value, _) = <Dynamic as libai::json::FromJson>::from_json(text.as_bytes()).unwrap();
+        let mut buf = Vec::new();
+        value.to_cbor(&mut buf);
+        assert_eq!(buf[0] >> 5, 5, "{}", text);
+    }
+}
@@ -0,0 +1,84 @@
+use libai::dynamic::Dynamic;
+use libai::{dmap, dvec};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+#[test]
+fn readers_and_writers() {
+    let list = dvec![];
+    let obj = dmap!("list"=> list.clone(), "count"=> 0i64);
+    let barrier = Arc::new(Barrier::new(8));
+    let writers: Vec<_> = (0..4i64).map(|t| {
+        let (list, obj, barrier) = (list.clone(), obj.clone(), barrier.clone());
+        thread::spawn(move || {
+            barrier.wait();
+            for i in 0..1000 {
+                list.push(t * 1000 + i).unwrap();
+                obj.set_key(&format!("w{}", t), i).unwrap();
+            }
+        })
+    }).collect();
+    //读的时候长度只会增加 看到的 key 总是完整的值
+    let readers: Vec<_> = (0..4).map(|_| {
+        let (obj, barrier) = (obj.clone(), barrier.clone());
+        thread::spawn(move || {
+            barrier.wait();
+            let mut last = 0;
+            for _ in 0..1000 {
+                let len = obj.get_key("list").unwrap().len().unwrap();
+                assert!(len >= last);
+                last = len;
+                for t in 0..4 {
+                    if let Ok(v) = obj.get_key(&format!("w{}", t)) {
+                        assert!(matches!(v, Dynamic::Int(i) if (0..1000).contains(&i)));
+                    }
+                }
+            }
+        })
+    }).collect();
+    writers.into_iter().chain(readers).for_each(|h| h.join().unwrap());
+
+    assert_eq!(list.len().unwrap(), 4000);
+    let mut values: Vec<i64> = list.clone().into_vec().unwrap().into_iter().map(|v| match v {
+        Dynamic::Int(i)=> i,
+        v=> panic!("{:?}", v)
+    }).collect();
+    values.sort_unstable();
+    assert_eq!(values, (0..4000).collect::<Vec<_>>());
+    for t in 0..4 {
+        assert_eq!(obj.get_key(&format!("w{}", t)).unwrap(), Dynamic::Int(999));
+    }
+}
+
+#[test]
+fn writer_panic() {
+    //某个线程写的时候 panic 锁也不会中毒 已经写进去的内容还在
+    let list = dvec![1i64, 2i64];
+    let map = dmap!("a"=> 1i64);
+    let (l, m) = (list.clone(), map.clone());
+    let result = thread::spawn(move || {
+        if let (Dynamic::Vec(v), Dynamic::Map(m)) = (&l, &m) {
+            let mut v = v.write();
+            let mut m = m.write();
+            v.push(Dynamic::Int(3));
+            m.insert("b".into(), Dynamic::Int(2));
+            panic!("writer panic");
+        }
+    }).join();
+    assert!(result.is_err());
+
+    list.push(4i64).unwrap();
+    assert_eq!(list.len().unwrap(), 4);
+    assert_eq!(list.get(2).unwrap(), Dynamic::Int(3));
+    map.set_key("c", 3i64).unwrap();
+    assert_eq!(map.get_key("b").unwrap(), Dynamic::Int(2));
+    assert_eq!(map.len().unwrap(), 3);
+
+    //其他线程也可以继续读写
+    let handle = {
+        let list = list.clone();
+        thread::spawn(move || list.push(5i64).unwrap())
+    };
+    handle.join().unwrap();
+    assert_eq!(list.len().unwrap(), 5);
+}
@@ -0,0 +1,77 @@
+use libai::csv::{parse, parse_with, write_csv, write_with, CsvOptions};
+use libai::dynamic::Dynamic;
+use libai::json::FromJson;
+
+fn json(text: &str) -> Dynamic {
+    Dynamic::from_json(text.as_bytes()).unwrap().0
+}
+
+#[test]
+fn rfc4180() {
+    //RFC 4180 的引号 类型推断 空的单元格是 null 引号里的内容总是字符串
+    let text = "id,name,score,passed,note\r\n1,\"Smith, J\",0.93,TRUE,\r\n002,\"say \"\"hi\"\"\",-1e3,false,\"multi\nline\"\n\n3,,\"7\",\"\",x";
+    let rows = parse(text).unwrap();
+    let expected = json(r#"[{"id":1,"name":"Smith, J","score":0.93,"passed":true,"note":null},
+        {"id":"002","name":"say \"hi\"","score":-1000.0,"passed":false,"note":"multi\nline"},
+        {"id":3,"name":null,"score":"7","passed":"","note":"x"}]"#);
+    assert_eq!(rows.fingerprint(), expected.fingerprint());
+}
+
+#[test]
+fn tsv() {
+    //TSV 没有 header 时每行是 Vec 不推断类型
+    let options = CsvOptions { header: false, infer: false, ..CsvOptions::tsv() };
+    let rows = parse_with("a\tb\n1\t2\n3", &options).unwrap();
+    assert_eq!(rows.fingerprint(), json(r#"[["a","b"],["1","2"],["3"]]"#).fingerprint());
+}
+
+#[test]
+fn single_column() {
+    //只有一列的时候空行是 null 多列的时候空行跳过
+    assert_eq!(parse("a\n1\n\n2\n").unwrap().fingerprint(), json(r#"[{"a":1},{"a":null},{"a":2}]"#).fingerprint());
+    assert_eq!(parse("\na,b\n1,2\n\n3,4\n").unwrap().len().unwrap(), 2);
+    let data = json(r#"[{"a":"x"},{"a":null},{"a":"y"},{"a":null}]"#);
+    let mut buf = String::new();
+    write_csv(&data, &mut buf).unwrap();
+    assert_eq!(parse(&buf).unwrap().fingerprint(), data.fingerprint(), "{}", buf);
+}
+
+#[test]
+fn errors() {
+    //错误带上行号
+    for (bad, message) in [("a,b\n1,2,3", "line 2"), ("a\n\"open", "line 2"), ("a\n\"x\"y", "line 2"), ("a,a\n1,2", "duplicate column a")] {
+        assert!(parse(bad).unwrap_err().to_string().contains(message), "{}", bad);
+    }
+    //分隔符只能是 ascii 的字节
+    for delimiter in [0xa7, b'"', b'\n'] {
+        let options = CsvOptions { delimiter, ..CsvOptions::default() };
+        assert!(parse_with("a\u{a7}b\n1\u{a7}2", &options).unwrap_err().to_string().contains("invalid csv delimiter"));
+        assert!(write_with(&json(r#"[[1,2]]"#), &mut String::new(), &options).is_err());
+    }
+}
+
+#[test]
+fn write() {
+    //嵌套的值展开成 a.b 的列 unflatten 读回来
+    let data = json(r#"[{"id":1,"input":{"prompt":"hi, there","tags":["a","b"]},"output":"ok","score":0.5},
+        {"id":2,"input":{"prompt":"line\nbreak","tags":["c","d"]},"output":"123","score":null,"extra":true}]"#);
+    let mut buf = String::new();
+    write_csv(&data, &mut buf).unwrap();
+    assert!(buf.starts_with("id,input.prompt,input.tags.0,input.tags.1,output,score,extra\n"), "{}", buf);
+    let options = CsvOptions { unflatten: true, ..CsvOptions::default() };
+    let back = parse_with(&buf, &options).unwrap();
+    data.get(0).unwrap().set_key("extra", Dynamic::Null).unwrap();
+    assert_eq!(back.fingerprint(), data.fingerprint(), "{}", buf);
+
+    //TSV 写出来再读回来
+    let options = CsvOptions::tsv();
+    let mut buf = String::new();
+    write_with(&data, &mut buf, &options).unwrap();
+    assert_eq!(parse_with(&buf, &options).unwrap().len().unwrap(), 2);
+    assert!(write_csv(&json(r#"[1,2]"#), &mut String::new()).is_err());
+
+    //Vec 的行里嵌套的值写成紧凑的 json 没有换行和缩进
+    let mut buf = String::new();
+    write_csv(&json(r#"[[1,{"a":[1,2],"b":"x y"},[]]]"#), &mut buf).unwrap();
+    assert_eq!(buf, "1,\"{\"\"a\"\":[1,2],\"\"b\"\":\"\"x y\"\"}\",[]\n");
+}
@@ -0,0 +1,75 @@
+use libai::dynamic::Dynamic;
+use libai::json::{self, FromJson};
+use libai::msgpack::{self, MsgPack, MsgUnpack};
+use libai::{dmap, dvec};
+
+fn compact(value: &Dynamic) -> String {
+    let mut buf = String::new();
+    json::write_compact(value, &mut buf);
+    buf
+}
+
+#[test]
+fn detect() {
+    let list = dvec![1i64];
+    assert!(!list.has_cycle());
+    list.push(list.clone()).unwrap();
+    assert!(list.has_cycle());
+    let map = dmap!("n" => 1i64);
+    map.set_key("list", dvec![map.clone()]).unwrap();
+    assert!(map.has_cycle());
+
+    //同一个容器出现两次但不是祖先 不算循环
+    let shared = dvec![1i64];
+    assert!(!dvec![shared.clone(), shared.clone()].has_cycle());
+    assert!(!Dynamic::Int(1).has_cycle());
+
+    let pairs = Dynamic::from_pairs(vec![(Dynamic::Int(1), Dynamic::Null)]);
+    pairs.set_key("1", pairs.clone()).unwrap();
+    assert!(pairs.has_cycle());
+    assert_eq!(pairs.break_cycles(), 1);
+    assert!(!pairs.has_cycle());
+
+    assert_eq!(list.break_cycles(), 1);
+    assert_eq!(map.break_cycles(), 1);
+    assert!(!list.has_cycle() && !map.has_cycle());
+    assert!(map.get_key("list").unwrap().get(0).unwrap().is_null());
+    assert_eq!(map.break_cycles(), 0);
+}
+
+#[test]
+fn debug() {
+    let list = dvec![1i64];
+    list.push(list.clone()).unwrap();
+    assert_eq!(format!("{:?}", list), "Vec([Int(1), <cycle>])");
+    let map = dmap!("n" => 1i64);
+    map.set_key("self", map.clone()).unwrap();
+    assert_eq!(format!("{:?}", map), r#"Map({"n": Int(1), "self": <cycle>})"#);
+    list.break_cycles();
+    map.break_cycles();
+}
+
+#[test]
+fn writers() {
+    //普通的 writer 遇到循环引用写 null canonical 的直接报错
+    let list = dvec![1i64];
+    list.push(list.clone()).unwrap();
+    let map = dmap!("n" => 1i64);
+    map.set_key("self", map.clone()).unwrap();
+    assert_eq!(compact(&list), "[1,null]");
+    assert_eq!(compact(&map), r#"{"n":1,"self":null}"#);
+    let (value, _) = Dynamic::from_json(compact(&dvec![map.clone(), list.clone()]).as_bytes()).unwrap();
+    assert_eq!(compact(&value), r#"[{"n":1,"self":null},[1,null]]"#);
+
+    let mut buf = Vec::new();
+    list.encode(&mut buf);
+    assert_eq!(buf, [0x92, 0x01, 0xc0]);
+    let mut buf = Vec::new();
+    map.encode(&mut buf);
+    assert_eq!(Dynamic::decode(&buf).unwrap().0.get_key("self").unwrap(), Dynamic::Null);
+
+    assert!(json::write_canonical(&map, &mut String::new()).unwrap_err().to_string().contains("cycle"));
+    assert!(msgpack::encode_canonical(&list, &mut Vec::new()).unwrap_err().to_string().contains("cycle"));
+    list.break_cycles();
+    map.break_cycles();
+}
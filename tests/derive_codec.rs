@@ -0,0 +1,59 @@
+#![allow(dead_code)]
+use libai::dynamic::Dynamic;
+use libai::json::{FromJson, ToJson};
+use libai::msgpack::{MsgPack, MsgUnpack};
+use libai::{FromJson, MsgPack, MsgUnpack, ToJson};
+
+#[derive(ToJson, FromJson, MsgPack, MsgUnpack, Debug)]
+struct Message {
+    role: Dynamic,
+    #[libai(rename = "content")]
+    text: Dynamic,
+    #[libai(skip)]
+    tokens: Dynamic,
+    #[libai(default)]
+    name: Dynamic,
+}
+
+#[derive(ToJson, FromJson, MsgPack, MsgUnpack, Debug)]
+#[libai(tag = "type")]
+enum Event {
+    Start,
+    Delta { index: Dynamic, message: Message },
+    #[libai(rename = "stop")]
+    Stop { reason: Dynamic },
+}
+
+#[test]
+fn json() {
+    //rename 的字段用新的名字 skip 的字段不读不写 default 的字段可以没有
+    let (event, _) = Event::from_json(br#"{"type": "Delta", "index": 0, "message": {"role": "user", "content": "hi", "tokens": 5}}"#).unwrap();
+    let Event::Delta { index, message } = &event else { panic!("{:?}", event) };
+    assert_eq!(*index, Dynamic::Int(0));
+    assert_eq!(message.text, Dynamic::from("hi"));
+    assert!(message.tokens.is_null() && message.name.is_null());
+
+    let mut json = String::new();
+    event.to_json(&mut json);
+    let (value, _) = Dynamic::from_json(json.as_bytes()).unwrap();
+    let expected = Dynamic::from_json(br#"{"type":"Delta","index":0,"message":{"role":"user","content":"hi","name":null}}"#).unwrap().0;
+    assert_eq!(value.fingerprint(), expected.fingerprint(), "{}", json);
+    let mut compact = String::new();
+    libai::json::write_compact(&event, &mut compact);
+    assert_eq!(compact, r#"{"type":"Delta","index":0,"message":{"role":"user","content":"hi","name":null}}"#);
+
+    assert!(matches!(Event::from_json(br#"{"type": "Start"}"#).unwrap().0, Event::Start));
+    assert!(Event::from_json(br#"{"type": "Stop", "reason": "x"}"#).is_err());
+    assert!(Event::from_json(br#"{"type": "Delta", "index": 0, "message": {"role": "user"}}"#).is_err());
+}
+
+#[test]
+fn msgpack() {
+    let mut buf = Vec::new();
+    Event::Stop { reason: "length".into() }.encode(&mut buf);
+    let value = Dynamic::decode(&buf).unwrap().0;
+    assert_eq!(value.fingerprint(), libai::dmap!("type" => "stop", "reason" => "length").fingerprint());
+    let (event, size) = Event::decode(&buf).unwrap();
+    assert_eq!(size, buf.len());
+    assert!(matches!(event, Event::Stop { reason } if reason == Dynamic::from("length")));
+}
@@ -0,0 +1,53 @@
+use libai::dynamic::Dynamic;
+use libai::fingerprint::*;
+use libai::{dmap, dvec};
+
+#[test]
+fn sha256() {
+    let mut hasher = Sha256::new();
+    hasher.update(b"abc");
+    assert_eq!(Fingerprint(hasher.finish()).to_hex(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+}
+
+fn hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    Fingerprint(hasher.finish()).to_hex()
+}
+
+#[test]
+fn nist_vectors() {
+    //FIPS 180-2 的例子 448 位和 896 位的消息正好跨过填充的边界
+    assert_eq!(hex(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    assert_eq!(hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"), "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+    assert_eq!(hex(b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu"), "cf5b16a778af8380036ce59e7b0492370b249b11e8f07a51afac45037afee9d1");
+    //一百万个 a 分成不整齐的块写入
+    let mut hasher = Sha256::new();
+    let chunk = [b'a'; 1000];
+    let mut left = 1_000_000;
+    while left > 0 {
+        let n = left.min(997);
+        hasher.update(&chunk[..n]);
+        left -= n;
+    }
+    assert_eq!(Fingerprint(hasher.finish()).to_hex(), "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0");
+    //55 56 64 字节的消息 填充需要一个或者两个块
+    assert_eq!(hex(&[b'a'; 55]), "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318");
+    assert_eq!(hex(&[b'a'; 56]), "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a");
+    assert_eq!(hex(&[b'a'; 64]), "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb");
+}
+
+#[test]
+fn dynamic() {
+    //map 的 key 顺序不影响摘要
+    let a = dmap!("x" => 1i64, "y" => dvec!(1.0f64, "s"));
+    let b = dmap!("y" => dvec!(1.0f64, "s"), "x" => 1i64);
+    assert_eq!(a.fingerprint(), b.fingerprint());
+    //默认整数和浮点数不同 NumberPolicy::Value 只看数值
+    let c = dmap!("x" => 1.0f64, "y" => dvec!(1i64, "s"));
+    assert_ne!(a.fingerprint(), c.fingerprint());
+    let v = FingerprintOptions { numbers: NumberPolicy::Value };
+    assert_eq!(a.fingerprint_with(&v), c.fingerprint_with(&v));
+    let any = Dynamic::from_pairs(vec![("y".into(), dvec!(1.0f64, "s")), ("x".into(), 1i64.into())]);
+    assert_eq!(any.fingerprint(), a.fingerprint());
+}
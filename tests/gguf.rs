@@ -0,0 +1,155 @@
+use libai::dynamic::Dynamic;
+use libai::gguf::{type_name, Gguf};
+use libai::json::FromJson;
+
+fn string(buf: &mut Vec<u8>, text: &str) {
+    buf.extend_from_slice(&(text.len() as u64).to_le_bytes());
+    buf.extend_from_slice(text.as_bytes());
+}
+
+fn key(buf: &mut Vec<u8>, name: &str, kind: u32, value: &[u8]) {
+    string(buf, name);
+    buf.extend_from_slice(&kind.to_le_bytes());
+    buf.extend_from_slice(value);
+}
+
+fn tensor(buf: &mut Vec<u8>, name: &str, dims: &[u64], kind: u32, offset: u64) {
+    string(buf, name);
+    buf.extend_from_slice(&(dims.len() as u32).to_le_bytes());
+    dims.iter().for_each(|d| buf.extend_from_slice(&d.to_le_bytes()));
+    buf.extend_from_slice(&kind.to_le_bytes());
+    buf.extend_from_slice(&offset.to_le_bytes());
+}
+
+//和 llama.cpp 写出来的一样的 header
+fn header(tensors: u64, keys: u64) -> Vec<u8> {
+    let mut buf = b"GGUF".to_vec();
+    buf.extend_from_slice(&3u32.to_le_bytes());
+    buf.extend_from_slice(&tensors.to_le_bytes());
+    buf.extend_from_slice(&keys.to_le_bytes());
+    buf
+}
+
+#[test]
+fn read() {
+    let mut buf = header(2, 13);
+    let mut text = Vec::new();
+    string(&mut text, "llama");
+    key(&mut buf, "general.architecture", 8, &text);
+    key(&mut buf, "general.file_type", 4, &15u32.to_le_bytes());
+    key(&mut buf, "u8", 0, &[200]);
+    key(&mut buf, "i8", 1, &[0xff]);
+    key(&mut buf, "u16", 2, &60000u16.to_le_bytes());
+    key(&mut buf, "i16", 3, &(-2i16).to_le_bytes());
+    key(&mut buf, "i32", 5, &(-3i32).to_le_bytes());
+    key(&mut buf, "llama.rope.freq_base", 6, &10000.0f32.to_le_bytes());
+    key(&mut buf, "bool", 7, &[1]);
+    key(&mut buf, "u64", 10, &u64::MAX.to_le_bytes());
+    key(&mut buf, "i64", 11, &(-5i64).to_le_bytes());
+    key(&mut buf, "f64", 12, &0.5f64.to_le_bytes());
+    let mut array = 9u32.to_le_bytes().to_vec();                //[["a", "bc"], []]
+    array.extend_from_slice(&2u64.to_le_bytes());
+    for items in [&["a", "bc"][..], &[]] {
+        array.extend_from_slice(&8u32.to_le_bytes());
+        array.extend_from_slice(&(items.len() as u64).to_le_bytes());
+        items.iter().for_each(|item| string(&mut array, item));
+    }
+    key(&mut buf, "tokenizer.nested", 9, &array);
+    tensor(&mut buf, "token_embd.weight", &[4096, 32000], 12, 0);
+    tensor(&mut buf, "output_norm.weight", &[4096], 0, 73728000);
+    let header_size = buf.len() as u64;
+    buf.extend_from_slice(&[0; 100]);                            //张量的数据不会读
+
+    let gguf = Gguf::read(buf.as_slice()).unwrap();
+    assert_eq!((gguf.version, gguf.alignment), (3, 32));
+    assert_eq!(gguf.data_offset, header_size.div_ceil(32) * 32);
+    let expected = Dynamic::from_json(br#"{"general.architecture":"llama","general.file_type":15,"u8":200,"i8":-1,"u16":60000,"i16":-2,"i32":-3,"llama.rope.freq_base":10000.0,"bool":true,"i64":-5,"f64":0.5,"tokenizer.nested":[["a","bc"],[]]}"#).unwrap().0;
+    expected.set_key("u64", Dynamic::UInt(u64::MAX)).unwrap();
+    expected.set_key("llama.rope.freq_base", Dynamic::Float(10000.0)).unwrap();
+    assert_eq!(gguf.metadata.fingerprint(), expected.fingerprint());
+    assert_eq!(gguf.tensors[0].elements(), Some(4096 * 32000));
+    assert_eq!(type_name(gguf.tensors[0].ggml_type), Some("Q4_K"));
+    let value = gguf.to_dynamic();
+    let tensors = value.get_key("tensors").unwrap();
+    assert_eq!(tensors.get(1).unwrap().get_key("type").unwrap().as_str().unwrap(), "F32");
+    assert_eq!(tensors.get(1).unwrap().get_key("offset").unwrap(), Dynamic::Int(73728000));
+    assert_eq!(value.get_key("version").unwrap(), Dynamic::Int(3));
+}
+
+#[test]
+fn version1() {
+    //版本 1 的长度是 u32 general.alignment 改变数据区的位置
+    let mut buf = b"GGUF".to_vec();
+    buf.extend_from_slice(&[1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+    buf.extend_from_slice(&[17, 0, 0, 0]);
+    buf.extend_from_slice(b"general.alignment");
+    buf.extend_from_slice(&[4, 0, 0, 0, 64, 0, 0, 0]);
+    buf.extend_from_slice(&[1, 0, 0, 0, b'w', 1, 0, 0, 0, 8, 0, 0, 0, 99, 0, 0, 0, 64, 0, 0, 0, 0, 0, 0, 0]);
+    let gguf = Gguf::read(buf.as_slice()).unwrap();
+    assert_eq!((gguf.version, gguf.alignment, gguf.data_offset, gguf.tensors[0].offset), (1, 64, 128, 64));
+    assert_eq!(type_name(99), None);
+    assert_eq!(gguf.to_dynamic().get_key("tensors").unwrap().get(0).unwrap().get_key("type").unwrap(), Dynamic::Int(99));
+}
+
+#[test]
+fn invalid() {
+    //不合法的文件
+    let mut big_endian = b"GGUF".to_vec();
+    big_endian.extend_from_slice(&3u32.to_be_bytes());
+    let mut bad_bool = header(0, 1);
+    key(&mut bad_bool, "b", 7, &[2]);
+    let mut bad_type = header(0, 1);
+    key(&mut bad_type, "x", 13, &[]);
+    let mut duplicate = header(0, 2);
+    key(&mut duplicate, "x", 0, &[1]);
+    key(&mut duplicate, "x", 0, &[2]);
+    let mut unaligned = header(1, 0);
+    tensor(&mut unaligned, "t", &[1], 0, 3);
+    let mut dims = header(1, 0);
+    tensor(&mut dims, "t", &[1; 5], 0, 0);
+    let mut long = header(0, 1);
+    long.extend_from_slice(&u64::MAX.to_le_bytes());
+    let mut short = header(0, 1);                               //声明 1GB 的字符串 实际只有几个字节
+    short.extend_from_slice(&(1u64 << 30).to_le_bytes());
+    short.extend_from_slice(b"abc");
+    let mut truncated = header(2, 0);
+    tensor(&mut truncated, "t", &[1], 0, 0);
+    for (index, bytes) in [b"GGML".to_vec(), big_endian, header(0, 0)[..10].to_vec(), bad_bool, bad_type, duplicate, unaligned, dims, long, short, truncated].iter().enumerate() {
+        let result = Gguf::read(bytes.as_slice());
+        assert!(result.is_err(), "{}", index);
+    }
+}
+
+#[test]
+fn depth() {
+    //嵌套的数组有层数的限制 不会栈溢出
+    let nested = |levels: usize| {
+        let mut buf = header(0, 1);
+        string(&mut buf, "deep");
+        buf.extend_from_slice(&9u32.to_le_bytes());
+        for _ in 1..levels {
+            buf.extend_from_slice(&9u32.to_le_bytes());
+            buf.extend_from_slice(&1u64.to_le_bytes());
+        }
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf.extend_from_slice(&0u64.to_le_bytes());
+        buf
+    };
+    let gguf = Gguf::read(nested(64).as_slice()).unwrap();
+    let mut value = gguf.metadata.get_key("deep").unwrap();
+    for _ in 1..64 {
+        value = value.get(0).unwrap();
+    }
+    assert_eq!(value.len().unwrap(), 0);
+    let error = Gguf::read(nested(100000).as_slice()).unwrap_err().to_string();
+    assert!(error.contains("nested deeper than 64"), "{}", error);
+}
+
+#[test]
+fn elements() {
+    //维度的乘积溢出时没有元素个数
+    let mut buf = header(1, 0);
+    tensor(&mut buf, "huge", &[1 << 40, 1 << 40], 0, 0);
+    let gguf = Gguf::read(buf.as_slice()).unwrap();
+    assert_eq!(gguf.tensors[0].elements(), None);
+}
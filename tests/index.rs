@@ -0,0 +1,63 @@
+use libai::dynamic::Dynamic;
+use libai::json::FromJson;
+use libai::dvec;
+
+fn json(text: &str) -> Dynamic {
+    Dynamic::from_json(text.as_bytes()).unwrap().0
+}
+
+#[test]
+fn at() {
+    let value = json(r#"{"choices":[{"text":"hi"},{"text":"there"}],"n":2}"#);
+    assert_eq!(value.at("choices").at(1).at("text"), Dynamic::from("there"));
+    assert_eq!(value.at("choices").at(1u64).at(String::from("text")), Dynamic::from("there"));
+    assert!(value.at("choices").at(2).is_null());
+    assert!(value.at("choices").at(-1).is_null());
+    assert!(value.at("missing").at(0).at("x").is_null());
+    assert!(value.at(0).is_null());
+
+    assert_eq!(value.set_at("n", 3i64).unwrap(), Some(Dynamic::Int(2)));
+    assert_eq!(value.at("choices").set_at(0, "x").unwrap().unwrap().at("text"), Dynamic::from("hi"));
+    assert!(value.at("choices").set_at(5, 1i64).unwrap_err().to_string().contains("outbound"));
+    assert!(value.at("choices").set_at(-1, 1i64).is_err());
+    assert!(value.set_at(0, 1i64).is_err());
+    assert_eq!(value.at("choices").at(0), Dynamic::from("x"));
+}
+
+#[test]
+fn any_map() {
+    //AnyMap 的 key 和 get_key 一样按照 key_text 匹配
+    let pairs = Dynamic::from_pairs(vec![(Dynamic::Int(1), "one".into()), ("name".into(), dvec![Dynamic::Bool(true)])]);
+    assert_eq!(pairs.at("1"), Dynamic::from("one"));
+    assert_eq!(pairs.at("1"), pairs.get_key("1").unwrap());
+    assert_eq!(pairs.pointer("/name/0"), Dynamic::Bool(true));
+    assert_eq!(pairs.set_at("1", "uno").unwrap(), Some(Dynamic::from("one")));
+    assert_eq!(pairs.set_pointer("/name/0", false).unwrap(), Some(Dynamic::Bool(true)));
+    pairs.set_pointer("/extra/deep", 1i64).unwrap();
+    assert_eq!(pairs.pointer("/extra/deep"), Dynamic::Int(1));
+    assert_eq!(pairs.len().unwrap(), 3);
+}
+
+#[test]
+fn pointer() {
+    let value = json(r#"{"a/b":{"m~n":[10,20]},"list":[],"":1}"#);
+    assert_eq!(value.pointer("/a~1b/m~0n/1"), Dynamic::Int(20));
+    assert_eq!(value.pointer("/"), Dynamic::Int(1));
+    assert!(value.pointer("").is_map());
+    assert!(value.pointer("a~1b").is_null());
+    assert!(value.pointer("/a~1b/m~0n/x").is_null());
+    assert!(value.pointer("/a~1b/m~0n/2").is_null());
+
+    //中间缺少的 Map 自动创建 "-" 追加到 Vec 末尾
+    assert_eq!(value.set_pointer("/x/y/z", true).unwrap(), None);
+    assert_eq!(value.pointer("/x/y/z"), Dynamic::Bool(true));
+    value.set_pointer("/list/-", 1i64).unwrap();
+    value.set_pointer("/list/-", 2i64).unwrap();
+    assert_eq!(value.set_pointer("/list/0", 3i64).unwrap(), Some(Dynamic::Int(1)));
+    assert_eq!(value.pointer("/list").len().unwrap(), 2);
+    assert!(value.set_pointer("/list/5", 1i64).is_err());
+    assert!(value.set_pointer("/list/x", 1i64).unwrap_err().to_string().contains("is not a index"));
+    assert!(value.set_pointer("/list/7/a", 1i64).unwrap_err().to_string().contains("is not existed"));
+    assert!(value.set_pointer("", 1i64).unwrap_err().to_string().contains("root"));
+    assert!(value.set_pointer("x", 1i64).unwrap_err().to_string().contains("must start with /"));
+}
@@ -0,0 +1,68 @@
+use libai::dynamic::Dynamic;
+use libai::{dmap, dvec};
+
+#[test]
+fn entries() {
+    let map = Dynamic::map();
+    for i in (0..100i64).rev() {
+        map.set_key(&format!("k{:03}", i), i).unwrap();
+    }
+    let entries = map.entries().unwrap();
+    assert_eq!(entries.len(), 100);
+    let keys: Vec<_> = entries.map(|(k, _)| k).collect();
+    let mut expected: Vec<_> = (0..100).map(|i| format!("k{:03}", i)).collect();
+    if cfg!(feature = "preserve_order") {                   //插入的顺序
+        expected.reverse();
+    }
+    assert_eq!(keys, expected);
+    assert_eq!(map.values().unwrap().map(|v| match v { Dynamic::Int(i)=> i, _=> -1 }).sum::<i64>(), 4950);
+    assert!(dvec![1i64].entries().is_err());
+}
+
+#[test]
+fn modify_after_iteration() {
+    //迭代器释放以后才能修改同一个容器 边遍历边修改要先复制出来
+    let list = dvec![1i64, 2i64];
+    for item in list.clone().into_vec().unwrap() {
+        list.push(item).unwrap();
+    }
+    assert_eq!(list.len().unwrap(), 4);
+    let map = dmap!("a"=> 1i64, "b"=> 2i64);
+    for (k, v) in map.clone().into_entries().unwrap() {
+        map.set_key(&format!("{}{}", k, k), v).unwrap();
+    }
+    assert_eq!(map.len().unwrap(), 4);
+    let mut iter = list.iter().unwrap();
+    iter.next();
+    assert_eq!(iter.len(), 3);
+    drop(iter);
+    list.push(5i64).unwrap();
+}
+
+#[test]
+fn entries_snapshot() {
+    //entries 是快照 循环里修改同一个 map 不会死锁 也不影响这次遍历
+    let map = dmap!("a"=> 1i64, "b"=> 2i64);
+    for (k, v) in map.entries().unwrap() {
+        map.set_key(&format!("{}{}", k, k), v).unwrap();
+    }
+    assert_eq!(map.len().unwrap(), 4);
+    let pairs = Dynamic::from_pairs(vec![(Dynamic::Int(1), "one".into()), ("name".into(), Dynamic::Int(2))]);
+    for (k, _) in pairs.entries().unwrap() {
+        pairs.set_key(&format!("{}!", k), Dynamic::Null).unwrap();
+    }
+    assert_eq!(pairs.keys().unwrap().collect::<Vec<_>>(), ["1", "name", "1!", "name!"]);
+}
+
+#[test]
+fn any_map() {
+    let pairs = Dynamic::from_pairs(vec![(Dynamic::Int(1), "one".into()), ("name".into(), Dynamic::Int(2)), (Dynamic::Bool(true), Dynamic::Null)]);
+    pairs.retain(|v| !v.is_null()).unwrap();
+    assert_eq!(pairs.len().unwrap(), 2);
+    pairs.for_each_mut(|v| if let Dynamic::Int(i) = v { *i *= 10 }).unwrap();
+    assert_eq!(pairs.get_key("name").unwrap(), Dynamic::Int(20));
+    let mapped = pairs.map_values(|v| Dynamic::Bool(v.is_string())).unwrap();
+    assert_eq!(mapped.get_key("1").unwrap(), Dynamic::Bool(true));
+    assert_eq!(pairs.get_key("1").unwrap(), Dynamic::from("one"));
+    assert_eq!(pairs.into_iter().count(), 2);
+}
@@ -0,0 +1,70 @@
+use libai::dynamic::Dynamic;
+use libai::json::{self, FromJson, ParseOptions, ToJson};
+use libai::map::DuplicatePolicy;
+
+fn to_json<T: ToJson>(value: &T) -> String {
+    let mut buf = String::new();
+    value.to_json(&mut buf);
+    buf
+}
+
+#[test]
+fn numbers() {
+    //Dynamic 的数字和对应的标量输出一样 json 表示不了的浮点数输出 null
+    assert_eq!(to_json(&Dynamic::Byte(200)), to_json(&200u8));
+    assert_eq!(to_json(&Dynamic::UInt(u64::MAX)), "18446744073709551615");
+    assert_eq!(to_json(&Dynamic::Float(0.1)), to_json(&0.1f32));
+    assert_eq!(to_json(&Dynamic::Double(-2.5)), "-2.5");
+    for value in [Dynamic::Double(f64::NAN), Dynamic::Double(f64::INFINITY), Dynamic::Float(f32::NEG_INFINITY)] {
+        assert_eq!(to_json(&value), "null");
+    }
+    let value = libai::dvec![Dynamic::UInt(42), Dynamic::Float(1.5), Dynamic::Byte(7), Dynamic::Double(f64::NAN)];
+    let text = to_json(&value);
+    let (back, _) = Dynamic::from_json(text.as_bytes()).unwrap();
+    assert_eq!(to_json(&back), text);
+    assert_eq!(back.get(2).unwrap(), Dynamic::Int(7));
+}
+
+#[test]
+fn duplicates() {
+    let text = br#"{"a":1,"b":2,"a":3,"a":[4]}"#;
+    let parse = |duplicates| json::parse_with(text, &ParseOptions { duplicates }).map(|(value, _)| value);
+    let compact = |value: &Dynamic| {
+        let mut buf = String::new();
+        json::write_compact(value, &mut buf);
+        buf
+    };
+    assert_eq!(compact(&parse(DuplicatePolicy::LastWins).unwrap()), r#"{"a":[4],"b":2}"#);
+    assert_eq!(compact(&parse(DuplicatePolicy::FirstWins).unwrap()), r#"{"a":1,"b":2}"#);
+    assert_eq!(compact(&parse(DuplicatePolicy::Collect).unwrap()), r#"{"a":[1,3,[4]],"b":2}"#);
+    assert_eq!(parse(DuplicatePolicy::Error).unwrap_err().to_string(), r#"duplicate key "a" at offset 13"#);
+    //嵌套的 object 偏移也是相对整个输入
+    let error = json::parse_with(br#"[{"x":{"k":1,"k":2}}]"#, &ParseOptions { duplicates: DuplicatePolicy::Error }).unwrap_err();
+    assert_eq!(error.to_string(), r#"duplicate key "k" at offset 13"#);
+    //第一个值本身是数组时 也是包一层
+    let (value, _) = json::parse_with(br#"{"a":[1],"a":2}"#, &ParseOptions { duplicates: DuplicatePolicy::Collect }).unwrap();
+    assert_eq!(compact(&value), r#"{"a":[[1],2]}"#);
+}
+
+#[test]
+fn uint() {
+    //i64 放不下的正整数解析为 UInt 写出去再读回来不变
+    for v in [u64::MAX, 1 << 63] {
+        let text = to_json(&Dynamic::UInt(v));
+        assert_eq!(text, v.to_string());
+        assert_eq!(Dynamic::from_json(text.as_bytes()).unwrap().0, Dynamic::UInt(v));
+    }
+    assert_eq!(Dynamic::from_json(b"-9223372036854775808").unwrap().0, Dynamic::Int(i64::MIN));
+    assert!(matches!(Dynamic::from_json(b"18446744073709551616").unwrap().0, Dynamic::Double(_)));
+    assert!(matches!(Dynamic::from_json(b"-9223372036854775809").unwrap().0, Dynamic::Double(_)));
+}
+
+#[test]
+fn compact() {
+    //直接输出紧凑格式 字符串里的空白保留 输出完以后 to_json 还是原来的格式
+    let nested = (vec![1i64, 2], std::collections::BTreeMap::from([("k", "a b\n")]), Some(Dynamic::from_pairs(vec![(Dynamic::Int(1), libai::dvec![Dynamic::Null])])));
+    let mut buf = String::from("x");
+    json::write_compact(&nested, &mut buf);
+    assert_eq!(buf, r#"x[[1,2],{"k":"a b\n"},{"1":[null]}]"#);
+    assert_eq!(to_json(&vec![1i64, 2]), "[1,\n2]");
+}
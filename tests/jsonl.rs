@@ -0,0 +1,68 @@
+use libai::dynamic::Dynamic;
+use libai::jsonl::{parse_parallel, ReadOptions, Reader, Writer};
+use std::io::Cursor;
+
+#[test]
+fn read() {
+    let data = "{\"id\": 1, \"text\": \"a\\nb\"}\n\n  \r\n{\"id\": 2}\r\n{\"id\": 3\n[1, 2] x\n{\"id\": 4}";
+
+    //默认遇到第一个错误以后结束 空行不算记录但是算行号
+    let results: Vec<_> = Reader::<_, Dynamic>::new(Cursor::new(data)).collect();
+    assert_eq!(results.len(), 3);
+    assert!(results[2].as_ref().unwrap_err().to_string().starts_with("line 5:"));
+
+    //继续读后面的行
+    let options = ReadOptions { continue_on_error: true };
+    let mut reader = Reader::<_, Dynamic>::with_options(Cursor::new(data), options);
+    let results: Vec<_> = reader.by_ref().collect();
+    assert_eq!(results.len(), 5);
+    assert!(results[3].as_ref().unwrap_err().to_string().starts_with("line 6:"));
+    assert_eq!(results[4].as_ref().unwrap().get_key("id").unwrap(), Dynamic::Int(4));
+    assert_eq!(reader.line(), 7);
+
+    //其他 FromJson 的类型
+    let rows: Vec<Vec<i64>> = Reader::new(Cursor::new("[1, 2]\n[3]\n")).collect::<Result<_, _>>().unwrap();
+    assert_eq!(rows, vec![vec![1, 2], vec![3]]);
+
+    //并行解析的错误也带上行号
+    let results = parse_parallel::<Dynamic>(data.as_bytes(), 4);
+    assert_eq!(results.len(), 5);
+    assert!(results[2].as_ref().unwrap_err().to_string().starts_with("line 5:"));
+    assert!(results[3].as_ref().unwrap_err().to_string().starts_with("line 6:"));
+}
+
+#[test]
+fn write() {
+    //写出来的每条记录是一行 读回来和原来一样
+    let mut writer = Writer::new(Vec::new());
+    for i in 0..1000i64 {
+        let record = libai::dmap!("id" => i, "prompt" => format!("line {}\nnext", i), "tags" => Dynamic::from_vec(vec![Dynamic::from("x"), Dynamic::Int(i)]));
+        writer.write(&record).unwrap();
+    }
+    writer.write(&vec![1i64, 2]).unwrap();
+    assert_eq!(writer.count(), 1001);
+    let output = writer.into_inner().unwrap();
+    assert_eq!(output.iter().filter(|c| **c == b'\n').count(), 1001);
+
+    //并行解析和顺序读的结果一样
+    let serial: Vec<_> = Reader::<_, Dynamic>::new(Cursor::new(&output)).map(|r| r.unwrap().fingerprint()).collect();
+    for threads in [0, 1, 3, 8, 5000] {
+        let parallel: Vec<_> = parse_parallel::<Dynamic>(&output, threads).into_iter().map(|r| r.unwrap().fingerprint()).collect();
+        assert_eq!(parallel, serial);
+    }
+}
+
+#[test]
+fn compact() {
+    //没有空白 控制字符都转义 每一行都是合法的 json
+    let record = libai::dmap!("a"=> "tab\tbell\u{7}nul\u{0} \"q\": x", "b"=> libai::dvec![1i64, libai::dmap!("c"=> Dynamic::Null)], "e"=> "\u{8}\u{c}\u{1f}");
+    let mut writer = Writer::new(Vec::new());
+    writer.write(&record).unwrap();
+    writer.write(&vec![Some("x, y"), None]).unwrap();
+    let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+    let lines: Vec<_> = output.lines().collect();
+    assert_eq!(lines[0], r#"{"a":"tab\tbell\u0007nul\u0000 \"q\": x","b":[1,{"c":null}],"e":"\b\f\u001f"}"#);
+    assert_eq!(lines[1], r#"["x, y",null]"#);
+    let back: Vec<_> = Reader::<_, Dynamic>::new(Cursor::new(&output)).map(|r| r.unwrap()).collect();
+    assert_eq!(back[0].fingerprint(), record.fingerprint());
+}
@@ -0,0 +1,122 @@
+use libai::dynamic::{Dynamic, DynamicMap};
+use libai::json::FromJson;
+use libai::map::MergeStrategy;
+use libai::{dmap, dvec};
+use std::cmp::Ordering;
+
+fn json(text: &str) -> Dynamic {
+    Dynamic::from_json(text.as_bytes()).unwrap().0
+}
+
+fn same(a: &Dynamic, b: &Dynamic) -> bool {
+    a.total_cmp(b) == Ordering::Equal
+}
+
+#[test]
+fn entry() {
+    let map = dmap!("a" => 1i64);
+    assert_eq!(map.entry("a").unwrap().key(), "a");
+    assert_eq!(map.entry("a").unwrap().or_insert(2i64), Dynamic::Int(1));
+    assert_eq!(map.entry("b").unwrap().or_insert(2i64), Dynamic::Int(2));
+    assert!(map.entry("c").unwrap().or_default().is_null());
+    assert_eq!(map.entry("d").unwrap().or_insert_with(|| "x".into()), Dynamic::from("x"));
+    map.entry("a").unwrap().and_modify(|v| *v = Dynamic::Int(10)).or_insert(0i64);
+    map.entry("e").unwrap().and_modify(|v| *v = Dynamic::Int(10)).or_insert(0i64);
+    assert!(map.entry("x").unwrap().get().is_none());
+    assert!(same(&map, &json(r#"{"a":10,"b":2,"c":null,"d":"x","e":0}"#)));
+    assert!(dvec![1i64].entry("a").is_err());
+
+    //不存在的时候不会插入
+    assert_eq!(map.get_or_default("a", 0i64).unwrap(), Dynamic::Int(10));
+    assert_eq!(map.get_or_default("z", "none").unwrap(), Dynamic::from("none"));
+    assert!(!map.contains("z").unwrap());
+}
+
+#[test]
+fn rename() {
+    let map = json(r#"{"b":1,"a":2,"c":3}"#);
+    assert!(map.rename_key("a", "z").unwrap());
+    assert!(!map.rename_key("missing", "y").unwrap());
+    assert!(map.rename_key("b", "b").unwrap());
+    assert!(map.rename_key("b", "c").unwrap_err().to_string().contains("key c is existed"));
+    assert!(same(&map, &json(r#"{"b":1,"z":2,"c":3}"#)));
+    let keys: Vec<_> = map.entries().unwrap().map(|(k, _)| k).collect();
+    if cfg!(feature = "preserve_order") {                   //改名的 key 留在原来的位置
+        assert_eq!(keys, ["b", "z", "c"]);
+    } else {
+        assert_eq!(keys, ["b", "c", "z"]);
+    }
+    map.retain_entries(|k, v| k != "b" && !v.is_null()).unwrap();
+    assert_eq!(map.keys_sorted().unwrap(), ["c", "z"]);
+}
+
+#[test]
+fn merge() {
+    let base = r#"{"name":"a","tags":["x"],"model":{"temperature":0.5,"stop":["\n"]},"n":1}"#;
+    let other = json(r#"{"name":"b","tags":["y"],"model":{"temperature":1.0,"top_p":0.9},"extra":true}"#);
+    let vectors = [
+        (MergeStrategy::Overwrite, r#"{"name":"b","tags":["y"],"model":{"temperature":1.0,"stop":["\n"],"top_p":0.9},"n":1,"extra":true}"#),
+        (MergeStrategy::Keep, r#"{"name":"a","tags":["x"],"model":{"temperature":0.5,"stop":["\n"],"top_p":0.9},"n":1,"extra":true}"#),
+        (MergeStrategy::ConcatVec, r#"{"name":"b","tags":["x","y"],"model":{"temperature":1.0,"stop":["\n"],"top_p":0.9},"n":1,"extra":true}"#),
+    ];
+    for (strategy, expected) in vectors {
+        let target = json(base);
+        target.deep_merge(&other, strategy).unwrap();
+        assert!(same(&target, &json(expected)), "{:?} {:?}", strategy, target);
+    }
+
+    //合并进来的是复制 修改 target 不影响 other
+    let target = json(base);
+    target.deep_merge(&other, MergeStrategy::Overwrite).unwrap();
+    target.get_key("tags").unwrap().push("z").unwrap();
+    assert_eq!(other.get_key("tags").unwrap().len().unwrap(), 1);
+
+    let target = json(base);
+    let error = target.deep_merge(&other, MergeStrategy::Error).unwrap_err().to_string();
+    assert!(error.contains("is conflicted") && error.starts_with("key /"), "{}", error);
+    let error = json(r#"{"model":{"temperature":0.5}}"#).deep_merge(&json(r#"{"model":{"temperature":1}}"#), MergeStrategy::Error).unwrap_err();
+    assert_eq!(error.to_string(), "key /model/temperature is conflicted");
+    assert!(target.deep_merge(&dvec![1i64], MergeStrategy::Overwrite).is_err());
+}
+
+#[test]
+fn cycle() {
+    //自己包含自己的 map 合并到自己 和 deep_clone 一样不会无限递归
+    let a = dmap!("n" => 1i64);
+    a.set_key("self", a.clone()).unwrap();
+    a.deep_merge(&a, MergeStrategy::Keep).unwrap();
+    a.deep_merge(&a, MergeStrategy::Overwrite).unwrap();
+    assert_eq!(a.get_key("n").unwrap(), Dynamic::Int(1));
+    let copy = a.deep_clone();
+    assert!(!copy.has_cycle());
+    let b = dmap!("n" => 2i64);
+    b.deep_merge(&a, MergeStrategy::Overwrite).unwrap();
+    assert_eq!(b.get_key("n").unwrap(), Dynamic::Int(1));
+    assert!(!b.has_cycle());
+    a.break_cycles();
+}
+
+#[test]
+fn from_map() {
+    //用 DynamicMap 构造 打不打开 preserve_order 都能编译
+    let mut map = DynamicMap::new();
+    map.insert("b".into(), Dynamic::Int(1));
+    map.insert("a".into(), Dynamic::Int(2));
+    let value = Dynamic::from_map(map);
+    let collected = Dynamic::from_map([("b".into(), Dynamic::Int(1)), ("a".into(), Dynamic::Int(2))].into_iter().collect());
+    assert!(same(&value, &collected));
+    assert_eq!(value.keys_sorted().unwrap(), ["a", "b"]);
+}
+
+#[cfg(feature = "preserve_order")]
+#[test]
+fn preserve_order() {
+    let map = json(r#"{"b":1,"a":2}"#);
+    assert_eq!(map.keys().unwrap().collect::<Vec<_>>(), ["b", "a"]);
+    let mut buf = String::new();
+    libai::json::write_compact(&map, &mut buf);
+    assert_eq!(buf, r#"{"b":1,"a":2}"#);
+    map.set_key("c", 3i64).unwrap();
+    map.remove_key("b").unwrap();
+    assert_eq!(map.keys().unwrap().collect::<Vec<_>>(), ["a", "c"]);
+}
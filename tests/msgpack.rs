@@ -0,0 +1,116 @@
+use libai::dynamic::Dynamic;
+use libai::json::ToJson;
+use libai::map::DuplicatePolicy;
+use libai::msgpack::{self, DecodeOptions, KeyPolicy, MsgPack, MsgUnpack};
+
+#[test]
+fn truncated() {
+    //长度超出输入的 bin str array 返回错误 不会越界
+    for raw in [&[0xc4, 0x02, 0x00][..], &[0xc5, 0x00, 0x02, 0x00], &[0xc6, 0x00, 0x00, 0x00, 0x02, 0x00], &[0xa2, 0x61], &[0x92, 0x01]] {
+        assert!(Dynamic::decode(raw).is_err(), "{:02x?}", raw);
+    }
+    let (value, size) = Dynamic::decode(&[0xc5, 0x00, 0x02, 0x01, 0x02]).unwrap();
+    assert_eq!(size, 5);
+    assert!(matches!(value, Dynamic::Bytes(b) if *b == [1, 2]));
+}
+
+#[test]
+fn any_map() {
+    //{1: "one", "name": "x", [1]: true} 的 key 都可以用字符串访问
+    let (map, _) = Dynamic::decode(&[0x83, 0x01, 0xa3, b'o', b'n', b'e', 0xa4, b'n', b'a', b'm', b'e', 0xa1, b'x', 0x91, 0x01, 0xc3]).unwrap();
+    assert!(map.is_any_map());
+    assert_eq!(map.get_key("1").unwrap(), Dynamic::from("one"));
+    assert_eq!(map.get_key("name").unwrap(), Dynamic::from("x"));
+    assert_eq!(map.get_key("[1]").unwrap(), Dynamic::Bool(true));
+    assert!(map.get_key("2").is_err());
+    assert!(map.contains("1").unwrap() && !map.contains("one").unwrap());
+
+    //替换的时候保留原来的 key 类型 新的 key 是字符串
+    assert_eq!(map.set_key("1", "uno").unwrap(), Some(Dynamic::from("one")));
+    assert_eq!(map.set_key("2", "dos").unwrap(), None);
+    let pairs = map.pairs().unwrap();
+    assert_eq!(pairs[0].0, Dynamic::Int(1));
+    assert_eq!(pairs[3].0, Dynamic::from("2"));
+    assert_eq!(map.remove_key("name").unwrap(), Some(Dynamic::from("x")));
+    assert_eq!(map.remove_key("name").unwrap(), None);
+    let keys: Vec<_> = map.keys().unwrap().collect();
+    assert_eq!(keys, ["1", "[1]", "2"]);
+    assert_eq!(map.entries().unwrap().len(), 3);
+
+    //json 不会丢掉容器 key
+    let mut buf = String::new();
+    map.to_json(&mut buf);
+    assert_eq!(buf, "{\"1\": \"uno\",\n\"[1]\": true,\n\"2\": \"dos\"}");
+}
+
+#[test]
+fn uint() {
+    //i64 放不下的 u64 编码成 uint64 解码回来还是原来的值
+    for v in [u64::MAX, 1 << 63] {
+        let mut buf = Vec::new();
+        Dynamic::UInt(v).encode(&mut buf);
+        assert_eq!(buf[0], 0xcf);
+        assert_eq!(Dynamic::decode(&buf).unwrap().0, Dynamic::UInt(v));
+    }
+    let mut buf = Vec::new();
+    Dynamic::UInt(7).encode(&mut buf);
+    assert_eq!((buf.as_slice(), Dynamic::decode(&buf).unwrap().0), (&[7u8][..], Dynamic::Int(7)));
+}
+
+#[test]
+fn key_policy() {
+    //{1: "a", "x": 2}
+    let raw = [0x82, 0x01, 0xa1, b'a', 0xa1, b'x', 0x02];
+    let strict = DecodeOptions { keys: KeyPolicy::Strict, ..Default::default() };
+    assert_eq!(msgpack::decode_with(&raw, &strict).unwrap_err().to_string(), "map key Int(1) at offset 1 is not a string");
+    let (map, _) = msgpack::decode_with(&[0x81, 0xa1, b'x', 0x02], &strict).unwrap();
+    assert!(map.is_map());
+
+    let stringify = DecodeOptions { keys: KeyPolicy::Stringify, ..Default::default() };
+    let (map, size) = msgpack::decode_with(&raw, &stringify).unwrap();
+    assert_eq!(size, raw.len());
+    assert!(map.is_map());
+    assert_eq!(map.keys_sorted().unwrap(), ["1", "x"]);
+    //容器不能转成字符串 key  {[1]: true}
+    assert!(msgpack::decode_with(&[0x81, 0x91, 0x01, 0xc3], &stringify).unwrap_err().to_string().contains("can not be a key"));
+    //1 和 "1" 转成同一个 key 后面的覆盖前面的
+    let (map, _) = msgpack::decode_with(&[0x82, 0x01, 0x01, 0xa1, b'1', 0x02], &stringify).unwrap();
+    assert_eq!(map.get_key("1").unwrap(), Dynamic::Int(2));
+}
+
+#[test]
+fn many_keys() {
+    //大量非字符串 key 按照第一次出现的顺序保留 重复的 key 不是线性查找
+    let count = 100_000i64;
+    let pairs = Dynamic::from_pairs((0..count).rev().map(|i| (Dynamic::Int(i), Dynamic::Int(i))).chain([(Dynamic::Int(5), Dynamic::Null)]).collect());
+    let mut buf = Vec::new();
+    pairs.encode(&mut buf);
+    let (map, _) = Dynamic::decode(&buf).unwrap();
+    let decoded = map.pairs().unwrap();
+    assert_eq!(decoded.len(), count as usize);
+    assert_eq!(decoded[0].0, Dynamic::Int(count - 1));
+    assert_eq!(map.get_key("5").unwrap(), Dynamic::Null);
+}
+
+#[test]
+fn duplicates() {
+    //{"a": 1, "a": 2} 和 {1: "x", 1: "y", "z": 0}
+    let strings = [0x82, 0xa1, b'a', 0x01, 0xa1, b'a', 0x02];
+    let pairs = [0x83, 0x01, 0xa1, b'x', 0x01, 0xa1, b'y', 0xa1, b'z', 0x00];
+    let decode = |raw: &[u8], duplicates| msgpack::decode_with(raw, &DecodeOptions { duplicates, ..Default::default() }).map(|(value, _)| value);
+
+    assert_eq!(decode(&strings, DuplicatePolicy::LastWins).unwrap().get_key("a").unwrap(), Dynamic::Int(2));
+    assert_eq!(decode(&strings, DuplicatePolicy::FirstWins).unwrap().get_key("a").unwrap(), Dynamic::Int(1));
+    let collected = decode(&strings, DuplicatePolicy::Collect).unwrap().get_key("a").unwrap();
+    assert_eq!(collected.into_vec().unwrap(), [Dynamic::Int(1), Dynamic::Int(2)]);
+    assert_eq!(decode(&strings, DuplicatePolicy::Error).unwrap_err().to_string(), r#"duplicate key "a" at offset 4"#);
+
+    let map = decode(&pairs, DuplicatePolicy::LastWins).unwrap();
+    assert!(map.is_any_map());
+    assert_eq!((map.len().unwrap(), map.get_key("1").unwrap()), (2, Dynamic::from("y")));
+    assert_eq!(decode(&pairs, DuplicatePolicy::FirstWins).unwrap().get_key("1").unwrap(), Dynamic::from("x"));
+    let map = decode(&pairs, DuplicatePolicy::Collect).unwrap();
+    assert_eq!(map.get_key("1").unwrap().into_vec().unwrap(), [Dynamic::from("x"), Dynamic::from("y")]);
+    assert_eq!(map.pairs().unwrap()[0].0, Dynamic::Int(1));
+    assert_eq!(decode(&pairs, DuplicatePolicy::Error).unwrap_err().to_string(), "duplicate key Int(1) at offset 4");
+}
@@ -0,0 +1,113 @@
+use libai::dynamic::Dynamic;
+use libai::json::FromJson;
+use libai::npy::{read_npy, read_npz, write_npy, write_npz, Dtype, NdArray};
+
+fn json(text: &str) -> Dynamic {
+    Dynamic::from_json(text.as_bytes()).unwrap().0
+}
+
+fn unhex(text: &str) -> Vec<u8> {
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+}
+
+fn npy(header: &str, data: &[u8]) -> Vec<u8> {
+    let mut buf = b"\x93NUMPY\x01\x00".to_vec();
+    buf.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    buf.extend_from_slice(header.as_bytes());
+    buf.extend_from_slice(data);
+    buf
+}
+
+#[test]
+fn npy_format() {
+    //和 np.save(np.arange(3)) 的字节一样
+    let array = NdArray::new(Dtype::I64, vec![3], [0i64, 1, 2].iter().flat_map(|v| v.to_le_bytes()).collect()).unwrap();
+    let mut buf = Vec::new();
+    write_npy(&array, &mut buf);
+    assert_eq!(buf.len(), 128 + 24);
+    assert_eq!(&buf[..10], b"\x93NUMPY\x01\x00\x76\x00");
+    assert_eq!(std::str::from_utf8(&buf[10..128]).unwrap().trim_end(), "{'descr': '<i8', 'fortran_order': False, 'shape': (3,), }");
+    assert_eq!(read_npy(&buf).unwrap().to_dynamic().fingerprint(), json("[0,1,2]").fingerprint());
+
+    //大端 Fortran 顺序 Python 2 的长整数 和标量
+    let data: Vec<u8> = [1i32, 4, 2, 5, 3, 6].iter().flat_map(|v| v.to_be_bytes()).collect();
+    let array = read_npy(&npy("{'descr': '>i4', 'fortran_order': True, 'shape': (2L, 3L), }       \n", &data)).unwrap();
+    assert_eq!((array.dtype(), array.shape().to_vec()), (Dtype::I32, vec![2, 3]));
+    assert_eq!(array.to_dynamic().fingerprint(), json("[[1,2,3],[4,5,6]]").fingerprint());
+    let data: Vec<u8> = (0..24u8).collect();
+    let array = read_npy(&npy("{\"descr\": \"|u1\", \"fortran_order\": True, \"shape\": (2, 3, 4)}\n", &data)).unwrap();
+    assert_eq!(array.to_dynamic().get(1).unwrap().get(2).unwrap().get(3).unwrap(), Dynamic::Int(1 + 2 * 2 + 3 * 6));
+    let scalar = read_npy(&npy("{'descr': '<f2', 'fortran_order': False, 'shape': (), }\n", &0x3e00u16.to_le_bytes())).unwrap();
+    assert_eq!(scalar.to_dynamic(), Dynamic::Float(1.5));
+    let flags = read_npy(&npy("{'descr': '|b1', 'fortran_order': False, 'shape': (0, 2), }\n", &[])).unwrap();
+    assert_eq!(flags.to_dynamic().len().unwrap(), 0);
+}
+
+#[test]
+fn dynamic_and_npz() {
+    //Dynamic 和数组互相转换
+    for (text, dtype) in [("[[1,2],[3,-4]]", Dtype::I64), ("[true,false]", Dtype::Bool), ("[[0.5],[1]]", Dtype::F64), ("[]", Dtype::F64), ("7", Dtype::I64)] {
+        let value = json(text);
+        let array = NdArray::from_dynamic(&value).unwrap();
+        assert_eq!(array.dtype(), dtype, "{}", text);
+        let mut buf = Vec::new();
+        write_npy(&array, &mut buf);
+        assert_eq!(buf.len() % 64, array.data().len() % 64);
+        let back = read_npy(&buf).unwrap();
+        assert_eq!(back.shape(), array.shape());
+        let expected = if text == "[[0.5],[1]]" { json("[[0.5],[1.0]]") } else { value };
+        assert_eq!(back.to_dynamic().fingerprint(), expected.fingerprint(), "{}", text);
+    }
+    let embeddings = Dynamic::from_vec((0..4).map(|i| Dynamic::from_vec((0..3).map(|j| Dynamic::Float((i * 3 + j) as f32 / 8.0)).collect())).collect());
+    let array = NdArray::from_dynamic(&embeddings).unwrap();
+    assert_eq!((array.dtype(), array.shape().to_vec()), (Dtype::F32, vec![4, 3]));
+    let map = array.to_map();
+    assert_eq!(map.get_key("dtype").unwrap().as_str().unwrap(), "<f4");
+    assert_eq!(NdArray::from_map(&map).unwrap().to_dynamic().fingerprint(), embeddings.fingerprint());
+    let big = libai::dmap!("dtype" => ">u2", "shape" => Dynamic::from_vec(vec![Dynamic::Int(2)]), "data" => Dynamic::from_bytes(vec![1, 0, 0, 2]));
+    assert_eq!(NdArray::from_map(&big).unwrap().to_dynamic().fingerprint(), json("[256,2]").fingerprint());
+    assert!(NdArray::from_dynamic(&json("[[1,2],[3]]")).is_err());
+    assert!(NdArray::from_dynamic(&json("[1,\"a\"]")).is_err());
+    assert!(NdArray::from_dynamic(&json("[[1],2]")).is_err());
+
+    //np.savez_compressed 写的 npz (deflate zip64) 不是 .npy 的文件忽略
+    let compressed = unhex("504b03042d0000000800000021000fbc5b38ffffffffffffffff05001400612e6e707901001000980000000000000056000000000000009bec17ea1b10c9c850c650ad9e925a9c5ca46ea5a06e9769a2aea3a09e965f54529498179f5f94920a120f292a4d050a17672416a402b91a463a0ac69a3a0ab50ae4032e060606462066016226206605626620660300504b03042d00000008000000210044796d5dffffffffffffffff05001400622e6e707901001000820000000000000044000000000000009bec17ea1b10c9c850c650ad9e925a9c5ca46ea5a06e9366a4aea3a09e965f54529498179f5f94920a12774bcc294e058a17672416a402f91a9a3a0ab50a14012e063b00504b03041400000008006d1e535d8316dc8c03000000010000000a000000726561646d652e747874ab0000504b01022d032d0000000800000021000fbc5b385600000098000000050000000000000000000000800100000000612e6e7079504b01022d032d00000008000000210044796d5d440000008200000005000000000000000000000080018d000000622e6e7079504b010214031400000008006d1e535d8316dc8c03000000010000000a0000000000000000000000800108010000726561646d652e747874504b050600000000030003009e000000330100000000");
+    let arrays = read_npz(&compressed).unwrap();
+    assert_eq!(arrays.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+    assert_eq!(arrays[0].1.to_dynamic().fingerprint(), json("[[1,2,3],[4,5,6]]").fingerprint());
+    assert_eq!(arrays[1].1.to_dynamic(), Dynamic::Float(1.5));
+    //中央目录声明的大小比解压出来的小 解压到声明的大小就停止
+    let mut short = compressed.clone();
+    let record = short.windows(12).position(|w| w == [0x0f, 0xbc, 0x5b, 0x38, 0x56, 0, 0, 0, 0x98, 0, 0, 0]).unwrap();
+    short[record + 8] = 0x10;
+    assert!(read_npz(&short).unwrap_err().to_string().starts_with("can not inflate a"));
+
+    //写出的 npz 读回来一样
+    let labels = NdArray::from_dynamic(&json("[3,1,2]")).unwrap();
+    let mut buf = Vec::new();
+    write_npz(&[("embeddings", &array), ("labels", &labels)], &mut buf).unwrap();
+    let arrays = read_npz(&buf).unwrap();
+    assert_eq!(arrays.len(), 2);
+    assert_eq!(arrays[0].1.to_dynamic().fingerprint(), embeddings.fingerprint());
+    assert_eq!(arrays[1].1.data(), labels.data());
+
+    //不合法的输入
+    let mut corrupt = buf.clone();
+    corrupt[200] ^= 1;
+    let invalid = [
+        npy("{'descr': '<c16', 'fortran_order': False, 'shape': (1,), }\n", &[0; 16]),
+        npy("{'descr': [('x', '<i4')], 'fortran_order': False, 'shape': (1,), }\n", &[0; 4]),
+        npy("{'descr': '<i4', 'fortran_order': False, 'shape': (2,), }\n", &[0; 4]),
+        npy("{'descr': '<i4', 'fortran_order': False, 'shape': (-1,), }\n", &[]),
+        npy("{'descr': '<i4', 'fortran_order': False, 'shape': (1,) \n", &[0; 4]),
+        npy("{'descr': '<i4', 'shape': (1,), }\n", &[0; 4]),
+        b"\x93NUMPY\x01\x00\xff\x00{".to_vec(),
+        b"PK\x03\x04".to_vec(),
+    ];
+    for (index, bytes) in invalid.iter().enumerate() {
+        let result = read_npy(bytes);
+        assert!(result.is_err(), "{}", index);
+    }
+    assert!(read_npz(&corrupt).unwrap_err().to_string().contains("crc mismatch"));
+    assert!(read_npz(&buf[..buf.len() - 1]).is_err());
+}
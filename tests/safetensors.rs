@@ -0,0 +1,61 @@
+use libai::dynamic::Dynamic;
+use libai::safetensors::{Dtype, SafeTensors};
+use std::io::Cursor;
+
+//header 后面补空格到 8 字节对齐 和 python 的 safetensors 写出来的一样
+fn file(header: &str, data: &[u8]) -> Vec<u8> {
+    let mut header = header.as_bytes().to_vec();
+    while !header.len().is_multiple_of(8) {
+        header.push(b' ');
+    }
+    let mut buf = (header.len() as u64).to_le_bytes().to_vec();
+    buf.extend_from_slice(&header);
+    buf.extend_from_slice(data);
+    buf
+}
+
+#[test]
+fn read() {
+    let weight: Vec<u8> = [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+    let mut data = weight.clone();
+    data.extend_from_slice(&[7, 0, 8, 0]);
+    data.extend_from_slice(&1.5f64.to_le_bytes());
+    let header = r#"{"__metadata__":{"format":"pt"},"model.weight":{"dtype":"F32","shape":[2,3],"data_offsets":[0,24]},"model.bias":{"dtype":"I16","shape":[2],"data_offsets":[24,28]},"scale":{"dtype":"F64","shape":[],"data_offsets":[28,36]},"empty":{"dtype":"BF16","shape":[0,4],"data_offsets":[36,36]}}"#;
+    let mut tensors = SafeTensors::new(Cursor::new(file(header, &data))).unwrap();
+    assert_eq!(tensors.metadata().get_key("format").unwrap().as_str().unwrap(), "pt");
+    let names: Vec<_> = tensors.tensors().iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, ["model.weight", "model.bias", "scale", "empty"]);
+    let info = tensors.tensor("model.weight").unwrap();
+    assert_eq!((info.dtype, info.shape.clone(), info.offsets, info.elements()), (Dtype::F32, vec![2, 3], (0, 24), 6));
+    assert_eq!(tensors.tensor("scale").unwrap().elements(), 1);
+    assert!(matches!(tensors.read("model.weight").unwrap(), Dynamic::Bytes(b) if *b == weight));
+    assert!(matches!(tensors.read("model.bias").unwrap(), Dynamic::Bytes(b) if *b == [7, 0, 8, 0]));
+    assert!(matches!(tensors.read("empty").unwrap(), Dynamic::Bytes(b) if b.is_empty()));
+    assert!(tensors.read("missing").is_err());
+    for dtype in ["BOOL", "U8", "I8", "F8_E5M2", "F8_E4M3", "I16", "U16", "F16", "BF16", "I32", "U32", "F32", "I64", "U64", "F64"] {
+        assert_eq!(Dtype::parse(dtype).unwrap().name(), dtype);
+    }
+}
+
+#[test]
+fn invalid() {
+    //不合法的文件
+    let invalid = [
+        file("", &[]),
+        file("[]", &[]),
+        file("{\"a\":{\"dtype\":\"F32\",\"shape\":[2],\"data_offsets\":[0,8]}} x", &[0; 8]),
+        file(r#"{"a":{"dtype":"F32","shape":[2],"data_offsets":[0,8]}}"#, &[0; 4]),
+        file(r#"{"a":{"dtype":"F32","shape":[3],"data_offsets":[0,8]}}"#, &[0; 8]),
+        file(r#"{"a":{"dtype":"F128","shape":[1],"data_offsets":[0,16]}}"#, &[0; 16]),
+        file(r#"{"a":{"dtype":"U8","shape":[4],"data_offsets":[4,0]}}"#, &[0; 4]),
+        file(r#"{"a":{"dtype":"U8","shape":[4]}}"#, &[0; 4]),
+        file(r#"{"a":{"dtype":"U8","shape":[4],"data_offsets":[0,4]},"b":{"dtype":"U8","shape":[4],"data_offsets":[2,6]}}"#, &[0; 6]),
+        file(r#"{"a":{"dtype":"U8","shape":[-1],"data_offsets":[0,4]}}"#, &[0; 4]),
+        vec![0xff; 16],
+        vec![1, 0, 0],
+    ];
+    for (index, bytes) in invalid.into_iter().enumerate() {
+        let result = SafeTensors::new(Cursor::new(bytes));
+        assert!(result.is_err(), "{}", index);
+    }
+}
@@ -0,0 +1,129 @@
+use libai::dynamic::Dynamic;
+use libai::json::FromJson;
+use libai::msgpack::MsgUnpack;
+use libai::schema::Schema;
+
+fn json(text: &str) -> Dynamic {
+    Dynamic::from_json(text.as_bytes()).unwrap().0
+}
+
+#[test]
+fn keywords() {
+    //(schema, 通过的值, 不通过的值)
+    let vectors = [
+        (r#"{"type":"integer"}"#, vec!["1", "-5", "1.0e0"], vec!["1.5", "\"1\"", "null"]),
+        (r#"{"type":["string","null"]}"#, vec!["\"a\"", "null"], vec!["1", "[]", "{}"]),
+        (r#"{"type":"number"}"#, vec!["1", "2.5"], vec!["true"]),
+        (r#"{"type":"array","items":{"type":"boolean"},"minItems":1,"maxItems":2}"#, vec!["[true]", "[true,false]"], vec!["[]", "[1]", "[true,true,true]", "{}"]),
+        (r#"{"prefixItems":[{"type":"string"},{"type":"integer"}],"items":false}"#, vec!["[]", "[\"a\",1]"], vec!["[1]", "[\"a\",1,2]"]),
+        (r#"{"enum":["a",1,null,[1,{"x":true}]]}"#, vec!["\"a\"", "1", "1.0", "null", "[1,{\"x\":true}]"], vec!["\"b\"", "2", "[1,{\"x\":false}]", "[1]"]),
+        (r#"{"const":{"a":[1,2]}}"#, vec!["{\"a\":[1,2.0]}"], vec!["{\"a\":[2,1]}", "{\"a\":[1,2],\"b\":1}", "{}"]),
+        (r#"{"minimum":1,"maximum":10}"#, vec!["1", "10", "5.5", "\"x\""], vec!["0", "10.5", "-1"]),
+        (r#"{"exclusiveMinimum":0,"exclusiveMaximum":1}"#, vec!["0.5"], vec!["0", "1"]),
+        (r#"{"minLength":2,"maxLength":3,"pattern":"^[a-z]+$"}"#, vec!["\"ab\"", "\"abc\"", "5"], vec!["\"a\"", "\"abcd\"", "\"AB\""]),
+        (r#"{"maxLength":2}"#, vec!["\"中文\""], vec!["\"中文字\""]),
+        (r#"{"type":"object","properties":{"a":{"type":"string"}},"required":["a"],"additionalProperties":{"type":"integer"}}"#, vec!["{\"a\":\"x\"}", "{\"a\":\"x\",\"b\":1}"], vec!["{}", "{\"a\":1}", "{\"a\":\"x\",\"b\":\"y\"}"]),
+        (r#"{"anyOf":[{"type":"string"},{"minimum":5}]}"#, vec!["\"a\"", "6"], vec!["1"]),
+        (r#"{"oneOf":[{"type":"integer"},{"minimum":5}]}"#, vec!["1", "5.5"], vec!["6", "1.5"]),
+        (r#"{"allOf":[{"type":"integer"},{"minimum":5}]}"#, vec!["6"], vec!["1", "5.5"]),
+        (r#"{"not":{"type":"null"}}"#, vec!["1", "{}"], vec!["null"]),
+        (r##"{"$defs":{"id":{"type":"string","minLength":1}},"type":"array","items":{"$ref":"#/$defs/id"}}"##, vec!["[\"a\"]"], vec!["[\"\"]", "[1]"]),
+        ("true", vec!["1", "null"], vec![]),
+        ("false", vec![], vec!["1", "null"]),
+    ];
+    for (schema, valid, invalid) in vectors {
+        let compiled = Schema::compile(&json(schema)).unwrap_or_else(|e| panic!("{}: {}", schema, e));
+        for value in valid {
+            assert!(compiled.is_valid(&json(value)), "{} {} {:?}", schema, value, compiled.validate(&json(value)));
+        }
+        for value in invalid {
+            assert!(!compiled.is_valid(&json(value)), "{} {}", schema, value);
+        }
+    }
+}
+
+#[test]
+fn numbers() {
+    //整数精确比较 不经过 f64 舍入
+    let schema = Schema::compile(&json(r#"{"enum":[9007199254740992]}"#)).unwrap();
+    assert!(schema.is_valid(&Dynamic::Int(9007199254740992)));
+    assert!(schema.is_valid(&Dynamic::Double(9007199254740992.0)));
+    assert!(!schema.is_valid(&Dynamic::Int(9007199254740993)));
+    let big = Schema::compile(&libai::dmap!("const" => Dynamic::UInt(u64::MAX))).unwrap();
+    assert!(big.is_valid(&Dynamic::UInt(u64::MAX)));
+    assert!(!big.is_valid(&Dynamic::UInt(u64::MAX - 1)));
+    assert!(!big.is_valid(&Dynamic::Int(-1)));
+    assert!(!Schema::compile(&json(r#"{"const":1}"#)).unwrap().is_valid(&Dynamic::Double(1.5)));
+    assert!(!Schema::compile(&json(r#"{"const":1.5}"#)).unwrap().is_valid(&Dynamic::Int(1)));
+
+    //AnyMap 和 Map 按照字符串的 key 比较
+    let (value, _) = Dynamic::decode(&[0x81, 0x01, 0xa1, b'a']).unwrap();
+    assert!(Schema::compile(&json(r#"{"const":{"1":"a"}}"#)).unwrap().is_valid(&value));
+    assert!(!Schema::compile(&json(r#"{"enum":[{"1":"b"},{"2":"a"}]}"#)).unwrap().is_valid(&value));
+}
+
+#[test]
+fn errors() {
+    //每个错误带上值的路径和 schema 的路径
+    let schema = Schema::compile(&json(r#"{"type":"object","properties":{"user":{"type":"object","properties":{"name":{"type":"string"},"tags":{"items":{"maxLength":1}}},"required":["name"]},"a/b":{"const":1}},"additionalProperties":false}"#)).unwrap();
+    let errors = schema.validate(&json(r#"{"user":{"tags":["x","long"]},"a/b":2,"extra":0}"#));
+    let mut found: Vec<_> = errors.iter().map(|e| (e.instance_path.as_str(), e.schema_path.as_str())).collect();
+    found.sort();
+    assert_eq!(found, [
+        ("/a~1b", "/properties/a~1b/const"),
+        ("/extra", "/additionalProperties"),
+        ("/user", "/properties/user/required"),
+        ("/user/tags/1", "/properties/user/properties/tags/items/maxLength"),
+    ]);
+    assert!(errors.iter().any(|e| e.message == "property name is required"));
+    assert!(errors.iter().any(|e| e.message == "schema is false"));
+
+    //不合法的 schema 编译时返回错误
+    for bad in [r#"1"#, r#"{"type":1}"#, r#"{"properties":[]}"#, r#"{"required":[1]}"#, r#"{"enum":1}"#, r#"{"pattern":"("}"#, r#"{"minLength":-1}"#, r##"{"$ref":"#/missing"}"##, r#"{"items":1}"#] {
+        assert!(Schema::compile(&json(bad)).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn ref_cycle() {
+    //不消耗值的 $ref 循环返回错误 不会栈溢出
+    for schema in [
+        r##"{"$defs":{"a":{"$ref":"#/$defs/a"}},"$ref":"#/$defs/a"}"##,
+        r##"{"$defs":{"a":{"$ref":"#/$defs/b"},"b":{"allOf":[{"$ref":"#/$defs/a"}]}},"$ref":"#/$defs/a"}"##,
+        r##"{"anyOf":[{"$ref":"#"}]}"##,
+        r##"{"not":{"$ref":"#"}}"##,
+    ] {
+        let compiled = Schema::compile(&json(schema)).unwrap();
+        let errors = compiled.validate(&json("1"));
+        assert!(!errors.is_empty(), "{}", schema);
+    }
+
+    //消耗值的递归 schema 正常校验
+    let tree = Schema::compile(&json(r##"{"type":"object","properties":{"value":{"type":"integer"},"children":{"type":"array","items":{"$ref":"#"}}},"required":["value"]}"##)).unwrap();
+    assert!(tree.is_valid(&json(r#"{"value":1,"children":[{"value":2,"children":[]},{"value":3}]}"#)));
+    let errors = tree.validate(&json(r#"{"value":1,"children":[{"value":"x"}]}"#));
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].instance_path, "/children/0/value");
+
+    //循环引用的值也不会一直递归
+    let node = json(r#"{"value":1,"children":[]}"#);
+    node.get_key("children").unwrap().push(node.clone()).unwrap();
+    assert!(!tree.is_valid(&node));
+    node.get_key("children").unwrap().pop().unwrap();
+    assert!(tree.is_valid(&node));
+}
+
+#[test]
+fn any_map() {
+    //{1: "a", "name": 2} 的 key 用字符串校验
+    let (value, _) = Dynamic::decode(&[0x82, 0x01, 0xa1, b'a', 0xa4, b'n', b'a', b'm', b'e', 0x02]).unwrap();
+    assert!(value.is_any_map());
+    let schema = Schema::compile(&json(r#"{"type":"object","properties":{"1":{"type":"string"},"name":{"type":"integer"}},"required":["1","name"],"additionalProperties":false}"#)).unwrap();
+    assert!(schema.is_valid(&value));
+    let strict = Schema::compile(&json(r#"{"type":"object","required":["missing"],"properties":{"1":{"type":"integer"}}}"#)).unwrap();
+    let errors = strict.validate(&value);
+    assert_eq!(errors.len(), 2);
+    assert!(errors.iter().any(|e| e.instance_path == "/1"));
+    let closed = Schema::compile(&json(r#"{"properties":{"name":{}},"additionalProperties":false}"#)).unwrap();
+    assert_eq!(closed.validate(&value)[0].instance_path, "/1");
+}
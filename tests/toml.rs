@@ -0,0 +1,87 @@
+use libai::dynamic::Dynamic;
+use libai::json::FromJson;
+use libai::toml::{parse, write_toml};
+
+fn json(text: &str) -> Dynamic {
+    Dynamic::from_json(text.as_bytes()).unwrap().0
+}
+
+#[test]
+fn vectors() {
+    //解析的结果和 json 比较 fingerprint 和 key 顺序无关
+    let vectors = [
+        ("title = \"TOML\" # 注释\nint = +99\nneg = -17\nbig = 1_000_000\nhex = 0xDEAD_beef\noct = 0o755\nbin = 0b1101\nflt = 6.626e-34\nexp = 5e+22\ninf = -inf\nyes = true",
+            r#"{"title":"TOML","int":99,"neg":-17,"big":1000000,"hex":3735928559,"oct":493,"bin":13,"flt":6.626e-34,"exp":5e22,"inf":null,"yes":true}"#),
+        ("[server]\nhost = \"localhost\"\nports = [ 8000, 8001,\n  8002, ]\n\n[server.limits]\nmax = 10\n\n[[workers]]\nname = \"a\"\n[[workers]]\nname = \"b\"\n[workers.gpu]\nid = 1",
+            r#"{"server":{"host":"localhost","ports":[8000,8001,8002],"limits":{"max":10}},"workers":[{"name":"a"},{"name":"b","gpu":{"id":1}}]}"#),
+        ("name = { first = \"Tom\", last = \"Preston-Werner\" }\npoint = { x = 1, y.z = 2 }\n\"quoted key\" = 'C:\\path'\nsite.\"google.com\" = true\n3.14 = \"pi\"",
+            r#"{"name":{"first":"Tom","last":"Preston-Werner"},"point":{"x":1,"y":{"z":2}},"quoted key":"C:\\path","site":{"google.com":true},"3":{"14":"pi"}}"#),
+        ("a = \"\"\"\nRoses are red\nViolets are blue\"\"\"\nb = \"\"\"\\\n  The quick \\\n\n  fox.\"\"\"\nc = '''\nraw \\n text'''\nd = \"\"\"\"quoted\"\"\"\"\ne = \"tab\\t\\u00e9\\U0001F600\"",
+            r#"{"a":"Roses are red\nViolets are blue","b":"The quick fox.","c":"raw \\n text","d":"\"quoted\"","e":"tab\té😀"}"#),
+        ("odt = 1979-05-27T07:32:00Z\nodt2 = 1979-05-27 00:32:00.999999-07:00\nldt = 1979-05-27T07:32:00\nld = 1979-05-27\nlt = 07:32:00.5",
+            r#"{"odt":{"$datetime":"1979-05-27T07:32:00Z"},"odt2":{"$datetime":"1979-05-27T00:32:00.999999-07:00"},"ldt":{"$datetime":"1979-05-27T07:32:00"},"ld":{"$datetime":"1979-05-27"},"lt":{"$datetime":"07:32:00.5"}}"#),
+    ];
+    for (toml, expected) in vectors {
+        let value = parse(toml).unwrap_or_else(|e| panic!("{}: {}", toml, e));
+        let expected = json(expected);
+        if let Ok(Dynamic::Double(v)) = value.get_key("inf") {         //json 里没有 inf
+            assert_eq!(v, f64::NEG_INFINITY);
+            expected.set_key("inf", v).unwrap();
+        }
+        assert_eq!(value.fingerprint(), expected.fingerprint(), "{}", toml);
+    }
+}
+
+#[test]
+fn datetime() {
+    //带时区的 本地日期时间 本地日期 本地时间 写回去不带引号 类型不变
+    let text = "odt = 1979-05-27T07:32:00Z\nodt2 = 1979-05-27T00:32:00.999999-07:00\nldt = 1979-05-27T07:32:00\nld = 1979-05-27\nlt = 07:32:00.5\nlist = [1979-05-27, 00:00:00]\ntext = \"1979-05-27\"\n";
+    let value = parse(text).unwrap();
+    assert!(value.get_key("text").unwrap().is_string());
+    let mut buf = String::new();
+    write_toml(&value, &mut buf).unwrap();
+    let mut lines: Vec<_> = buf.lines().collect();
+    let mut expected: Vec<_> = text.lines().collect();
+    lines.sort_unstable();
+    expected.sort_unstable();
+    assert_eq!(lines, expected);
+    assert_eq!(parse(&buf).unwrap().fingerprint(), value.fingerprint());
+
+    //不合法的 $datetime 当作普通的表
+    let value = json(r#"{"a":{"$datetime":"1979-02-30"},"b":{"$datetime":"07:32:00","x":1}}"#);
+    let mut buf = String::new();
+    write_toml(&value, &mut buf).unwrap();
+    assert_eq!(parse(&buf).unwrap().fingerprint(), value.fingerprint(), "{}", buf);
+}
+
+#[test]
+fn invalid() {
+    //不合法的 toml
+    let invalid = [
+        "a = 1\na = 2", "[a]\n[a]", "a = 1\n[a]", "a.b = 1\n[a]", "t = { x = 1 }\n[t]", "a = [1]\n[[a]]", "a = 01", "a = 1__0", "a = _1",
+        "a = 1979-02-30", "a = 25:00:00", "a = \"open", "a = 1 b = 2", "a = \"\\q\"", "= 1", "a = 1.", "a = .5",
+    ];
+    for toml in invalid {
+        assert!(parse(toml).is_err(), "{}", toml);
+    }
+    assert!(parse("[a]\nx = 1\n[a]").unwrap_err().to_string().contains("line 3"));
+}
+
+#[test]
+fn write() {
+    //写出之后再解析得到同样的内容
+    let value = json(r#"{"model":"llama","temperature":0.7,"max_tokens":512,"stop":["\n\n","END"],"prompt":"","gpu":{"ids":[0,1],"memory":{"fraction":0.9}},"workers":[{"name":"a","tags":[]},{"name":"b","opts":{"x":1}}],"nested":{"only":{"deep":true}},"odd key":{},"mixed":[1,{"k":"v"},[2]]}"#);
+    value.set_key("prompt", "You are a \"helpful\" assistant.\nAnswer briefly.\\n").unwrap();
+    let mut buf = String::new();
+    write_toml(&value, &mut buf).unwrap();
+    assert_eq!(parse(&buf).unwrap().fingerprint(), value.fingerprint(), "{}", buf);
+
+    //不能表示的值
+    for bad in [r#"[1,2]"#, r#"{"a":null}"#, r#"{"a":[1,null]}"#, "1"] {
+        assert!(write_toml(&json(bad), &mut String::new()).is_err(), "{}", bad);
+    }
+    let cycle = Dynamic::map();
+    cycle.set_key("self", cycle.clone()).unwrap();
+    assert!(write_toml(&cycle, &mut String::new()).is_err());
+    cycle.remove_key("self").unwrap();
+}
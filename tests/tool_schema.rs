@@ -0,0 +1,129 @@
+#![allow(dead_code)]
+use libai::ToolSchema;
+use libai::dynamic::Dynamic;
+use libai::json::FromJson;
+use libai::schema::{Schema, ToolSchema as _};
+
+fn json(text: &str) -> Dynamic {
+    Dynamic::from_json(text.as_bytes()).unwrap().0
+}
+
+/// 查询城市的天气预报
+#[derive(ToolSchema)]
+struct Weather {
+    /// 城市名
+    city: String,
+    /// 预报的天数
+    days: Option<u32>,
+    unit: Unit,
+}
+
+/// 温度单位
+#[derive(ToolSchema)]
+enum Unit {
+    Celsius,
+    Fahrenheit,
+}
+
+#[test]
+fn derive() {
+    let schema = Weather::tool_schema();
+    let expected = json(r##"{"$defs":{"Unit":{"description":"温度单位","enum":["Celsius","Fahrenheit"],"type":"string"}},
+        "additionalProperties":false,"description":"查询城市的天气预报","type":"object","required":["city","unit"],
+        "properties":{"city":{"description":"城市名","type":"string"},"days":{"description":"预报的天数","anyOf":[{"type":"integer"},{"type":"null"}]},"unit":{"$ref":"#/$defs/Unit"}}}"##);
+    assert_eq!(schema.fingerprint(), expected.fingerprint());
+
+    //生成的 schema 可以直接用来校验参数
+    let compiled = Schema::compile(&schema).unwrap();
+    assert!(compiled.is_valid(&json(r#"{"city":"北京","unit":"Celsius"}"#)));
+    assert!(compiled.is_valid(&json(r#"{"city":"北京","days":3,"unit":"Fahrenheit"}"#)));
+    assert!(compiled.is_valid(&json(r#"{"city":"北京","days":null,"unit":"Fahrenheit"}"#)));
+    for bad in [r#"{"city":"北京"}"#, r#"{"city":"北京","unit":"Kelvin"}"#, r#"{"city":1,"unit":"Celsius"}"#, r#"{"city":"北京","unit":"Celsius","x":1}"#] {
+        assert!(!compiled.is_valid(&json(bad)), "{}", bad);
+    }
+}
+
+/// 包装
+#[derive(ToolSchema)]
+struct Wrapper<T> {
+    value: T,
+}
+
+#[derive(ToolSchema)]
+struct Pair {
+    a: Wrapper<i32>,
+    b: Wrapper<String>,
+    c: Wrapper<Wrapper<bool>>,
+}
+
+#[test]
+fn generic() {
+    //泛型的每个实例有自己的定义
+    let schema = Pair::tool_schema();
+    let defs = schema.get_key("$defs").unwrap();
+    assert_eq!(defs.keys().unwrap().count(), 4);
+    let value_type = |name: &str| defs.get_key(name).unwrap().get_key("properties").unwrap().get_key("value").unwrap();
+    assert_eq!(value_type("Wrapper_i32").get_key("type").unwrap(), Dynamic::from("integer"));
+    assert_eq!(value_type("Wrapper_String").get_key("type").unwrap(), Dynamic::from("string"));
+    assert_eq!(value_type("Wrapper_Wrapper_bool").get_key("$ref").unwrap(), Dynamic::from("#/$defs/Wrapper_bool"));
+    assert_eq!(schema.get_key("properties").unwrap().get_key("b").unwrap().get_key("$ref").unwrap(), Dynamic::from("#/$defs/Wrapper_String"));
+    let compiled = Schema::compile(&schema).unwrap();
+    assert!(compiled.is_valid(&json(r#"{"a":{"value":1},"b":{"value":"x"},"c":{"value":{"value":true}}}"#)));
+    assert!(!compiled.is_valid(&json(r#"{"a":{"value":1},"b":{"value":1},"c":{"value":{"value":true}}}"#)));
+}
+
+mod a {
+    #[derive(libai::ToolSchema)]
+    pub struct Config {
+        pub name: String,
+    }
+}
+
+mod b {
+    #[derive(libai::ToolSchema)]
+    pub struct Config {
+        pub size: i64,
+    }
+}
+
+#[derive(ToolSchema)]
+struct Both {
+    a: a::Config,
+    b: b::Config,
+    again: a::Config,
+}
+
+#[test]
+fn same_name() {
+    //不同模块里同名的类型 后面的用带模块路径的名字
+    let schema = Both::tool_schema();
+    let defs = schema.get_key("$defs").unwrap();
+    assert_eq!(defs.keys().unwrap().collect::<Vec<_>>(), ["Config", "tool_schema_b_Config"]);
+    let reference = |field: &str| schema.get_key("properties").unwrap().get_key(field).unwrap().get_key("$ref").unwrap();
+    assert_eq!(reference("a"), Dynamic::from("#/$defs/Config"));
+    assert_eq!(reference("again"), Dynamic::from("#/$defs/Config"));
+    assert_eq!(reference("b"), Dynamic::from("#/$defs/tool_schema_b_Config"));
+    let compiled = Schema::compile(&schema).unwrap();
+    assert!(compiled.is_valid(&json(r#"{"a":{"name":"x"},"b":{"size":1},"again":{"name":"y"}}"#)));
+    assert!(!compiled.is_valid(&json(r#"{"a":{"name":"x"},"b":{"name":"x"},"again":{"name":"y"}}"#)));
+}
+
+/// 搜索
+#[derive(ToolSchema, libai::ToJson)]
+struct Search {
+    query: String,
+    limit: Option<u32>,
+    tags: Vec<Option<String>>,
+}
+
+#[test]
+fn option() {
+    //derive 的 ToJson 把 None 写成 null 也能通过自己的 schema
+    let compiled = Schema::compile(&Search::tool_schema()).unwrap();
+    let search = Search { query: "rust".into(), limit: None, tags: vec![Some("a".into()), None] };
+    let mut text = String::new();
+    libai::json::ToJson::to_json(&search, &mut text);
+    assert!(compiled.is_valid(&json(&text)), "{}", text);
+    assert!(compiled.is_valid(&json(r#"{"query":"rust","tags":[]}"#)));
+    assert!(!compiled.is_valid(&json(r#"{"query":"rust","limit":"10","tags":[]}"#)));
+}
@@ -0,0 +1,79 @@
+use libai::dynamic::Dynamic;
+use libai::dvec;
+use std::cmp::Ordering;
+
+#[test]
+fn edit() {
+    let v = dvec![1i64, 2i64, 3i64];
+    v.insert(0, "a").unwrap();
+    v.insert(4, "z").unwrap();
+    assert!(v.insert(6, 0i64).is_err());
+    assert_eq!(v.remove(1).unwrap(), Dynamic::Int(1));
+    assert!(v.remove(4).is_err());
+    assert_eq!(v.set(0, "b").unwrap(), Dynamic::from("a"));
+    assert!(v.set(4, 0i64).is_err());
+    assert_eq!(v.total_cmp(&dvec!["b", 2i64, 3i64, "z"]), Ordering::Equal);
+    v.truncate(2).unwrap();
+    v.truncate(10).unwrap();
+    assert_eq!(v.len().unwrap(), 2);
+    v.extend(v.clone().into_vec().unwrap()).unwrap();                //来自同一个 Vec 不会死锁
+    assert_eq!(v.total_cmp(&dvec!["b", 2i64, "b", 2i64]), Ordering::Equal);
+    assert!(Dynamic::map().insert(0, 1i64).is_err());
+}
+
+#[test]
+fn range() {
+    let v = dvec![0i64, 1i64, 2i64, 3i64, 4i64];
+    assert_eq!(v.slice(1..3).unwrap().total_cmp(&dvec![1i64, 2i64]), Ordering::Equal);
+    assert_eq!(v.slice(..).unwrap().len().unwrap(), 5);
+    assert_eq!(v.slice(3..=4).unwrap().len().unwrap(), 2);
+    assert_eq!(v.slice(5..).unwrap().len().unwrap(), 0);
+    let (start, end) = (3, 2);
+    for bad in [v.slice(start..end), v.slice(4..6), v.slice(0..=5), v.slice(0..=usize::MAX)] {
+        assert!(bad.unwrap_err().to_string().contains("outbound"));
+    }
+    let removed = v.splice(1..3, ["a", "b", "c"]).unwrap();
+    assert_eq!(removed.total_cmp(&dvec![1i64, 2i64]), Ordering::Equal);
+    assert_eq!(v.total_cmp(&dvec![0i64, "a", "b", "c", 3i64, 4i64]), Ordering::Equal);
+    assert!(v.splice(..=usize::MAX, [1i64]).is_err());
+}
+
+#[test]
+fn order() {
+    //类型之间 Null < Bool < 数字 < String 数字按照数值比较
+    let v = dvec!["x", 2.5f64, Dynamic::Null, 2i64, true, dvec![1i64], Dynamic::Byte(1), "a", 2i64];
+    v.sort().unwrap();
+    let expected = dvec![Dynamic::Null, true, Dynamic::Byte(1), 2i64, 2i64, 2.5f64, "a", "x", dvec![1i64]];
+    assert_eq!(v.total_cmp(&expected), Ordering::Equal);
+    v.dedup().unwrap();
+    assert_eq!(v.len().unwrap(), 8);
+    assert_eq!(v.index_of(&Dynamic::from("a")).unwrap(), Some(5));
+    assert_eq!(v.index_of(&dvec![1i64]).unwrap(), Some(7));
+    assert_eq!(v.index_of(&Dynamic::Int(3)).unwrap(), None);
+    assert!(v.contains_value(&Dynamic::Double(2.5)).unwrap());
+    v.sort_by(|a, b| b.total_cmp(a)).unwrap();
+    assert!(v.get(0).unwrap().is_vec());
+    v.reverse().unwrap();
+    assert!(v.get(0).unwrap().is_null());
+    //数值相等的 Int 和 Double 也有确定的顺序
+    assert_eq!(Dynamic::Int(1).total_cmp(&Dynamic::Double(1.0)), Ordering::Less);
+    assert_eq!(Dynamic::UInt(u64::MAX).total_cmp(&Dynamic::Int(-1)), Ordering::Greater);
+}
+
+#[test]
+fn cycle() {
+    //两个自己包含自己的 Vec 比较不会栈溢出
+    let (a, b) = (dvec![1i64], dvec![1i64]);
+    a.push(a.clone()).unwrap();
+    b.push(b.clone()).unwrap();
+    assert_eq!(a.total_cmp(&b), Ordering::Equal);
+    let c = dvec![1i64];
+    c.push(dvec![2i64]).unwrap();
+    assert_eq!(a.total_cmp(&c), Ordering::Less);
+    let list = dvec![a.clone(), b.clone()];
+    list.sort().unwrap();
+    list.dedup().unwrap();
+    assert_eq!(list.len().unwrap(), 1);
+    a.break_cycles();
+    b.break_cycles();
+}
@@ -0,0 +1,96 @@
+use libai::dynamic::Dynamic;
+use libai::json::FromJson;
+use libai::xml::{extract, parse, parse_with, write_xml, ParseOptions};
+
+fn json(text: &str) -> Dynamic {
+    Dynamic::from_json(text.as_bytes()).unwrap().0
+}
+
+#[test]
+fn vectors() {
+    //解析的结果和 json 比较
+    let vectors = [
+        ("<a/>", r#"{"a":null}"#),
+        ("<a>  hello world </a>", r#"{"a":"  hello world "}"#),
+        ("<?xml version=\"1.0\"?>\n<!-- c -->\n<!DOCTYPE a [<!ENTITY x \"y\">]>\n<a> </a>\n", r#"{"a":null}"#),
+        ("<a x=\"1\" y='2'><b>hi</b><b/><c>&lt;&amp;&#65;&#x42;</c>\n  text\n</a>", r##"{"a":{"@x":"1","@y":"2","b":["hi",null],"c":"<&AB","#text":"text"}}"##),
+        ("<r><item id=\"1\">one</item><item id=\"2\"><![CDATA[<raw> & ]]></item><other/><item>3</item></r>", r##"{"r":{"item":[{"@id":"1","#text":"one"},{"@id":"2","#text":"<raw> &"}],"other":null}}"##),
+        ("<soap:Envelope xmlns:soap=\"urn:x\"><soap:Body><m:r xmlns:m=\"urn:m\">ok</m:r></soap:Body></soap:Envelope>", r##"{"soap:Envelope":{"@xmlns:soap":"urn:x","soap:Body":{"m:r":{"@xmlns:m":"urn:m","#text":"ok"}}}}"##),
+        ("<a t=\"x&#10;y\tz\">\r\n<?pi?><!-- <b/> --></a>", r#"{"a":{"@t":"x\ny z"}}"#),
+    ];
+    for (xml, expected) in vectors {
+        let value = parse(xml).unwrap_or_else(|e| panic!("{}: {}", xml, e));
+        if xml.starts_with("<r>") {                 //第三个 item 加到 Vec 里面
+            let expected = json(expected);
+            expected.get_key("r").unwrap().get_key("item").unwrap().push(Dynamic::from("3")).unwrap();
+            assert_eq!(value.fingerprint(), expected.fingerprint(), "{}", xml);
+            continue;
+        }
+        assert_eq!(value.fingerprint(), json(expected).fingerprint(), "{}", xml);
+    }
+}
+
+#[test]
+fn invalid() {
+    //不合法的 xml
+    let invalid = [
+        "", "text", "<a>", "<a></b>", "<a><b></a></b>", "<a/><b/>", "<a x=1/>", "<a x=\"1\" x=\"2\"/>", "<a>&nbsp;</a>",
+        "<a>1 < 2</a>", "<a x=\"<\"/>", "<1a/>", "<a/>text", "<a><!-- x</a>",
+    ];
+    for xml in invalid {
+        assert!(parse(xml).is_err(), "{}", xml);
+    }
+    assert!(parse("<a>\n  <b>\n</a>").unwrap_err().to_string().contains("line 3"));
+}
+
+#[test]
+fn write() {
+    //写出之后再解析得到同样的内容
+    let value = json(r##"{"request":{"@id":"42","@note":"a \"quoted\"\nline","tool":"search","args":{"query":"1 < 2 & 3","limit":"10"},"tag":["x","y",null,{"@k":"v"}],"mixed":{"@lang":"en","#text":"hello","b":"bold"},"empty":{}}}"##);
+    let mut buf = String::new();
+    write_xml(&value, &mut buf).unwrap();
+    value.get_key("request").unwrap().set_key("empty", Dynamic::Null).unwrap();      //空的 map 写成 <empty/>
+    assert_eq!(parse(&buf).unwrap().fingerprint(), value.fingerprint(), "{}", buf);
+    let mut buf = String::new();
+    write_xml(&libai::dmap!("n" => libai::dmap!("@v" => 1.5, "#text" => 7i64, "f" => true)), &mut buf).unwrap();
+    assert_eq!(buf, "<n v=\"1.5\">7<f>true</f></n>\n");
+
+    //混合内容的文字合并在一起 写回时在子元素前面
+    let value = parse("<a><b>1</b>mid<b>2</b></a>").unwrap();
+    let mut buf = String::new();
+    write_xml(&value, &mut buf).unwrap();
+    assert_eq!(buf, "<a>mid<b>1</b><b>2</b></a>\n");
+
+    //不能表示的值
+    for bad in [r#"[1]"#, r#"{"a":1,"b":2}"#, r#"{"a":[1,2]}"#, r#"{"a":{"b":[[1]]}}"#, r#"{"1a":1}"#, r#"{"a":{"@b c":1}}"#, r#"{"a":{"@b":[1]}}"#, "{\"a\":\"\\u0001\"}", r#"{"@a":1}"#] {
+        assert!(write_xml(&json(bad), &mut String::new()).is_err(), "{}", bad);
+    }
+    let cycle = Dynamic::map();
+    cycle.set_key("self", cycle.clone()).unwrap();
+    assert!(write_xml(&libai::dmap!("root" => cycle.clone()), &mut String::new()).is_err());
+    cycle.remove_key("self").unwrap();
+}
+
+#[test]
+fn lenient() {
+    //模型的输出 标签外面的文字忽略 不配对的 < & 和没有关闭的标签
+    let answer = "Sure! Here is my reasoning.\n<thinking>\nIf a < b && b < c then a < c.\n</thinking>\n\n<answer>\n  <value>42</value>\n  <unit>m</unit>\n</answer>\nsome trailing words <note>truncated by max_tokens";
+    let value = parse_with(answer, &ParseOptions { lenient: true }).unwrap();
+    let expected = json(r#"{"thinking":"If a < b && b < c then a < c.","answer":{"value":"42","unit":"m"},"note":"truncated by max_tokens"}"#);
+    assert_eq!(value.fingerprint(), expected.fingerprint(), "{:?}", value);
+    let value = parse_with("<a><b>x</a> <a>y</c></a> <p>one<p>two</p></p> 1 <2 </a>", &ParseOptions { lenient: true }).unwrap();
+    let expected = json(r##"{"a":[{"b":"x"},"y"],"p":{"#text":"one","p":"two"}}"##);
+    assert_eq!(value.fingerprint(), expected.fingerprint(), "{:?}", value);
+    //不配对的结束标签关闭当前的元素 不会变成文字
+    let value = parse_with("<a>x</b> <c><d>y</e>z</c>", &ParseOptions { lenient: true }).unwrap();
+    let expected = json(r##"{"a":"x","c":{"d":"y","#text":"z"}}"##);
+    assert_eq!(value.fingerprint(), expected.fingerprint(), "{:?}", value);
+    assert_eq!(parse_with("no tags at all", &ParseOptions { lenient: true }).unwrap().len().unwrap(), 0);
+
+    //原始的内容
+    assert_eq!(extract(answer, "thinking"), vec!["If a < b && b < c then a < c."]);
+    assert_eq!(extract(answer, "answer"), vec!["<value>42</value>\n  <unit>m</unit>"]);
+    assert_eq!(extract(answer, "note"), vec!["truncated by max_tokens"]);
+    assert_eq!(extract("<answers>x</answers><answer/><answer id=\"2\">a</answer><answer>b", "answer"), vec!["a", "b"]);
+    assert!(extract(answer, "missing").is_empty());
+}
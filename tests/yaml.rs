@@ -0,0 +1,91 @@
+use libai::dynamic::Dynamic;
+use libai::json::FromJson;
+use libai::yaml::{parse, parse_all, parse_with, write_yaml, ParseOptions};
+
+fn json(text: &str) -> Dynamic {
+    Dynamic::from_json(text.as_bytes()).unwrap().0
+}
+
+#[test]
+fn vectors() {
+    //解析的结果和 json 比较 fingerprint 和 key 顺序无关
+    let vectors = [
+        ("a: 1\nb: -2.5\nc: true\nd: ~\ne: hello world\nf: '1'\ng: 0x1f\nh: 0o17\ni: 1e3", r#"{"a":1,"b":-2.5,"c":true,"d":null,"e":"hello world","f":"1","g":31,"h":15,"i":1000.0}"#),
+        ("- a\n- - b\n  - c\n- d: 1\n  e: 2\n-\n  f: 3", r#"["a",["b","c"],{"d":1,"e":2},{"f":3}]"#),
+        ("key:\n- 1\n- 2\nnext:\n  inner: x # 注释\n\nlast:", r#"{"key":[1,2],"next":{"inner":"x"},"last":null}"#),
+        ("flow: {a: 1, b: [x, 'y', \"z\"], c: }\nlist: [1, {k: v}, a: b,\n  ]", r#"{"flow":{"a":1,"b":["x","y","z"],"c":null},"list":[1,{"k":"v"},{"a":"b"}]}"#),
+        ("plain: this is\n  folded\n\n  text\nsingle: 'it''s\n  here'\ndouble: \"tab\\tand \\u00e9 \\\n  joined\"", r#"{"plain":"this is folded\ntext","single":"it's here","double":"tab\tand é joined"}"#),
+        ("literal: |\n  line 1\n    indented\n\n  line 3\nkeep: |+\n  a\n\nstrip: >-\n  folded\n  line\n\n  para\nclip: >\n  x\n", r#"{"literal":"line 1\n  indented\n\nline 3\n","keep":"a\n\n","strip":"folded line\npara","clip":"x\n"}"#),
+        ("base: &base\n  a: 1\n  b: 2\nderived:\n  <<: *base\n  b: 3\ncopy: *base\nlist: &l [1, 2]\nagain: *l", r#"{"base":{"a":1,"b":2},"derived":{"a":1,"b":3},"copy":{"a":1,"b":2},"list":[1,2],"again":[1,2]}"#),
+        ("%YAML 1.2\n---\n!!str 123: !!float 1\ntext: !!str true\nnumber: !!int \"7\"\nurl: http://example.com/a#b", r#"{"123":1.0,"text":"true","number":7,"url":"http://example.com/a#b"}"#),
+        ("- |2\n   leading space\n- \"quoted: key\": 1\n  'x': [a, b]", r#"[" leading space\n",{"quoted: key":1,"x":["a","b"]}]"#),
+        ("{\"json\": [1, 2.5, \"x\", null, true], \"nested\": {\"a\": {}}}", r#"{"json":[1,2.5,"x",null,true],"nested":{"a":{}}}"#),
+        ("d: \"a'b\"", r#"{"d":"a'b"}"#),
+        ("- 'a\"b'", r#"["a\"b"]"#),
+        ("d: 'y\"'", r#"{"d":"y\""}"#),
+        ("a: 'C:\\dir'", r#"{"a":"C:\\dir"}"#),
+        ("- \"it's \\\"x\\\" 'y'\"\n- 'say \"\\n\" ''z'''", r#"["it's \"x\" 'y'","say \"\\n\" 'z'"]"#),
+    ];
+    for (yaml, expected) in vectors {
+        let value = parse(yaml).unwrap_or_else(|e| panic!("{}: {}", yaml, e));
+        assert_eq!(value.fingerprint(), json(expected).fingerprint(), "{}", yaml);
+    }
+}
+
+#[test]
+fn documents() {
+    //多个文档
+    let documents = parse_all("# 开头的注释\n--- 1\n--- |\n  text\n...\n---\na: b\n---\n").unwrap();
+    assert_eq!(documents.len(), 4);
+    assert_eq!(documents[0], Dynamic::Int(1));
+    assert_eq!(documents[1], Dynamic::from("text\n"));
+    assert!(documents[3].is_null());
+    assert!(parse("a\n---\nb").is_err());
+    assert!(parse_all("").unwrap().is_empty());
+}
+
+#[test]
+fn alias() {
+    //alias 展开成独立的复制
+    let value = parse("a: &x [1]\nb: *x").unwrap();
+    value.get_key("b").unwrap().push(2i64).unwrap();
+    assert_eq!(value.get_key("a").unwrap().len().unwrap(), 1);
+
+    //billion laughs 每一层是上一层的 10 倍 超过展开的上限返回错误
+    let mut text = String::from("a: &a [x, x, x, x, x, x, x, x, x, x]\n");
+    for (name, prev) in ["b", "c", "d", "e", "f", "g", "h", "i"].iter().zip(["a", "b", "c", "d", "e", "f", "g", "h"]) {
+        text.push_str(&format!("{}: &{} [{}]\n", name, name, vec![format!("*{}", prev); 10].join(", ")));
+    }
+    assert!(parse(&text).unwrap_err().to_string().contains("aliases expand to more than 1000000 nodes"));
+    let options = ParseOptions { max_alias_nodes: 10, ..ParseOptions::default() };
+    assert!(parse_with("a: &a [1, 2]\nb: [*a, *a, *a]", &options).is_ok());
+    assert!(parse_with("a: &a [1, 2]\nb: [*a, *a, *a]\nc: *a", &options).is_err());
+    //[1, 2] 是 3 个节点 每个文档重新计数
+    assert_eq!(parse_with("a: &a [1, 2]\nb: [*a, *a, *a]\n---\na: &a [1, 2]\nb: [*a, *a, *a]", &options).unwrap().len(), 2);
+}
+
+#[test]
+fn write() {
+    //输出之后再解析得到同样的内容 多行的字符串用 | 块
+    let prompt = "You are a helpful assistant.\n\nRules:\n  - be brief\n  - cite sources\n";
+    let value = json(r#"{"name":"agent","version":1.0,"tags":["a","true","","- x"],"prompt":"","steps":[{"tool":"search","args":{"q":"rust: yaml"}},[1,2],[]],"empty":{},"nan":null}"#);
+    value.set_key("prompt", prompt).unwrap();
+    value.get_key("steps").unwrap().push(" starts with space\nsecond").unwrap();
+    value.set_key("bytes", Dynamic::from_bytes(vec![0, 1, 2, 250])).unwrap();
+    let mut buf = String::new();
+    write_yaml(&value, &mut buf);
+    assert!(buf.contains("prompt: |\n  You are a helpful assistant.\n\n  Rules:\n    - be brief\n"), "{}", buf);
+    let reparsed = parse(&buf).unwrap();
+    assert_eq!(reparsed.fingerprint(), value.fingerprint(), "{}", buf);
+
+    //顶层的标量和块字符串
+    for value in [Dynamic::from("a\n b"), Dynamic::from(" x\n\n\n"), Dynamic::Double(f64::INFINITY), Dynamic::from("null")] {
+        let mut buf = String::new();
+        write_yaml(&value, &mut buf);
+        assert_eq!(parse(&buf).unwrap().fingerprint(), value.fingerprint(), "{}", buf);
+    }
+
+    //错误带上行号
+    assert!(parse("a: 1\n b: 2").unwrap_err().to_string().contains("line 2"));
+    assert!(parse("a: *missing").unwrap_err().to_string().contains("unknown anchor"));
+}